@@ -61,6 +61,14 @@ fn main() -> Result<()> {
         )
         .unwrap();
 
+    tonic_build::configure()
+        .build_client(false)
+        .compile(
+            &["proto/arrow/flight.proto", "proto/arrow/flight_sql.proto"],
+            &["proto"],
+        )
+        .unwrap();
+
     let mut config = prost_build::Config::new();
     config
         .type_attribute(
@@ -113,6 +121,8 @@ fn main() -> Result<()> {
         &["proto"],
     )?;
 
+    prost_build::Config::new().compile_protos(&["proto/loki/push.proto"], &["proto"])?;
+
     // build information
     let output = Command::new("git")
         .args(["describe", "--tags", "--abbrev=0"])
@@ -13,6 +13,7 @@
 // limitations under the License.
 
 pub mod file_data;
+pub mod promql;
 pub mod stats;
 pub mod tmpfs;
 
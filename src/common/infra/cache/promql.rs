@@ -0,0 +1,157 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use lru::LruCache;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::common::infra::config::CONFIG;
+use crate::service::promql::value::{RangeValue, Sample};
+
+static RESULTS: Lazy<Mutex<ResultCache>> = Lazy::new(|| Mutex::new(ResultCache::new()));
+
+/// The result of a range query for the evaluation timestamps in `[start, end]`.
+#[derive(Debug, Clone)]
+pub struct CachedResult {
+    pub start: i64,
+    pub end: i64,
+    pub series: Vec<RangeValue>,
+}
+
+impl CachedResult {
+    fn size(&self) -> usize {
+        self.series
+            .iter()
+            .map(|s| {
+                s.labels
+                    .iter()
+                    .map(|l| l.name.len() + l.value.len())
+                    .sum::<usize>()
+                    + s.samples.len() * std::mem::size_of::<Sample>()
+            })
+            .sum()
+    }
+}
+
+pub struct ResultCache {
+    max_size: usize,
+    cur_size: usize,
+    data: LruCache<String, (usize, CachedResult)>,
+}
+
+impl Default for ResultCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResultCache {
+    pub fn new() -> ResultCache {
+        ResultCache::with_capacity(CONFIG.memory_cache.promql_max_size)
+    }
+
+    pub fn with_capacity(max_size: usize) -> ResultCache {
+        ResultCache {
+            max_size,
+            cur_size: 0,
+            data: LruCache::unbounded(),
+        }
+    }
+
+    pub fn get(&mut self, key: &str) -> Option<CachedResult> {
+        self.data.get(key).map(|(_, result)| result.clone())
+    }
+
+    pub fn set(&mut self, key: &str, result: CachedResult) {
+        let data_size = key.len() + result.size();
+        if let Some((old_size, _)) = self.data.pop(key) {
+            self.cur_size -= old_size;
+        }
+        if data_size > self.max_size {
+            return;
+        }
+        // cache is full, release the least recently used results
+        while self.cur_size + data_size > self.max_size {
+            match self.data.pop_lru() {
+                Some((_, (size, _))) => self.cur_size -= size,
+                None => break,
+            }
+        }
+        self.cur_size += data_size;
+        self.data.put(key.to_string(), (data_size, result));
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+#[inline]
+pub fn get(key: &str) -> Option<CachedResult> {
+    RESULTS.lock().get(key)
+}
+
+#[inline]
+pub fn set(key: &str, result: CachedResult) {
+    RESULTS.lock().set(key, result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::promql::value::Label;
+    use std::sync::Arc;
+
+    fn result(name: &str, samples: usize) -> CachedResult {
+        let series = RangeValue::new(
+            vec![Arc::new(Label::new("__name__", name))],
+            (0..samples).map(|i| Sample::new(i as i64, 1.0)),
+        );
+        CachedResult {
+            start: 0,
+            end: samples as i64,
+            series: vec![series],
+        }
+    }
+
+    #[test]
+    fn test_result_cache() {
+        // key + labels + 10 samples = 1 + 10 + 160 bytes
+        let mut cache = ResultCache::with_capacity(400);
+        cache.set("a", result("up", 10));
+        cache.set("b", result("up", 10));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("a").unwrap().series[0].samples.len(), 10);
+
+        // "b" is the least recently used result
+        cache.set("c", result("up", 10));
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+
+        // replacing a result releases its old size
+        cache.set("a", result("up", 1));
+        cache.set("d", result("up", 10));
+        assert_eq!(cache.len(), 3);
+
+        // too large to be cached
+        cache.set("e", result("up", 100));
+        assert!(cache.get("e").is_none());
+        assert_eq!(cache.len(), 3);
+    }
+}
@@ -28,11 +28,16 @@ use vector_enrichment::TableRegistry;
 
 use crate::common::{
     meta::{
-        alert::{AlertDestination, AlertList, DestinationTemplate, Trigger, TriggerTimer},
+        alert::{
+            AlertDestination, AlertList, DestinationTemplate, MaintenanceWindow, Silence, Trigger,
+            TriggerTimer,
+        },
+        fluent::FluentRoute,
         functions::{StreamFunctionsList, Transform},
+        kafka::KafkaSource,
         maxmind::MaxmindClient,
         organization::OrganizationSetting,
-        prom::ClusterLeader,
+        prom::{ClusterLeader, RecordingRule},
         syslog::SyslogRoute,
         user::User,
     },
@@ -134,6 +139,12 @@ pub static METRIC_CLUSTER_MAP: Lazy<Arc<RwAHashMap<String, Vec<String>>>> =
     Lazy::new(|| Arc::new(tokio::sync::RwLock::new(AHashMap::new())));
 pub static METRIC_CLUSTER_LEADER: Lazy<Arc<RwAHashMap<String, ClusterLeader>>> =
     Lazy::new(|| Arc::new(tokio::sync::RwLock::new(AHashMap::new())));
+pub static RECORDING_RULES: Lazy<RwHashMap<String, RecordingRule>> = Lazy::new(DashMap::default);
+pub static RECORDING_RULE_LEADERS: Lazy<RwHashMap<String, ClusterLeader>> =
+    Lazy::new(DashMap::default);
+pub static KAFKA_SOURCES: Lazy<RwHashMap<String, KafkaSource>> = Lazy::new(DashMap::default);
+pub static KAFKA_SOURCE_LEADERS: Lazy<RwHashMap<String, ClusterLeader>> =
+    Lazy::new(DashMap::default);
 pub static STREAM_ALERTS: Lazy<RwHashMap<String, AlertList>> = Lazy::new(DashMap::default);
 pub static TRIGGERS: Lazy<RwHashMap<String, Trigger>> = Lazy::new(DashMap::default);
 pub static TRIGGERS_IN_PROCESS: Lazy<RwHashMap<String, TriggerTimer>> = Lazy::new(DashMap::default);
@@ -141,8 +152,14 @@ pub static ALERTS_TEMPLATES: Lazy<RwHashMap<String, DestinationTemplate>> =
     Lazy::new(Default::default);
 pub static ALERTS_DESTINATIONS: Lazy<RwHashMap<String, AlertDestination>> =
     Lazy::new(Default::default);
+pub static ALERT_SILENCES: Lazy<RwHashMap<String, Silence>> = Lazy::new(Default::default);
+pub static ALERT_MAINTENANCE_WINDOWS: Lazy<RwHashMap<String, MaintenanceWindow>> =
+    Lazy::new(Default::default);
 pub static SYSLOG_ROUTES: Lazy<RwHashMap<String, SyslogRoute>> = Lazy::new(Default::default);
 pub static SYSLOG_ENABLED: Lazy<Arc<RwLock<bool>>> = Lazy::new(|| Arc::new(RwLock::new(false)));
+pub static FLUENT_ROUTES: Lazy<RwHashMap<String, FluentRoute>> = Lazy::new(Default::default);
+// org_id -> shared key of the Fluent Forward senders of the org
+pub static FLUENT_KEYS: Lazy<RwHashMap<String, String>> = Lazy::new(Default::default);
 pub static ENRICHMENT_TABLES: Lazy<RwHashMap<String, StreamTable>> = Lazy::new(Default::default);
 pub static ENRICHMENT_REGISTRY: Lazy<Arc<TableRegistry>> =
     Lazy::new(|| Arc::new(TableRegistry::default()));
@@ -169,8 +186,12 @@ pub struct Config {
     pub dynamo: Dynamo,
     pub s3: S3,
     pub tcp: TCP,
+    pub fluent: Fluent,
+    pub kafka: Kafka,
     pub prom: Prometheus,
+    pub loki: Loki,
     pub profiling: Pyroscope,
+    pub smtp: Smtp,
 }
 
 #[derive(EnvConfig)]
@@ -222,6 +243,46 @@ pub struct TCP {
     pub tcp_port: u16,
     #[env_config(name = "ZO_UDP_PORT", default = 5514)]
     pub udp_port: u16,
+    #[env_config(name = "ZO_TCP_TLS_ENABLED", default = false)]
+    pub tcp_tls_enabled: bool,
+    #[env_config(name = "ZO_TCP_TLS_CERT_PATH", default = "")]
+    pub tcp_tls_cert_path: String,
+    #[env_config(name = "ZO_TCP_TLS_KEY_PATH", default = "")]
+    pub tcp_tls_key_path: String,
+    #[env_config(name = "ZO_TCP_MAX_MESSAGE_SIZE", default = 65536)] // in bytes
+    pub tcp_max_message_size: usize,
+    // connections sending more invalid messages are closed, 0 to keep them open
+    #[env_config(name = "ZO_TCP_MAX_BAD_MESSAGES", default = 100)]
+    pub tcp_max_bad_messages: u64,
+}
+
+#[derive(EnvConfig)]
+pub struct Fluent {
+    #[env_config(name = "ZO_FLUENT_ENABLED", default = false)]
+    pub enabled: bool,
+    #[env_config(name = "ZO_FLUENT_PORT", default = 24224)]
+    pub port: u16,
+    #[env_config(name = "ZO_FLUENT_MAX_CHUNK_SIZE", default = 8388608)] // in bytes
+    pub max_chunk_size: usize,
+    #[env_config(name = "ZO_FLUENT_HANDSHAKE_TIMEOUT", default = 10)] // in seconds
+    pub handshake_timeout: u64,
+    // connections sending more invalid messages are closed, 0 to keep them open
+    #[env_config(name = "ZO_FLUENT_MAX_BAD_MESSAGES", default = 100)]
+    pub max_bad_messages: u64,
+}
+
+#[derive(EnvConfig)]
+pub struct Kafka {
+    #[env_config(name = "ZO_KAFKA_POLL_INTERVAL", default = 5)] // in seconds
+    pub poll_interval: u64,
+    // per partition and poll
+    #[env_config(name = "ZO_KAFKA_FETCH_MAX_BYTES", default = 1048576)]
+    pub fetch_max_bytes: i32,
+    #[env_config(name = "ZO_KAFKA_FETCH_MAX_WAIT", default = 500)] // in milliseconds
+    pub fetch_max_wait: i32,
+    // of the requests to the brokers, connecting included
+    #[env_config(name = "ZO_KAFKA_REQUEST_TIMEOUT", default = 30)] // in seconds
+    pub request_timeout: u64,
 }
 
 #[derive(EnvConfig)]
@@ -290,8 +351,29 @@ pub struct Common {
     pub ui_sql_base64_enabled: bool,
     #[env_config(name = "ZO_METRICS_DEDUP_ENABLED", default = true)]
     pub metrics_dedup_enabled: bool,
+    // store OTLP delta sums and histograms as cumulative ones, the running totals are kept
+    // per ingester so all the points of a series have to go to the same ingester
+    #[env_config(name = "ZO_METRICS_DELTA_TO_CUMULATIVE", default = false)]
+    pub metrics_delta_to_cumulative: bool,
     #[env_config(name = "ZO_TRACES_BLOOM_FILTER_ENABLED", default = false)]
     pub traces_bloom_filter_enabled: bool,
+    // derive request, error and duration metrics from the ingested spans
+    #[env_config(name = "ZO_TRACES_SPAN_METRICS_ENABLED", default = false)]
+    pub traces_span_metrics_enabled: bool,
+    // upper bounds of the span duration histogram buckets, in milliseconds
+    #[env_config(
+        name = "ZO_TRACES_SPAN_METRICS_BUCKETS",
+        default = "2,4,6,8,10,50,100,200,400,800,1000,1400,2000,5000,10000,15000"
+    )]
+    pub traces_span_metrics_buckets: String,
+    // write a term index beside each logs parquet file, full text search skips the
+    // files which can't match
+    #[env_config(name = "ZO_INVERTED_INDEX_ENABLED", default = false)]
+    pub inverted_index_enabled: bool,
+    // store the values of the string columns of each file in file_list, equality filters
+    // skip the files which can't match
+    #[env_config(name = "ZO_FILE_LIST_FIELD_SUMMARY_ENABLED", default = false)]
+    pub file_list_field_summary_enabled: bool,
     #[env_config(name = "ZO_TRACING_ENABLED", default = false)]
     pub tracing_enabled: bool,
     #[env_config(name = "OTEL_OTLP_HTTP_ENDPOINT", default = "")]
@@ -369,6 +451,15 @@ pub struct Limit {
     pub query_thread_num: usize,
     #[env_config(name = "ZO_QUERY_TIMEOUT", default = 600)]
     pub query_timeout: u64,
+    #[env_config(name = "ZO_ALERT_HISTORY_LIMIT", default = 100)] // state changes kept per alert
+    pub alert_history_limit: usize,
+    #[env_config(name = "ZO_ALERT_NOTIFICATION_RETRIES", default = 3)]
+    pub alert_notification_retries: usize,
+    #[env_config(name = "ZO_ALERT_NOTIFICATION_BACKOFF", default = 1000)] // milliseconds
+    pub alert_notification_backoff: u64,
+    // of every attempt to send a notification, connecting included
+    #[env_config(name = "ZO_ALERT_NOTIFICATION_TIMEOUT", default = 30)] // seconds
+    pub alert_notification_timeout: u64,
     #[env_config(name = "ZO_INGEST_ALLOWED_UPTO", default = 5)] // in hours - in past
     pub ingest_allowed_upto: i64,
     #[env_config(name = "ZO_LOGS_FILE_RETENTION", default = "hourly")]
@@ -381,6 +472,34 @@ pub struct Limit {
     pub metrics_leader_push_interval: u64,
     #[env_config(name = "ZO_METRICS_LEADER_ELECTION_INTERVAL", default = 30)]
     pub metrics_leader_election_interval: i64,
+    #[env_config(name = "ZO_METRICS_DELTA_MAX_STALE", default = 300)] // in seconds
+    pub metrics_delta_max_stale: i64,
+    // metrics a remote read query without an equality matcher on the name may select
+    #[env_config(name = "ZO_PROMETHEUS_REMOTE_READ_MAX_METRICS", default = 100)]
+    pub prometheus_remote_read_max_metrics: usize,
+    #[env_config(name = "ZO_TRACES_SPAN_METRICS_INTERVAL", default = 15)] // in seconds
+    pub traces_span_metrics_interval: u64,
+    // spans buffered for tail sampling decisions, more are written without sampling
+    #[env_config(name = "ZO_TRACES_TAIL_SAMPLING_MAX_SPANS", default = 100000)]
+    pub traces_tail_sampling_max_spans: usize,
+    // files with more distinct terms get no index and are always scanned
+    #[env_config(name = "ZO_INVERTED_INDEX_MAX_TERMS", default = 1000000)]
+    pub inverted_index_max_terms: usize,
+    // columns with more distinct values in a file only keep their min and max value
+    #[env_config(name = "ZO_FILE_LIST_FIELD_SUMMARY_MAX_VALUES", default = 16)]
+    pub file_list_field_summary_max_values: usize,
+    // bytes of the summary of a file, the columns beyond it aren't summarized. DynamoDB
+    // items, with the file_list entry, are limited to 400KB
+    #[env_config(name = "ZO_FILE_LIST_FIELD_SUMMARY_MAX_SIZE", default = 65536)]
+    pub file_list_field_summary_max_size: usize,
+    // files sent to each querier for a chunk of a streaming search
+    #[env_config(name = "ZO_SEARCH_STREAM_CHUNK_FILES", default = 100)]
+    pub search_stream_chunk_files: usize,
+    // rows of an Arrow Flight query without LIMIT whose hits are merged in memory
+    #[env_config(name = "ZO_SEARCH_ARROW_MERGE_LIMIT", default = 100000)]
+    pub search_arrow_merge_limit: usize,
+    #[env_config(name = "ZO_RECORDING_RULES_CHECK_INTERVAL", default = 10)] // in seconds
+    pub recording_rules_check_interval: u64,
     #[env_config(name = "ZO_HEARTBEAT_INTERVAL", default = 30)] // in minutes
     pub hb_interval: i64,
     #[env_config(name = "ZO_COLS_PER_RECORD_LIMIT", default = 1000)]
@@ -440,6 +559,18 @@ pub struct MemoryCache {
     pub datafusion_max_size: usize,
     #[env_config(name = "ZO_MEMORY_CACHE_DATAFUSION_MEMORY_POOL", default = "")]
     pub datafusion_memory_pool: String,
+    #[env_config(name = "ZO_MEMORY_CACHE_PROMQL_ENABLED", default = true)]
+    pub promql_enabled: bool,
+    // MB, results of PromQL range queries, default is 5% of max_size
+    #[env_config(name = "ZO_MEMORY_CACHE_PROMQL_MAX_SIZE", default = 0)]
+    pub promql_max_size: usize,
+    // seconds, steps this close to now are not cached, samples arriving later than
+    // this are missing from the cached results until they are evicted
+    #[env_config(name = "ZO_MEMORY_CACHE_PROMQL_FRESHNESS", default = 300)]
+    pub promql_freshness: i64,
+    // MB, decoded inverted indexes, default is 5% of max_size
+    #[env_config(name = "ZO_MEMORY_CACHE_INVERTED_INDEX_MAX_SIZE", default = 0)]
+    pub inverted_index_max_size: usize,
 }
 
 #[derive(EnvConfig)]
@@ -576,6 +707,33 @@ pub struct Prometheus {
     pub ha_replica_label: String,
 }
 
+#[derive(Debug, EnvConfig)]
+pub struct Loki {
+    // label of the pushed streams holding the name of the target stream
+    #[env_config(name = "ZO_LOKI_STREAM_LABEL", default = "stream_name")]
+    pub stream_label: String,
+}
+
+#[derive(EnvConfig)]
+pub struct Smtp {
+    #[env_config(name = "ZO_SMTP_ENABLED", default = false)]
+    pub smtp_enabled: bool,
+    #[env_config(name = "ZO_SMTP_HOST", default = "localhost")]
+    pub smtp_host: String,
+    #[env_config(name = "ZO_SMTP_PORT", default = 25)]
+    pub smtp_port: u16,
+    #[env_config(name = "ZO_SMTP_USER_NAME", default = "")]
+    pub smtp_username: String,
+    #[env_config(name = "ZO_SMTP_PASSWORD", default = "")]
+    pub smtp_password: String,
+    #[env_config(name = "ZO_SMTP_FROM_EMAIL", default = "")]
+    pub smtp_from_email: String,
+    #[env_config(name = "ZO_SMTP_REPLY_TO", default = "")]
+    pub smtp_reply_to: String,
+    #[env_config(name = "ZO_SMTP_ENCRYPTION", default = "")] // "", "ssl" or "starttls"
+    pub smtp_encryption: String,
+}
+
 pub fn init() -> Config {
     dotenv().ok();
     let mut cfg = Config::init().unwrap();
@@ -637,6 +795,11 @@ pub fn init() -> Config {
         panic!("dynamo config error: {e}");
     }
 
+    // check syslog tcp config
+    if let Err(e) = check_tcp_config(&mut cfg) {
+        panic!("tcp config error: {e}");
+    }
+
     cfg
 }
 
@@ -649,6 +812,19 @@ fn check_common_config(cfg: &mut Config) -> Result<(), anyhow::Error> {
     if cfg.limit.req_cols_per_record_limit == 0 {
         cfg.limit.req_cols_per_record_limit = 1000;
     }
+    if cfg.limit.traces_span_metrics_interval == 0 {
+        cfg.limit.traces_span_metrics_interval = 15;
+    }
+    if cfg
+        .common
+        .traces_span_metrics_buckets
+        .split(',')
+        .any(|v| !matches!(v.trim().parse::<f64>(), Ok(v) if v > 0.0))
+    {
+        return Err(anyhow::anyhow!(
+            "ZO_TRACES_SPAN_METRICS_BUCKETS must be a list of positive numbers"
+        ));
+    }
 
     // HACK instance_name
     if cfg.common.instance_name.is_empty() {
@@ -814,6 +990,38 @@ fn check_sled_config(cfg: &mut Config) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+fn check_tcp_config(cfg: &mut Config) -> Result<(), anyhow::Error> {
+    if cfg.tcp.tcp_tls_enabled
+        && (cfg.tcp.tcp_tls_cert_path.is_empty() || cfg.tcp.tcp_tls_key_path.is_empty())
+    {
+        return Err(anyhow::anyhow!(
+            "ZO_TCP_TLS_CERT_PATH and ZO_TCP_TLS_KEY_PATH are required when ZO_TCP_TLS_ENABLED is true"
+        ));
+    }
+    if cfg.tcp.tcp_max_message_size == 0 {
+        cfg.tcp.tcp_max_message_size = 65536;
+    }
+    if cfg.fluent.handshake_timeout == 0 {
+        cfg.fluent.handshake_timeout = 10;
+    }
+    if cfg.fluent.max_chunk_size == 0 {
+        cfg.fluent.max_chunk_size = 8388608;
+    }
+    if cfg.limit.alert_notification_timeout == 0 {
+        cfg.limit.alert_notification_timeout = 30;
+    }
+    if cfg.kafka.request_timeout == 0 {
+        cfg.kafka.request_timeout = 30;
+    }
+    if cfg.kafka.poll_interval == 0 {
+        cfg.kafka.poll_interval = 5;
+    }
+    if cfg.kafka.fetch_max_bytes <= 0 {
+        cfg.kafka.fetch_max_bytes = 1048576;
+    }
+    Ok(())
+}
+
 fn check_memory_cache_config(cfg: &mut Config) -> Result<(), anyhow::Error> {
     let mem_total = cgroup::get_memory_limit();
     cfg.limit.mem_total = mem_total;
@@ -839,6 +1047,16 @@ fn check_memory_cache_config(cfg: &mut Config) -> Result<(), anyhow::Error> {
     } else {
         cfg.memory_cache.datafusion_max_size *= 1024 * 1024;
     }
+    if cfg.memory_cache.promql_max_size == 0 {
+        cfg.memory_cache.promql_max_size = cfg.memory_cache.max_size / 20;
+    } else {
+        cfg.memory_cache.promql_max_size *= 1024 * 1024;
+    }
+    if cfg.memory_cache.inverted_index_max_size == 0 {
+        cfg.memory_cache.inverted_index_max_size = cfg.memory_cache.max_size / 20;
+    } else {
+        cfg.memory_cache.inverted_index_max_size *= 1024 * 1024;
+    }
     Ok(())
 }
 
@@ -526,6 +526,7 @@ pub fn get_dynamo_key(db_key: &str, operation: DbOperation) -> DynamoTableDetail
         || db_key.starts_with("/instance")
         || db_key.starts_with("/meta/kv/version")
         || db_key.starts_with("/syslog")
+        || db_key.starts_with("/fluent")
     {
         return DynamoTableDetails {
             name: CONFIG.dynamo.meta_table.clone(),
@@ -554,7 +555,7 @@ pub fn get_dynamo_key(db_key: &str, operation: DbOperation) -> DynamoTableDetail
                 if parts.len() == 1 || parts[1].is_empty() {
                     DynamoTableDetails {
                         pk_value: parts[1].to_string(),
-                        rk_value: parts[0].to_string(),
+                        rk_value: format!("{}/", parts[0]),
                         name: CONFIG.dynamo.org_meta_table.clone(),
                         pk: "org".to_string(),
                         rk: "key".to_string(),
@@ -564,7 +565,7 @@ pub fn get_dynamo_key(db_key: &str, operation: DbOperation) -> DynamoTableDetail
                 } else {
                     DynamoTableDetails {
                         pk_value: parts[1].to_string(),
-                        rk_value: parts[0].to_string(),
+                        rk_value: format!("{}/", parts[0]),
                         name: CONFIG.dynamo.org_meta_table.clone(),
                         pk: "org".to_string(),
                         rk: "key".to_string(),
@@ -575,6 +576,45 @@ pub fn get_dynamo_key(db_key: &str, operation: DbOperation) -> DynamoTableDetail
             }
         },
 
+        // nested keys of an org, e.g. `/trigger_history/{org}/{alert}/{id}`, are kept whole
+        // in the range key. The listed prefix keeps its trailing slash so that it doesn't
+        // match entities sharing the name, e.g. `trigger` and `trigger_history`.
+        "trigger_history"
+        | "silences"
+        | "maintenance_windows"
+        | "recording_rules"
+        | "recording_rules_leader"
+        | "kafka_sources"
+        | "kafka_sources_leader"
+        | "kafka_offsets" => {
+            let rk_value = std::iter::once(parts[0])
+                .chain(parts.iter().skip(2).copied())
+                .collect::<Vec<_>>()
+                .join("/");
+            match operation {
+                DbOperation::List if parts.len() == 1 || parts[1].is_empty() => {
+                    DynamoTableDetails {
+                        pk_value: parts.get(1).unwrap_or(&"").to_string(),
+                        rk_value: format!("{}/", parts[0]),
+                        name: CONFIG.dynamo.org_meta_table.clone(),
+                        pk: "org".to_string(),
+                        rk: "key".to_string(),
+                        operation: "scan".to_string(),
+                        entity: entity.to_string(),
+                    }
+                }
+                _ => DynamoTableDetails {
+                    pk_value: parts[1].to_string(),
+                    rk_value,
+                    name: CONFIG.dynamo.org_meta_table.clone(),
+                    pk: "org".to_string(),
+                    rk: "key".to_string(),
+                    operation: "query".to_string(),
+                    entity: entity.to_string(),
+                },
+            }
+        }
+
         "alerts" => match operation {
             DbOperation::Get | DbOperation::Put | DbOperation::Delete => DynamoTableDetails {
                 pk_value: parts[1].to_string(),
@@ -805,3 +845,51 @@ async fn create_table_inner(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_dynamo_key_nested() {
+        let table = get_dynamo_key("/trigger_history/org1/alert1/123", DbOperation::Put);
+        assert_eq!(table.pk_value, "org1");
+        assert_eq!(table.rk_value, "trigger_history/alert1/123");
+        let table = get_dynamo_key("/trigger_history/org1/alert1/", DbOperation::List);
+        assert_eq!(table.operation, "query");
+        assert_eq!(table.rk_value, "trigger_history/alert1/");
+        let table = get_dynamo_key("/trigger_history/", DbOperation::List);
+        assert_eq!(table.operation, "scan");
+        assert_eq!(table.rk_value, "trigger_history/");
+
+        let table = get_dynamo_key("/silences/org1/id1", DbOperation::Get);
+        assert_eq!(table.rk_value, "silences/id1");
+        let table = get_dynamo_key("/maintenance_windows/org1/nightly", DbOperation::Get);
+        assert_eq!(table.rk_value, "maintenance_windows/nightly");
+
+        // the triggers don't list the history
+        let table = get_dynamo_key("/trigger/", DbOperation::List);
+        assert_eq!(table.rk_value, "trigger/");
+
+        let table = get_dynamo_key("/recording_rules/org1/rule1", DbOperation::Put);
+        assert_eq!(table.pk_value, "org1");
+        assert_eq!(table.rk_value, "recording_rules/rule1");
+        let table = get_dynamo_key("/recording_rules/", DbOperation::List);
+        assert_eq!(table.rk_value, "recording_rules/");
+        let table = get_dynamo_key("/recording_rules_leader/org1/rule1", DbOperation::Put);
+        assert_eq!(table.rk_value, "recording_rules_leader/rule1");
+
+        let table = get_dynamo_key("/kafka_sources/org1/src1", DbOperation::Get);
+        assert_eq!(table.pk_value, "org1");
+        assert_eq!(table.rk_value, "kafka_sources/src1");
+        let table = get_dynamo_key("/kafka_sources/", DbOperation::List);
+        assert_eq!(table.rk_value, "kafka_sources/");
+        let table = get_dynamo_key("/kafka_sources_leader/org1/src1", DbOperation::Put);
+        assert_eq!(table.rk_value, "kafka_sources_leader/src1");
+        let table = get_dynamo_key("/kafka_offsets/org1/src1/logs/0", DbOperation::Put);
+        assert_eq!(table.pk_value, "org1");
+        assert_eq!(table.rk_value, "kafka_offsets/src1/logs/0");
+        let table = get_dynamo_key("/kafka_offsets/org1/src1/logs/", DbOperation::List);
+        assert_eq!(table.rk_value, "kafka_offsets/src1/logs/");
+    }
+}
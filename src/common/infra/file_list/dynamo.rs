@@ -82,7 +82,7 @@ impl super::FileList for DynamoFileList {
         let org_id = stream_key[..stream_key.find('/').unwrap()].to_string();
         let file_name = format!("{date_key}/{file_name}");
         let client = DYNAMO_DB_CLIENT.get().await.clone();
-        client
+        let mut req = client
             .put_item()
             .table_name(&self.file_list_table)
             .item("org", AttributeValue::S(org_id))
@@ -103,8 +103,11 @@ impl super::FileList for DynamoFileList {
             .item(
                 "created_at",
                 AttributeValue::N(Utc::now().timestamp_micros().to_string()),
-            )
-            .send()
+            );
+        if let Some(summary) = meta.field_summary_json() {
+            req = req.item("field_summary", AttributeValue::S(summary));
+        }
+        req.send()
             .await
             .map_err(|e| Error::Message(e.to_string()))?;
         Ok(())
@@ -273,17 +273,21 @@ pub struct FileRecord {
     pub records: i64,
     pub original_size: i64,
     pub compressed_size: i64,
+    pub field_summary: Option<String>,
 }
 
 impl From<&FileRecord> for FileMeta {
     fn from(record: &FileRecord) -> Self {
-        Self {
+        let mut meta = Self {
             min_ts: record.min_ts,
             max_ts: record.max_ts,
             records: record.records,
             original_size: record.original_size,
             compressed_size: record.compressed_size,
-        }
+            field_summary: Default::default(),
+        };
+        meta.set_field_summary_json(record.field_summary.as_deref());
+        meta
     }
 }
 
@@ -67,8 +67,8 @@ impl super::FileList for PostgresFileList {
         let org_id = stream_key[..stream_key.find('/').unwrap()].to_string();
         match  sqlx::query(
             r#"
-INSERT INTO file_list (org, stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size)
-    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+INSERT INTO file_list (org, stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size, field_summary)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
     ON CONFLICT DO NOTHING;
             "#,
         )
@@ -82,6 +82,7 @@ INSERT INTO file_list (org, stream, date, file, deleted, min_ts, max_ts, records
         .bind(meta.records)
         .bind(meta.original_size)
         .bind(meta.compressed_size)
+        .bind(meta.field_summary_json())
         .execute(&pool)
         .await {
             Err(sqlx::Error::Database(e)) => if e.is_unique_violation() {
@@ -114,7 +115,7 @@ INSERT INTO file_list (org, stream, date, file, deleted, min_ts, max_ts, records
         let chunks = files.chunks(100);
         for files in chunks {
             let mut tx = pool.begin().await?;
-            let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new("INSERT INTO file_list (org, stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size)");
+            let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new("INSERT INTO file_list (org, stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size, field_summary)");
             query_builder.push_values(files, |mut b, item| {
                 let (stream_key, date_key, file_name) =
                     super::parse_file_key_columns(&item.key).expect("parse file key failed");
@@ -128,7 +129,8 @@ INSERT INTO file_list (org, stream, date, file, deleted, min_ts, max_ts, records
                     .push_bind(item.meta.max_ts)
                     .push_bind(item.meta.records)
                     .push_bind(item.meta.original_size)
-                    .push_bind(item.meta.compressed_size);
+                    .push_bind(item.meta.compressed_size)
+                    .push_bind(item.meta.field_summary_json());
             });
             let need_single_insert = match query_builder.build().execute(&mut *tx).await {
                 Ok(_) => false,
@@ -279,7 +281,7 @@ INSERT INTO file_list (org, stream, date, file, deleted, min_ts, max_ts, records
         let (stream_key, date_key, file_name) = super::parse_file_key_columns(file)?;
         let ret = sqlx::query_as::<_, super::FileRecord>(
             r#"
-SELECT stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size
+SELECT stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size, field_summary
     FROM file_list WHERE stream = $1 AND date = $2 AND file = $3;
             "#,
         )
@@ -296,7 +298,7 @@ SELECT stream, date, file, deleted, min_ts, max_ts, records, original_size, comp
         let (stream_key, date_key, file_name) = super::parse_file_key_columns(file)?;
         let ret = sqlx::query(
             r#"
-SELECT stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size
+SELECT stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size, field_summary
     FROM file_list WHERE stream = $1 AND date = $2 AND file = $3;
             "#,
         )
@@ -313,7 +315,7 @@ SELECT stream, date, file, deleted, min_ts, max_ts, records, original_size, comp
 
     async fn list(&self) -> Result<Vec<(String, FileMeta)>> {
         let pool = CLIENT.clone();
-        let ret = sqlx::query_as::<_, super::FileRecord>(r#"SELECT stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size FROM file_list;"#)
+        let ret = sqlx::query_as::<_, super::FileRecord>(r#"SELECT stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size, field_summary FROM file_list;"#)
         .fetch_all(&pool)
         .await?;
         Ok(ret
@@ -345,7 +347,7 @@ SELECT stream, date, file, deleted, min_ts, max_ts, records, original_size, comp
         let pool = CLIENT.clone();
         let ret = sqlx::query_as::<_, super::FileRecord>(
             r#"
-SELECT stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size
+SELECT stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size, field_summary
     FROM file_list 
     WHERE stream = $1 AND min_ts <= $2 AND max_ts >= $3;
             "#,
@@ -609,13 +611,19 @@ CREATE TABLE IF NOT EXISTS file_list
     max_ts   BIGINT not null,
     records  BIGINT not null,
     original_size   BIGINT not null,
-    compressed_size BIGINT not null
+    compressed_size BIGINT not null,
+    field_summary   TEXT
 );
         "#,
     )
     .execute(&pool)
     .await?;
 
+    // add the field_summary column to the table of older versions
+    sqlx::query(r#"ALTER TABLE file_list ADD COLUMN IF NOT EXISTS field_summary TEXT;"#)
+        .execute(&pool)
+        .await?;
+
     sqlx::query(
         r#"
 CREATE TABLE IF NOT EXISTS file_list_deleted
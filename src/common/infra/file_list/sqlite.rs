@@ -164,7 +164,7 @@ impl super::FileList for SqliteFileList {
         let (stream_key, date_key, file_name) = super::parse_file_key_columns(file)?;
         let ret = sqlx::query_as::<_, super::FileRecord>(
             r#"
-SELECT stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size
+SELECT stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size, field_summary
     FROM file_list WHERE stream = $1 AND date = $2 AND file = $3;
             "#,
         )
@@ -181,7 +181,7 @@ SELECT stream, date, file, deleted, min_ts, max_ts, records, original_size, comp
         let (stream_key, date_key, file_name) = super::parse_file_key_columns(file)?;
         let ret = sqlx::query(
             r#"
-SELECT stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size
+SELECT stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size, field_summary
     FROM file_list WHERE stream = $1 AND date = $2 AND file = $3;
             "#,
         )
@@ -198,7 +198,7 @@ SELECT stream, date, file, deleted, min_ts, max_ts, records, original_size, comp
 
     async fn list(&self) -> Result<Vec<(String, FileMeta)>> {
         let pool = CLIENT.clone();
-        let ret = sqlx::query_as::<_, super::FileRecord>(r#"SELECT stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size FROM file_list;"#)
+        let ret = sqlx::query_as::<_, super::FileRecord>(r#"SELECT stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size, field_summary FROM file_list;"#)
         .fetch_all(&pool)
         .await?;
         Ok(ret
@@ -230,7 +230,7 @@ SELECT stream, date, file, deleted, min_ts, max_ts, records, original_size, comp
         let pool = CLIENT.clone();
         let ret = sqlx::query_as::<_, super::FileRecord>(
             r#"
-SELECT stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size
+SELECT stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size, field_summary
     FROM file_list 
     WHERE stream = $1 AND min_ts <= $2 AND max_ts >= $3;
             "#,
@@ -418,8 +418,8 @@ pub async fn add(client: &Pool<Sqlite>, file: &str, meta: &FileMeta) -> Result<(
     let org_id = stream_key[..stream_key.find('/').unwrap()].to_string();
     match  sqlx::query(
             r#"
-INSERT INTO file_list (org, stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size)
-    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10);
+INSERT INTO file_list (org, stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size, field_summary)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11);
         "#,
     )
         .bind(org_id)
@@ -432,6 +432,7 @@ INSERT INTO file_list (org, stream, date, file, deleted, min_ts, max_ts, records
         .bind(meta.records)
         .bind(meta.original_size)
         .bind(meta.compressed_size)
+        .bind(meta.field_summary_json())
         .execute(client)
         .await {
             Err(sqlx::Error::Database(e)) => if e.is_unique_violation() {
@@ -448,7 +449,7 @@ pub async fn batch_add(client: &Pool<Sqlite>, files: &[FileKey]) -> Result<()> {
     let chunks = files.chunks(100);
     for files in chunks {
         let mut tx = client.begin().await?;
-        let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new("INSERT INTO file_list (org, stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size)");
+        let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new("INSERT INTO file_list (org, stream, date, file, deleted, min_ts, max_ts, records, original_size, compressed_size, field_summary)");
         query_builder.push_values(files, |mut b, item| {
             let (stream_key, date_key, file_name) =
                 super::parse_file_key_columns(&item.key).expect("parse file key failed");
@@ -462,7 +463,8 @@ pub async fn batch_add(client: &Pool<Sqlite>, files: &[FileKey]) -> Result<()> {
                 .push_bind(item.meta.max_ts)
                 .push_bind(item.meta.records)
                 .push_bind(item.meta.original_size)
-                .push_bind(item.meta.compressed_size);
+                .push_bind(item.meta.compressed_size)
+                .push_bind(item.meta.field_summary_json());
         });
         let need_single_insert = match query_builder.build().execute(&mut *tx).await {
             Ok(_) => false,
@@ -736,13 +738,24 @@ CREATE TABLE IF NOT EXISTS file_list
     max_ts   BIGINT not null,
     records  BIGINT not null,
     original_size   BIGINT not null,
-    compressed_size BIGINT not null
+    compressed_size BIGINT not null,
+    field_summary   TEXT
 );
         "#,
     )
     .execute(client)
     .await?;
 
+    // add the field_summary column to the table of older versions
+    if let Err(e) = sqlx::query(r#"ALTER TABLE file_list ADD COLUMN field_summary TEXT;"#)
+        .execute(client)
+        .await
+    {
+        if !e.to_string().contains("duplicate column name") {
+            return Err(e.into());
+        }
+    }
+
     sqlx::query(
         r#"
 CREATE TABLE IF NOT EXISTS file_list_deleted
@@ -32,6 +32,15 @@ pub fn generate() -> String {
     format!("{}{}", id, generate_random_string(6))
 }
 
+/// Returns the time an id was generated at, in microseconds. The ids start with a
+/// 19 digits snowflake id, the milliseconds since the Unix epoch above 22 bits of node and
+/// sequence, followed by random characters which may be digits too.
+pub fn timestamp_micros(id: &str) -> Option<i64> {
+    let end = id.find(|c: char| !c.is_ascii_digit()).unwrap_or(id.len());
+    let id = id[..end.min(19)].parse::<i64>().ok()?;
+    Some((id >> 22) * 1000)
+}
+
 fn generate_random_string(len: usize) -> String {
     let mut rng = thread_rng();
     iter::repeat(())
@@ -51,6 +60,22 @@ mod tests {
         assert_ne!(id, "");
     }
 
+    #[test]
+    fn test_timestamp_micros() {
+        let before = chrono::Utc::now().timestamp_millis() * 1000;
+        let ts = timestamp_micros(&generate()).unwrap();
+        assert!(ts >= before && ts <= chrono::Utc::now().timestamp_micros());
+        assert_eq!(
+            timestamp_micros("7024989428549181440"),
+            Some(1674887997758000)
+        );
+        assert_eq!(
+            timestamp_micros("7024989428549181440123abc"),
+            Some(1674887997758000)
+        );
+        assert!(timestamp_micros("abc").is_none());
+    }
+
     #[test]
     fn test_generate_random_string() {
         let random_string = generate_random_string(10);
@@ -123,6 +123,18 @@ pub static INGEST_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
     )
     .expect("Metric created")
 });
+pub static INGEST_SAMPLED_SPANS: Lazy<IntCounterVec> = Lazy::new(|| {
+    IntCounterVec::new(
+        Opts::new(
+            "ingest_sampled_spans",
+            "Trace spans by sampling decision. ".to_owned() + HELP_SUFFIX,
+        )
+        .namespace(NAMESPACE)
+        .const_labels(create_const_labels()),
+        &["organization", "stream", "decision"],
+    )
+    .expect("Metric created")
+});
 pub static INGEST_WAL_USED_BYTES: Lazy<IntGaugeVec> = Lazy::new(|| {
     IntGaugeVec::new(
         Opts::new(
@@ -516,6 +528,9 @@ fn register_metrics(registry: &Registry) {
     registry
         .register(Box::new(INGEST_BYTES.clone()))
         .expect("Metric registered");
+    registry
+        .register(Box::new(INGEST_SAMPLED_SPANS.clone()))
+        .expect("Metric registered");
     registry
         .register(Box::new(INGEST_WAL_USED_BYTES.clone()))
         .expect("Metric registered");
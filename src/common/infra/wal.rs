@@ -116,12 +116,18 @@ pub async fn get_search_in_memory_files(
     Ok(files)
 }
 
-pub async fn flush_all_to_disk() {
+/// Flushes the open WAL files to the disk, the rotated ones are flushed when closed.
+pub async fn sync_all() {
     for data in MANAGER.data.iter() {
-        for (_, file) in data.read().await.iter() {
+        let files = data.read().await.values().cloned().collect::<Vec<_>>();
+        for file in files {
             file.sync().await;
         }
     }
+}
+
+pub async fn flush_all_to_disk() {
+    sync_all().await;
 
     for (file, data) in MEMORY_FILES.list("").await.iter() {
         let file_path = format!("{}{}", CONFIG.common.data_wal_dir, file);
@@ -13,7 +13,9 @@
 // limitations under the License.
 
 use ahash::HashMap;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use sqlparser::{ast::Expr as SqlExpr, dialect::GenericDialect, parser::Parser, tokenizer::Token};
 use std::fmt;
 use utoipa::ToSchema;
 
@@ -28,9 +30,27 @@ pub struct Alert {
     pub stream: String,
     #[schema(value_type = Option<SearchQuery>)]
     pub query: Option<Query>,
+    #[serde(default)]
     pub condition: Condition,
+    /// Boolean tree of conditions, takes precedence over `condition` when set
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub conditions: Option<ConditionList>,
+    /// Aggregate threshold, evaluated once per group on the scheduled path
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aggregation: Option<Aggregation>,
+    /// PromQL expression for metrics streams, every resulting series is an alert instance
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub promql: Option<String>,
     pub duration: i64,
     pub frequency: i64,
+    /// how long, in minutes, the condition has to keep matching before the alert fires
+    #[serde(default)]
+    #[serde(rename = "for")]
+    pub for_duration: i64,
     pub time_between_alerts: i64,
     pub destination: String,
     #[serde(default)]
@@ -45,24 +65,41 @@ pub struct Alert {
 #[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
 pub struct AlertDestination {
     pub name: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "type")]
+    pub destination_type: AlertDestType,
+    /// webhook url, alertmanager base url or pagerduty events url
+    #[serde(default)]
     pub url: String,
+    #[serde(default)]
     pub method: AlertHTTPType,
     #[serde(default)]
     pub skip_tls_verify: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub headers: Option<HashMap<String, String>>,
     pub template: String,
+    /// recipients of an email destination
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub emails: Vec<String>,
+    /// integration key of a pagerduty destination
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub routing_key: Option<String>,
 }
 
 impl AlertDestination {
     pub fn to_dest_resp(&self, template: Option<DestinationTemplate>) -> AlertDestinationResponse {
         AlertDestinationResponse {
+            destination_type: self.destination_type.clone(),
             url: self.url.clone(),
             method: self.method.clone(),
             skip_tls_verify: self.skip_tls_verify,
             headers: self.headers.clone(),
             template,
             name: self.name.clone().unwrap(),
+            emails: self.emails.clone(),
+            routing_key: self.routing_key.clone(),
         }
     }
 }
@@ -70,6 +107,9 @@ impl AlertDestination {
 #[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
 pub struct AlertDestinationResponse {
     pub name: String,
+    #[serde(default)]
+    #[serde(rename = "type")]
+    pub destination_type: AlertDestType,
     pub url: String,
     pub method: AlertHTTPType,
     #[serde(default)]
@@ -77,6 +117,12 @@ pub struct AlertDestinationResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub headers: Option<HashMap<String, String>>,
     pub template: Option<DestinationTemplate>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub emails: Vec<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub routing_key: Option<String>,
 }
 
 #[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize, ToSchema)]
@@ -113,8 +159,14 @@ pub struct DestinationTemplate {
 #[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize, ToSchema)]
 pub enum AlertDestType {
     #[default]
+    #[serde(rename = "http")]
+    Http,
+    #[serde(rename = "email")]
+    Email,
     #[serde(rename = "slack")]
     Slack,
+    #[serde(rename = "pagerduty")]
+    PagerDuty,
     #[serde(rename = "alertmanager")]
     AlertManager,
 }
@@ -122,12 +174,52 @@ pub enum AlertDestType {
 impl fmt::Display for AlertDestType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            AlertDestType::Http => write!(f, "http"),
+            AlertDestType::Email => write!(f, "email"),
             AlertDestType::Slack => write!(f, "slack"),
+            AlertDestType::PagerDuty => write!(f, "pagerduty"),
             AlertDestType::AlertManager => write!(f, "alertmanager"),
         }
     }
 }
 
+impl Alert {
+    /// Returns true if the alert is evaluated over aggregated groups instead of single rows.
+    pub fn is_aggregate(&self) -> bool {
+        self.aggregation.is_some()
+    }
+
+    pub fn is_promql(&self) -> bool {
+        self.promql.is_some()
+    }
+}
+
+impl Evaluate for Alert {
+    fn evaluate(&self, row: Map<String, Value>) -> bool {
+        let matched = match &self.conditions {
+            Some(conditions) => conditions.evaluate(row.clone()),
+            // aggregate alerts may only define a threshold, promql alerts match every series
+            None if self.condition.column.is_empty()
+                && (self.is_aggregate() || self.is_promql()) =>
+            {
+                true
+            }
+            None => self.condition.evaluate(row.clone()),
+        };
+        match &self.aggregation {
+            Some(aggregation) => {
+                // aggregated value can be null, e.g. avg over no rows
+                matched
+                    && row
+                        .get(ALERT_AGG_VALUE_COLUMN)
+                        .map_or(false, |v| v.is_number())
+                    && aggregation.threshold().evaluate(row)
+            }
+            None => matched,
+        }
+    }
+}
+
 impl PartialEq for Alert {
     fn eq(&self, other: &Self) -> bool {
         self.name == other.name && self.stream == other.stream
@@ -159,6 +251,10 @@ pub struct Trigger {
     pub is_ingest_time: bool,
     #[serde(default)]
     pub stream_type: StreamType,
+    /// state of every alert instance of a scheduled alert, keyed by group key
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub instances: HashMap<String, TriggerState>,
 }
 
 impl Default for Trigger {
@@ -173,10 +269,269 @@ impl Default for Trigger {
             count: 0,
             stream_type: StreamType::Logs,
             is_ingest_time: false,
+            instances: HashMap::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    #[default]
+    Resolved,
+    Pending,
+    Firing,
+}
+
+impl fmt::Display for AlertState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AlertState::Resolved => write!(f, "resolved"),
+            AlertState::Pending => write!(f, "pending"),
+            AlertState::Firing => write!(f, "firing"),
         }
     }
 }
 
+/// State of one alert instance, i.e. the alert itself or one group of an aggregate alert.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TriggerState {
+    #[serde(default)]
+    pub state: AlertState,
+    /// since when the condition is matching
+    #[serde(default)]
+    pub active_at: i64,
+    #[serde(default)]
+    pub last_sent_at: i64,
+    /// group by values identifying the instance
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, String>,
+}
+
+impl TriggerState {
+    /// Moves the instance through pending -> firing -> resolved and returns the state a
+    /// notification has to be sent for, if any. All arguments are in microseconds.
+    pub fn transition(
+        &mut self,
+        is_active: bool,
+        now: i64,
+        for_duration: i64,
+        time_between_alerts: i64,
+    ) -> Option<AlertState> {
+        match (self.state, is_active) {
+            (AlertState::Resolved, true) => {
+                self.active_at = now;
+                self.state = AlertState::Pending;
+                self.fire_if_due(now, for_duration)
+            }
+            (AlertState::Pending, true) => self.fire_if_due(now, for_duration),
+            (AlertState::Firing, true) => {
+                if now - self.last_sent_at > time_between_alerts {
+                    self.last_sent_at = now;
+                    Some(AlertState::Firing)
+                } else {
+                    None
+                }
+            }
+            (AlertState::Pending, false) => {
+                // never fired, nothing to resolve
+                self.state = AlertState::Resolved;
+                self.active_at = 0;
+                None
+            }
+            (AlertState::Firing, false) => {
+                self.state = AlertState::Resolved;
+                self.active_at = 0;
+                self.last_sent_at = now;
+                Some(AlertState::Resolved)
+            }
+            (AlertState::Resolved, false) => None,
+        }
+    }
+
+    fn fire_if_due(&mut self, now: i64, for_duration: i64) -> Option<AlertState> {
+        if now - self.active_at < for_duration {
+            return None;
+        }
+        self.state = AlertState::Firing;
+        self.last_sent_at = now;
+        Some(AlertState::Firing)
+    }
+}
+
+/// A state change of an alert instance, kept in the meta store.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct TriggerHistory {
+    pub timestamp: i64,
+    pub org: String,
+    pub stream: String,
+    pub stream_type: StreamType,
+    pub alert_name: String,
+    #[serde(default)]
+    pub group_key: String,
+    pub from: AlertState,
+    pub to: AlertState,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct TriggerHistoryList {
+    pub list: Vec<TriggerHistory>,
+}
+
+impl Trigger {
+    /// Labels of an alert instance that silences and maintenance windows are matched against.
+    pub fn labels(&self, group_labels: &HashMap<String, String>) -> HashMap<String, String> {
+        let mut labels = group_labels.clone();
+        labels.insert("alertname".to_string(), self.alert_name.clone());
+        labels.insert("organization".to_string(), self.org.clone());
+        labels.insert("stream".to_string(), self.stream.clone());
+        labels.insert("stream_type".to_string(), self.stream_type.to_string());
+        labels
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Matcher {
+    pub name: String,
+    pub value: String,
+    #[serde(default)]
+    pub is_regex: bool,
+    /// the anchored regex of the value, set by `compile`
+    #[serde(skip)]
+    regex: Option<Regex>,
+}
+
+impl PartialEq for Matcher {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.value == other.value && self.is_regex == other.is_regex
+    }
+}
+
+impl Eq for Matcher {}
+
+impl Matcher {
+    /// Compiles the regex of the matcher, which has to be done before matching.
+    pub fn compile(&mut self) -> Result<(), regex::Error> {
+        if self.is_regex && self.regex.is_none() {
+            self.regex = Some(Regex::new(&format!("^(?:{})$", self.value))?);
+        }
+        Ok(())
+    }
+
+    pub fn matches(&self, labels: &HashMap<String, String>) -> bool {
+        let value = labels
+            .get(&self.name)
+            .map(|v| v.as_str())
+            .unwrap_or_default();
+        if self.is_regex {
+            match &self.regex {
+                Some(re) => re.is_match(value),
+                None => false,
+            }
+        } else {
+            value == self.value
+        }
+    }
+}
+
+/// Compiles the regexes of the matchers.
+pub fn compile_matchers(matchers: &mut [Matcher]) -> Result<(), String> {
+    for matcher in matchers.iter_mut() {
+        if matcher.compile().is_err() {
+            return Err(format!("Invalid matcher regex: {}", matcher.value));
+        }
+    }
+    Ok(())
+}
+
+/// Mutes the notifications of every alert instance matching all matchers between
+/// `starts_at` and `ends_at`, in microseconds.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Silence {
+    #[serde(default)]
+    pub id: String,
+    pub matchers: Vec<Matcher>,
+    pub starts_at: i64,
+    pub ends_at: i64,
+    #[serde(default)]
+    pub comment: String,
+}
+
+impl Silence {
+    pub fn is_active(&self, now: i64) -> bool {
+        self.starts_at <= now && now < self.ends_at
+    }
+
+    pub fn matches(&self, labels: &HashMap<String, String>) -> bool {
+        !self.matchers.is_empty() && self.matchers.iter().all(|m| m.matches(labels))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct SilenceList {
+    pub list: Vec<Silence>,
+}
+
+/// A silence repeating every day, or on the given weekdays, at `start_time` (UTC, `HH:MM`)
+/// for `duration` minutes.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct MaintenanceWindow {
+    #[serde(default)]
+    pub name: String,
+    pub matchers: Vec<Matcher>,
+    /// 0 is Monday and 6 is Sunday, empty means every day
+    #[serde(default)]
+    pub weekdays: Vec<u32>,
+    pub start_time: String,
+    pub duration: i64,
+}
+
+impl MaintenanceWindow {
+    /// Minutes after midnight the window starts at.
+    pub fn start_minute(&self) -> Option<i64> {
+        let (hour, minute) = self.start_time.split_once(':')?;
+        let hour: i64 = hour.trim().parse().ok()?;
+        let minute: i64 = minute.trim().parse().ok()?;
+        if !(0..24).contains(&hour) || !(0..60).contains(&minute) {
+            return None;
+        }
+        Some(hour * 60 + minute)
+    }
+
+    pub fn is_active(&self, now: i64) -> bool {
+        let Some(start_minute) = self.start_minute() else {
+            return false;
+        };
+        let minute_micros = 60 * 1_000_000;
+        let day_micros = 24 * 60 * minute_micros;
+        let today = now - now.rem_euclid(day_micros);
+        // the window can have started on one of the previous days
+        for days_back in 0..=(self.duration / (24 * 60) + 1) {
+            let start = today - days_back * day_micros + start_minute * minute_micros;
+            let end = start + self.duration * minute_micros;
+            if now < start || now >= end {
+                continue;
+            }
+            // 1970-01-01 was a Thursday
+            let weekday = ((start.div_euclid(day_micros) + 3) % 7) as u32;
+            if self.weekdays.is_empty() || self.weekdays.contains(&weekday) {
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn matches(&self, labels: &HashMap<String, String>) -> bool {
+        !self.matchers.is_empty() && self.matchers.iter().all(|m| m.matches(labels))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct MaintenanceWindowList {
+    pub list: Vec<MaintenanceWindow>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TriggerTimer {
     #[serde(default)]
@@ -185,7 +540,7 @@ pub struct TriggerTimer {
     pub expires_at: i64,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Condition {
     pub column: String,
@@ -269,6 +624,172 @@ impl Evaluate for Condition {
     }
 }
 
+/// A boolean tree of conditions, serialized as e.g.
+/// `{"and": [{"condition": {...}}, {"not": {"condition": {...}}}]}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConditionList {
+    And(Vec<ConditionList>),
+    Or(Vec<ConditionList>),
+    Not(Box<ConditionList>),
+    Condition(Condition),
+}
+
+impl ConditionList {
+    /// Calls `f` for every leaf condition of the tree.
+    pub fn for_each_condition_mut<F: FnMut(&mut Condition)>(&mut self, f: &mut F) {
+        match self {
+            ConditionList::And(items) | ConditionList::Or(items) => {
+                for item in items.iter_mut() {
+                    item.for_each_condition_mut(f);
+                }
+            }
+            ConditionList::Not(item) => item.for_each_condition_mut(f),
+            ConditionList::Condition(cond) => f(cond),
+        }
+    }
+}
+
+impl Evaluate for ConditionList {
+    fn evaluate(&self, row: Map<String, Value>) -> bool {
+        match self {
+            ConditionList::And(items) => items.iter().all(|item| item.evaluate(row.clone())),
+            ConditionList::Or(items) => items.iter().any(|item| item.evaluate(row.clone())),
+            ConditionList::Not(item) => !item.evaluate(row),
+            ConditionList::Condition(cond) => cond.evaluate(row),
+        }
+    }
+}
+
+/// Column name of the aggregated value in the result of an aggregate alert query
+pub const ALERT_AGG_VALUE_COLUMN: &str = "zo_alert_value";
+
+/// Maximum number of groups an aggregate alert evaluates
+pub const ALERT_AGG_MAX_GROUPS: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Aggregation {
+    #[serde(default)]
+    pub group_by: Vec<String>,
+    pub function: AggFunction,
+    /// column to aggregate, ignored by `count`
+    #[serde(default)]
+    pub column: String,
+    /// optional sql predicate applied before aggregating
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    pub operator: AllOperator,
+    #[schema(value_type = Object)]
+    pub value: Value,
+}
+
+impl Aggregation {
+    /// Builds the sql that computes the aggregated value for every group, fails if the
+    /// filter isn't a single sql expression.
+    pub fn to_sql(&self, stream_name: &str) -> Result<String, anyhow::Error> {
+        let agg_expr = match self.function {
+            AggFunction::Count => "count(*)".to_string(),
+            AggFunction::Sum => format!("sum(\"{}\")", self.column),
+            AggFunction::Avg => format!("avg(\"{}\")", self.column),
+            AggFunction::Min => format!("min(\"{}\")", self.column),
+            AggFunction::Max => format!("max(\"{}\")", self.column),
+            AggFunction::P50 => format!("approx_percentile_cont(\"{}\", 0.5)", self.column),
+            AggFunction::P75 => format!("approx_percentile_cont(\"{}\", 0.75)", self.column),
+            AggFunction::P90 => format!("approx_percentile_cont(\"{}\", 0.9)", self.column),
+            AggFunction::P95 => format!("approx_percentile_cont(\"{}\", 0.95)", self.column),
+            AggFunction::P99 => format!("approx_percentile_cont(\"{}\", 0.99)", self.column),
+        };
+        let group_by = self
+            .group_by
+            .iter()
+            .map(|field| format!("\"{field}\""))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut sql = if group_by.is_empty() {
+            format!("SELECT {agg_expr} AS {ALERT_AGG_VALUE_COLUMN} FROM {stream_name}")
+        } else {
+            format!("SELECT {group_by}, {agg_expr} AS {ALERT_AGG_VALUE_COLUMN} FROM {stream_name}")
+        };
+        if let Some(filter) = self.filter.as_deref().filter(|v| !v.trim().is_empty()) {
+            sql.push_str(&format!(" WHERE {}", parse_filter(filter)?));
+        }
+        if !group_by.is_empty() {
+            sql.push_str(&format!(" GROUP BY {group_by}"));
+        }
+        sql.push_str(&format!(" LIMIT {ALERT_AGG_MAX_GROUPS}"));
+        Ok(sql)
+    }
+
+    /// The condition the aggregated value of a group is compared with.
+    pub fn threshold(&self) -> Condition {
+        Condition {
+            column: ALERT_AGG_VALUE_COLUMN.to_string(),
+            operator: self.operator.clone(),
+            ignore_case: None,
+            value: self.value.clone(),
+            is_numeric: Some(true),
+        }
+    }
+
+    /// The group by values of a result row.
+    pub fn group_labels(&self, row: &Map<String, Value>) -> HashMap<String, String> {
+        self.group_by
+            .iter()
+            .map(|field| {
+                let value = match row.get(field) {
+                    Some(Value::String(v)) => v.clone(),
+                    Some(v) => v.to_string(),
+                    None => String::new(),
+                };
+                (field.clone(), value)
+            })
+            .collect()
+    }
+
+    /// Identifies the group a result row belongs to, e.g. `service=api,host=a`.
+    pub fn group_key(&self, row: &Map<String, Value>) -> String {
+        self.group_by
+            .iter()
+            .map(|field| match row.get(field) {
+                Some(Value::String(v)) => format!("{field}={v}"),
+                Some(v) => format!("{field}={v}"),
+                None => format!("{field}="),
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Parses the filter of an aggregation, which has to be a single sql expression.
+fn parse_filter(filter: &str) -> Result<SqlExpr, anyhow::Error> {
+    let dialect = GenericDialect {};
+    let mut parser = Parser::new(&dialect).try_with_sql(filter)?;
+    let expr = parser.parse_expr()?;
+    let next = parser.peek_token();
+    if next.token != Token::EOF {
+        return Err(anyhow::anyhow!(
+            "unexpected {next} after the filter expression"
+        ));
+    }
+    Ok(expr)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AggFunction {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+    P50,
+    P75,
+    P90,
+    P95,
+    P99,
+}
+
 fn get_numeric_val(value: &Value) -> f64 {
     if value.is_boolean() {
         f64::INFINITY
@@ -333,4 +854,139 @@ mod tests {
         let row = json!({"Country":"USA","occurrence": 10});
         condition.evaluate(row.as_object().unwrap().clone());
     }
+
+    #[test]
+    fn test_evaluate_condition_list() {
+        let conditions: ConditionList = crate::common::utils::json::from_str(
+            r#"{"and": [
+                {"condition": {"column": "Country", "operator": "=", "value": "USA", "isNumeric": false}},
+                {"not": {"condition": {"column": "occurrence", "operator": "<", "value": 5, "isNumeric": true}}}
+            ]}"#,
+        )
+        .unwrap();
+        let row = json!({"Country":"USA","occurrence": 10});
+        assert!(conditions.evaluate(row.as_object().unwrap().clone()));
+        let row = json!({"Country":"USA","occurrence": 1});
+        assert!(!conditions.evaluate(row.as_object().unwrap().clone()));
+    }
+
+    #[test]
+    fn test_aggregation() {
+        let aggregation = Aggregation {
+            group_by: vec!["service".to_string()],
+            function: AggFunction::P95,
+            column: "latency".to_string(),
+            filter: Some("level = 'error'".to_string()),
+            operator: AllOperator::GreaterThan,
+            value: json!(2),
+        };
+        assert_eq!(
+            aggregation.to_sql("default").unwrap(),
+            "SELECT \"service\", approx_percentile_cont(\"latency\", 0.95) AS zo_alert_value FROM default WHERE level = 'error' GROUP BY \"service\" LIMIT 1000"
+        );
+        for filter in [
+            "level = 'error' GROUP BY level",
+            "a = 1; DROP TABLE b",
+            "level =",
+        ] {
+            let aggregation = Aggregation {
+                filter: Some(filter.to_string()),
+                ..aggregation.clone()
+            };
+            assert!(aggregation.to_sql("default").is_err(), "{filter}");
+        }
+        let row = json!({"service":"api","zo_alert_value": 3.5});
+        let row = row.as_object().unwrap();
+        assert_eq!(aggregation.group_key(row), "service=api");
+        assert!(aggregation.threshold().evaluate(row.clone()));
+    }
+
+    #[test]
+    fn test_trigger_state_transition() {
+        let mut state = TriggerState::default();
+        // condition starts matching, has to hold for 10
+        assert_eq!(state.transition(true, 100, 10, 50), None);
+        assert_eq!(state.state, AlertState::Pending);
+        assert_eq!(state.transition(true, 105, 10, 50), None);
+        assert_eq!(
+            state.transition(true, 110, 10, 50),
+            Some(AlertState::Firing)
+        );
+        // throttled while firing
+        assert_eq!(state.transition(true, 120, 10, 50), None);
+        assert_eq!(
+            state.transition(true, 161, 10, 50),
+            Some(AlertState::Firing)
+        );
+        assert_eq!(
+            state.transition(false, 170, 10, 50),
+            Some(AlertState::Resolved)
+        );
+        assert_eq!(state.state, AlertState::Resolved);
+
+        // a pending instance clears without notifications
+        assert_eq!(state.transition(true, 200, 10, 50), None);
+        assert_eq!(state.transition(false, 205, 10, 50), None);
+        assert_eq!(state.state, AlertState::Resolved);
+
+        // without a for duration the alert fires right away
+        assert_eq!(state.transition(true, 300, 0, 50), Some(AlertState::Firing));
+    }
+
+    #[test]
+    fn test_silence() {
+        let mut silence = Silence {
+            id: "1".to_string(),
+            matchers: vec![
+                Matcher {
+                    name: "alertname".to_string(),
+                    value: "medals".to_string(),
+                    is_regex: false,
+                    regex: None,
+                },
+                Matcher {
+                    name: "service".to_string(),
+                    value: "api-.*".to_string(),
+                    is_regex: true,
+                    regex: None,
+                },
+            ],
+            starts_at: 100,
+            ends_at: 200,
+            comment: "deploy".to_string(),
+        };
+        let mut labels = HashMap::default();
+        labels.insert("alertname".to_string(), "medals".to_string());
+        labels.insert("service".to_string(), "api-gateway".to_string());
+        assert!(silence.is_active(150));
+        assert!(!silence.is_active(200));
+        // regex matchers match nothing until compiled
+        assert!(!silence.matches(&labels));
+        compile_matchers(&mut silence.matchers).unwrap();
+        assert!(silence.matches(&labels));
+        labels.insert("service".to_string(), "web".to_string());
+        assert!(!silence.matches(&labels));
+    }
+
+    #[test]
+    fn test_maintenance_window() {
+        let window = MaintenanceWindow {
+            name: "nightly".to_string(),
+            matchers: vec![],
+            // Saturday
+            weekdays: vec![5],
+            start_time: "23:00".to_string(),
+            duration: 120,
+        };
+        let minute = 60 * 1_000_000;
+        // 2023-09-02 is a Saturday
+        let saturday = 1_693_612_800_000_000;
+        assert!(!window.is_active(saturday + 22 * 60 * minute));
+        assert!(window.is_active(saturday + 23 * 60 * minute));
+        // still active after midnight on Sunday
+        assert!(window.is_active(saturday + 24 * 60 * minute + 30 * minute));
+        assert!(!window.is_active(saturday + 25 * 60 * minute));
+        // not on Sunday evening
+        assert!(!window.is_active(saturday + 47 * 60 * minute));
+    }
 }
@@ -15,9 +15,9 @@
 use aws_sdk_dynamodb::types::AttributeValue;
 use byteorder::{ByteOrder, LittleEndian};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-use crate::common::infra::file_list::parse_file_key_columns;
+use crate::common::{infra::file_list::parse_file_key_columns, utils::json};
 
 #[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
 pub struct FileKey {
@@ -77,6 +77,9 @@ impl From<&FileKey> for HashMap<String, AttributeValue> {
             "compressed_size".to_string(),
             AttributeValue::N(file_key.meta.compressed_size.to_string()),
         );
+        if let Some(summary) = file_key.meta.field_summary_json() {
+            item.insert("field_summary".to_string(), AttributeValue::S(summary));
+        }
         item.insert(
             "created_at".to_string(),
             AttributeValue::N(chrono::Utc::now().timestamp_micros().to_string()),
@@ -115,6 +118,10 @@ impl From<&HashMap<String, AttributeValue>> for FileKey {
                 "compressed_size" => {
                     item.meta.compressed_size = v.as_n().unwrap().parse::<i64>().unwrap();
                 }
+                "field_summary" => {
+                    item.meta
+                        .set_field_summary_json(v.as_s().ok().map(|v| v.as_str()));
+                }
                 _ => {}
             }
         }
@@ -129,6 +136,51 @@ pub struct FileMeta {
     pub records: i64,
     pub original_size: i64,
     pub compressed_size: i64,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub field_summary: BTreeMap<String, FieldSummary>, // string columns, by name
+}
+
+impl FileMeta {
+    /// The field summary as stored in the file_list backends, `None` if empty.
+    pub fn field_summary_json(&self) -> Option<String> {
+        if self.field_summary.is_empty() {
+            None
+        } else {
+            json::to_string(&self.field_summary).ok()
+        }
+    }
+
+    /// Sets the field summary from the file_list backends, an invalid one is dropped, the
+    /// file is then never skipped by its values.
+    pub fn set_field_summary_json(&mut self, data: Option<&str>) {
+        self.field_summary = data
+            .and_then(|v| json::from_str(v).ok())
+            .unwrap_or_default();
+    }
+}
+
+/// Values of a string column of a file, used to skip the files which can't match an
+/// equality filter. `values` are the sorted distinct values, empty when there are more
+/// than `ZO_FILE_LIST_FIELD_SUMMARY_MAX_VALUES`.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FieldSummary {
+    pub min: String,
+    pub max: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub values: Vec<String>,
+}
+
+impl FieldSummary {
+    pub fn may_contain(&self, value: &str) -> bool {
+        if value < self.min.as_str() || value > self.max.as_str() {
+            return false;
+        }
+        self.values.is_empty()
+            || self
+                .values
+                .binary_search_by(|v| v.as_str().cmp(value))
+                .is_ok()
+    }
 }
 
 impl From<&FileMeta> for Vec<u8> {
@@ -164,6 +216,7 @@ impl TryFrom<&[u8]> for FileMeta {
             records,
             original_size,
             compressed_size,
+            field_summary: BTreeMap::new(),
         })
     }
 }
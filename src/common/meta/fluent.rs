@@ -0,0 +1,46 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Routes the events of Fluent Forward senders to a stream by their tag.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FluentRoute {
+    #[serde(default)]
+    pub org_id: String,
+    /// Target stream, the tag of the events is used when empty
+    #[serde(default)]
+    pub stream_name: String,
+    /// Tag pattern as used by fluentd `<match>`, `*` matches one part of the tag and `**`
+    /// zero or more parts, e.g. `app.**`
+    #[serde(default)]
+    pub tag: String,
+    #[serde(default)]
+    pub id: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct FluentRoutes {
+    pub routes: Vec<FluentRoute>,
+}
+
+/// Key the Fluent Forward senders of an organization authenticate with, it also tells the
+/// server which organization they send to.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FluentSharedKey {
+    pub shared_key: String,
+}
@@ -282,6 +282,144 @@ pub struct GCPIngestionResponse {
     pub timestamp: String,
 }
 
+/// JSON body of the Loki push API, every value is `[<unix epoch in ns>, <line>]`
+/// optionally followed by an object of structured metadata.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LokiPushRequest {
+    pub streams: Vec<LokiStream>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LokiStream {
+    #[serde(default)]
+    pub stream: HashMap<String, String>,
+    pub values: Vec<Vec<json::Value>>,
+}
+
+/// An event sent to the Splunk HTTP Event Collector, cf.
+/// https://docs.splunk.com/Documentation/Splunk/latest/Data/FormateventsforHTTPEventCollector
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HecEvent {
+    /// Epoch time in seconds, may have decimals and be sent as string
+    #[serde(default)]
+    pub time: Option<json::Value>,
+    #[serde(default)]
+    pub host: Option<String>,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub sourcetype: Option<String>,
+    #[serde(default)]
+    pub index: Option<String>,
+    #[serde(default)]
+    pub event: Option<json::Value>,
+    #[serde(default)]
+    pub fields: Option<json::Map<String, json::Value>>,
+}
+
+/// Query parameters of the raw HEC endpoint, applied to every line of the body
+#[derive(Default, Debug, Clone, Deserialize)]
+pub struct HecRawQuery {
+    pub channel: Option<String>,
+    pub host: Option<String>,
+    pub source: Option<String>,
+    pub sourcetype: Option<String>,
+    pub index: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct HecResponse {
+    pub text: String,
+    pub code: u16,
+    #[serde(
+        rename = "invalid-event-number",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub invalid_event_number: Option<usize>,
+    #[serde(rename = "ackId", skip_serializing_if = "Option::is_none")]
+    pub ack_id: Option<u64>,
+}
+
+impl HecResponse {
+    fn new(text: &str, code: u16) -> Self {
+        HecResponse {
+            text: text.to_string(),
+            code,
+            invalid_event_number: None,
+            ack_id: None,
+        }
+    }
+
+    pub fn success(ack_id: Option<u64>) -> Self {
+        HecResponse {
+            ack_id,
+            ..Self::new("Success", 0)
+        }
+    }
+
+    pub fn token_required() -> Self {
+        Self::new("Token is required", 2)
+    }
+
+    pub fn invalid_token() -> Self {
+        Self::new("Invalid token", 4)
+    }
+
+    pub fn no_data() -> Self {
+        Self::new("No data", 5)
+    }
+
+    pub fn invalid_data_format(event_number: usize) -> Self {
+        HecResponse {
+            invalid_event_number: Some(event_number),
+            ..Self::new("Invalid data format", 6)
+        }
+    }
+
+    pub fn server_error() -> Self {
+        Self::new("Internal server error", 8)
+    }
+
+    pub fn channel_missing() -> Self {
+        Self::new("Data channel is missing", 10)
+    }
+
+    pub fn event_required(event_number: usize) -> Self {
+        HecResponse {
+            invalid_event_number: Some(event_number),
+            ..Self::new("Event field is required", 12)
+        }
+    }
+
+    pub fn event_blank(event_number: usize) -> Self {
+        HecResponse {
+            invalid_event_number: Some(event_number),
+            ..Self::new("Event field cannot be blank", 13)
+        }
+    }
+
+    /// HTTP status code Splunk answers with for this response
+    pub fn status_code(&self) -> u16 {
+        match self.code {
+            0 => 200,
+            2 => 401,
+            4 => 403,
+            8 => 500,
+            _ => 400,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct HecAckRequest {
+    pub acks: Vec<u64>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct HecAckResponse {
+    pub acks: HashMap<String, bool>,
+}
+
 pub enum IngestionRequest<'a> {
     JSON(&'a web::Bytes),
     Multi(&'a web::Bytes),
@@ -0,0 +1,62 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Consumes Kafka topics into a logs stream. The source is consumed by one ingester at a
+/// time, which commits the offsets of the topic partitions once the records are written.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct KafkaSource {
+    #[serde(default)]
+    pub name: String,
+    /// Bootstrap brokers, as `host:port`
+    pub brokers: Vec<String>,
+    pub topics: Vec<String>,
+    /// Target logs stream
+    pub stream_name: String,
+    #[serde(default)]
+    pub format: KafkaFormat,
+    /// Where to start partitions without committed offset
+    #[serde(default)]
+    pub start_from: KafkaStartOffset,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum KafkaFormat {
+    /// A JSON object, or an array of objects, per message
+    #[default]
+    Json,
+    /// Plain text, every line is stored in the `message` field of a record
+    Lines,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum KafkaStartOffset {
+    Earliest,
+    #[default]
+    Latest,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct KafkaSourceList {
+    pub list: Vec<KafkaSource>,
+}
@@ -32,6 +32,10 @@ pub struct GeoInfoData<'a> {
     pub location: Option<Location<'a>>,
 }
 
+/// The organization of the Splunk HEC token, set by the HEC authentication.
+#[derive(Clone, Debug)]
+pub struct HecOrg(pub String);
+
 /// This is the custom data which is provided by `browser-sdk`
 /// in form of query-parameters.
 /// NOTE: the only condition is that the prefix of such params is `oo`.
@@ -18,9 +18,11 @@ use utoipa::ToSchema;
 pub mod alert;
 pub mod common;
 pub mod dashboards;
+pub mod fluent;
 pub mod functions;
 pub mod http;
 pub mod ingestion;
+pub mod kafka;
 pub mod maxmind;
 pub mod meta_store;
 pub mod middleware_data;
@@ -18,6 +18,7 @@ use strum::Display;
 use utoipa::ToSchema;
 
 use crate::common::infra::config::FxIndexMap;
+use crate::common::utils::json;
 use crate::service::metrics::prom::prometheus as proto;
 
 pub const NAME_LABEL: &str = "__name__";
@@ -28,6 +29,22 @@ pub const LE_LABEL: &str = "le";
 pub const QUANTILE_LABEL: &str = "quantile";
 pub const METADATA_LABEL: &str = "prom_metadata"; // for schema metadata key
 
+// Columns of native histogram samples, the count of observations is stored as `value`.
+pub const HISTOGRAM_SUM_LABEL: &str = "__hist_sum__";
+pub const HISTOGRAM_SCHEMA_LABEL: &str = "__hist_schema__";
+pub const HISTOGRAM_ZERO_THRESHOLD_LABEL: &str = "__hist_zero_threshold__";
+pub const HISTOGRAM_ZERO_COUNT_LABEL: &str = "__hist_zero_count__";
+pub const HISTOGRAM_POSITIVE_LABEL: &str = "__hist_positive__";
+pub const HISTOGRAM_NEGATIVE_LABEL: &str = "__hist_negative__";
+pub const HISTOGRAM_LABELS: [&str; 6] = [
+    HISTOGRAM_SUM_LABEL,
+    HISTOGRAM_SCHEMA_LABEL,
+    HISTOGRAM_ZERO_THRESHOLD_LABEL,
+    HISTOGRAM_ZERO_COUNT_LABEL,
+    HISTOGRAM_POSITIVE_LABEL,
+    HISTOGRAM_NEGATIVE_LABEL,
+];
+
 #[derive(Debug, Clone, Serialize)]
 pub struct Metric<'a> {
     #[serde(flatten)]
@@ -35,6 +52,106 @@ pub struct Metric<'a> {
     pub value: f64,
 }
 
+/// A Prometheus native (exponential) histogram with absolute bucket counts.
+///
+/// The bucket with index `i` covers `(base^(i-1), base^i]` with `base = 2^(2^-schema)`,
+/// negative buckets mirror that. Buckets are `(index, count)` pairs sorted by index.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NativeHistogram {
+    pub schema: i32,
+    pub zero_threshold: f64,
+    pub zero_count: f64,
+    pub count: f64,
+    pub sum: f64,
+    pub positive_buckets: Vec<(i32, f64)>,
+    pub negative_buckets: Vec<(i32, f64)>,
+}
+
+impl NativeHistogram {
+    /// Adds the dedicated histogram columns to a metrics record.
+    pub fn write_columns(&self, record: &mut json::Map<String, json::Value>) {
+        record.insert(HISTOGRAM_SUM_LABEL.to_string(), json::json!(self.sum));
+        record.insert(HISTOGRAM_SCHEMA_LABEL.to_string(), json::json!(self.schema));
+        record.insert(
+            HISTOGRAM_ZERO_THRESHOLD_LABEL.to_string(),
+            json::json!(self.zero_threshold),
+        );
+        record.insert(
+            HISTOGRAM_ZERO_COUNT_LABEL.to_string(),
+            json::json!(self.zero_count),
+        );
+        record.insert(
+            HISTOGRAM_POSITIVE_LABEL.to_string(),
+            json::Value::String(json::to_string(&self.positive_buckets).unwrap()),
+        );
+        record.insert(
+            HISTOGRAM_NEGATIVE_LABEL.to_string(),
+            json::Value::String(json::to_string(&self.negative_buckets).unwrap()),
+        );
+    }
+}
+
+impl From<&proto::Histogram> for NativeHistogram {
+    fn from(h: &proto::Histogram) -> Self {
+        use proto::histogram::{Count, ZeroCount};
+
+        let count = match h.count {
+            Some(Count::CountInt(v)) => v as f64,
+            Some(Count::CountFloat(v)) => v,
+            None => 0.0,
+        };
+        let zero_count = match h.zero_count {
+            Some(ZeroCount::ZeroCountInt(v)) => v as f64,
+            Some(ZeroCount::ZeroCountFloat(v)) => v,
+            None => 0.0,
+        };
+        Self {
+            schema: h.schema,
+            zero_threshold: h.zero_threshold,
+            zero_count,
+            count,
+            sum: h.sum,
+            positive_buckets: expand_buckets(
+                &h.positive_spans,
+                &h.positive_deltas,
+                &h.positive_counts,
+            ),
+            negative_buckets: expand_buckets(
+                &h.negative_spans,
+                &h.negative_deltas,
+                &h.negative_counts,
+            ),
+        }
+    }
+}
+
+/// Resolves the spans of a protobuf histogram to `(index, count)` pairs, integer
+/// histograms encode the counts as deltas to the previous bucket. Empty buckets are
+/// dropped.
+fn expand_buckets(spans: &[proto::BucketSpan], deltas: &[i64], counts: &[f64]) -> Vec<(i32, f64)> {
+    let mut buckets = Vec::with_capacity(deltas.len().max(counts.len()));
+    let mut index = 0;
+    let mut position = 0;
+    let mut current = 0;
+    for span in spans {
+        index += span.offset;
+        for _ in 0..span.length {
+            let count = if deltas.is_empty() {
+                counts.get(position).copied().unwrap_or_default()
+            } else {
+                current += deltas.get(position).copied().unwrap_or_default();
+                current as f64
+            };
+            if count != 0.0 {
+                buckets.push((index, count));
+            }
+            index += 1;
+            position += 1;
+        }
+    }
+    buckets
+}
+
 #[derive(Debug, Clone, Serialize, Eq, PartialEq, Deserialize)]
 pub struct ClusterLeader {
     pub name: String,
@@ -43,6 +160,38 @@ pub struct ClusterLeader {
     pub updated_by: String, // instance id of ingestor
 }
 
+/// A PromQL expression evaluated on a schedule, its result is written back as the
+/// metric `metric`, cf. https://prometheus.io/docs/prometheus/latest/configuration/recording_rules/
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RecordingRule {
+    #[serde(default)]
+    pub name: String,
+    pub query: String,
+    /// Name of the recorded metric, also the target metrics stream
+    pub metric: String,
+    /// Labels to add or overwrite on every recorded series
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    /// Evaluation interval in seconds
+    #[serde(default = "default_recording_interval")]
+    pub interval: i64,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+fn default_recording_interval() -> i64 {
+    60
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RecordingRuleList {
+    pub list: Vec<RecordingRule>,
+}
+
 // cf. https://github.com/prometheus/prometheus/blob/f5fcaa3872ce03808567fabc56afc9cf61c732cb/model/textparse/interface.go#L106-L119
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, Display, ToSchema)]
 #[strum(serialize_all = "lowercase")]
@@ -232,4 +381,49 @@ mod tests {
         assert_eq!(format!("{}", MetricType::Unknown), "unknown");
         assert_eq!(MetricType::Unknown.to_string(), "unknown");
     }
+
+    #[test]
+    fn test_native_histogram_from_proto() {
+        let h = proto::Histogram {
+            count: Some(proto::histogram::Count::CountInt(12)),
+            sum: 18.4,
+            schema: 1,
+            zero_threshold: 0.001,
+            zero_count: Some(proto::histogram::ZeroCount::ZeroCountInt(2)),
+            positive_spans: vec![
+                proto::BucketSpan {
+                    offset: 0,
+                    length: 2,
+                },
+                proto::BucketSpan {
+                    offset: 1,
+                    length: 2,
+                },
+            ],
+            positive_deltas: vec![1, 1, -1, 0],
+            negative_spans: vec![proto::BucketSpan {
+                offset: 0,
+                length: 2,
+            }],
+            negative_counts: vec![2.0, 0.0],
+            timestamp: 1_000,
+            ..Default::default()
+        };
+        let h = NativeHistogram::from(&h);
+        assert_eq!(h.count, 12.0);
+        assert_eq!(h.zero_count, 2.0);
+        assert_eq!(
+            h.positive_buckets,
+            vec![(0, 1.0), (1, 2.0), (3, 1.0), (4, 1.0)]
+        );
+        assert_eq!(h.negative_buckets, vec![(0, 2.0)]);
+
+        let mut record = json::Map::new();
+        h.write_columns(&mut record);
+        assert_eq!(record[HISTOGRAM_SCHEMA_LABEL], json::json!(1));
+        assert_eq!(
+            record[HISTOGRAM_POSITIVE_LABEL],
+            json::Value::String("[[0,1.0],[1,2.0],[3,1.0],[4,1.0]]".to_string())
+        );
+    }
 }
@@ -232,6 +232,93 @@ impl Response {
     }
 }
 
+/// Encoding of the chunks of a streaming search.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StreamFormat {
+    /// One JSON object per line, `{"hits": [...]}` for each chunk
+    #[default]
+    Json,
+    /// One Arrow IPC stream per chunk
+    Arrow,
+}
+
+impl From<&str> for StreamFormat {
+    fn from(s: &str) -> Self {
+        match s.to_lowercase().as_str() {
+            "arrow" => StreamFormat::Arrow,
+            _ => StreamFormat::Json,
+        }
+    }
+}
+
+impl StreamFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            StreamFormat::Json => "application/x-ndjson",
+            StreamFormat::Arrow => "application/vnd.apache.arrow.stream",
+        }
+    }
+}
+
+/// Position of a streaming search, the trailer of a page gives it encoded to fetch the
+/// next page.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamCursor {
+    /// End of the time range, fixed by the first page so later data isn't mixed in
+    pub end_time: i64,
+    /// The WAL of the ingesters was searched
+    #[serde(default)]
+    pub wal_done: bool,
+    /// Key of the last searched file, the files are searched in key order
+    #[serde(default)]
+    pub file_key: String,
+    /// Files created after this, in microseconds, aren't searched. It's fixed by the
+    /// first page, before the WAL is searched, so that the files listed for every page
+    /// are the same and the WAL data written to files meanwhile isn't searched twice
+    #[serde(default)]
+    pub created_before: i64,
+    /// Digest of the keys of the files left to search. The files of a later page only
+    /// differ if some were merged by compaction meanwhile, the page then ends with an
+    /// error as their hits would be missed
+    #[serde(default)]
+    pub files_digest: Option<u64>,
+}
+
+impl StreamCursor {
+    pub fn encode(&self) -> String {
+        base64::encode_url(&json::to_string(self).unwrap())
+    }
+
+    pub fn decode(s: &str) -> Result<Self, std::io::Error> {
+        let data = base64::decode_url(s)?;
+        json::from_str(&data).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("invalid cursor: {e}"),
+            )
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct StreamChunk {
+    pub hits: Vec<json::Value>,
+}
+
+/// Last line of a streaming search page.
+#[derive(Clone, Debug, Serialize, Deserialize, Default, ToSchema)]
+#[schema(as = SearchStreamTrailer)]
+pub struct StreamTrailer {
+    /// Cursor of the next page, none after the last page
+    pub cursor: Option<String>,
+    pub total: usize,
+    pub took: usize,
+    pub scan_size: usize,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -298,4 +385,30 @@ mod test {
         assert_eq!(req.query.sql, "select * from test");
         assert_eq!(req.aggs.get("sql").unwrap(), "select * from olympics");
     }
+
+    #[test]
+    fn test_stream_cursor() {
+        let cursor = StreamCursor {
+            end_time: 1675185660872049,
+            wal_done: true,
+            file_key: "files/default/logs/app/2023/01/31/00/7024989428549181440.parquet"
+                .to_string(),
+            created_before: 1675185660872049,
+            files_digest: Some(42),
+        };
+        let encoded = cursor.encode();
+        assert!(encoded
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        assert_eq!(StreamCursor::decode(&encoded).unwrap(), cursor);
+        assert!(StreamCursor::decode("not a cursor").is_err());
+        assert!(StreamCursor::decode(&base64::encode_url("{}")).is_err());
+    }
+
+    #[test]
+    fn test_stream_format() {
+        assert_eq!(StreamFormat::from("ARROW"), StreamFormat::Arrow);
+        assert_eq!(StreamFormat::from("json"), StreamFormat::Json);
+        assert_eq!(StreamFormat::from(""), StreamFormat::Json);
+    }
 }
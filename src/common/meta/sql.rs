@@ -37,6 +37,7 @@ pub struct Sql {
     pub(crate) full_text: Vec<(String, SqlOperator)>, // fulltext: value1 and value2, and: true / false
     pub(crate) time_range: Option<(i64, i64)>,
     pub(crate) field_alias: Vec<(String, String)>, // alias for select field
+    pub(crate) equal_filters: Vec<(String, String)>, // field = 'value', not under OR / NOT
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
@@ -60,6 +61,7 @@ pub enum SqlValue {
 
 pub struct Projection<'a>(pub(crate) &'a Vec<SelectItem>);
 pub struct Quicktext<'a>(pub(crate) &'a Option<SqlExpr>);
+pub struct Equalfilter<'a>(pub(crate) &'a Option<SqlExpr>);
 pub struct Fulltext<'a>(pub(crate) &'a Option<SqlExpr>);
 pub struct Timerange<'a>(pub(crate) &'a Option<SqlExpr>);
 pub struct Source<'a>(pub(crate) &'a [TableWithJoins]);
@@ -140,6 +142,7 @@ impl TryFrom<&Statement> for Sql {
                     Quicktext(&selection).try_into()?;
                 let full_text: Vec<(String, SqlOperator)> = Fulltext(&selection).try_into()?;
                 let time_range: Option<(i64, i64)> = Timerange(&selection).try_into()?;
+                let equal_filters: Vec<(String, String)> = Equalfilter(&selection).into();
 
                 Ok(Sql {
                     fields,
@@ -153,6 +156,7 @@ impl TryFrom<&Statement> for Sql {
                     full_text,
                     time_range,
                     field_alias,
+                    equal_filters,
                 })
             }
             _ => Err(anyhow::anyhow!("We only support Query at the moment")),
@@ -248,6 +252,44 @@ impl<'a> TryFrom<Quicktext<'a>> for Vec<(String, String, SqlOperator)> {
     }
 }
 
+impl<'a> From<Equalfilter<'a>> for Vec<(String, String)> {
+    fn from(selection: Equalfilter<'a>) -> Self {
+        let mut filters = Vec::new();
+        if let Some(expr) = selection.0 {
+            parse_expr_equal_filters(expr, &mut filters);
+        }
+        filters
+    }
+}
+
+/// Collects the `field = 'value'` conditions joined by AND, the ones under an OR or a
+/// NOT are skipped as the matching rows don't need to satisfy them.
+fn parse_expr_equal_filters(expr: &SqlExpr, filters: &mut Vec<(String, String)>) {
+    match expr {
+        SqlExpr::Nested(e) => parse_expr_equal_filters(e, filters),
+        SqlExpr::BinaryOp {
+            left,
+            op: BinaryOperator::And,
+            right,
+        } => {
+            parse_expr_equal_filters(left, filters);
+            parse_expr_equal_filters(right, filters);
+        }
+        SqlExpr::BinaryOp {
+            left,
+            op: BinaryOperator::Eq,
+            right,
+        } => match (left.as_ref(), right.as_ref()) {
+            (SqlExpr::Identifier(ident), SqlExpr::Value(Value::SingleQuotedString(value)))
+            | (SqlExpr::Value(Value::SingleQuotedString(value)), SqlExpr::Identifier(ident)) => {
+                filters.push((ident.value.to_string(), value.to_string()));
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
 impl<'a> TryFrom<Timerange<'a>> for Option<(i64, i64)> {
     type Error = anyhow::Error;
 
@@ -872,6 +914,32 @@ mod tests {
         assert_eq!(sql.fields, vec!["a", "b", "c"]);
     }
 
+    #[test]
+    fn parse_equal_filters() {
+        let dialect = sqlparser::dialect::GenericDialect {};
+        let cases = [
+            (
+                "select * from t where a = 'x' and ('y' = \"b\" and c > 'z')",
+                vec![("a", "x"), ("b", "y")],
+            ),
+            ("select * from t where a = 'x' or b = 'y'", vec![]),
+            ("select * from t where a != 'x' and b = 1", vec![]),
+            (
+                "select * from t where (a = 'x' or c = 'z') and b = 'y'",
+                vec![("b", "y")],
+            ),
+        ];
+        for (sql, expected) in cases {
+            let statement = &Parser::parse_sql(&dialect, sql).unwrap()[0];
+            let sql: Sql = statement.try_into().unwrap();
+            let expected = expected
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<Vec<_>>();
+            assert_eq!(sql.equal_filters, expected);
+        }
+    }
+
     #[test]
     fn test_new_sql() {
         let table = "index.1.2022";
@@ -25,7 +25,7 @@ use crate::common::{
     utils::json,
 };
 
-use super::prom::Metadata;
+use super::{prom::Metadata, traces::TraceSampling};
 
 #[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
 pub struct Stream {
@@ -197,8 +197,14 @@ pub struct StreamSettings {
     #[serde(skip_serializing_if = "Vec::is_empty")]
     #[serde(default)]
     pub full_text_search_keys: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub bloom_filter_fields: Vec<String>,
     #[serde(default)]
     pub data_retention: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub trace_sampling: Option<TraceSampling>,
 }
 
 impl Serialize for StreamSettings {
@@ -206,7 +212,7 @@ impl Serialize for StreamSettings {
     where
         S: Serializer,
     {
-        let mut state = serializer.serialize_struct("stream_settings", 4)?;
+        let mut state = serializer.serialize_struct("stream_settings", 6)?;
         let mut part_keys = HashMap::new();
         for (index, key) in self.partition_keys.iter().enumerate() {
             part_keys.insert(format!("L{index}"), key.to_string());
@@ -217,7 +223,11 @@ impl Serialize for StreamSettings {
             &self.partition_time_level.unwrap_or_default(),
         )?;
         state.serialize_field("full_text_search_keys", &self.full_text_search_keys)?;
+        state.serialize_field("bloom_filter_fields", &self.bloom_filter_fields)?;
         state.serialize_field("data_retention", &self.data_retention)?;
+        if let Some(trace_sampling) = &self.trace_sampling {
+            state.serialize_field("trace_sampling", trace_sampling)?;
+        }
         state.end()
     }
 }
@@ -249,16 +259,29 @@ impl From<&str> for StreamSettings {
             }
         }
 
+        let mut bloom_filter_fields = Vec::new();
+        if let Some(value) = settings.get("bloom_filter_fields") {
+            for item in value.as_array().unwrap() {
+                bloom_filter_fields.push(item.as_str().unwrap().to_string())
+            }
+        }
+
         let mut data_retention = 0;
         if let Some(v) = settings.get("data_retention") {
             data_retention = v.as_i64().unwrap();
         };
 
+        let trace_sampling = settings
+            .get("trace_sampling")
+            .and_then(|v| json::from_value(v.clone()).ok());
+
         Self {
             partition_keys,
             partition_time_level,
             full_text_search_keys,
+            bloom_filter_fields,
             data_retention,
+            trace_sampling,
         }
     }
 }
@@ -15,6 +15,7 @@
 use ahash::AHashMap;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use utoipa::ToSchema;
 
 use crate::common::utils::json;
 
@@ -59,18 +60,321 @@ pub struct Event {
     #[serde(skip_serializing_if = "HashMap::is_empty")]
     pub attributes: AHashMap<String, json::Value>,
 }
-/* #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+
+/// Query parameters of the trace lookup.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct TraceQuery {
+    /// Start of the time range, in microseconds. Defaults to an hour before the end.
+    #[serde(default)]
+    pub start_time: i64,
+    /// End of the time range, in microseconds. Defaults to now.
+    #[serde(default)]
+    pub end_time: i64,
+}
+
+/// Query parameters of the trace search, all filters are optional.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct TraceSearchQuery {
+    pub service_name: Option<String>,
+    pub operation_name: Option<String>,
+    /// Minimum span duration, in microseconds.
+    pub min_duration: Option<u64>,
+    /// Maximum span duration, in microseconds.
+    pub max_duration: Option<u64>,
+    /// Span status, one of `OK`, `ERROR` or `UNSET`.
+    pub status: Option<String>,
+    #[serde(default)]
+    pub start_time: i64,
+    #[serde(default)]
+    pub end_time: i64,
+    #[serde(default = "default_search_size")]
+    pub size: usize,
+}
+
+fn default_search_size() -> usize {
+    20
+}
+
+/// A span with the spans it's the parent of.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SpanNode {
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    pub span: json::Map<String, json::Value>,
+    pub children: Vec<SpanNode>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Trace {
+    pub trace_id: String,
+    /// Start time of the earliest span, in nanoseconds.
+    pub start_time: u64,
+    /// End time of the latest span, in nanoseconds.
+    pub end_time: u64,
+    /// In microseconds.
+    pub duration: u64,
+    pub span_count: usize,
+    pub error_count: usize,
+    pub services: Vec<String>,
+    /// Root spans of the trace, spans whose parent wasn't found are roots too.
+    pub spans: Vec<SpanNode>,
+    /// The trace has more spans than were loaded.
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TraceSummary {
+    pub trace_id: String,
+    /// Service and operation of the root span.
+    pub service_name: String,
+    pub operation_name: String,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub duration: u64,
+    pub span_count: usize,
+    pub error_count: usize,
+    pub services: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TraceSearchResponse {
+    pub took: usize,
+    pub total: usize,
+    pub traces: Vec<TraceSummary>,
+    /// The traces have more spans than were loaded, their summaries are partial.
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ServiceGraph {
+    pub nodes: Vec<ServiceNode>,
+    pub edges: Vec<ServiceEdge>,
+    /// The time range has more spans than the graph was computed from.
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ServiceNode {
+    pub name: String,
+    pub span_count: usize,
+    pub error_count: usize,
+}
+
+/// Calls from spans of `source` to child spans of `target`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ServiceEdge {
+    pub source: String,
+    pub target: String,
+    pub call_count: usize,
+    pub error_count: usize,
+}
+
+/// Sampling policy of a traces stream, set in the stream settings.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TraceSampling {
+    /// Probability to keep a trace, by service name, `*` for the other services.
+    #[serde(default)]
+    pub head_rates: HashMap<String, f64>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tail: Option<TailSampling>,
+    /// Spans matching any rule are dropped, like health checks.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub drop_rules: Vec<SpanDropRule>,
+}
+
+/// Buffers the spans of a trace for `decision_wait` seconds and keeps the whole trace if
+/// any span errored or took longer than `latency_threshold`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TailSampling {
+    #[serde(default = "default_keep_errors")]
+    pub keep_errors: bool,
+    /// In milliseconds, 0 to not keep traces by latency.
+    #[serde(default)]
+    pub latency_threshold: u64,
+    /// Probability to keep the other traces.
+    #[serde(default)]
+    pub rate: f64,
+    /// In seconds.
+    #[serde(default = "default_decision_wait")]
+    pub decision_wait: u64,
+}
+
+fn default_keep_errors() -> bool {
+    true
+}
+
+fn default_decision_wait() -> u64 {
+    10
+}
+
+/// Matches spans by service, operation and span attributes, all set conditions have to
+/// match. Values can use `*` wildcards.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SpanDropRule {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_name: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub operation_name: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub attributes: HashMap<String, String>,
+}
+
+// Jaeger query API models
+// refer: https://github.com/jaegertracing/jaeger/blob/main/model/json/model.go
+
+/// Response envelope of the Jaeger query API.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JaegerResponse<T> {
+    pub data: Option<T>,
+    pub total: usize,
+    pub limit: usize,
+    pub offset: usize,
+    pub errors: Option<Vec<JaegerError>>,
+}
+
+impl<T> JaegerResponse<T> {
+    pub fn data(data: T, total: usize) -> Self {
+        JaegerResponse {
+            data: Some(data),
+            total,
+            limit: 0,
+            offset: 0,
+            errors: None,
+        }
+    }
+
+    pub fn error(code: u16, msg: String) -> Self {
+        JaegerResponse {
+            data: None,
+            total: 0,
+            limit: 0,
+            offset: 0,
+            errors: Some(vec![JaegerError {
+                code,
+                msg,
+                trace_id: None,
+            }]),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JaegerError {
+    pub code: u16,
+    pub msg: String,
+    #[serde(rename = "traceID")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+}
+
+/// Query parameters of the Jaeger trace search, times are in microseconds and
+/// durations are Go duration strings like `1.2s`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JaegerTraceQuery {
+    pub service: Option<String>,
+    pub operation: Option<String>,
+    /// JSON object of span tags, `{"http.status_code":"500"}`.
+    pub tags: Option<String>,
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+    pub lookback: Option<String>,
+    pub min_duration: Option<String>,
+    pub max_duration: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// Query parameters of the Jaeger trace lookup, times are in microseconds.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct JaegerGetTraceQuery {
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+}
+
+/// Query parameters of the Jaeger dependencies, times are in milliseconds.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JaegerDependenciesQuery {
+    pub end_ts: Option<i64>,
+    pub lookback: Option<i64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JaegerTrace {
+    #[serde(rename = "traceID")]
+    pub trace_id: String,
+    pub spans: Vec<JaegerSpan>,
+    pub processes: HashMap<String, Service>,
+    pub warnings: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JaegerSpan {
+    #[serde(rename = "traceID")]
+    pub trace_id: String,
+    #[serde(rename = "spanID")]
+    pub span_id: String,
+    pub flags: u32,
+    pub operation_name: String,
+    pub references: Vec<SpanReference>,
+    /// In microseconds.
+    pub start_time: u64,
+    /// In microseconds.
+    pub duration: u64,
+    pub tags: Vec<KeyValue>,
+    pub logs: Vec<JaegerLog>,
+    #[serde(rename = "processID")]
+    pub process_id: String,
+    pub warnings: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct SpanReference {
-    pub parent_trace_id: String,
-    pub parent_span_id: String,
     pub ref_type: SpanRefType,
+    #[serde(rename = "traceID")]
+    pub trace_id: String,
+    #[serde(rename = "spanID")]
+    pub span_id: String,
 }
 
-//Service which has generated the trace id - span id ...in distributed tracing there can be multiple services using same trace id
+/// Service which has generated the spans, Jaeger calls it process. In distributed
+/// tracing there can be multiple services using the same trace id.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Service {
+    #[serde(rename = "serviceName")]
     pub name: String,
-    #[serde(flatten)]
-    #[serde(skip_serializing_if = "HashMap::is_empty")]
-    pub attributes: AHashMap<String, Value>,
-} */
+    pub tags: Vec<KeyValue>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeyValue {
+    pub key: String,
+    #[serde(rename = "type")]
+    pub value_type: String,
+    pub value: json::Value,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JaegerLog {
+    /// In microseconds.
+    pub timestamp: u64,
+    pub fields: Vec<KeyValue>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JaegerDependency {
+    pub parent: String,
+    pub child: String,
+    pub call_count: usize,
+}
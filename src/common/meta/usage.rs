@@ -65,6 +65,7 @@ pub enum UsageEvent {
     Ingestion,
     Search,
     Functions,
+    SampledOut,
     Other,
 }
 
@@ -74,6 +75,7 @@ impl std::fmt::Display for UsageEvent {
             UsageEvent::Ingestion => write!(f, "Ingestion"),
             UsageEvent::Search => write!(f, "Search"),
             UsageEvent::Functions => write!(f, "Functions"),
+            UsageEvent::SampledOut => write!(f, "SampledOut"),
             UsageEvent::Other => write!(f, "Other"),
         }
     }
@@ -92,12 +94,17 @@ impl From<UsageType> for UsageEvent {
             | UsageType::EnrichmentTable
             | UsageType::Syslog
             | UsageType::JsonMetrics
-            | UsageType::Logs => UsageEvent::Ingestion,
+            | UsageType::Logs
+            | UsageType::Loki
+            | UsageType::Hec
+            | UsageType::Fluent
+            | UsageType::Kafka => UsageEvent::Ingestion,
             UsageType::Search
             | UsageType::SearchAround
             | UsageType::SearchTopNValues
             | UsageType::MetricSearch => UsageEvent::Search,
             UsageType::Functions => UsageEvent::Functions,
+            UsageType::TracesSampling => UsageEvent::SampledOut,
             UsageType::Retention => UsageEvent::Other,
         }
     }
@@ -115,6 +122,8 @@ pub enum UsageType {
     Logs,
     #[serde(rename = "/traces")]
     Traces,
+    #[serde(rename = "/traces/_sampling")]
+    TracesSampling,
     #[serde(rename = "/v1/write")]
     Metrics,
     #[serde(rename = "/_search")]
@@ -137,6 +146,12 @@ pub enum UsageType {
     JsonMetrics,
     Syslog,
     EnrichmentTable,
+    #[serde(rename = "/loki/api/v1/push")]
+    Loki,
+    #[serde(rename = "/services/collector")]
+    Hec,
+    Fluent,
+    Kafka,
 }
 
 impl ToString for UsageType {
@@ -147,6 +162,7 @@ impl ToString for UsageType {
             UsageType::JsonMetrics => "metrics/_json".to_owned(),
             UsageType::Multi => "logs/_multi".to_owned(),
             UsageType::Traces => "/traces".to_owned(),
+            UsageType::TracesSampling => "/traces/_sampling".to_owned(),
             UsageType::Metrics => "/v1/write".to_owned(),
             UsageType::Search => "/_search".to_owned(),
             UsageType::Functions => "functions".to_owned(),
@@ -159,6 +175,10 @@ impl ToString for UsageType {
             UsageType::GCPSubscription => "/gcp/_sub".to_owned(),
             UsageType::MetricSearch => "/metrics/_search".to_owned(),
             UsageType::Logs => "/v1/logs".to_owned(),
+            UsageType::Loki => "/loki/api/v1/push".to_owned(),
+            UsageType::Hec => "/services/collector".to_owned(),
+            UsageType::Fluent => "fluent".to_owned(),
+            UsageType::Kafka => "kafka".to_owned(),
         }
     }
 }
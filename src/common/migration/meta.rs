@@ -9,16 +9,25 @@ use crate::common::{
     utils::{file::get_file_meta, json},
 };
 
-const ITEM_PREFIXES: [&str; 11] = [
+const ITEM_PREFIXES: [&str; 20] = [
     "/user",
     "/schema",
     "/syslog",
+    "/fluent",
     "/function",
     "/dashboard",
-    "/templates",    // alert
-    "/destinations", // alert
-    "/alerts",       // alert
-    "/trigger",      // alert
+    "/templates",            // alert
+    "/destinations",         // alert
+    "/alerts",               // alert
+    "/trigger/",             // alert
+    "/trigger_history/",     // alert
+    "/silences/",            // alert
+    "/maintenance_windows/", // alert
+    "/recording_rules/",
+    "/recording_rules_leader/",
+    "/kafka_sources/",
+    "/kafka_sources_leader/",
+    "/kafka_offsets/",
     "/compact",
     "/kv",
 ];
@@ -52,7 +61,7 @@ pub async fn load_meta_from_sled() -> Result<(), anyhow::Error> {
 
         for (key, value) in res.iter() {
             let final_key;
-            let key = if key.starts_with("/trigger") {
+            let key = if key.starts_with("/trigger/") {
                 let local_val: Trigger = json::from_slice(value).unwrap();
                 final_key = format!("/trigger/{}/{}", local_val.org, local_val.alert_name);
                 &final_key
@@ -98,7 +107,7 @@ pub async fn load_meta_from_etcd() -> Result<(), anyhow::Error> {
         let mut count = 0;
         for (key, value) in res.iter() {
             let final_key;
-            let key = if key.starts_with("/trigger") {
+            let key = if key.starts_with("/trigger/") {
                 let local_val: Trigger = json::from_slice(value).unwrap();
                 final_key = format!("/trigger/{}/{}", local_val.org, local_val.alert_name);
                 &final_key
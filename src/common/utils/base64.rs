@@ -46,6 +46,21 @@ pub(crate) fn decode_raw(s: &str) -> Result<Vec<u8>, Error> {
         .map_err(|e| Error::new(ErrorKind::InvalidData, format!("base64 decode error: {e}")))
 }
 
+/// Encodes with the URL safe alphabet, without padding.
+#[inline(always)]
+pub(crate) fn encode_url(s: &str) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(s.as_bytes())
+}
+
+#[inline(always)]
+pub(crate) fn decode_url(s: &str) -> Result<String, Error> {
+    let ns = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(s.as_bytes())
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("base64 decode error: {e}")))?;
+    String::from_utf8(ns)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("base64 decode error: {e}")))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
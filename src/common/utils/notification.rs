@@ -12,15 +12,72 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use ahash::HashMap;
 use std::error::Error as StdError;
 
-use crate::common::meta::alert::{self, Alert};
-use crate::common::utils::json::{self, Value};
-use crate::service::db;
+use crate::common::meta::alert::{self, Alert, AlertState};
+use crate::common::utils::json::{self, Map, Value};
+use crate::service::{alerts::destinations, db};
+
+/// Number of repeat intervals a firing notification is valid for.
+const FIRING_VALIDITY_INTERVALS: i64 = 3;
 
 pub async fn send_notification(
     alert: &Alert,
     trigger: &alert::Trigger,
+) -> Result<(), Box<dyn StdError>> {
+    send(alert, trigger, AlertState::Firing, "", vec![], vec![], 0).await
+}
+
+/// Sends the notification for one instance of a scheduled alert, the template can refer to
+/// `{alert_state}`, `{group_key}` and to every column of the result row, e.g.
+/// `{zo_alert_value}`. There is no row for resolved instances. `active_at` is the time the
+/// instance became active, in microseconds.
+pub async fn send_group_notification(
+    alert: &Alert,
+    trigger: &alert::Trigger,
+    state: AlertState,
+    group_key: &str,
+    labels: &HashMap<String, String>,
+    row: Option<&Map<String, Value>>,
+    active_at: i64,
+) -> Result<(), Box<dyn StdError>> {
+    let mut vars = vec![];
+    for (key, value) in row.into_iter().flatten() {
+        let value = match value {
+            Value::String(v) => v.clone(),
+            v => v.to_string(),
+        };
+        vars.push((key.clone(), value));
+    }
+    let mut labels: Vec<(String, String)> = labels
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    labels.sort();
+    send(alert, trigger, state, group_key, labels, vars, active_at).await
+}
+
+/// How long a firing notification stays valid without being sent again, in microseconds:
+/// a few times the interval it is repeated at, so that e.g. Alertmanager resolves the
+/// alert by itself only once it isn't evaluated anymore.
+fn firing_validity(alert: &Alert) -> i64 {
+    let interval = if alert.is_real_time {
+        alert.duration
+    } else {
+        alert.frequency
+    };
+    FIRING_VALIDITY_INTERVALS * interval.max(alert.time_between_alerts).max(1) * 60 * 1_000_000
+}
+
+async fn send(
+    alert: &Alert,
+    trigger: &alert::Trigger,
+    state: AlertState,
+    group_key: &str,
+    labels: Vec<(String, String)>,
+    vars: Vec<(String, String)>,
+    active_at: i64,
 ) -> Result<(), Box<dyn StdError>> {
     let alert_type = match &trigger.is_ingest_time {
         true => "Real time",
@@ -30,7 +87,7 @@ pub async fn send_notification(
     match db::alerts::destinations::get(&trigger.org, &alert.destination).await {
         Ok(dest) => match dest {
             Some(local_dest) => {
-                let body = local_dest.template.unwrap().body;
+                let body = local_dest.template.as_ref().unwrap().body.clone();
                 let resp_str = json::to_string(&body).unwrap();
 
                 let mut resp = resp_str
@@ -38,8 +95,14 @@ pub async fn send_notification(
                     .replace("{org_name}", &trigger.org)
                     .replace("{alert_name}", &trigger.alert_name)
                     .replace("{alert_type}", alert_type)
+                    .replace("{alert_state}", &state.to_string())
+                    .replace("{group_key}", group_key)
                     .replace("{timestamp}", &curr_ts.to_string());
 
+                for (key, value) in vars.iter() {
+                    resp = resp.replace(&format!("{{{key}}}"), value);
+                }
+
                 // Replace contextual information with values if any from alert
                 if alert.context_attributes.is_some() {
                     for (key, value) in alert.context_attributes.as_ref().unwrap() {
@@ -55,39 +118,24 @@ pub async fn send_notification(
                     },
                     _ => msg,
                 };
-                let client = if local_dest.skip_tls_verify {
-                    reqwest::Client::builder()
-                        .danger_accept_invalid_certs(true)
-                        .build()?
-                } else {
-                    reqwest::Client::new()
-                };
-                let url = url::Url::parse(&local_dest.url);
-                let mut req = match local_dest.method {
-                    alert::AlertHTTPType::POST => client.post(url.unwrap()),
-                    alert::AlertHTTPType::PUT => client.put(url.unwrap()),
-                    alert::AlertHTTPType::GET => client.get(url.unwrap()),
-                }
-                .header("Content-type", "application/json");
-
-                // Add additional headers if any from destination description
-                if local_dest.headers.is_some() {
-                    for (key, value) in local_dest.headers.unwrap() {
-                        if !key.is_empty() && !value.is_empty() {
-                            req = req.header(key, value);
-                        }
-                    }
-                };
 
-                let resp = req.json(&msg).send().await;
-                match resp {
-                    Ok(resp) => {
-                        if !resp.status().is_success() {
-                            log::error!("Notification sent error: {:?}", resp.bytes().await);
-                        }
-                    }
-                    Err(err) => log::error!("Notification sending error {:?}", err),
-                }
+                let notification = destinations::Notification {
+                    org: trigger.org.clone(),
+                    stream: trigger.stream.clone(),
+                    stream_type: trigger.stream_type,
+                    alert_name: trigger.alert_name.clone(),
+                    state,
+                    group_key: group_key.to_string(),
+                    labels,
+                    message: msg,
+                    timestamp: curr_ts,
+                    starts_at: if active_at > 0 { active_at } else { curr_ts },
+                    ends_at: match state {
+                        AlertState::Resolved => curr_ts,
+                        _ => curr_ts + firing_validity(alert),
+                    },
+                };
+                destinations::deliver(local_dest, notification);
             }
 
             None => log::error!("Destination Not found"),
@@ -115,12 +163,15 @@ mod tests {
         let _ = db::alerts::templates::set("default", "testTemplate", template).await;
 
         let destination = AlertDestination {
+            destination_type: alert::AlertDestType::Http,
             url: "http://dummy/alert".to_string(),
             method: alert::AlertHTTPType::POST,
             skip_tls_verify: false,
             headers: None,
             template: "testTemplate".to_string(),
             name: Some("test".to_string()),
+            emails: vec![],
+            routing_key: None,
         };
         let _ = db::alerts::destinations::set("default", "testDest", destination).await;
 
@@ -134,6 +185,7 @@ mod tests {
             last_sent_at: chrono::Utc::now().timestamp_micros(),
             count: 1,
             is_ingest_time: true,
+            ..Default::default()
         };
         let alert = Alert {
             name: "testAlert".to_string(),
@@ -166,6 +218,10 @@ mod tests {
             destination: "testDest".to_string(),
             is_real_time: true,
             context_attributes: None,
+            for_duration: 0,
+            conditions: None,
+            aggregation: None,
+            promql: None,
         };
 
         send_notification(&alert, &obj).await.unwrap();
@@ -13,15 +13,27 @@
 // limitations under the License.
 
 use actix_web::HttpResponse;
-use datafusion::arrow::{datatypes::Schema, json as arrow_json, record_batch::RecordBatch};
+use datafusion::arrow::{
+    array::{Array, StringArray},
+    datatypes::{DataType, Schema},
+    json as arrow_json,
+    record_batch::RecordBatch,
+};
 use datafusion::{datasource::MemTable, prelude::SessionContext};
+use std::collections::{BTreeMap, BTreeSet};
 use std::io::{Error, ErrorKind};
 use std::sync::Arc;
 
-use crate::common::infra::config::{CONFIG, FILE_EXT_JSON};
-use crate::common::meta::{common::FileMeta, StreamType};
+use crate::common::infra::config::{CONFIG, FILE_EXT_JSON, SQL_FULL_TEXT_SEARCH_FIELDS};
+use crate::common::meta::{
+    common::{FieldSummary, FileMeta},
+    StreamType,
+};
 use crate::common::utils::json;
 
+/// Columns with longer values are free text, they aren't summarized.
+const FIELD_SUMMARY_MAX_VALUE_LEN: usize = 128;
+
 #[inline(always)]
 pub fn stream_type_query_param_error() -> Result<HttpResponse, Error> {
     /*  return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
@@ -112,6 +124,95 @@ pub async fn populate_file_meta(
     Ok(())
 }
 
+/// Summarizes the values of the string columns, if `ZO_FILE_LIST_FIELD_SUMMARY_ENABLED`.
+/// The full text search fields, default and `fts_fields` of the stream, and the columns
+/// with long values are skipped.
+pub fn populate_field_summary(
+    batches: &[RecordBatch],
+    fts_fields: &[String],
+    file_meta: &mut FileMeta,
+) {
+    if !CONFIG.common.file_list_field_summary_enabled {
+        return;
+    }
+    file_meta.field_summary = summarize_fields(
+        batches,
+        fts_fields,
+        CONFIG.limit.file_list_field_summary_max_values,
+        CONFIG.limit.file_list_field_summary_max_size,
+    );
+}
+
+/// Summarizes the columns in schema order while the serialized summary stays within
+/// `max_size` bytes.
+fn summarize_fields(
+    batches: &[RecordBatch],
+    fts_fields: &[String],
+    max_values: usize,
+    max_size: usize,
+) -> BTreeMap<String, FieldSummary> {
+    let mut summary = BTreeMap::new();
+    let mut size = 0;
+    let Some(schema) = batches.first().map(|batch| batch.schema()) else {
+        return summary;
+    };
+    'fields: for field in schema.fields() {
+        if field.data_type() != &DataType::Utf8
+            || SQL_FULL_TEXT_SEARCH_FIELDS.contains(field.name())
+            || fts_fields.contains(field.name())
+        {
+            continue;
+        }
+        let mut min: Option<&str> = None;
+        let mut max: Option<&str> = None;
+        let mut values = BTreeSet::new();
+        for batch in batches {
+            let Some(column) = batch.column_by_name(field.name()) else {
+                continue;
+            };
+            let column = column.as_any().downcast_ref::<StringArray>().unwrap();
+            for value in column.iter().flatten() {
+                if value.len() > FIELD_SUMMARY_MAX_VALUE_LEN {
+                    continue 'fields;
+                }
+                match min {
+                    Some(v) if v <= value => {}
+                    _ => min = Some(value),
+                }
+                match max {
+                    Some(v) if v >= value => {}
+                    _ => max = Some(value),
+                }
+                if values.len() <= max_values {
+                    values.insert(value);
+                }
+            }
+        }
+        // all null
+        let (Some(min), Some(max)) = (min, max) else {
+            continue;
+        };
+        let values = if values.len() > max_values {
+            vec![]
+        } else {
+            values.into_iter().map(|v| v.to_string()).collect()
+        };
+        let field_summary = FieldSummary {
+            min: min.to_string(),
+            max: max.to_string(),
+            values,
+        };
+        // `"name":{...},`
+        let field_size = field.name().len() + json::to_string(&field_summary).unwrap().len() + 4;
+        if size + field_size > max_size {
+            continue;
+        }
+        size += field_size;
+        summary.insert(field.name().to_string(), field_summary);
+    }
+    summary
+}
+
 #[cfg(test)]
 mod tests {
     use datafusion::arrow::array::{Int64Array, StringArray};
@@ -174,6 +275,7 @@ mod tests {
             records: 0,
             original_size: 1000,
             compressed_size: 700,
+            field_summary: BTreeMap::new(),
         };
         populate_file_meta(schema, vec![vec![batch]], &mut file_meta)
             .await
@@ -182,6 +284,57 @@ mod tests {
         assert_eq!(file_meta.min_ts, val - 100);
     }
 
+    #[test]
+    fn test_summarize_fields() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("log", DataType::Utf8, true),
+            Field::new("pod", DataType::Utf8, true),
+            Field::new("user_id", DataType::Utf8, true),
+            Field::new("empty", DataType::Utf8, true),
+            Field::new("code", DataType::Int64, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(StringArray::from(vec!["a", "b", "c", "d"])),
+                Arc::new(StringArray::from(vec![
+                    Some("web-2"),
+                    None,
+                    Some("web-1"),
+                    Some("web-2"),
+                ])),
+                Arc::new(StringArray::from(vec!["u3", "u1", "u4", "u2"])),
+                Arc::new(StringArray::from(vec![None::<&str>; 4])),
+                Arc::new(Int64Array::from(vec![1, 2, 1, 2])),
+            ],
+        )
+        .unwrap();
+        let summary = summarize_fields(&[batch.clone()], &[], 3, 1024);
+        assert_eq!(summary.keys().collect::<Vec<_>>(), vec!["pod", "user_id"]);
+
+        let pod = &summary["pod"];
+        assert_eq!(pod.values, vec!["web-1", "web-2"]);
+        assert!(pod.may_contain("web-1"));
+        assert!(!pod.may_contain("web-3"));
+
+        // too many values, only the range is kept
+        let user_id = &summary["user_id"];
+        assert!(user_id.values.is_empty());
+        assert_eq!((user_id.min.as_str(), user_id.max.as_str()), ("u1", "u4"));
+        assert!(user_id.may_contain("u25"));
+        assert!(!user_id.may_contain("u5"));
+
+        // full text search fields of the stream aren't summarized
+        let summary = summarize_fields(&[batch.clone()], &["pod".to_string()], 3, 1024);
+        assert_eq!(summary.keys().collect::<Vec<_>>(), vec!["user_id"]);
+
+        // the summary stays within the max size
+        let summary = summarize_fields(&[batch], &[], 3, 40);
+        assert_eq!(summary.keys().collect::<Vec<_>>(), vec!["user_id"]);
+        let size = json::to_string(&summary).unwrap().len();
+        assert!(size <= 40, "{size}");
+    }
+
     #[test]
     fn test_stream_type_query_param_error() {
         let res = stream_type_query_param_error();
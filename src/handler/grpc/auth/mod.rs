@@ -24,30 +24,30 @@ use tonic::{Request, Status};
 
 pub fn check_auth(req: Request<()>) -> Result<Request<()>, Status> {
     let metadata = req.metadata();
-    if !metadata.contains_key(&CONFIG.grpc.org_header_key)
-        && !metadata.contains_key("authorization")
-    {
-        return Err(Status::unauthenticated("No valid auth token"));
-    }
-
-    let token = req
-        .metadata()
+    // the internal requests of the cluster, like the file list events, have no org
+    let token = metadata
         .get("authorization")
-        .unwrap()
-        .to_str()
-        .unwrap()
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| Status::unauthenticated("No valid auth token"))?
         .to_string();
     if token.eq(get_internal_grpc_token().as_str()) {
         Ok(req)
     } else {
-        let org_id = metadata.get(&CONFIG.grpc.org_header_key);
-        if org_id.is_none() {
+        let Some(org_id) = metadata.get(&CONFIG.grpc.org_header_key) else {
             return Err(Status::invalid_argument(format!(
                 "Please specify organization id with header key '{}' ",
                 &CONFIG.grpc.org_header_key
             )));
-        }
-
+        };
+        let org_id = org_id
+            .to_str()
+            .map_err(|_| Status::unauthenticated("No valid auth token"))?;
+
+        // Flight SQL clients send the credentials of the handshake as a bearer token
+        let token = match token.strip_prefix("Bearer ") {
+            Some(credentials) => format!("Basic {credentials}"),
+            None => token,
+        };
         let credentials = match Credentials::from_header(token) {
             Ok(c) => c,
             Err(err) => {
@@ -59,11 +59,7 @@ pub fn check_auth(req: Request<()>) -> Result<Request<()>, Status> {
         let user_id = credentials.user_id;
         let user = if is_root_user(&user_id) {
             ROOT_USER.get("root").unwrap()
-        } else if let Some(user) = USERS.get(&format!(
-            "{}/{}",
-            org_id.unwrap().to_str().unwrap(),
-            &user_id
-        )) {
+        } else if let Some(user) = USERS.get(&format!("{org_id}/{user_id}")) {
             user
         } else {
             return Err(Status::unauthenticated("No valid auth token"));
@@ -118,6 +114,18 @@ mod tests {
         assert!(res.is_err())
     }
 
+    #[actix_web::test]
+    async fn test_check_auth_org_without_token() {
+        let mut request = tonic::Request::new(());
+        let org_id: MetadataValue<_> = "default".parse().unwrap();
+        request
+            .metadata_mut()
+            .insert(CONFIG.grpc.org_header_key.as_str(), org_id);
+
+        let res = check_auth(request);
+        assert_eq!(res.unwrap_err().code(), tonic::Code::Unauthenticated);
+    }
+
     #[actix_web::test]
     async fn test_check_auth() {
         INSTANCE_ID.insert("instance_id".to_owned(), "instance".to_string());
@@ -172,4 +180,32 @@ mod tests {
         let res = check_auth(request);
         assert!(res.is_err())
     }
+
+    #[actix_web::test]
+    async fn test_check_bearer_auth() {
+        INSTANCE_ID.insert("instance_id".to_owned(), "instance".to_string());
+        ROOT_USER.insert(
+            "root".to_string(),
+            User {
+                email: "root@example.com".to_string(),
+                password: "Complexpass#123".to_string(),
+                role: crate::common::meta::user::UserRole::Root,
+                salt: "Complexpass#123".to_string(),
+                first_name: "root".to_owned(),
+                last_name: "".to_owned(),
+                token: "token".to_string(),
+                rum_token: Some("rum_token".to_string()),
+                org: "dummy".to_owned(),
+            },
+        );
+
+        let mut request = tonic::Request::new(());
+        let token: MetadataValue<_> = "Bearer cm9vdEBleGFtcGxlLmNvbTp0b2tlbg==".parse().unwrap();
+        let org_id: MetadataValue<_> = "default".parse().unwrap();
+        let meta: &mut tonic::metadata::MetadataMap = request.metadata_mut();
+        meta.insert("authorization", token);
+        meta.insert(CONFIG.grpc.org_header_key.as_str(), org_id);
+
+        assert!(check_auth(request).is_ok())
+    }
 }
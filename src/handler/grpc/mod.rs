@@ -25,6 +25,14 @@ pub mod cluster_rpc {
     tonic::include_proto!("cluster");
 }
 
+pub mod flight_rpc {
+    tonic::include_proto!("arrow.flight.protocol");
+
+    pub mod sql {
+        tonic::include_proto!("arrow.flight.protocol.sql");
+    }
+}
+
 impl From<meta::search::Request> for cluster_rpc::SearchRequest {
     fn from(req: meta::search::Request) -> Self {
         let req_query = cluster_rpc::SearchQuery {
@@ -75,19 +83,23 @@ impl From<&meta::common::FileMeta> for cluster_rpc::FileMeta {
             records: req.records,
             original_size: req.original_size,
             compressed_size: req.compressed_size,
+            field_summary: req.field_summary_json().unwrap_or_default(),
         }
     }
 }
 
 impl From<&cluster_rpc::FileMeta> for meta::common::FileMeta {
     fn from(req: &cluster_rpc::FileMeta) -> Self {
-        meta::common::FileMeta {
+        let mut file_meta = meta::common::FileMeta {
             min_ts: req.min_ts,
             max_ts: req.max_ts,
             records: req.records,
             original_size: req.original_size,
             compressed_size: req.compressed_size,
-        }
+            field_summary: Default::default(),
+        };
+        file_meta.set_field_summary_json(Some(req.field_summary.as_str()));
+        file_meta
     }
 }
 
@@ -215,6 +227,7 @@ mod test {
             records: 300,
             original_size: 10,
             compressed_size: 1,
+            field_summary: Default::default(),
         };
 
         let rpc_meta = cluster_rpc::FileMeta::from(&file_meta);
@@ -0,0 +1,843 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Arrow Flight SQL server, for the ADBC/JDBC drivers and pandas. Clients authenticate like
+// the other gRPC services, with basic auth and the org in the `organization` header (or
+// `ZO_GRPC_ORG_HEADER_KEY`). There are no sessions: the handshake gives the base64 basic
+// credentials back as the bearer token, so the token is as secret as the password and
+// stays valid until the password changes.
+// The catalog is the org, the schemas are the stream types and the tables the streams.
+// A statement searches the stream of its FROM clause in the first stream type which has
+// it. The hits of a plain select are all sent as the nodes answer, the other queries are
+// merged in memory and limited to `ZO_SEARCH_ARROW_MERGE_LIMIT` rows without a LIMIT.
+
+use datafusion::arrow::{
+    array::{
+        new_empty_array, ArrayRef, BinaryArray, BooleanArray, StringArray, UInt32Array, UnionArray,
+    },
+    buffer::Buffer,
+    datatypes::{DataType, Field, Schema},
+    error::ArrowError,
+    ipc::writer::{self, DictionaryTracker, EncodedData, IpcDataGenerator, IpcWriteOptions},
+    record_batch::RecordBatch,
+};
+use futures::{stream, Stream, StreamExt};
+use prost::Message;
+use prost_types::Any;
+use std::{pin::Pin, sync::Arc};
+use tonic::{metadata::MetadataValue, Request, Response, Status, Streaming};
+
+use crate::common::{
+    infra::{
+        config::{CONFIG, VERSION},
+        errors::Error,
+        metrics,
+    },
+    meta::{self, sql::Sql as MetaSql, StreamType},
+};
+use crate::handler::grpc::flight_rpc::{
+    flight_service_server::FlightService,
+    sql::{
+        ActionClosePreparedStatementRequest, ActionCreatePreparedStatementRequest,
+        ActionCreatePreparedStatementResult, CommandGetCatalogs, CommandGetDbSchemas,
+        CommandGetSqlInfo, CommandGetTableTypes, CommandGetTables, CommandPreparedStatementQuery,
+        CommandStatementQuery, SqlInfo, TicketStatementQuery,
+    },
+    Action, ActionType, Criteria, Empty, FlightData, FlightDescriptor, FlightEndpoint, FlightInfo,
+    HandshakeRequest, HandshakeResponse, PutResult, SchemaResult, Ticket,
+};
+use crate::service::{db, search as SearchService};
+
+const TYPE_URL_PREFIX: &str = "type.googleapis.com/arrow.flight.protocol.sql.";
+const CREATE_PREPARED_STATEMENT: &str = "CreatePreparedStatement";
+const CLOSE_PREPARED_STATEMENT: &str = "ClosePreparedStatement";
+const TABLE_TYPE: &str = "TABLE";
+
+/// Stream types searched for the table of a statement, in order.
+const STATEMENT_STREAM_TYPES: [StreamType; 4] = [
+    StreamType::Logs,
+    StreamType::Metrics,
+    StreamType::Traces,
+    StreamType::EnrichmentTables,
+];
+
+type FlightStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send + 'static>>;
+
+pub struct FlightSqlServer;
+
+/// Flight SQL messages, sent packed in a `google.protobuf.Any`.
+#[derive(Debug, PartialEq)]
+enum Command {
+    StatementQuery(CommandStatementQuery),
+    TicketStatementQuery(TicketStatementQuery),
+    PreparedStatementQuery(CommandPreparedStatementQuery),
+    CreatePreparedStatementRequest(ActionCreatePreparedStatementRequest),
+    CreatePreparedStatementResult(ActionCreatePreparedStatementResult),
+    ClosePreparedStatementRequest(ActionClosePreparedStatementRequest),
+    GetSqlInfo(CommandGetSqlInfo),
+    GetCatalogs(CommandGetCatalogs),
+    GetDbSchemas(CommandGetDbSchemas),
+    GetTables(CommandGetTables),
+    GetTableTypes(CommandGetTableTypes),
+}
+
+impl Command {
+    fn decode(data: &[u8]) -> Result<Self, Status> {
+        let any = Any::decode(data)
+            .map_err(|e| Status::invalid_argument(format!("invalid command: {e}")))?;
+        let value = any.value.as_slice();
+        let name = any
+            .type_url
+            .strip_prefix(TYPE_URL_PREFIX)
+            .unwrap_or_default();
+        let command = match name {
+            "CommandStatementQuery" => Message::decode(value).map(Command::StatementQuery),
+            "TicketStatementQuery" => Message::decode(value).map(Command::TicketStatementQuery),
+            "CommandPreparedStatementQuery" => {
+                Message::decode(value).map(Command::PreparedStatementQuery)
+            }
+            "ActionCreatePreparedStatementRequest" => {
+                Message::decode(value).map(Command::CreatePreparedStatementRequest)
+            }
+            "ActionCreatePreparedStatementResult" => {
+                Message::decode(value).map(Command::CreatePreparedStatementResult)
+            }
+            "ActionClosePreparedStatementRequest" => {
+                Message::decode(value).map(Command::ClosePreparedStatementRequest)
+            }
+            "CommandGetSqlInfo" => Message::decode(value).map(Command::GetSqlInfo),
+            "CommandGetCatalogs" => Message::decode(value).map(Command::GetCatalogs),
+            "CommandGetDbSchemas" => Message::decode(value).map(Command::GetDbSchemas),
+            "CommandGetTables" => Message::decode(value).map(Command::GetTables),
+            "CommandGetTableTypes" => Message::decode(value).map(Command::GetTableTypes),
+            _ => {
+                return Err(Status::unimplemented(format!(
+                    "unsupported command: {}",
+                    any.type_url
+                )))
+            }
+        };
+        command.map_err(|e| Status::invalid_argument(format!("invalid command {name}: {e}")))
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let (name, value) = match self {
+            Command::StatementQuery(v) => ("CommandStatementQuery", v.encode_to_vec()),
+            Command::TicketStatementQuery(v) => ("TicketStatementQuery", v.encode_to_vec()),
+            Command::PreparedStatementQuery(v) => {
+                ("CommandPreparedStatementQuery", v.encode_to_vec())
+            }
+            Command::CreatePreparedStatementRequest(v) => {
+                ("ActionCreatePreparedStatementRequest", v.encode_to_vec())
+            }
+            Command::CreatePreparedStatementResult(v) => {
+                ("ActionCreatePreparedStatementResult", v.encode_to_vec())
+            }
+            Command::ClosePreparedStatementRequest(v) => {
+                ("ActionClosePreparedStatementRequest", v.encode_to_vec())
+            }
+            Command::GetSqlInfo(v) => ("CommandGetSqlInfo", v.encode_to_vec()),
+            Command::GetCatalogs(v) => ("CommandGetCatalogs", v.encode_to_vec()),
+            Command::GetDbSchemas(v) => ("CommandGetDbSchemas", v.encode_to_vec()),
+            Command::GetTables(v) => ("CommandGetTables", v.encode_to_vec()),
+            Command::GetTableTypes(v) => ("CommandGetTableTypes", v.encode_to_vec()),
+        };
+        Any {
+            type_url: format!("{TYPE_URL_PREFIX}{name}"),
+            value,
+        }
+        .encode_to_vec()
+    }
+}
+
+#[tonic::async_trait]
+impl FlightService for FlightSqlServer {
+    type HandshakeStream = FlightStream<HandshakeResponse>;
+    type ListFlightsStream = FlightStream<FlightInfo>;
+    type DoGetStream = FlightStream<FlightData>;
+    type DoPutStream = FlightStream<PutResult>;
+    type DoExchangeStream = FlightStream<FlightData>;
+    type DoActionStream = FlightStream<crate::handler::grpc::flight_rpc::Result>;
+    type ListActionsStream = FlightStream<ActionType>;
+
+    async fn handshake(
+        &self,
+        req: Request<Streaming<HandshakeRequest>>,
+    ) -> Result<Response<Self::HandshakeStream>, Status> {
+        // the credentials were checked by the auth interceptor, the token is them in base64
+        // and the interceptor takes it as basic auth again
+        let token = req
+            .metadata()
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| {
+                v.strip_prefix("Basic ")
+                    .or_else(|| v.strip_prefix("basic "))
+            })
+            .ok_or_else(|| Status::unauthenticated("basic auth required"))?
+            .to_string();
+        let bearer: MetadataValue<_> = format!("Bearer {token}")
+            .parse()
+            .map_err(|_| Status::unauthenticated("No valid auth token"))?;
+        let output = stream::iter(vec![Ok(HandshakeResponse {
+            protocol_version: 0,
+            payload: token.into_bytes(),
+        })]);
+        let mut resp = Response::new(Box::pin(output) as Self::HandshakeStream);
+        resp.metadata_mut().insert("authorization", bearer);
+        Ok(resp)
+    }
+
+    async fn list_flights(
+        &self,
+        _req: Request<Criteria>,
+    ) -> Result<Response<Self::ListFlightsStream>, Status> {
+        Err(Status::unimplemented("ListFlights is not supported"))
+    }
+
+    async fn get_flight_info(
+        &self,
+        req: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        org_id(&req)?;
+        let descriptor = req.into_inner();
+        let command = Command::decode(&descriptor.cmd)?;
+        let (ticket, schema) = match command {
+            Command::StatementQuery(query) => (
+                Command::TicketStatementQuery(TicketStatementQuery {
+                    statement_handle: query.query.into_bytes(),
+                }),
+                // the schema of the results is only known once the query ran
+                vec![],
+            ),
+            Command::PreparedStatementQuery(_) => (command, vec![]),
+            Command::GetSqlInfo(_) => (command, schema_bytes(&sql_info_batch(&[])?.schema())?),
+            Command::GetCatalogs(_) => (command, schema_bytes(&catalogs_schema())?),
+            Command::GetDbSchemas(_) => (command, schema_bytes(&db_schemas_schema())?),
+            Command::GetTables(CommandGetTables { include_schema, .. }) => {
+                (command, schema_bytes(&tables_schema(include_schema))?)
+            }
+            Command::GetTableTypes(_) => (command, schema_bytes(&table_types_schema())?),
+            _ => return Err(Status::invalid_argument("unexpected command")),
+        };
+        Ok(Response::new(FlightInfo {
+            schema,
+            flight_descriptor: Some(descriptor),
+            endpoint: vec![FlightEndpoint {
+                ticket: Some(Ticket {
+                    ticket: ticket.encode(),
+                }),
+                location: vec![],
+            }],
+            total_records: -1,
+            total_bytes: -1,
+            ordered: false,
+        }))
+    }
+
+    async fn get_schema(
+        &self,
+        _req: Request<FlightDescriptor>,
+    ) -> Result<Response<SchemaResult>, Status> {
+        Err(Status::unimplemented("GetSchema is not supported"))
+    }
+
+    #[tracing::instrument(name = "grpc:flight:do_get", skip_all)]
+    async fn do_get(&self, req: Request<Ticket>) -> Result<Response<Self::DoGetStream>, Status> {
+        let org_id = org_id(&req)?;
+        let batch = match Command::decode(&req.get_ref().ticket)? {
+            Command::TicketStatementQuery(ticket) => {
+                let sql = String::from_utf8(ticket.statement_handle)
+                    .map_err(|_| Status::invalid_argument("invalid statement handle"))?;
+                return statement(&org_id, &sql).await;
+            }
+            Command::PreparedStatementQuery(query) => {
+                let sql = String::from_utf8(query.prepared_statement_handle)
+                    .map_err(|_| Status::invalid_argument("invalid statement handle"))?;
+                return statement(&org_id, &sql).await;
+            }
+            Command::GetSqlInfo(query) => sql_info_batch(&query.info)?,
+            Command::GetCatalogs(_) => catalogs_batch(&org_id)?,
+            Command::GetDbSchemas(query) => db_schemas_batch(&org_id, &query).await?,
+            Command::GetTables(query) => tables_batch(&org_id, &query).await?,
+            Command::GetTableTypes(_) => table_types_batch()?,
+            _ => return Err(Status::invalid_argument("unexpected ticket")),
+        };
+        let data = flight_data(&batch.schema(), &[batch])?;
+        let output = data.into_iter().map(Ok::<_, Status>);
+        Ok(Response::new(
+            Box::pin(stream::iter(output)) as FlightStream<_>
+        ))
+    }
+
+    async fn do_put(
+        &self,
+        _req: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoPutStream>, Status> {
+        Err(Status::unimplemented("streams are read only"))
+    }
+
+    async fn do_exchange(
+        &self,
+        _req: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoExchangeStream>, Status> {
+        Err(Status::unimplemented("DoExchange is not supported"))
+    }
+
+    async fn do_action(
+        &self,
+        req: Request<Action>,
+    ) -> Result<Response<Self::DoActionStream>, Status> {
+        org_id(&req)?;
+        let action = req.into_inner();
+        let body = match (action.r#type.as_str(), Command::decode(&action.body)?) {
+            (CREATE_PREPARED_STATEMENT, Command::CreatePreparedStatementRequest(req)) => {
+                // statements are stateless, the handle is the query itself
+                let result =
+                    Command::CreatePreparedStatementResult(ActionCreatePreparedStatementResult {
+                        prepared_statement_handle: req.query.into_bytes(),
+                        dataset_schema: vec![],
+                        parameter_schema: vec![],
+                    });
+                vec![result.encode()]
+            }
+            (CLOSE_PREPARED_STATEMENT, Command::ClosePreparedStatementRequest(_)) => vec![],
+            _ => {
+                return Err(Status::unimplemented(format!(
+                    "unsupported action: {}",
+                    action.r#type
+                )))
+            }
+        };
+        let output = body
+            .into_iter()
+            .map(|body| Ok::<_, Status>(crate::handler::grpc::flight_rpc::Result { body }));
+        Ok(Response::new(
+            Box::pin(stream::iter(output)) as FlightStream<_>
+        ))
+    }
+
+    async fn list_actions(
+        &self,
+        _req: Request<Empty>,
+    ) -> Result<Response<Self::ListActionsStream>, Status> {
+        let output = [CREATE_PREPARED_STATEMENT, CLOSE_PREPARED_STATEMENT]
+            .into_iter()
+            .map(|name| {
+                Ok::<_, Status>(ActionType {
+                    r#type: name.to_string(),
+                    description: "".to_string(),
+                })
+            });
+        Ok(Response::new(
+            Box::pin(stream::iter(output)) as FlightStream<_>
+        ))
+    }
+}
+
+fn org_id<T>(req: &Request<T>) -> Result<String, Status> {
+    req.metadata()
+        .get(&CONFIG.grpc.org_header_key)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+        .ok_or_else(|| {
+            Status::invalid_argument(format!(
+                "Please specify organization id with header key '{}' ",
+                &CONFIG.grpc.org_header_key
+            ))
+        })
+}
+
+/// Runs the query with the search service, the batches are streamed back as they come.
+async fn statement(org_id: &str, sql: &str) -> Result<Response<FlightStream<FlightData>>, Status> {
+    let start = std::time::Instant::now();
+    let stream_type = statement_stream_type(org_id, sql).await;
+    let req = meta::search::Request {
+        query: meta::search::Query {
+            sql: sql.to_string(),
+            sql_mode: "full".to_string(),
+            size: 0,
+            ..Default::default()
+        },
+        aggs: Default::default(),
+        encoding: meta::search::RequestEncoding::Empty,
+        timeout: 0,
+    };
+    let ret = SearchService::search_arrow(org_id, stream_type, &req).await;
+    let status = if ret.is_ok() { "200" } else { "500" };
+    metrics::GRPC_RESPONSE_TIME
+        .with_label_values(&[
+            "/flight_sql",
+            status,
+            org_id,
+            "",
+            stream_type.to_string().as_str(),
+        ])
+        .observe(start.elapsed().as_secs_f64());
+    metrics::GRPC_INCOMING_REQUESTS
+        .with_label_values(&[
+            "/flight_sql",
+            status,
+            org_id,
+            "",
+            stream_type.to_string().as_str(),
+        ])
+        .inc();
+
+    let mut batches = ret.map_err(search_error)?;
+    // the schema message goes first, it is the one of the batches
+    let first = batches.recv().await.transpose().map_err(search_error)?;
+    let schema = match &first {
+        Some(batch) => batch.schema(),
+        None => Arc::new(Schema::empty()),
+    };
+    let options = IpcWriteOptions::default();
+    let head = to_flight_data(IpcDataGenerator::default().schema_to_bytes(&schema, &options));
+    let tail = stream::unfold(
+        (first, batches, DictionaryTracker::new(false)),
+        |(mut first, mut batches, mut tracker)| async move {
+            let batch = match first.take() {
+                Some(batch) => Ok(batch),
+                None => batches.recv().await?.map_err(search_error),
+            };
+            let data = batch.and_then(|batch| encode_batch(&batch, &mut tracker));
+            Some((data, (first, batches, tracker)))
+        },
+    )
+    .flat_map(|data| {
+        stream::iter(match data {
+            Ok(data) => data.into_iter().map(Ok).collect(),
+            Err(e) => vec![Err(e)],
+        })
+    });
+    let output = stream::once(async { Ok(head) }).chain(tail);
+    Ok(Response::new(Box::pin(output) as FlightStream<_>))
+}
+
+fn search_error(e: Error) -> Status {
+    log::error!("flight sql query error: {}", e);
+    Status::internal(e.to_string())
+}
+
+async fn statement_stream_type(org_id: &str, sql: &str) -> StreamType {
+    let Ok(meta) = MetaSql::new(sql) else {
+        return StreamType::Logs;
+    };
+    for stream_type in STATEMENT_STREAM_TYPES {
+        if let Ok(schema) = db::schema::get(org_id, &meta.source, stream_type).await {
+            if schema != Schema::empty() {
+                return stream_type;
+            }
+        }
+    }
+    StreamType::Logs
+}
+
+fn catalogs_schema() -> Schema {
+    Schema::new(vec![Field::new("catalog_name", DataType::Utf8, false)])
+}
+
+fn db_schemas_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("catalog_name", DataType::Utf8, true),
+        Field::new("db_schema_name", DataType::Utf8, false),
+    ])
+}
+
+fn tables_schema(include_schema: bool) -> Schema {
+    let mut fields = vec![
+        Field::new("catalog_name", DataType::Utf8, true),
+        Field::new("db_schema_name", DataType::Utf8, true),
+        Field::new("table_name", DataType::Utf8, false),
+        Field::new("table_type", DataType::Utf8, false),
+    ];
+    if include_schema {
+        fields.push(Field::new("table_schema", DataType::Binary, false));
+    }
+    Schema::new(fields)
+}
+
+fn table_types_schema() -> Schema {
+    Schema::new(vec![Field::new("table_type", DataType::Utf8, false)])
+}
+
+fn catalogs_batch(org_id: &str) -> Result<RecordBatch, Status> {
+    RecordBatch::try_new(
+        Arc::new(catalogs_schema()),
+        vec![Arc::new(StringArray::from(vec![org_id]))],
+    )
+    .map_err(arrow_error)
+}
+
+async fn db_schemas_batch(
+    org_id: &str,
+    query: &CommandGetDbSchemas,
+) -> Result<RecordBatch, Status> {
+    let mut schemas = Vec::new();
+    if query.catalog.as_deref().unwrap_or(org_id) == org_id {
+        let streams = db::schema::list(org_id, None, false)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        schemas = streams
+            .iter()
+            .map(|s| s.stream_type.to_string())
+            .filter(|v| match query.db_schema_filter_pattern.as_deref() {
+                Some(pattern) => like(pattern, v),
+                None => true,
+            })
+            .collect();
+        schemas.sort();
+        schemas.dedup();
+    }
+    RecordBatch::try_new(
+        Arc::new(db_schemas_schema()),
+        vec![
+            Arc::new(StringArray::from(vec![org_id; schemas.len()])),
+            Arc::new(StringArray::from(schemas)),
+        ],
+    )
+    .map_err(arrow_error)
+}
+
+async fn tables_batch(org_id: &str, query: &CommandGetTables) -> Result<RecordBatch, Status> {
+    let mut streams = Vec::new();
+    let type_matches =
+        query.table_types.is_empty() || query.table_types.iter().any(|v| v == TABLE_TYPE);
+    if type_matches && query.catalog.as_deref().unwrap_or(org_id) == org_id {
+        streams = db::schema::list(org_id, None, query.include_schema)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        streams.retain(|s| {
+            let schema_matches = match query.db_schema_filter_pattern.as_deref() {
+                Some(pattern) => like(pattern, &s.stream_type.to_string()),
+                None => true,
+            };
+            let name_matches = match query.table_name_filter_pattern.as_deref() {
+                Some(pattern) => like(pattern, &s.stream_name),
+                None => true,
+            };
+            schema_matches && name_matches
+        });
+        streams.sort_by(|a, b| {
+            (a.stream_type.to_string(), &a.stream_name)
+                .cmp(&(b.stream_type.to_string(), &b.stream_name))
+        });
+    }
+
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from(vec![org_id; streams.len()])),
+        Arc::new(StringArray::from(
+            streams
+                .iter()
+                .map(|s| s.stream_type.to_string())
+                .collect::<Vec<_>>(),
+        )),
+        Arc::new(StringArray::from(
+            streams
+                .iter()
+                .map(|s| s.stream_name.as_str())
+                .collect::<Vec<_>>(),
+        )),
+        Arc::new(StringArray::from(vec![TABLE_TYPE; streams.len()])),
+    ];
+    if query.include_schema {
+        let schemas = streams
+            .iter()
+            .map(|s| schema_bytes(&s.schema))
+            .collect::<Result<Vec<_>, _>>()?;
+        columns.push(Arc::new(BinaryArray::from_iter_values(schemas)));
+    }
+    RecordBatch::try_new(Arc::new(tables_schema(query.include_schema)), columns)
+        .map_err(arrow_error)
+}
+
+fn table_types_batch() -> Result<RecordBatch, Status> {
+    RecordBatch::try_new(
+        Arc::new(table_types_schema()),
+        vec![Arc::new(StringArray::from(vec![TABLE_TYPE]))],
+    )
+    .map_err(arrow_error)
+}
+
+enum SqlInfoValue {
+    String(&'static str),
+    Bool(bool),
+}
+
+fn sql_info() -> Vec<(SqlInfo, SqlInfoValue)> {
+    vec![
+        (
+            SqlInfo::FlightSqlServerName,
+            SqlInfoValue::String("OpenObserve"),
+        ),
+        (
+            SqlInfo::FlightSqlServerVersion,
+            SqlInfoValue::String(VERSION),
+        ),
+        (
+            SqlInfo::FlightSqlServerArrowVersion,
+            SqlInfoValue::String("46"),
+        ),
+        (SqlInfo::FlightSqlServerReadOnly, SqlInfoValue::Bool(true)),
+        (SqlInfo::FlightSqlServerSql, SqlInfoValue::Bool(true)),
+        (SqlInfo::FlightSqlServerSubstrait, SqlInfoValue::Bool(false)),
+        (SqlInfo::FlightSqlServerCancel, SqlInfoValue::Bool(false)),
+        (SqlInfo::SqlDdlCatalog, SqlInfoValue::Bool(false)),
+        (SqlInfo::SqlDdlSchema, SqlInfoValue::Bool(false)),
+        (SqlInfo::SqlDdlTable, SqlInfoValue::Bool(false)),
+        (SqlInfo::SqlIdentifierQuoteChar, SqlInfoValue::String("\"")),
+    ]
+}
+
+/// The values are a dense union of all the SqlInfo value types, only the string and
+/// boolean ones are used.
+fn sql_info_batch(info: &[u32]) -> Result<RecordBatch, Status> {
+    let mut names = Vec::new();
+    let mut type_ids = Vec::new();
+    let mut offsets = Vec::new();
+    let mut strings = Vec::new();
+    let mut bools = Vec::new();
+    for (name, value) in sql_info() {
+        if !info.is_empty() && !info.contains(&(name as u32)) {
+            continue;
+        }
+        names.push(name as u32);
+        match value {
+            SqlInfoValue::String(v) => {
+                type_ids.push(0_i8);
+                offsets.push(strings.len() as i32);
+                strings.push(v);
+            }
+            SqlInfoValue::Bool(v) => {
+                type_ids.push(1_i8);
+                offsets.push(bools.len() as i32);
+                bools.push(v);
+            }
+        }
+    }
+
+    let string_list = DataType::List(Arc::new(Field::new("item", DataType::Utf8, true)));
+    let int32_list = DataType::List(Arc::new(Field::new("item", DataType::Int32, true)));
+    let int32_to_int32_list_map = DataType::Map(
+        Arc::new(Field::new(
+            "entries",
+            DataType::Struct(
+                vec![
+                    Field::new("keys", DataType::Int32, false),
+                    Field::new("values", int32_list, true),
+                ]
+                .into(),
+            ),
+            false,
+        )),
+        false,
+    );
+    let children: Vec<(Field, ArrayRef)> = vec![
+        (
+            Field::new("string_value", DataType::Utf8, false),
+            Arc::new(StringArray::from(strings)),
+        ),
+        (
+            Field::new("bool_value", DataType::Boolean, false),
+            Arc::new(BooleanArray::from(bools)),
+        ),
+        (
+            Field::new("bigint_value", DataType::Int64, false),
+            new_empty_array(&DataType::Int64),
+        ),
+        (
+            Field::new("int32_bitmask", DataType::Int32, false),
+            new_empty_array(&DataType::Int32),
+        ),
+        (
+            Field::new("string_list", string_list.clone(), true),
+            new_empty_array(&string_list),
+        ),
+        (
+            Field::new(
+                "int32_to_int32_list_map",
+                int32_to_int32_list_map.clone(),
+                true,
+            ),
+            new_empty_array(&int32_to_int32_list_map),
+        ),
+    ];
+    let values = UnionArray::try_new(
+        &[0, 1, 2, 3, 4, 5],
+        Buffer::from_slice_ref(&type_ids),
+        Some(Buffer::from_slice_ref(&offsets)),
+        children,
+    )
+    .map_err(arrow_error)?;
+    let schema = Schema::new(vec![
+        Field::new("info_name", DataType::UInt32, false),
+        Field::new("value", values.data_type().clone(), false),
+    ]);
+    RecordBatch::try_new(
+        Arc::new(schema),
+        vec![Arc::new(UInt32Array::from(names)), Arc::new(values)],
+    )
+    .map_err(arrow_error)
+}
+
+/// Encodes the schema as an IPC message, the form used by `FlightInfo` and the tables
+/// metadata.
+fn schema_bytes(schema: &Schema) -> Result<Vec<u8>, Status> {
+    let options = IpcWriteOptions::default();
+    let data = IpcDataGenerator::default().schema_to_bytes(schema, &options);
+    let mut buf = Vec::new();
+    writer::write_message(&mut buf, data, &options).map_err(arrow_error)?;
+    Ok(buf)
+}
+
+fn flight_data(schema: &Schema, batches: &[RecordBatch]) -> Result<Vec<FlightData>, Status> {
+    let options = IpcWriteOptions::default();
+    let mut tracker = DictionaryTracker::new(false);
+    let mut data = vec![to_flight_data(
+        IpcDataGenerator::default().schema_to_bytes(schema, &options),
+    )];
+    for batch in batches {
+        data.extend(encode_batch(batch, &mut tracker)?);
+    }
+    Ok(data)
+}
+
+/// Encodes a batch, after the dictionaries the tracker hasn't sent yet.
+fn encode_batch(
+    batch: &RecordBatch,
+    tracker: &mut DictionaryTracker,
+) -> Result<Vec<FlightData>, Status> {
+    let (dictionaries, batch) = IpcDataGenerator::default()
+        .encoded_batch(batch, tracker, &IpcWriteOptions::default())
+        .map_err(arrow_error)?;
+    let mut data = dictionaries
+        .into_iter()
+        .map(to_flight_data)
+        .collect::<Vec<_>>();
+    data.push(to_flight_data(batch));
+    Ok(data)
+}
+
+fn to_flight_data(data: EncodedData) -> FlightData {
+    FlightData {
+        flight_descriptor: None,
+        data_header: data.ipc_message,
+        app_metadata: vec![],
+        data_body: data.arrow_data,
+    }
+}
+
+/// Matches a SQL LIKE pattern, `%` is any string and `_` any character.
+fn like(pattern: &str, value: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let value = value.chars().collect::<Vec<_>>();
+    // positions in the value the pattern prefix can end at
+    let mut ends = vec![false; value.len() + 1];
+    ends[0] = true;
+    for p in pattern {
+        let mut next = vec![false; value.len() + 1];
+        for i in 0..=value.len() {
+            if !ends[i] {
+                continue;
+            }
+            match p {
+                '%' => next[i..].iter_mut().for_each(|v| *v = true),
+                '_' if i < value.len() => next[i + 1] = true,
+                c if i < value.len() && value[i] == c => next[i + 1] = true,
+                _ => {}
+            }
+        }
+        ends = next;
+    }
+    ends[value.len()]
+}
+
+fn arrow_error(e: ArrowError) -> Status {
+    Status::internal(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::ipc::reader;
+
+    #[test]
+    fn test_command() {
+        let command = Command::StatementQuery(CommandStatementQuery {
+            query: "select * from app".to_string(),
+            transaction_id: None,
+        });
+        let data = command.encode();
+        let any = Any::decode(data.as_slice()).unwrap();
+        assert_eq!(
+            any.type_url,
+            "type.googleapis.com/arrow.flight.protocol.sql.CommandStatementQuery"
+        );
+        assert_eq!(Command::decode(&data).unwrap(), command);
+
+        let unknown = Any {
+            type_url: format!("{TYPE_URL_PREFIX}CommandStatementUpdate"),
+            value: vec![],
+        };
+        assert_eq!(
+            Command::decode(&unknown.encode_to_vec())
+                .unwrap_err()
+                .code(),
+            tonic::Code::Unimplemented
+        );
+    }
+
+    #[test]
+    fn test_like() {
+        assert!(like("%", ""));
+        assert!(like("%", "logs"));
+        assert!(like("lo_s", "logs"));
+        assert!(like("k8s%", "k8s_events"));
+        assert!(like("%_events", "k8s_events"));
+        assert!(!like("k8s", "k8s_events"));
+        assert!(!like("_", ""));
+    }
+
+    #[test]
+    fn test_sql_info_batch() {
+        let batch = sql_info_batch(&[]).unwrap();
+        assert_eq!(batch.num_rows(), sql_info().len());
+        let batch = sql_info_batch(&[
+            SqlInfo::FlightSqlServerName as u32,
+            SqlInfo::FlightSqlServerReadOnly as u32,
+        ])
+        .unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        let values = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<UnionArray>()
+            .unwrap();
+        assert_eq!(values.type_id(0), 0);
+        assert_eq!(values.type_id(1), 1);
+    }
+
+    #[test]
+    fn test_flight_data() {
+        let batch = table_types_batch().unwrap();
+        let data = flight_data(&batch.schema(), &[batch.clone()]).unwrap();
+        assert_eq!(data.len(), 2);
+
+        // the IPC message of the schema is the flight data header with its prefix
+        let schema = schema_bytes(&batch.schema()).unwrap();
+        let mut reader = reader::StreamReader::try_new(schema.as_slice(), None).unwrap();
+        assert_eq!(reader.schema(), batch.schema());
+        assert!(reader.next().is_none());
+    }
+}
@@ -13,7 +13,8 @@
 // limitations under the License.
 
 use crate::common::infra::config::CONFIG;
-use crate::common::meta::ingestion::INGESTION_EP;
+use crate::common::meta::ingestion::{HecResponse, INGESTION_EP};
+use crate::common::meta::middleware_data::HecOrg;
 use crate::common::meta::user::UserRole;
 use crate::common::utils::{
     auth::{get_hash, is_root_user},
@@ -22,10 +23,10 @@ use crate::common::utils::{
 use crate::service::{db, users};
 use actix_web::{
     dev::ServiceRequest,
-    error::{ErrorForbidden, ErrorUnauthorized},
+    error::{ErrorForbidden, ErrorUnauthorized, InternalError},
     http::header,
-    http::Method,
-    web, Error,
+    http::{Method, StatusCode},
+    web, Error, HttpMessage, HttpResponse,
 };
 use actix_web_httpauth::extractors::basic::BasicAuth;
 
@@ -275,6 +276,39 @@ pub async fn validator_rum(
     }
 }
 
+/// Validates Splunk HEC tokens, sent as `Authorization: Splunk <token>` or as the password
+/// of basic auth. The token is the ingestion passcode of a user and selects its organization.
+pub async fn validator_splunk(
+    req: ServiceRequest,
+    credentials: Option<BasicAuth>,
+) -> Result<ServiceRequest, (Error, ServiceRequest)> {
+    let token = match credentials {
+        Some(credentials) => credentials.password().map(|v| v.trim().to_string()),
+        None => req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Splunk "))
+            .map(|v| v.trim().to_string()),
+    };
+    let Some(token) = token.filter(|v| !v.is_empty()) else {
+        return Err((hec_error(HecResponse::token_required()), req));
+    };
+    match users::get_org_by_ingestion_token(&token) {
+        Some(org_id) => {
+            req.extensions_mut().insert(HecOrg(org_id));
+            Ok(req)
+        }
+        None => Err((hec_error(HecResponse::invalid_token()), req)),
+    }
+}
+
+fn hec_error(resp: HecResponse) -> Error {
+    let status =
+        StatusCode::from_u16(resp.status_code()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    InternalError::from_response(resp.text.clone(), HttpResponse::build(status).json(resp)).into()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -13,6 +13,7 @@
 // limitations under the License.
 
 pub mod destinations;
+pub mod silences;
 pub mod templates;
 
 use actix_web::{delete, get, http, post, put, web, HttpRequest, HttpResponse, Responder};
@@ -227,6 +228,29 @@ async fn delete_alert(
     alerts::delete_alert(org_id, stream_name, stream_type.unwrap(), name).await
 }
 
+/** GetAlertHistory */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Alerts",
+    operation_id = "GetAlertHistory",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("stream_name" = String, Path, description = "Stream name"),
+        ("alert_name" = String, Path, description = "Alert name"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = TriggerHistoryList),
+    )
+)]
+#[get("/{org_id}/{stream_name}/alerts/{alert_name}/history")]
+async fn get_alert_history(path: web::Path<(String, String, String)>) -> impl Responder {
+    let (org_id, stream_name, name) = path.into_inner();
+    alerts::get_alert_history(org_id, stream_name, name).await
+}
+
 /** TriggerAlert */
 #[utoipa::path(
     context_path = "/api",
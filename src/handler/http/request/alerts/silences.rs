@@ -0,0 +1,160 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use std::io::Error;
+
+use crate::common::meta::alert::{MaintenanceWindow, Silence};
+use crate::service::alerts::silences;
+
+/** CreateSilence */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Alerts",
+    operation_id = "CreateSilence",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+      ),
+    request_body(content = Silence, description = "Silence data", content_type = "application/json"),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = Silence),
+        (status = 400, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/alerts/silences")]
+pub async fn save_silence(
+    path: web::Path<String>,
+    silence: web::Json<Silence>,
+) -> Result<HttpResponse, Error> {
+    let org_id = path.into_inner();
+    silences::save_silence(org_id, silence.into_inner()).await
+}
+
+/** ListSilences */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Alerts",
+    operation_id = "ListSilences",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+      ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = SilenceList),
+    )
+)]
+#[get("/{org_id}/alerts/silences")]
+async fn list_silences(path: web::Path<String>) -> impl Responder {
+    let org_id = path.into_inner();
+    silences::list_silences(org_id).await
+}
+
+/** DeleteSilence */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Alerts",
+    operation_id = "DeleteSilence",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("silence_id" = String, Path, description = "Silence id"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse),
+        (status = 404, description="NotFound", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[delete("/{org_id}/alerts/silences/{silence_id}")]
+async fn delete_silence(path: web::Path<(String, String)>) -> impl Responder {
+    let (org_id, id) = path.into_inner();
+    silences::delete_silence(org_id, id).await
+}
+
+/** CreateMaintenanceWindow */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Alerts",
+    operation_id = "CreateMaintenanceWindow",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("window_name" = String, Path, description = "Maintenance window name"),
+      ),
+    request_body(content = MaintenanceWindow, description = "Maintenance window data", content_type = "application/json"),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse),
+        (status = 400, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/alerts/maintenance_windows/{window_name}")]
+pub async fn save_maintenance_window(
+    path: web::Path<(String, String)>,
+    window: web::Json<MaintenanceWindow>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, name) = path.into_inner();
+    silences::save_maintenance_window(org_id, name, window.into_inner()).await
+}
+
+/** ListMaintenanceWindows */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Alerts",
+    operation_id = "ListMaintenanceWindows",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+      ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = MaintenanceWindowList),
+    )
+)]
+#[get("/{org_id}/alerts/maintenance_windows")]
+async fn list_maintenance_windows(path: web::Path<String>) -> impl Responder {
+    let org_id = path.into_inner();
+    silences::list_maintenance_windows(org_id).await
+}
+
+/** DeleteMaintenanceWindow */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Alerts",
+    operation_id = "DeleteMaintenanceWindow",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("window_name" = String, Path, description = "Maintenance window name"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse),
+        (status = 404, description="NotFound", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[delete("/{org_id}/alerts/maintenance_windows/{window_name}")]
+async fn delete_maintenance_window(path: web::Path<(String, String)>) -> impl Responder {
+    let (org_id, name) = path.into_inner();
+    silences::delete_maintenance_window(org_id, name).await
+}
@@ -0,0 +1,167 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+use std::io::Error;
+
+use crate::{common::meta::fluent::FluentRoute, service::fluent_routes};
+
+/** CreateFluentRoute */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Fluent Routes",
+    operation_id = "CreateFluentRoute",
+    security(
+        ("Authorization" = [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    request_body(
+        content = FluentRoute,
+        description = "FluentRoute details",
+    ),
+    responses(
+        (status = StatusCode::CREATED, description = "Route created", body = FluentRoute),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid route", body = HttpResponse),
+        (status = StatusCode::INTERNAL_SERVER_ERROR, description = "Internal Server Error", body = HttpResponse),
+    ),
+)]
+#[post("/{org_id}/fluent-routes")]
+pub async fn create_route(
+    path: web::Path<String>,
+    details: web::Json<FluentRoute>,
+) -> Result<HttpResponse, Error> {
+    let org_id = path.into_inner();
+    fluent_routes::create_route(&org_id, details.into_inner()).await
+}
+
+/// UpdateFluentRoute
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Fluent Routes",
+    operation_id = "UpdateFluentRoute",
+    security(
+        ("Authorization" = [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("id" = String, Path, description = "Route ID"),
+    ),
+    request_body(
+        content = FluentRoute,
+        description = "FluentRoute details",
+    ),
+    responses(
+        (status = StatusCode::OK, description = "FluentRoute updated", body = FluentRoute),
+        (status = StatusCode::NOT_FOUND, description = "FluentRoute not found", body = HttpResponse),
+        (status = StatusCode::INTERNAL_SERVER_ERROR, description = "Failed to update the FluentRoute", body = HttpResponse),
+    ),
+)]
+#[put("/{org_id}/fluent-routes/{id}")]
+async fn update_route(
+    path: web::Path<(String, String)>,
+    details: web::Json<FluentRoute>,
+) -> impl Responder {
+    let (org_id, id) = path.into_inner();
+    fluent_routes::update_route(&org_id, &id, &mut details.into_inner()).await
+}
+
+/// ListFluentRoutes
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Fluent Routes",
+    operation_id = "ListFluentRoutes",
+    security(
+        ("Authorization" = [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    responses(
+        (status = StatusCode::OK, body = FluentRoutes),
+    ),
+)]
+#[get("/{org_id}/fluent-routes")]
+async fn list_routes(path: web::Path<String>) -> impl Responder {
+    let org_id = path.into_inner();
+    fluent_routes::list_routes(&org_id).await
+}
+
+/// DeleteFluentRoute
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Fluent Routes",
+    operation_id = "DeleteFluentRoute",
+    security(
+        ("Authorization" = [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("id" = String, Path, description = "FluentRoute Id"),
+    ),
+    responses(
+        (status = StatusCode::OK, description = "Route deleted", body = HttpResponse),
+        (status = StatusCode::NOT_FOUND, description = "Route not found", body = HttpResponse),
+    ),
+)]
+#[delete("/{org_id}/fluent-routes/{id}")]
+async fn delete_route(path: web::Path<(String, String)>) -> impl Responder {
+    let (org_id, id) = path.into_inner();
+    fluent_routes::delete_route(&org_id, &id).await
+}
+
+/// GetFluentSharedKey
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Fluent Routes",
+    operation_id = "GetFluentSharedKey",
+    security(
+        ("Authorization" = [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    responses(
+        (status = StatusCode::OK, body = FluentSharedKey),
+        (status = StatusCode::BAD_REQUEST, description = "No shared key generated yet", body = HttpResponse),
+    ),
+)]
+#[get("/{org_id}/fluent-key")]
+async fn get_shared_key(path: web::Path<String>) -> impl Responder {
+    let org_id = path.into_inner();
+    fluent_routes::get_shared_key(&org_id).await
+}
+
+/// RotateFluentSharedKey
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Fluent Routes",
+    operation_id = "RotateFluentSharedKey",
+    security(
+        ("Authorization" = [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    responses(
+        (status = StatusCode::OK, description = "New shared key", body = FluentSharedKey),
+        (status = StatusCode::INTERNAL_SERVER_ERROR, description = "Internal Server Error", body = HttpResponse),
+    ),
+)]
+#[post("/{org_id}/fluent-key")]
+async fn rotate_shared_key(path: web::Path<String>) -> impl Responder {
+    let org_id = path.into_inner();
+    fluent_routes::rotate_shared_key(&org_id).await
+}
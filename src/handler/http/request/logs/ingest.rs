@@ -17,7 +17,8 @@ use std::io::Error;
 
 use crate::common::infra::config::CONFIG;
 use crate::common::meta::http::HttpResponse as MetaHttpResponse;
-use crate::common::meta::ingestion::IngestionRequest;
+use crate::common::meta::ingestion::{HecAckRequest, HecRawQuery, HecResponse, IngestionRequest};
+use crate::common::meta::middleware_data::HecOrg;
 use crate::handler::http::request::{CONTENT_TYPE_JSON, CONTENT_TYPE_PROTO};
 use crate::service::logs::otlp_http::{logs_json_handler, logs_proto_handler};
 use crate::{
@@ -378,3 +379,166 @@ pub async fn otlp_logs_write(
         )))
     }
 }
+
+/** LokiPush */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Logs",
+    operation_id = "LokiPush",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    request_body(content = String, description = "Loki PushRequest, snappy compressed protobuf or JSON", content_type = "application/x-protobuf"),
+    responses(
+        (status = 204, description="Success"),
+        (status = 400, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/loki/api/v1/push")]
+pub async fn loki_push(
+    org_id: web::Path<String>,
+    thread_id: web::Data<usize>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let org_id = org_id.into_inner();
+    let content_type = req
+        .headers()
+        .get("Content-Type")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or(CONTENT_TYPE_PROTO);
+    let in_stream_name = req
+        .headers()
+        .get(&CONFIG.grpc.stream_header_key)
+        .and_then(|header| header.to_str().ok());
+    let ret = if content_type.starts_with(CONTENT_TYPE_JSON) {
+        logs::loki::ingest_json(&org_id, body, **thread_id, in_stream_name).await
+    } else {
+        logs::loki::ingest_proto(&org_id, body, **thread_id, in_stream_name).await
+    };
+    Ok(match ret {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            log::error!("Error processing loki push request: {:?}", e);
+            HttpResponse::BadRequest().json(MetaHttpResponse::error(
+                http::StatusCode::BAD_REQUEST.into(),
+                e.to_string(),
+            ))
+        }
+    })
+}
+
+/** HecEvent Splunk HEC compatible ingestion API */
+#[utoipa::path(
+    context_path = "/services/collector",
+    tag = "Logs",
+    operation_id = "HecEvent",
+    security(
+        ("Authorization"= [])
+    ),
+    request_body(content = String, description = "One or more HEC events (JSON)", content_type = "application/json"),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HecResponse, example = json!({"text": "Success", "code": 0})),
+        (status = 400, description="Failure", content_type = "application/json", body = HecResponse),
+        (status = 401, description="Unauthorized", content_type = "application/json", body = HecResponse),
+        (status = 403, description="Forbidden", content_type = "application/json", body = HecResponse),
+    )
+)]
+#[post("/event")]
+pub async fn hec_event(
+    org: web::ReqData<HecOrg>,
+    thread_id: web::Data<usize>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let channel = hec_channel(&req);
+    let resp = logs::hec::ingest_events(&org.0, body, **thread_id, channel.as_deref()).await;
+    Ok(hec_response(resp))
+}
+
+/** HecRaw Splunk HEC compatible ingestion API */
+#[utoipa::path(
+    context_path = "/services/collector",
+    tag = "Logs",
+    operation_id = "HecRaw",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("channel" = Option<String>, Query, description = "Channel id"),
+        ("host" = Option<String>, Query, description = "Host of the events"),
+        ("source" = Option<String>, Query, description = "Source of the events"),
+        ("sourcetype" = Option<String>, Query, description = "Source type of the events"),
+        ("index" = Option<String>, Query, description = "Stream name"),
+    ),
+    request_body(content = String, description = "Raw events, one per line", content_type = "text/plain"),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HecResponse, example = json!({"text": "Success", "code": 0})),
+        (status = 400, description="Failure", content_type = "application/json", body = HecResponse),
+        (status = 401, description="Unauthorized", content_type = "application/json", body = HecResponse),
+        (status = 403, description="Forbidden", content_type = "application/json", body = HecResponse),
+    )
+)]
+#[post("/raw")]
+pub async fn hec_raw(
+    org: web::ReqData<HecOrg>,
+    thread_id: web::Data<usize>,
+    req: HttpRequest,
+    query: web::Query<HecRawQuery>,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let mut query = query.into_inner();
+    query.channel = hec_channel(&req);
+    let resp = logs::hec::ingest_raw(&org.0, body, **thread_id, &query).await;
+    Ok(hec_response(resp))
+}
+
+/** HecAck Splunk HEC compatible acknowledgement API */
+#[utoipa::path(
+    context_path = "/services/collector",
+    tag = "Logs",
+    operation_id = "HecAck",
+    security(
+        ("Authorization"= [])
+    ),
+    request_body(content = HecAckRequest, description = "Ack ids to query", content_type = "application/json"),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HecAckResponse, example = json!({"acks": {"0": true, "1": true}})),
+        (status = 400, description="Failure", content_type = "application/json", body = HecResponse),
+    )
+)]
+#[post("/ack")]
+pub async fn hec_ack(
+    org: web::ReqData<HecOrg>,
+    req: HttpRequest,
+    body: web::Json<HecAckRequest>,
+) -> Result<HttpResponse, Error> {
+    let channel = hec_channel(&req);
+    Ok(match logs::hec::ack(&org.0, channel.as_deref(), &body) {
+        Ok(v) => HttpResponse::Ok().json(v),
+        Err(e) => hec_response(e),
+    })
+}
+
+/// Returns the channel of a HEC request, sent as header or query parameter.
+fn hec_channel(req: &HttpRequest) -> Option<String> {
+    if let Some(channel) = req
+        .headers()
+        .get("X-Splunk-Request-Channel")
+        .and_then(|v| v.to_str().ok())
+    {
+        return Some(channel.to_string());
+    }
+    web::Query::<HecRawQuery>::from_query(req.query_string())
+        .ok()
+        .and_then(|query| query.into_inner().channel)
+}
+
+fn hec_response(resp: HecResponse) -> HttpResponse {
+    let status = http::StatusCode::from_u16(resp.status_code())
+        .unwrap_or(http::StatusCode::INTERNAL_SERVER_ERROR);
+    HttpResponse::build(status).json(resp)
+}
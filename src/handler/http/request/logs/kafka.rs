@@ -0,0 +1,118 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use std::io::Error;
+
+use crate::common::meta::kafka::KafkaSource;
+use crate::service::logs::kafka;
+
+/** SaveKafkaSource */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Logs",
+    operation_id = "SaveKafkaSource",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("source_name" = String, Path, description = "Kafka source name"),
+      ),
+    request_body(content = KafkaSource, description = "Kafka source data", content_type = "application/json", example = json!({
+        "brokers": ["kafka:9092"],
+        "topics": ["app-logs"],
+        "stream_name": "app",
+        "format": "json",
+        "start_from": "latest"
+    })),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse),
+        (status = 400, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/kafka_sources/{source_name}")]
+pub async fn save_source(
+    path: web::Path<(String, String)>,
+    source: web::Json<KafkaSource>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, name) = path.into_inner();
+    kafka::save_source(org_id, name, source.into_inner()).await
+}
+
+/** GetKafkaSource */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Logs",
+    operation_id = "GetKafkaSource",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("source_name" = String, Path, description = "Kafka source name"),
+      ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = KafkaSource),
+        (status = 404, description="NotFound", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/kafka_sources/{source_name}")]
+async fn get_source(path: web::Path<(String, String)>) -> impl Responder {
+    let (org_id, name) = path.into_inner();
+    kafka::get_source(org_id, name).await
+}
+
+/** ListKafkaSources */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Logs",
+    operation_id = "ListKafkaSources",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+      ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = KafkaSourceList),
+    )
+)]
+#[get("/{org_id}/kafka_sources")]
+async fn list_sources(path: web::Path<String>) -> impl Responder {
+    let org_id = path.into_inner();
+    kafka::list_sources(org_id).await
+}
+
+/** DeleteKafkaSource */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Logs",
+    operation_id = "DeleteKafkaSource",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("source_name" = String, Path, description = "Kafka source name"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse),
+        (status = 404, description="NotFound", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[delete("/{org_id}/kafka_sources/{source_name}")]
+async fn delete_source(path: web::Path<(String, String)>) -> impl Responder {
+    let (org_id, name) = path.into_inner();
+    kafka::delete_source(org_id, name).await
+}
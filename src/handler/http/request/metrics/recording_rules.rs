@@ -0,0 +1,113 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use std::io::Error;
+
+use crate::common::meta::prom::RecordingRule;
+use crate::service::metrics::recording_rules;
+
+/** CreateRecordingRule */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "CreateRecordingRule",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("rule_name" = String, Path, description = "Recording rule name"),
+      ),
+    request_body(content = RecordingRule, description = "Recording rule data", content_type = "application/json"),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse),
+        (status = 400, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/recording_rules/{rule_name}")]
+pub async fn save_rule(
+    path: web::Path<(String, String)>,
+    rule: web::Json<RecordingRule>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, name) = path.into_inner();
+    recording_rules::save_rule(org_id, name, rule.into_inner()).await
+}
+
+/** GetRecordingRule */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "GetRecordingRule",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("rule_name" = String, Path, description = "Recording rule name"),
+      ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = RecordingRule),
+        (status = 404, description="NotFound", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/recording_rules/{rule_name}")]
+async fn get_rule(path: web::Path<(String, String)>) -> impl Responder {
+    let (org_id, name) = path.into_inner();
+    recording_rules::get_rule(org_id, name).await
+}
+
+/** ListRecordingRules */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "ListRecordingRules",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+      ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = RecordingRuleList),
+    )
+)]
+#[get("/{org_id}/recording_rules")]
+async fn list_rules(path: web::Path<String>) -> impl Responder {
+    let org_id = path.into_inner();
+    recording_rules::list_rules(org_id).await
+}
+
+/** DeleteRecordingRule */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "DeleteRecordingRule",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("rule_name" = String, Path, description = "Recording rule name"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse),
+        (status = 404, description="NotFound", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[delete("/{org_id}/recording_rules/{rule_name}")]
+async fn delete_rule(path: web::Path<(String, String)>) -> impl Responder {
+    let (org_id, name) = path.into_inner();
+    recording_rules::delete_rule(org_id, name).await
+}
@@ -15,6 +15,7 @@
 pub mod alerts;
 pub mod dashboards;
 pub mod enrichment_table;
+pub mod fluent;
 pub mod functions;
 pub mod kv;
 pub mod logs;
@@ -67,6 +67,56 @@ pub async fn remote_write(
     }
 }
 
+/** prometheus remote-read endpoint for metrics */
+// refer: https://prometheus.io/docs/prometheus/latest/querying/remote_read_api/
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "PrometheusRemoteRead",
+        security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    request_body(content = String, description = "prometheus ReadRequest", content_type = "application/x-protobuf"),
+    responses(
+        (status = 200, description="Success", content_type = "application/x-protobuf", body = String),
+        (status = 400, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/prometheus/api/v1/read")]
+pub async fn remote_read(
+    org_id: web::Path<String>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let org_id = org_id.into_inner();
+    let content_type = req
+        .headers()
+        .get("Content-Type")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    // only the media type, without parameters such as `; proto=...`
+    let media_type = content_type.split(';').next().unwrap_or_default().trim();
+    if !media_type.eq_ignore_ascii_case("application/x-protobuf") {
+        return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+            http::StatusCode::BAD_REQUEST.into(),
+            "Bad Request".to_string(),
+        )));
+    }
+    Ok(match metrics::prom::remote_read(&org_id, body).await {
+        Ok(resp) => HttpResponse::Ok()
+            .content_type("application/x-protobuf")
+            .insert_header(("Content-Encoding", "snappy"))
+            .body(resp),
+        Err(e) => HttpResponse::BadRequest().json(MetaHttpResponse::error(
+            http::StatusCode::BAD_REQUEST.into(),
+            e.to_string(),
+        )),
+    })
+}
+
 /** prometheus instant queries */
 // refer: https://prometheus.io/docs/prometheus/latest/querying/api/#instant-queries
 #[utoipa::path(
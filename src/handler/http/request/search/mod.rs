@@ -16,6 +16,7 @@ use actix_web::{get, http::StatusCode, post, web, HttpRequest, HttpResponse};
 use ahash::AHashMap;
 use chrono::Duration;
 use std::{collections::HashMap, io::Error};
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
 
 use crate::common::{
     infra::{
@@ -120,21 +121,7 @@ pub async fn search(
         return Ok(MetaHttpResponse::bad_request(e));
     }
 
-    let mut query_fn = req.query.query_fn.and_then(|v| base64::decode(&v).ok());
-
-    if let Some(vrl_function) = &query_fn {
-        if !vrl_function.trim().ends_with('.') {
-            query_fn = Some(format!("{} \n .", vrl_function));
-        }
-    }
-    req.query.query_fn = query_fn;
-
-    for fn_name in functions::get_all_transform_keys(&org_id).await {
-        if req.query.sql.contains(&format!("{}(", fn_name)) {
-            req.query.uses_zo_fn = true;
-            break;
-        }
-    }
+    prepare_query_fn(&org_id, &mut req).await;
 
     // get a local search queue lock
     let locker = SearchService::QUEUE_LOCKER.clone();
@@ -220,6 +207,138 @@ pub async fn search(
     }
 }
 
+/// Decodes the VRL function of the query and flags the use of the org functions in the
+/// SQL.
+async fn prepare_query_fn(org_id: &str, req: &mut meta::search::Request) {
+    let mut query_fn = req
+        .query
+        .query_fn
+        .take()
+        .and_then(|v| base64::decode(&v).ok());
+
+    if let Some(vrl_function) = &query_fn {
+        if !vrl_function.trim().ends_with('.') {
+            query_fn = Some(format!("{} \n .", vrl_function));
+        }
+    }
+    req.query.query_fn = query_fn;
+
+    for fn_name in functions::get_all_transform_keys(org_id).await {
+        if req.query.sql.contains(&format!("{}(", fn_name)) {
+            req.query.uses_zo_fn = true;
+            break;
+        }
+    }
+}
+
+/** SearchStream*/
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Search",
+    operation_id = "SearchStream",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("type" = Option<String>, Query, description = "Stream type, defaults to logs"),
+        ("format" = Option<String>, Query, description = "Chunk encoding, json (newline-delimited) or arrow (Arrow IPC streams), defaults to json"),
+        ("cursor" = Option<String>, Query, description = "Cursor of the page, from the trailer of the previous page"),
+    ),
+    request_body(content = SearchRequest, description = "Search query, size is the minimum number of hits of a page", content_type = "application/json", example = json!({
+        "query": {
+            "sql": "select * from k8s ",
+            "start_time": 1675182660872049i64,
+            "end_time": 1675185660872049i64,
+            "size": 100000
+        }
+    })),
+    responses(
+        (status = 200, description="Success, one line per chunk and a trailer line", content_type = "application/x-ndjson", body = SearchStreamTrailer, example = json!({
+            "cursor": "eyJlbmRfdGltZSI6MTY3NTE4NTY2MDg3MjA0OSwid2FsX2RvbmUiOnRydWUsImZpbGVfa2V5IjoiIn0",
+            "total": 100512,
+            "took": 2310,
+            "scan_size": 180943
+        })),
+        (status = 400, description="Failure", content_type = "application/json", body = HttpResponse),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/_search_stream")]
+pub async fn search_stream(
+    org_id: web::Path<String>,
+    in_req: HttpRequest,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let start = std::time::Instant::now();
+    let org_id = org_id.into_inner();
+    let query = web::Query::<AHashMap<String, String>>::from_query(in_req.query_string()).unwrap();
+    let stream_type = match get_stream_type_from_request(&query) {
+        Ok(v) => v.unwrap_or(StreamType::Logs),
+        Err(e) => return Ok(MetaHttpResponse::bad_request(e)),
+    };
+    let format = meta::search::StreamFormat::from(
+        query.get("format").map(|v| v.as_str()).unwrap_or_default(),
+    );
+
+    let mut req: meta::search::Request = match json::from_slice(&body) {
+        Ok(v) => v,
+        Err(e) => return Ok(MetaHttpResponse::bad_request(e)),
+    };
+    if let Err(e) = req.decode() {
+        return Ok(MetaHttpResponse::bad_request(e));
+    }
+    prepare_query_fn(&org_id, &mut req).await;
+
+    let cursor = query.get("cursor").map(|v| v.as_str());
+    let (status, resp) =
+        match SearchService::streaming::search(&org_id, stream_type, &req, cursor, format).await {
+            Ok(rx) => (
+                "200",
+                HttpResponse::Ok()
+                    .content_type(format.content_type())
+                    .streaming(ReceiverStream::new(rx).map(Ok::<_, Error>)),
+            ),
+            Err(err) => {
+                log::error!("search stream error: {:?}", err);
+                match err {
+                    errors::Error::Message(e) => ("400", MetaHttpResponse::bad_request(e)),
+                    errors::Error::ErrorCode(code) => (
+                        "500",
+                        HttpResponse::InternalServerError()
+                            .json(meta::http::HttpResponse::error_code(code)),
+                    ),
+                    _ => (
+                        "500",
+                        HttpResponse::InternalServerError().json(meta::http::HttpResponse::error(
+                            StatusCode::INTERNAL_SERVER_ERROR.into(),
+                            err.to_string(),
+                        )),
+                    ),
+                }
+            }
+        };
+    metrics::HTTP_RESPONSE_TIME
+        .with_label_values(&[
+            "/api/org/_search_stream",
+            status,
+            &org_id,
+            "",
+            stream_type.to_string().as_str(),
+        ])
+        .observe(start.elapsed().as_secs_f64());
+    metrics::HTTP_INCOMING_REQUESTS
+        .with_label_values(&[
+            "/api/org/_search_stream",
+            status,
+            &org_id,
+            "",
+            stream_type.to_string().as_str(),
+        ])
+        .inc();
+    Ok(resp)
+}
+
 /** SearchAround*/
 #[utoipa::path(
     context_path = "/api",
@@ -0,0 +1,286 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Jaeger HTTP query API, as used by the Jaeger UI. Point the UI at
+// `/api/{org_id}/{stream_name}/jaeger` to browse the traces of a stream.
+
+use actix_web::{get, http::StatusCode, web, HttpResponse};
+use chrono::{Duration, Utc};
+use serde::Serialize;
+use std::io::Error;
+
+use crate::common::{
+    infra::errors,
+    meta::traces::{
+        JaegerDependenciesQuery, JaegerGetTraceQuery, JaegerResponse, JaegerTraceQuery,
+    },
+};
+use crate::service::traces::{jaeger, query};
+
+/** JaegerServices */
+// refer: https://www.jaegertracing.io/docs/latest/apis/#http-json-internal
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "JaegerServices",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("stream_name" = String, Path, description = "Stream name"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse, example = json!({
+            "data": ["frontend", "backend"],
+            "total": 2,
+            "limit": 0,
+            "offset": 0,
+            "errors": null
+        })),
+    )
+)]
+#[get("/{org_id}/{stream_name}/jaeger/api/services")]
+pub async fn services(path: web::Path<(String, String)>) -> Result<HttpResponse, Error> {
+    let (org_id, stream_name) = path.into_inner();
+    Ok(match jaeger::services(&org_id, &stream_name).await {
+        Ok(services) => data(services),
+        Err(err) => search_error(err),
+    })
+}
+
+/** JaegerOperations */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "JaegerOperations",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("stream_name" = String, Path, description = "Stream name"),
+        ("service" = String, Path, description = "Service name"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse, example = json!({
+            "data": ["GET /", "GET /users"],
+            "total": 2,
+            "limit": 0,
+            "offset": 0,
+            "errors": null
+        })),
+    )
+)]
+#[get("/{org_id}/{stream_name}/jaeger/api/services/{service}/operations")]
+pub async fn operations(path: web::Path<(String, String, String)>) -> Result<HttpResponse, Error> {
+    let (org_id, stream_name, service) = path.into_inner();
+    Ok(
+        match jaeger::operations(&org_id, &stream_name, &service).await {
+            Ok(operations) => data(operations),
+            Err(err) => search_error(err),
+        },
+    )
+}
+
+/** JaegerFindTraces */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "JaegerFindTraces",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("stream_name" = String, Path, description = "Stream name"),
+        ("service" = Option<String>, Query, description = "Service name"),
+        ("operation" = Option<String>, Query, description = "Operation name"),
+        ("tags" = Option<String>, Query, description = "Span tags, JSON object"),
+        ("start" = Option<i64>, Query, description = "Start time, in microseconds"),
+        ("end" = Option<i64>, Query, description = "End time, in microseconds"),
+        ("lookback" = Option<String>, Query, description = "Time range before end, used without start, like 1h"),
+        ("minDuration" = Option<String>, Query, description = "Minimum span duration, like 100ms"),
+        ("maxDuration" = Option<String>, Query, description = "Maximum span duration, like 1.2s"),
+        ("limit" = Option<usize>, Query, description = "Maximum number of traces, defaults to 20"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse, example = json!({
+            "data": [{
+                "traceID": "0af7651916cd43dd8448eb211c80319c",
+                "spans": [{
+                    "traceID": "0af7651916cd43dd8448eb211c80319c",
+                    "spanID": "00f067aa0ba902b7",
+                    "flags": 1,
+                    "operationName": "GET /",
+                    "references": [],
+                    "startTime": 1675182660872049i64,
+                    "duration": 1200,
+                    "tags": [{"key": "span.kind", "type": "string", "value": "server"}],
+                    "logs": [],
+                    "processID": "p1",
+                    "warnings": null
+                }],
+                "processes": {"p1": {"serviceName": "frontend", "tags": []}},
+                "warnings": null
+            }],
+            "total": 1,
+            "limit": 0,
+            "offset": 0,
+            "errors": null
+        })),
+        (status = 400, description="Invalid query", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/{stream_name}/jaeger/api/traces")]
+pub async fn find_traces(
+    path: web::Path<(String, String)>,
+    req: web::Query<JaegerTraceQuery>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, stream_name) = path.into_inner();
+    let filters = match jaeger::trace_filters(&req) {
+        Ok(v) => v,
+        Err(e) => return Ok(error(StatusCode::BAD_REQUEST, e)),
+    };
+    let (start_time, end_time) = match jaeger::time_range(&req) {
+        Ok(v) => v,
+        Err(e) => return Ok(error(StatusCode::BAD_REQUEST, e)),
+    };
+    Ok(
+        match jaeger::find_traces(
+            &org_id,
+            &stream_name,
+            &filters,
+            start_time,
+            end_time,
+            req.limit,
+        )
+        .await
+        {
+            Ok(traces) => data(traces),
+            Err(err) => search_error(err),
+        },
+    )
+}
+
+/** JaegerGetTrace */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "JaegerGetTrace",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("stream_name" = String, Path, description = "Stream name"),
+        ("trace_id" = String, Path, description = "Trace ID, hex encoded"),
+        ("start" = Option<i64>, Query, description = "Start time, in microseconds, defaults to 7 days before end"),
+        ("end" = Option<i64>, Query, description = "End time, in microseconds, defaults to now"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse),
+        (status = 404, description="Trace not found", content_type = "application/json", body = HttpResponse, example = json!({
+            "data": null,
+            "total": 0,
+            "limit": 0,
+            "offset": 0,
+            "errors": [{"code": 404, "msg": "trace not found"}]
+        })),
+    )
+)]
+#[get("/{org_id}/{stream_name}/jaeger/api/traces/{trace_id}")]
+pub async fn get_trace(
+    path: web::Path<(String, String, String)>,
+    req: web::Query<JaegerGetTraceQuery>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, stream_name, trace_id) = path.into_inner();
+    if !query::is_valid_trace_id(&trace_id) {
+        return Ok(error(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid trace id: {trace_id}"),
+        ));
+    }
+    let trace_id = jaeger::normalize_trace_id(&trace_id);
+    let (start_time, end_time) = jaeger::trace_time_range(req.start, req.end);
+    Ok(
+        match jaeger::get_trace(&org_id, &stream_name, &trace_id, start_time, end_time).await {
+            Ok(Some(trace)) => data(vec![trace]),
+            Ok(None) => error(StatusCode::NOT_FOUND, "trace not found".to_string()),
+            Err(err) => search_error(err),
+        },
+    )
+}
+
+/** JaegerDependencies */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "JaegerDependencies",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("stream_name" = String, Path, description = "Stream name"),
+        ("endTs" = Option<i64>, Query, description = "End time, in milliseconds, defaults to now"),
+        ("lookback" = Option<i64>, Query, description = "Time range before endTs, in milliseconds, defaults to an hour"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse, example = json!({
+            "data": [{"parent": "frontend", "child": "backend", "callCount": 12}],
+            "total": 1,
+            "limit": 0,
+            "offset": 0,
+            "errors": null
+        })),
+    )
+)]
+#[get("/{org_id}/{stream_name}/jaeger/api/dependencies")]
+pub async fn dependencies(
+    path: web::Path<(String, String)>,
+    req: web::Query<JaegerDependenciesQuery>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, stream_name) = path.into_inner();
+    let end_time = req
+        .end_ts
+        .filter(|v| *v > 0)
+        .map(|v| v * 1000)
+        .unwrap_or_else(|| Utc::now().timestamp_micros());
+    let lookback = req
+        .lookback
+        .filter(|v| *v > 0)
+        .map(|v| v * 1000)
+        .unwrap_or_else(|| Duration::hours(1).num_microseconds().unwrap());
+    Ok(
+        match jaeger::dependencies(&org_id, &stream_name, end_time - lookback, end_time).await {
+            Ok(dependencies) => data(dependencies),
+            Err(err) => search_error(err),
+        },
+    )
+}
+
+fn data<T: Serialize>(data: Vec<T>) -> HttpResponse {
+    let total = data.len();
+    HttpResponse::Ok().json(JaegerResponse::data(data, total))
+}
+
+fn error(code: StatusCode, msg: String) -> HttpResponse {
+    HttpResponse::build(code).json(JaegerResponse::<()>::error(code.as_u16(), msg))
+}
+
+fn search_error(err: errors::Error) -> HttpResponse {
+    log::error!("jaeger query error: {:?}", err);
+    error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
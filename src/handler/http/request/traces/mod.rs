@@ -12,15 +12,25 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use actix_web::{http, post, web, HttpRequest, HttpResponse};
+use actix_web::{get, http, post, web, HttpRequest, HttpResponse};
+use chrono::{Duration, Utc};
 use std::io::Error;
 
 use crate::{
-    common::{infra::config::CONFIG, meta},
+    common::{
+        infra::{config::CONFIG, errors},
+        meta::{
+            self,
+            http::HttpResponse as MetaHttpResponse,
+            traces::{TraceQuery, TraceSearchQuery},
+        },
+    },
     handler::http::request::{CONTENT_TYPE_JSON, CONTENT_TYPE_PROTO},
-    service::traces::otlp_http,
+    service::traces::{otlp_http, query},
 };
 
+pub mod jaeger;
+
 /** TracesIngest */
 #[utoipa::path(
     context_path = "/api",
@@ -76,3 +86,182 @@ async fn handle_req(
         )
     }
 }
+
+/** GetTrace */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "GetTrace",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("stream_name" = String, Path, description = "Stream name"),
+        ("trace_id" = String, Path, description = "Trace ID, hex encoded"),
+        ("start_time" = Option<i64>, Query, description = "Start time, in microseconds, defaults to an hour before end time"),
+        ("end_time" = Option<i64>, Query, description = "End time, in microseconds, defaults to now"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = Trace),
+        (status = 400, description="Invalid trace ID, stream name or time range", content_type = "application/json", body = HttpResponse),
+        (status = 404, description="Trace not found", content_type = "application/json", body = HttpResponse),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/{stream_name}/traces/{trace_id}")]
+pub async fn get_trace(
+    path: web::Path<(String, String, String)>,
+    req: web::Query<TraceQuery>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, stream_name, trace_id) = path.into_inner();
+    if !query::is_valid_trace_id(&trace_id) {
+        return Ok(MetaHttpResponse::bad_request(format!(
+            "Invalid trace id: {trace_id}"
+        )));
+    }
+    let (start_time, end_time) = match check_query(&stream_name, req.start_time, req.end_time) {
+        Ok(time_range) => time_range,
+        Err(resp) => return Ok(resp),
+    };
+    match query::get_trace(&org_id, &stream_name, &trace_id, start_time, end_time).await {
+        Ok(Some(trace)) => Ok(HttpResponse::Ok().json(trace)),
+        Ok(None) => Ok(HttpResponse::NotFound().json(MetaHttpResponse::error(
+            http::StatusCode::NOT_FOUND.into(),
+            format!("Trace {trace_id} not found"),
+        ))),
+        Err(err) => Ok(search_error(err)),
+    }
+}
+
+/** SearchTraces */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "SearchTraces",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("stream_name" = String, Path, description = "Stream name"),
+        ("service_name" = Option<String>, Query, description = "Service of a span of the trace"),
+        ("operation_name" = Option<String>, Query, description = "Operation of a span of the trace"),
+        ("min_duration" = Option<u64>, Query, description = "Minimum span duration, in microseconds"),
+        ("max_duration" = Option<u64>, Query, description = "Maximum span duration, in microseconds"),
+        ("status" = Option<String>, Query, description = "Span status, OK, ERROR or UNSET"),
+        ("start_time" = Option<i64>, Query, description = "Start time, in microseconds, defaults to an hour before end time"),
+        ("end_time" = Option<i64>, Query, description = "End time, in microseconds, defaults to now"),
+        ("size" = Option<usize>, Query, description = "Maximum number of traces, defaults to 20"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = TraceSearchResponse),
+        (status = 400, description="Invalid query", content_type = "application/json", body = HttpResponse),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/{stream_name}/traces/_search")]
+pub async fn search_traces(
+    path: web::Path<(String, String)>,
+    req: web::Query<TraceSearchQuery>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, stream_name) = path.into_inner();
+    let mut req = req.into_inner();
+    (req.start_time, req.end_time) = match check_query(&stream_name, req.start_time, req.end_time) {
+        Ok(time_range) => time_range,
+        Err(resp) => return Ok(resp),
+    };
+    if let Some(status) = req.status.as_deref().filter(|v| !v.is_empty()) {
+        if !query::is_valid_status(status) {
+            return Ok(MetaHttpResponse::bad_request(format!(
+                "Invalid span status: {status}"
+            )));
+        }
+    }
+    if req.size == 0 {
+        return Ok(MetaHttpResponse::bad_request("size must be greater than 0"));
+    }
+    match query::search_traces(&org_id, &stream_name, &req).await {
+        Ok(res) => Ok(HttpResponse::Ok().json(res)),
+        Err(err) => Ok(search_error(err)),
+    }
+}
+
+/** GetServiceGraph */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "GetServiceGraph",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("stream_name" = String, Path, description = "Stream name"),
+        ("start_time" = Option<i64>, Query, description = "Start time, in microseconds, defaults to an hour before end time"),
+        ("end_time" = Option<i64>, Query, description = "End time, in microseconds, defaults to now"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = ServiceGraph),
+        (status = 400, description="Invalid stream name or time range", content_type = "application/json", body = HttpResponse),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/{stream_name}/traces/_service_graph")]
+pub async fn service_graph(
+    path: web::Path<(String, String)>,
+    req: web::Query<TraceQuery>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, stream_name) = path.into_inner();
+    let (start_time, end_time) = match check_query(&stream_name, req.start_time, req.end_time) {
+        Ok(time_range) => time_range,
+        Err(resp) => return Ok(resp),
+    };
+    match query::service_graph(&org_id, &stream_name, start_time, end_time).await {
+        Ok(graph) => Ok(HttpResponse::Ok().json(graph)),
+        Err(err) => Ok(search_error(err)),
+    }
+}
+
+/// Checks the stream name and returns the time range of the query, the hour before
+/// the end time if no start time is given.
+fn check_query(
+    stream_name: &str,
+    start_time: i64,
+    end_time: i64,
+) -> Result<(i64, i64), HttpResponse> {
+    if !query::is_valid_stream_name(stream_name) {
+        return Err(MetaHttpResponse::bad_request(format!(
+            "Invalid stream name: {stream_name}"
+        )));
+    }
+    let end_time = if end_time > 0 {
+        end_time
+    } else {
+        Utc::now().timestamp_micros()
+    };
+    let start_time = if start_time > 0 {
+        start_time
+    } else {
+        end_time - Duration::hours(1).num_microseconds().unwrap()
+    };
+    if !query::is_valid_time_range(start_time, end_time) {
+        return Err(MetaHttpResponse::bad_request(format!(
+            "Invalid time range: [{start_time}, {end_time}]"
+        )));
+    }
+    Ok((start_time, end_time))
+}
+
+fn search_error(err: errors::Error) -> HttpResponse {
+    log::error!("trace search error: {:?}", err);
+    match err {
+        errors::Error::ErrorCode(code) => {
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error_code(code))
+        }
+        _ => HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+            http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+            err.to_string(),
+        )),
+    }
+}
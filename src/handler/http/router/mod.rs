@@ -25,10 +25,10 @@ use std::rc::Rc;
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
-use super::auth::{validator, validator_aws, validator_gcp, validator_rum};
+use super::auth::{validator, validator_aws, validator_gcp, validator_rum, validator_splunk};
 use super::request::{
-    alerts::*, dashboards::folders::*, dashboards::*, enrichment_table, functions, kv, logs,
-    metrics, organization, prom, rum, search, status, stream, syslog, traces, users,
+    alerts::*, dashboards::folders::*, dashboards::*, enrichment_table, fluent, functions, kv,
+    logs, metrics, organization, prom, rum, search, status, stream, syslog, traces, users,
 };
 use crate::common::{infra::config::CONFIG, meta::middleware_data::RumExtraData};
 use actix_web_lab::middleware::from_fn;
@@ -126,6 +126,7 @@ pub fn get_service_routes(cfg: &mut web::ServiceConfig) {
             .service(logs::ingest::json)
             .service(metrics::ingest::json)
             .service(search::search)
+            .service(search::search_stream)
             .service(search::around)
             .service(search::values)
             .service(stream::schema)
@@ -145,6 +146,7 @@ pub fn get_service_routes(cfg: &mut web::ServiceConfig) {
             .service(users::delete)
             .service(users::add_user_to_org)
             .service(prom::remote_write)
+            .service(prom::remote_read)
             .service(prom::query_get)
             .service(prom::query_post)
             .service(prom::query_range_get)
@@ -163,10 +165,19 @@ pub fn get_service_routes(cfg: &mut web::ServiceConfig) {
             .service(get_dashboard)
             .service(delete_dashboard)
             .service(traces::traces_write)
+            .service(traces::search_traces)
+            .service(traces::service_graph)
+            .service(traces::get_trace)
+            .service(traces::jaeger::services)
+            .service(traces::jaeger::operations)
+            .service(traces::jaeger::find_traces)
+            .service(traces::jaeger::get_trace)
+            .service(traces::jaeger::dependencies)
             .service(save_alert)
             .service(get_alert)
             .service(list_alerts)
             .service(trigger_alert)
+            .service(get_alert_history)
             .service(list_stream_alerts)
             .service(delete_alert)
             .service(organization::organizations)
@@ -194,6 +205,12 @@ pub fn get_service_routes(cfg: &mut web::ServiceConfig) {
             .service(destinations::get_destination)
             .service(destinations::list_destinations)
             .service(destinations::delete_destination)
+            .service(silences::save_silence)
+            .service(silences::list_silences)
+            .service(silences::delete_silence)
+            .service(silences::save_maintenance_window)
+            .service(silences::list_maintenance_windows)
+            .service(silences::delete_maintenance_window)
             .service(kv::get)
             .service(kv::set)
             .service(kv::delete)
@@ -203,9 +220,24 @@ pub fn get_service_routes(cfg: &mut web::ServiceConfig) {
             .service(syslog::delete_route)
             .service(syslog::update_route)
             .service(syslog::toggle_state)
+            .service(fluent::list_routes)
+            .service(fluent::create_route)
+            .service(fluent::delete_route)
+            .service(fluent::update_route)
+            .service(fluent::get_shared_key)
+            .service(fluent::rotate_shared_key)
             .service(enrichment_table::save_enrichment_table)
             .service(metrics::ingest::otlp_metrics_write)
+            .service(metrics::recording_rules::save_rule)
+            .service(metrics::recording_rules::get_rule)
+            .service(metrics::recording_rules::list_rules)
+            .service(metrics::recording_rules::delete_rule)
             .service(logs::ingest::otlp_logs_write)
+            .service(logs::ingest::loki_push)
+            .service(logs::kafka::save_source)
+            .service(logs::kafka::get_source)
+            .service(logs::kafka::list_sources)
+            .service(logs::kafka::delete_source)
             .service(traces::otlp_traces_write)
             .service(create_folder)
             .service(list_folders)
@@ -238,6 +270,16 @@ pub fn get_other_service_routes(cfg: &mut web::ServiceConfig) {
             .service(logs::ingest::handle_gcp_request),
     );
 
+    let splunk_auth = HttpAuthentication::with_fn(validator_splunk);
+    cfg.service(
+        web::scope("/services/collector")
+            .wrap(cors.clone())
+            .wrap(splunk_auth)
+            .service(logs::ingest::hec_event)
+            .service(logs::ingest::hec_raw)
+            .service(logs::ingest::hec_ack),
+    );
+
     //NOTE: Here the order of middlewares matter. Once we consume the api-token in `rum_auth`,
     //we drop it in the RumExtraData data.
     //https://docs.rs/actix-web/latest/actix_web/middleware/index.html#ordering
@@ -30,10 +30,22 @@ use crate::handler::http::request;
         request::logs::ingest::handle_kinesis_request,
         request::logs::ingest::multi,
         request::logs::ingest::json,
+        request::logs::ingest::loki_push,
+        request::logs::ingest::hec_event,
+        request::logs::ingest::hec_raw,
+        request::logs::ingest::hec_ack,
+        request::logs::kafka::save_source,
+        request::logs::kafka::get_source,
+        request::logs::kafka::list_sources,
+        request::logs::kafka::delete_source,
         request::rum::ingest::log,
         request::rum::ingest::data,
         request::rum::ingest::sessionreplay,
         request::metrics::ingest::json,
+        request::metrics::recording_rules::save_rule,
+        request::metrics::recording_rules::get_rule,
+        request::metrics::recording_rules::list_rules,
+        request::metrics::recording_rules::delete_rule,
         request::dashboards::create_dashboard,
         request::dashboards::update_dashboard,
         request::dashboards::list_dashboards,
@@ -46,6 +58,7 @@ use crate::handler::http::request;
         request::dashboards::folders::update_folder,
         request::dashboards::move_dashboard,
         request::search::search,
+        request::search::search_stream,
         request::search::around,
         request::search::values,
         request::functions::list_functions,
@@ -61,6 +74,7 @@ use crate::handler::http::request;
         request::alerts::get_alert,
         request::alerts::delete_alert,
         request::alerts::trigger_alert,
+        request::alerts::get_alert_history,
         request::alerts::templates::list_templates,
         request::alerts::templates::get_template,
         request::alerts::templates::save_template,
@@ -69,6 +83,12 @@ use crate::handler::http::request;
         request::alerts::destinations::get_destination,
         request::alerts::destinations::save_destination,
         request::alerts::destinations::delete_destination,
+        request::alerts::silences::save_silence,
+        request::alerts::silences::list_silences,
+        request::alerts::silences::delete_silence,
+        request::alerts::silences::save_maintenance_window,
+        request::alerts::silences::list_maintenance_windows,
+        request::alerts::silences::delete_maintenance_window,
         request::users::list,
         request::users::save,
         request::users::update,
@@ -90,6 +110,7 @@ use crate::handler::http::request;
         request::kv::list,
         request::status::healthz,
         request::prom::remote_write,
+        request::prom::remote_read,
         request::prom::query_get,
         request::prom::query_range_get,
         request::prom::metadata,
@@ -98,10 +119,24 @@ use crate::handler::http::request;
         request::prom::label_values,
         request::prom::format_query_get,
         request::traces::traces_write,
+        request::traces::get_trace,
+        request::traces::search_traces,
+        request::traces::service_graph,
+        request::traces::jaeger::services,
+        request::traces::jaeger::operations,
+        request::traces::jaeger::find_traces,
+        request::traces::jaeger::get_trace,
+        request::traces::jaeger::dependencies,
         request::syslog::create_route,
         request::syslog::update_route,
         request::syslog::list_routes,
         request::syslog::delete_route,
+        request::fluent::create_route,
+        request::fluent::update_route,
+        request::fluent::list_routes,
+        request::fluent::delete_route,
+        request::fluent::get_shared_key,
+        request::fluent::rotate_shared_key,
         request::enrichment_table::save_enrichment_table,
     ),
     components(
@@ -121,6 +156,9 @@ use crate::handler::http::request;
             meta::ingestion::KFHRecordRequest,
             meta::ingestion::StreamStatus,
             meta::ingestion::IngestionResponse,
+            meta::ingestion::HecResponse,
+            meta::ingestion::HecAckRequest,
+            meta::ingestion::HecAckResponse,
             meta::dashboards::Dashboard,
             meta::dashboards::Dashboards,
             meta::dashboards::v1::AxisItem,
@@ -143,9 +181,20 @@ use crate::handler::http::request;
             meta::search::RequestEncoding,
             meta::search::Response,
             meta::search::ResponseTook,
+            meta::search::StreamTrailer,
             meta::alert::Alert,
             meta::alert::AlertList,
             meta::alert::Condition,
+            meta::alert::Aggregation,
+            meta::alert::AggFunction,
+            meta::alert::AlertState,
+            meta::alert::TriggerHistory,
+            meta::alert::TriggerHistoryList,
+            meta::alert::Matcher,
+            meta::alert::Silence,
+            meta::alert::SilenceList,
+            meta::alert::MaintenanceWindow,
+            meta::alert::MaintenanceWindowList,
             meta::alert::AllOperator,
             meta::alert::AlertDestination,
             meta::alert::AlertDestinationResponse,
@@ -181,8 +230,27 @@ use crate::handler::http::request;
             meta::ingestion::BulkResponseError,
             meta::syslog::SyslogRoute,
             meta::syslog::SyslogRoutes,
+            meta::fluent::FluentRoute,
+            meta::fluent::FluentRoutes,
+            meta::fluent::FluentSharedKey,
+            meta::kafka::KafkaSource,
+            meta::kafka::KafkaSourceList,
+            meta::kafka::KafkaFormat,
+            meta::kafka::KafkaStartOffset,
+            meta::traces::Trace,
+            meta::traces::SpanNode,
+            meta::traces::TraceSummary,
+            meta::traces::TraceSearchResponse,
+            meta::traces::ServiceGraph,
+            meta::traces::ServiceNode,
+            meta::traces::ServiceEdge,
+            meta::traces::TraceSampling,
+            meta::traces::TailSampling,
+            meta::traces::SpanDropRule,
             meta::prom::Metadata,
             meta::prom::MetricType,
+            meta::prom::RecordingRule,
+            meta::prom::RecordingRuleList,
          ),
     ),
     modifiers(&SecurityAddon),
@@ -199,8 +267,9 @@ use crate::handler::http::request;
         (name = "Users", description = "Users retrieval & management operations"),
         (name = "KV", description = "Key Value retrieval & management operations"),
         (name = "Metrics", description = "Metrics data ingestion operations"),
-        (name = "Traces", description = "Traces data ingestion operations"),
+        (name = "Traces", description = "Traces data ingestion and retrieval operations"),
         (name = "Syslog Routes", description = "Syslog Routes retrieval & management operations"),
+        (name = "Fluent Routes", description = "Fluent Forward Routes retrieval & management operations"),
     ),
     info(
         description = "OpenObserve API documents [https://openobserve.ai/docs/](https://openobserve.ai/docs/)",
@@ -0,0 +1,414 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::BytesMut;
+use rmpv::Value as MsgValue;
+use sha2::{Digest, Sha512};
+use std::{net::SocketAddr, time::Duration};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpListener,
+};
+
+use super::ConnectionStats;
+use crate::common::infra::config::{CONFIG, FLUENT_KEYS};
+use crate::service::logs::fluent::{self, as_bytes};
+
+/// Accepts Fluent Forward connections, as sent by the `forward` output of Fluent Bit and
+/// Fluentd.
+pub async fn fluent_server(listener: TcpListener) {
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("Fluent server - error accepting connection: {}", e);
+                continue;
+            }
+        };
+        tokio::task::spawn(async move {
+            handle_connection(stream, addr).await;
+        });
+    }
+}
+
+async fn handle_connection<S>(mut stream: S, addr: SocketAddr)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let max_size = CONFIG.fluent.max_chunk_size;
+    let max_bad_messages = CONFIG.fluent.max_bad_messages;
+    let mut buf = BytesMut::with_capacity(64 * 1024);
+    let mut stats = ConnectionStats::default();
+
+    // the shared key of the sender tells the org it writes to
+    let timeout = Duration::from_secs(CONFIG.fluent.handshake_timeout);
+    let org_id = match tokio::time::timeout(
+        timeout,
+        handshake(&mut stream, &mut buf, max_size, &mut stats),
+    )
+    .await
+    {
+        Ok(Ok(org_id)) => org_id,
+        Ok(Err(e)) => {
+            log::warn!("Fluent server - handshake with {addr} failed: {e}");
+            return;
+        }
+        Err(_) => {
+            log::warn!("Fluent server - handshake with {addr} timed out");
+            return;
+        }
+    };
+
+    loop {
+        let msg = match read_message(&mut stream, &mut buf, max_size, &mut stats).await {
+            Ok(Some(msg)) => msg,
+            Ok(None) => break,
+            Err(e) => {
+                log::warn!("Fluent server - closing connection from {addr}: {e}");
+                break;
+            }
+        };
+        let msg = match fluent::decode_message(msg) {
+            Ok(msg) => msg,
+            Err(e) => {
+                stats.bad_messages += 1;
+                log::debug!("Fluent server - dropped message from {addr}: {e}");
+                if too_many_bad_messages(&stats, max_bad_messages, addr) {
+                    break;
+                }
+                continue;
+            }
+        };
+        let tag = msg.tag.clone();
+        let chunk = msg.chunk.clone();
+        if let Err(e) = fluent::ingest(&org_id, msg).await {
+            // without ack the sender retries the chunk
+            stats.bad_messages += 1;
+            log::warn!("Fluent server - error ingesting tag {tag} from {addr} into {org_id}: {e}");
+            if too_many_bad_messages(&stats, max_bad_messages, addr) {
+                break;
+            }
+            continue;
+        }
+        stats.messages += 1;
+        if let Some(chunk) = chunk {
+            let ack = MsgValue::Map(vec![(MsgValue::from("ack"), MsgValue::from(chunk))]);
+            if let Err(e) = write_message(&mut stream, &ack).await {
+                log::warn!("Fluent server - error sending ack to {addr}: {e}");
+                break;
+            }
+        }
+    }
+    log::info!(
+        "Fluent server - connection from {addr} closed, bytes: {}, messages: {}, bad messages: {}",
+        stats.bytes,
+        stats.messages,
+        stats.bad_messages
+    );
+}
+
+fn too_many_bad_messages(stats: &ConnectionStats, max_bad_messages: u64, addr: SocketAddr) -> bool {
+    if max_bad_messages > 0 && stats.bad_messages > max_bad_messages {
+        log::warn!(
+            "Fluent server - closing connection from {addr} after {} bad messages",
+            stats.bad_messages
+        );
+        return true;
+    }
+    false
+}
+
+/// Authenticates the sender with the shared key of an org and returns the org: the server
+/// sends HELO with a nonce and the sender answers with PING holding a digest of the key,
+/// the server answers with PONG.
+async fn handshake<S>(
+    stream: &mut S,
+    buf: &mut BytesMut,
+    max_size: usize,
+    stats: &mut ConnectionStats,
+) -> Result<String, anyhow::Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let nonce: [u8; 16] = rand::random();
+    let helo = MsgValue::Array(vec![
+        MsgValue::from("HELO"),
+        MsgValue::Map(vec![
+            (MsgValue::from("nonce"), MsgValue::Binary(nonce.to_vec())),
+            // user authentication is not supported
+            (MsgValue::from("auth"), MsgValue::from("")),
+            (MsgValue::from("keepalive"), MsgValue::Boolean(true)),
+        ]),
+    ]);
+    write_message(stream, &helo).await?;
+
+    let Some(ping) = read_message(stream, buf, max_size, stats).await? else {
+        return Err(anyhow::anyhow!("connection closed before PING"));
+    };
+    let keys = FLUENT_KEYS
+        .iter()
+        .map(|v| (v.key().to_string(), v.value().to_string()))
+        .collect::<Vec<_>>();
+    let server_hostname = CONFIG.common.instance_name.as_bytes();
+    let (pong, org_id) = match check_ping(&ping, &nonce, &keys) {
+        Ok((salt, org_id, shared_key)) => (
+            MsgValue::Array(vec![
+                MsgValue::from("PONG"),
+                MsgValue::Boolean(true),
+                MsgValue::from(""),
+                MsgValue::from(CONFIG.common.instance_name.as_str()),
+                MsgValue::from(sha512_hex(&[
+                    &salt,
+                    server_hostname,
+                    nonce.as_slice(),
+                    shared_key.as_bytes(),
+                ])),
+            ]),
+            org_id.to_string(),
+        ),
+        Err(reason) => {
+            let pong = MsgValue::Array(vec![
+                MsgValue::from("PONG"),
+                MsgValue::Boolean(false),
+                MsgValue::from(reason.as_str()),
+                MsgValue::from(""),
+                MsgValue::from(""),
+            ]);
+            write_message(stream, &pong).await?;
+            return Err(anyhow::anyhow!(reason));
+        }
+    };
+    write_message(stream, &pong).await?;
+    Ok(org_id)
+}
+
+/// Checks the digest of `["PING", hostname, salt, digest, username, password]` against the
+/// `(org_id, shared_key)` pairs and returns the salt and the pair of the sender.
+fn check_ping<'a>(
+    ping: &MsgValue,
+    nonce: &[u8],
+    keys: &'a [(String, String)],
+) -> Result<(Vec<u8>, &'a str, &'a str), String> {
+    let items = ping.as_array().map(|v| v.as_slice()).unwrap_or_default();
+    if items.len() < 4 || as_bytes(&items[0]) != Some(b"PING".as_slice()) {
+        return Err("expected PING".to_string());
+    }
+    let (Some(hostname), Some(salt), Some(digest)) = (
+        as_bytes(&items[1]),
+        as_bytes(&items[2]),
+        as_bytes(&items[3]),
+    ) else {
+        return Err("invalid PING".to_string());
+    };
+    keys.iter()
+        .find(|(_, shared_key)| {
+            sha512_hex(&[salt, hostname, nonce, shared_key.as_bytes()]).as_bytes() == digest
+        })
+        .map(|(org_id, shared_key)| (salt.to_vec(), org_id.as_str(), shared_key.as_str()))
+        .ok_or_else(|| "shared key mismatch".to_string())
+}
+
+fn sha512_hex(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha512::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Reads the next message, returns `None` when the sender closed the connection.
+async fn read_message<S>(
+    stream: &mut S,
+    buf: &mut BytesMut,
+    max_size: usize,
+    stats: &mut ConnectionStats,
+) -> Result<Option<MsgValue>, anyhow::Error>
+where
+    S: AsyncRead + Unpin,
+{
+    loop {
+        if let Some(len) = message_len(buf)? {
+            let msg = buf.split_to(len);
+            return Ok(Some(rmpv::decode::read_value(&mut &msg[..])?));
+        }
+        if buf.len() > max_size {
+            return Err(anyhow::anyhow!(
+                "message exceeds the limit of {max_size} bytes"
+            ));
+        }
+        let n = stream.read_buf(buf).await?;
+        if n == 0 {
+            if buf.is_empty() {
+                return Ok(None);
+            }
+            return Err(anyhow::anyhow!("connection closed within a message"));
+        }
+        stats.bytes += n as u64;
+    }
+}
+
+async fn write_message<S>(stream: &mut S, msg: &MsgValue) -> Result<(), anyhow::Error>
+where
+    S: AsyncWrite + Unpin,
+{
+    let mut data = Vec::new();
+    rmpv::encode::write_value(&mut data, msg)?;
+    stream.write_all(&data).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// Returns the length of the first MessagePack value of `buf`, or `None` if it is
+/// incomplete. Only the headers are inspected, so waiting for a large chunk doesn't decode
+/// it over and over.
+fn message_len(buf: &[u8]) -> Result<Option<usize>, anyhow::Error> {
+    let mut pos = 0;
+    let mut pending: u64 = 1;
+    while pending > 0 {
+        pending -= 1;
+        let Some(&marker) = buf.get(pos) else {
+            return Ok(None);
+        };
+        let size = |n: usize| -> Option<u64> {
+            let data = buf.get(pos + 1..pos + 1 + n)?;
+            Some(data.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64))
+        };
+        // (header length, payload length, number of nested values)
+        let (header, payload, nested) = match marker {
+            0x00..=0x7f | 0xe0..=0xff | 0xc0 | 0xc2 | 0xc3 => (1, 0, 0),
+            0x80..=0x8f => (1, 0, 2 * (marker & 0x0f) as u64),
+            0x90..=0x9f => (1, 0, (marker & 0x0f) as u64),
+            0xa0..=0xbf => (1, (marker & 0x1f) as u64, 0),
+            0xc4 | 0xd9 => match size(1) {
+                Some(n) => (2, n, 0),
+                None => return Ok(None),
+            },
+            0xc5 | 0xda => match size(2) {
+                Some(n) => (3, n, 0),
+                None => return Ok(None),
+            },
+            0xc6 | 0xdb => match size(4) {
+                Some(n) => (5, n, 0),
+                None => return Ok(None),
+            },
+            // ext: length, type and data
+            0xc7 => match size(1) {
+                Some(n) => (3, n, 0),
+                None => return Ok(None),
+            },
+            0xc8 => match size(2) {
+                Some(n) => (4, n, 0),
+                None => return Ok(None),
+            },
+            0xc9 => match size(4) {
+                Some(n) => (6, n, 0),
+                None => return Ok(None),
+            },
+            0xca => (5, 0, 0),
+            0xcb => (9, 0, 0),
+            0xcc | 0xd0 => (2, 0, 0),
+            0xcd | 0xd1 => (3, 0, 0),
+            0xce | 0xd2 => (5, 0, 0),
+            0xcf | 0xd3 => (9, 0, 0),
+            // fixext: type and data
+            0xd4 => (2, 1, 0),
+            0xd5 => (2, 2, 0),
+            0xd6 => (2, 4, 0),
+            0xd7 => (2, 8, 0),
+            0xd8 => (2, 16, 0),
+            0xdc => match size(2) {
+                Some(n) => (3, 0, n),
+                None => return Ok(None),
+            },
+            0xdd => match size(4) {
+                Some(n) => (5, 0, n),
+                None => return Ok(None),
+            },
+            0xde => match size(2) {
+                Some(n) => (3, 0, 2 * n),
+                None => return Ok(None),
+            },
+            0xdf => match size(4) {
+                Some(n) => (5, 0, 2 * n),
+                None => return Ok(None),
+            },
+            0xc1 => return Err(anyhow::anyhow!("invalid MessagePack data")),
+        };
+        pos += header + payload as usize;
+        pending += nested;
+    }
+    if pos > buf.len() {
+        return Ok(None);
+    }
+    Ok(Some(pos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(value: &MsgValue) -> Vec<u8> {
+        let mut data = Vec::new();
+        rmpv::encode::write_value(&mut data, value).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_message_len() {
+        let msg = MsgValue::Array(vec![
+            MsgValue::from("app.nginx"),
+            MsgValue::Binary(vec![0u8; 300]),
+            MsgValue::Map(vec![
+                (MsgValue::from("size"), MsgValue::from(1)),
+                (MsgValue::from("time"), MsgValue::F64(1.5)),
+                (MsgValue::from("ts"), MsgValue::Ext(0, vec![0u8; 8])),
+                (MsgValue::from("n"), MsgValue::from(-100_000)),
+            ]),
+        ]);
+        let data = encode(&msg);
+        assert_eq!(message_len(&data).unwrap(), Some(data.len()));
+        for len in [0, 1, 12, data.len() - 1] {
+            assert_eq!(message_len(&data[..len]).unwrap(), None);
+        }
+        let mut two = data.clone();
+        two.extend_from_slice(&data);
+        assert_eq!(message_len(&two).unwrap(), Some(data.len()));
+        assert!(message_len(&[0xc1]).is_err());
+    }
+
+    #[test]
+    fn test_check_ping() {
+        let nonce = b"0123456789abcdef";
+        let digest = sha512_hex(&[b"salt", b"client", nonce, b"secret"]);
+        let ping = MsgValue::Array(vec![
+            MsgValue::from("PING"),
+            MsgValue::from("client"),
+            MsgValue::from("salt"),
+            MsgValue::from(digest.as_str()),
+            MsgValue::from(""),
+            MsgValue::from(""),
+        ]);
+        let keys = [
+            ("org1".to_string(), "other".to_string()),
+            ("org2".to_string(), "secret".to_string()),
+        ];
+        assert_eq!(
+            check_ping(&ping, nonce, &keys).unwrap(),
+            (b"salt".to_vec(), "org2", "secret")
+        );
+        assert!(check_ping(&ping, nonce, &keys[..1]).is_err());
+        assert!(check_ping(&ping, nonce, &[]).is_err());
+        assert!(check_ping(&MsgValue::from("PING"), nonce, &keys).is_err());
+    }
+}
@@ -0,0 +1,255 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::{Buf, BytesMut};
+
+// the octet count can't be longer than the digits of the largest message
+const MAX_OCTET_COUNT_DIGITS: usize = 10;
+
+#[derive(Debug, PartialEq)]
+pub enum Frame {
+    Message(BytesMut),
+    /// A frame that was dropped, the decoder skips its bytes and continues with the next one
+    Invalid(String),
+}
+
+#[derive(Debug, PartialEq)]
+enum Discard {
+    None,
+    Bytes(usize),
+    Line,
+}
+
+/// Splits a syslog TCP stream into messages, cf. https://www.rfc-editor.org/rfc/rfc6587#section-3.4
+/// Messages starting with a digit use octet counting (`<len> <msg>`), also used by TLS
+/// transports (RFC 5425), all others are terminated by a newline.
+pub struct SyslogDecoder {
+    max_len: usize,
+    discard: Discard,
+}
+
+impl SyslogDecoder {
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            max_len,
+            discard: Discard::None,
+        }
+    }
+
+    /// Returns the next frame of `buf`, or `None` if more data is needed.
+    pub fn decode(&mut self, buf: &mut BytesMut) -> Option<Frame> {
+        loop {
+            match self.discard {
+                Discard::None => {}
+                Discard::Bytes(n) => {
+                    let len = n.min(buf.len());
+                    buf.advance(len);
+                    if len < n {
+                        self.discard = Discard::Bytes(n - len);
+                        return None;
+                    }
+                    self.discard = Discard::None;
+                }
+                Discard::Line => match buf.iter().position(|b| *b == b'\n') {
+                    Some(pos) => {
+                        buf.advance(pos + 1);
+                        self.discard = Discard::None;
+                    }
+                    None => {
+                        buf.clear();
+                        return None;
+                    }
+                },
+            }
+
+            // skip the trailers left between messages
+            let start = buf
+                .iter()
+                .position(|b| !matches!(b, b'\n' | b'\r' | b'\0'))
+                .unwrap_or(buf.len());
+            buf.advance(start);
+            if buf.is_empty() {
+                return None;
+            }
+
+            if buf[0].is_ascii_digit() {
+                return self.decode_octet_counting(buf);
+            }
+
+            match buf.iter().position(|b| *b == b'\n') {
+                Some(pos) if pos <= self.max_len => {
+                    let mut line = buf.split_to(pos);
+                    buf.advance(1);
+                    if line.ends_with(b"\r") {
+                        line.truncate(line.len() - 1);
+                    }
+                    return Some(Frame::Message(line));
+                }
+                Some(pos) => {
+                    buf.advance(pos + 1);
+                    return Some(Frame::Invalid(format!(
+                        "message of {pos} bytes exceeds the limit of {} bytes",
+                        self.max_len
+                    )));
+                }
+                None if buf.len() > self.max_len => {
+                    buf.clear();
+                    self.discard = Discard::Line;
+                    return Some(Frame::Invalid(format!(
+                        "message exceeds the limit of {} bytes",
+                        self.max_len
+                    )));
+                }
+                None => return None,
+            }
+        }
+    }
+
+    /// Returns the frame left in `buf` when the connection is closed, senders using newline
+    /// framing may not terminate the last message.
+    pub fn decode_eof(&mut self, buf: &mut BytesMut) -> Option<Frame> {
+        if let Some(frame) = self.decode(buf) {
+            return Some(frame);
+        }
+        if self.discard != Discard::None || buf.is_empty() {
+            return None;
+        }
+        let rest = buf.split();
+        if rest[0].is_ascii_digit() {
+            return Some(Frame::Invalid(
+                "incomplete octet counted message".to_string(),
+            ));
+        }
+        Some(Frame::Message(rest))
+    }
+
+    fn decode_octet_counting(&mut self, buf: &mut BytesMut) -> Option<Frame> {
+        let header = buf
+            .iter()
+            .take(MAX_OCTET_COUNT_DIGITS + 1)
+            .position(|b| *b == b' ');
+        let Some(header) = header else {
+            if buf.len() <= MAX_OCTET_COUNT_DIGITS && buf.iter().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            self.discard = Discard::Line;
+            return Some(Frame::Invalid("invalid octet count".to_string()));
+        };
+        let len = match std::str::from_utf8(&buf[..header])
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+        {
+            Some(len) => len,
+            None => {
+                self.discard = Discard::Line;
+                return Some(Frame::Invalid("invalid octet count".to_string()));
+            }
+        };
+        if len > self.max_len {
+            buf.advance(header + 1);
+            self.discard = Discard::Bytes(len);
+            return Some(Frame::Invalid(format!(
+                "message of {len} bytes exceeds the limit of {} bytes",
+                self.max_len
+            )));
+        }
+        if buf.len() < header + 1 + len {
+            buf.reserve(header + 1 + len - buf.len());
+            return None;
+        }
+        buf.advance(header + 1);
+        Some(Frame::Message(buf.split_to(len)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(decoder: &mut SyslogDecoder, buf: &mut BytesMut) -> Vec<Frame> {
+        let mut frames = vec![];
+        while let Some(frame) = decoder.decode(buf) {
+            frames.push(frame);
+        }
+        frames
+    }
+
+    fn message(v: &str) -> Frame {
+        Frame::Message(BytesMut::from(v))
+    }
+
+    #[test]
+    fn test_newline_framing() {
+        let mut decoder = SyslogDecoder::new(1024);
+        let mut buf = BytesMut::from("<13>first\r\n<13>second\n<13>par");
+        assert_eq!(
+            decode_all(&mut decoder, &mut buf),
+            vec![message("<13>first"), message("<13>second")]
+        );
+        buf.extend_from_slice(b"tial\n<13>last");
+        assert_eq!(
+            decode_all(&mut decoder, &mut buf),
+            vec![message("<13>partial")]
+        );
+        assert_eq!(decoder.decode_eof(&mut buf), Some(message("<13>last")));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_octet_counting() {
+        let mut decoder = SyslogDecoder::new(1024);
+        let mut buf = BytesMut::from("11 <13>with\nlf10 <13>secon");
+        assert_eq!(
+            decode_all(&mut decoder, &mut buf),
+            vec![message("<13>with\nlf")]
+        );
+        buf.extend_from_slice(b"d");
+        assert_eq!(
+            decode_all(&mut decoder, &mut buf),
+            vec![message("<13>second")]
+        );
+        let mut buf = BytesMut::from("12");
+        assert_eq!(decoder.decode(&mut buf), None);
+        assert_eq!(buf.len(), 2);
+    }
+
+    #[test]
+    fn test_oversized_messages() {
+        let mut decoder = SyslogDecoder::new(8);
+        let mut buf = BytesMut::from("16 <13>far too long<13>ok\n<13>also far too long\n");
+        let frames = decode_all(&mut decoder, &mut buf);
+        assert_eq!(frames.len(), 3);
+        assert!(matches!(frames[0], Frame::Invalid(_)));
+        assert_eq!(frames[1], message("<13>ok"));
+        assert!(matches!(frames[2], Frame::Invalid(_)));
+
+        // a message without newline is dropped until the next one
+        let mut buf = BytesMut::from("<13>no newline at all");
+        assert!(matches!(decoder.decode(&mut buf), Some(Frame::Invalid(_))));
+        buf.extend_from_slice(b" still\n<13>next\n");
+        assert_eq!(
+            decode_all(&mut decoder, &mut buf),
+            vec![message("<13>next")]
+        );
+    }
+
+    #[test]
+    fn test_invalid_octet_count() {
+        let mut decoder = SyslogDecoder::new(1024);
+        let mut buf = BytesMut::from("12x <13>bad\n<13>good\n");
+        let frames = decode_all(&mut decoder, &mut buf);
+        assert!(matches!(frames[0], Frame::Invalid(_)));
+        assert_eq!(frames[1..], [message("<13>good")]);
+    }
+}
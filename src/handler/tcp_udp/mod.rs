@@ -13,63 +13,200 @@
 // limitations under the License.
 
 use bytes::BytesMut;
+use std::{fs::File, io::BufReader, net::SocketAddr, sync::Arc};
 use tokio::{
-    io::AsyncReadExt,
+    io::{AsyncRead, AsyncReadExt},
     net::{TcpListener, UdpSocket},
+    sync::broadcast,
 };
+use tokio_rustls::{rustls, TlsAcceptor};
 
+use crate::common::infra::config::CONFIG;
 use crate::{job::syslog_server::BROADCASTER, service::logs::syslog};
 
+use framing::{Frame, SyslogDecoder};
+
+pub mod fluent;
+mod framing;
+
 pub static STOP_SRV: &str = "ZO_STOP_TCP_UDP";
 
+#[derive(Debug, Default)]
+struct ConnectionStats {
+    bytes: u64,
+    messages: u64,
+    bad_messages: u64,
+}
+
 pub async fn udp_server(socket: UdpSocket) {
     let mut buf_udp = vec![0u8; 1472];
-    let sender = BROADCASTER.read().await;
-    let mut udp_receiver_rx = sender.subscribe();
+    let mut udp_receiver_rx = BROADCASTER.read().await.subscribe();
     loop {
-        let (recv_len, addr) = socket.recv_from(&mut buf_udp).await.unwrap();
-        let message = BytesMut::from(&buf_udp[..recv_len]);
-        let input_str = String::from_utf8(message.to_vec()).unwrap();
-        if input_str != STOP_SRV {
-            let _ = syslog::ingest(&input_str, addr).await;
-        }
-        if let Ok(val) = udp_receiver_rx.try_recv() {
-            if !val {
+        let (recv_len, addr) = tokio::select! {
+            ret = socket.recv_from(&mut buf_udp) => match ret {
+                Ok(v) => v,
+                Err(e) => {
+                    log::error!("UDP server - error receiving message: {}", e);
+                    continue;
+                }
+            },
+            ret = udp_receiver_rx.recv() => {
+                if matches!(ret, Ok(true)) {
+                    continue;
+                }
                 log::warn!("UDP server - received the stop signal, exiting.");
-                drop(socket);
                 break;
             }
         };
+        match std::str::from_utf8(&buf_udp[..recv_len]) {
+            Ok(msg) if msg == STOP_SRV => {}
+            Ok(msg) => {
+                let _ = syslog::ingest(msg, addr).await;
+            }
+            Err(_) => log::debug!("UDP server - dropped invalid UTF-8 message from {addr}"),
+        }
     }
 }
 
-pub async fn tcp_server(listener: TcpListener) {
-    let sender = BROADCASTER.read().await;
-    let mut tcp_receiver_rx = sender.subscribe();
-    let mut buf_tcp = vec![0u8; 1460];
+pub async fn tcp_server(listener: TcpListener, tls: Option<TlsAcceptor>) {
+    let mut tcp_receiver_rx = BROADCASTER.read().await.subscribe();
     loop {
-        let (mut stream, _) = listener.accept().await.unwrap();
-
-        match stream.peer_addr() {
-            Ok(addr) => {
-                let len = stream.read(&mut buf_tcp).await.unwrap();
-                let message = BytesMut::from(&buf_tcp[..len]);
-                let input_str = String::from_utf8(message.to_vec()).unwrap();
-                if input_str != STOP_SRV {
-                    let _ = syslog::ingest(&input_str, addr).await;
+        let (stream, addr) = tokio::select! {
+            ret = listener.accept() => match ret {
+                Ok(v) => v,
+                Err(e) => {
+                    log::error!("TCP server - error accepting connection: {}", e);
+                    continue;
+                }
+            },
+            ret = tcp_receiver_rx.recv() => {
+                if matches!(ret, Ok(true)) {
+                    continue;
                 }
-                if let Ok(val) = tcp_receiver_rx.try_recv() {
-                    if !val {
-                        log::warn!("TCP server - received the stop signal, exiting.");
-                        drop(listener);
-                        break;
-                    }
-                };
+                log::warn!("TCP server - received the stop signal, exiting.");
+                break;
+            }
+        };
+
+        // every connection gets its own task, so a slow or misbehaving sender only
+        // affects itself
+        let stop_rx = BROADCASTER.read().await.subscribe();
+        let tls = tls.clone();
+        tokio::task::spawn(async move {
+            match tls {
+                Some(acceptor) => match acceptor.accept(stream).await {
+                    Ok(stream) => handle_connection(stream, addr, stop_rx).await,
+                    Err(e) => log::warn!("TCP server - TLS handshake with {addr} failed: {e}"),
+                },
+                None => handle_connection(stream, addr, stop_rx).await,
             }
-            Err(e) => {
-                log::error!("Error while writing to TCP stream: {}", e);
-                continue;
+        });
+    }
+}
+
+/// Reads syslog messages from a connection until the sender closes it, the server is
+/// stopped or the sender exceeds `ZO_TCP_MAX_BAD_MESSAGES`.
+async fn handle_connection<S>(
+    mut stream: S,
+    addr: SocketAddr,
+    mut stop_rx: broadcast::Receiver<bool>,
+) where
+    S: AsyncRead + Unpin,
+{
+    let max_bad_messages = CONFIG.tcp.tcp_max_bad_messages;
+    let mut decoder = SyslogDecoder::new(CONFIG.tcp.tcp_max_message_size);
+    let mut buf = BytesMut::with_capacity(8 * 1024);
+    let mut stats = ConnectionStats::default();
+    'conn: loop {
+        let eof = tokio::select! {
+            ret = stream.read_buf(&mut buf) => match ret {
+                Ok(0) => true,
+                Ok(n) => {
+                    stats.bytes += n as u64;
+                    false
+                }
+                Err(e) => {
+                    log::warn!("TCP server - error reading from {addr}: {e}");
+                    break;
+                }
+            },
+            ret = stop_rx.recv() => {
+                if matches!(ret, Ok(true)) {
+                    continue;
+                }
+                break;
             }
         };
+
+        loop {
+            let frame = if eof {
+                decoder.decode_eof(&mut buf)
+            } else {
+                decoder.decode(&mut buf)
+            };
+            let Some(frame) = frame else {
+                break;
+            };
+            if let Err(e) = ingest_frame(frame, addr).await {
+                stats.bad_messages += 1;
+                log::debug!("TCP server - dropped message from {addr}: {e}");
+                if max_bad_messages > 0 && stats.bad_messages > max_bad_messages {
+                    log::warn!(
+                        "TCP server - closing connection from {addr} after {} bad messages",
+                        stats.bad_messages
+                    );
+                    break 'conn;
+                }
+            } else {
+                stats.messages += 1;
+            }
+        }
+        if eof {
+            break;
+        }
+    }
+    log::info!(
+        "TCP server - connection from {addr} closed, bytes: {}, messages: {}, bad messages: {}",
+        stats.bytes,
+        stats.messages,
+        stats.bad_messages
+    );
+}
+
+async fn ingest_frame(frame: Frame, addr: SocketAddr) -> Result<(), anyhow::Error> {
+    let msg = match frame {
+        Frame::Message(msg) => msg,
+        Frame::Invalid(reason) => return Err(anyhow::anyhow!(reason)),
+    };
+    let msg = std::str::from_utf8(&msg).map_err(|_| anyhow::anyhow!("invalid UTF-8"))?;
+    if msg == STOP_SRV {
+        return Ok(());
+    }
+    syslog::ingest(msg, addr).await?;
+    Ok(())
+}
+
+/// Loads the certificate chain and private key for syslog over TLS, cf. RFC 5425.
+pub fn tls_acceptor(cert_path: &str, key_path: &str) -> Result<TlsAcceptor, anyhow::Error> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(cert_path)?))?
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect::<Vec<_>>();
+    if certs.is_empty() {
+        return Err(anyhow::anyhow!("no certificate found in {cert_path}"));
     }
+    let key = rustls_pemfile::read_all(&mut BufReader::new(File::open(key_path)?))?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(rustls::PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| anyhow::anyhow!("no private key found in {key_path}"))?;
+    let config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
 }
@@ -30,5 +30,8 @@ pub async fn run() -> Result<(), anyhow::Error> {
         if ret.is_err() {
             log::error!("[ALERT MANAGER] run error: {}", ret.err().unwrap());
         }
+        if let Err(e) = service::alerts::silences::prune_expired().await {
+            log::error!("[ALERT MANAGER] prune silences error: {}", e);
+        }
     }
 }
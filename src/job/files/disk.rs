@@ -22,21 +22,20 @@ use std::{
 use tokio::{sync::Semaphore, task, time};
 
 use crate::common::{
-    infra::{
-        cluster,
-        config::{COLUMN_TRACE_ID, CONFIG},
-        metrics, storage, wal,
-    },
+    infra::{cluster, config::CONFIG, metrics, storage, wal},
     meta::{common::FileMeta, stream::StreamParams, StreamType},
     utils::{
         file::scan_files,
         json,
         schema::{infer_json_schema_from_iterator, infer_json_schema_from_seekable},
-        stream::populate_file_meta,
+        stream::{populate_field_summary, populate_file_meta},
     },
 };
 use crate::service::{
-    db, schema::schema_evolution, search::datafusion::new_parquet_writer,
+    db,
+    schema::schema_evolution,
+    search::{datafusion::new_parquet_writer, inverted_index},
+    stream::{get_stream_setting_bloom_filter_fields, get_stream_setting_fts_fields},
     usage::report_compression_stats,
 };
 
@@ -279,22 +278,27 @@ async fn upload_file(
         records: 0,
         original_size: file_size as i64,
         compressed_size: 0,
+        field_summary: Default::default(),
     };
     populate_file_meta(arrow_schema.clone(), vec![batches.to_vec()], &mut file_meta).await?;
+    let stream_schema = db::schema::get(org_id, stream_name, stream_type).await?;
+    let fts_fields = get_stream_setting_fts_fields(&stream_schema).unwrap_or_default();
+    populate_field_summary(&batches, &fts_fields, &mut file_meta);
+
+    let index = if inverted_index::enabled(stream_type) {
+        inverted_index::build(&batches)
+    } else {
+        None
+    };
 
     // write parquet file
     let mut buf_parquet = Vec::new();
-    let bf_fields =
-        if CONFIG.common.traces_bloom_filter_enabled && stream_type == StreamType::Traces {
-            Some(vec![COLUMN_TRACE_ID])
-        } else {
-            None
-        };
+    let bf_fields = get_stream_setting_bloom_filter_fields(&stream_schema, stream_type);
     let mut writer = new_parquet_writer(
         &mut buf_parquet,
         &arrow_schema,
         file_meta.records as u64,
-        bf_fields,
+        &bf_fields,
     );
     for batch in batches {
         writer.write(&batch)?;
@@ -313,6 +317,8 @@ async fn upload_file(
 
     let new_file_name =
         super::generate_storage_file_name(org_id, stream_type, stream_name, file_name);
+    // upload the index before the file, so queries never list the file without it
+    inverted_index::write(&new_file_name, index).await;
     drop(file);
     let file_name = new_file_name.to_owned();
     match task::spawn_blocking(move || async move {
@@ -0,0 +1,28 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+
+use crate::{common::infra::config::CONFIG, handler::tcp_udp::fluent::fluent_server};
+
+pub async fn run() -> Result<(), anyhow::Error> {
+    let addr: SocketAddr = format!("0.0.0.0:{}", CONFIG.fluent.port).parse()?;
+    log::info!("Starting Fluent Forward server on {addr}");
+    let listener = TcpListener::bind(addr).await?;
+    tokio::task::spawn(async move {
+        fluent_server(listener).await;
+    });
+    Ok(())
+}
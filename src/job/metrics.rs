@@ -42,6 +42,7 @@ pub async fn run() -> Result<(), anyhow::Error> {
         if let Err(e) = update_storage_metrics().await {
             log::error!("Error update storage metrics: {}", e);
         }
+        crate::service::metrics::temporality::clean_stale_series();
         interval.tick().await;
     }
 }
@@ -29,12 +29,18 @@ mod alert_manager;
 mod compact;
 pub(crate) mod file_list;
 pub(crate) mod files;
+mod fluent_server;
+mod hec_acks;
+mod kafka;
 mod metrics;
 mod mmdb_downloader;
 mod prom;
+mod recording_rules;
+mod span_metrics;
 mod stats;
 pub(crate) mod syslog_server;
 mod telemetry;
+mod trace_sampling;
 
 pub async fn init() -> Result<(), anyhow::Error> {
     let email_regex = Regex::new(
@@ -104,8 +110,14 @@ pub async fn init() -> Result<(), anyhow::Error> {
     tokio::task::spawn(async move { db::functions::watch().await });
     tokio::task::spawn(async move { db::compact::retention::watch().await });
     tokio::task::spawn(async move { db::metrics::watch_prom_cluster_leader().await });
+    tokio::task::spawn(async move { db::recording_rules::watch().await });
+    tokio::task::spawn(async move { db::recording_rules::watch_leaders().await });
+    tokio::task::spawn(async move { db::kafka::watch().await });
+    tokio::task::spawn(async move { db::kafka::watch_leaders().await });
     tokio::task::spawn(async move { db::alerts::templates::watch().await });
     tokio::task::spawn(async move { db::alerts::destinations::watch().await });
+    tokio::task::spawn(async move { db::alerts::silences::watch().await });
+    tokio::task::spawn(async move { db::alerts::maintenance_windows::watch().await });
     tokio::task::spawn(async move { db::alerts::watch().await });
     tokio::task::spawn(async move { db::triggers::watch().await });
     tokio::task::spawn(async move { db::organization::watch().await });
@@ -122,6 +134,18 @@ pub async fn init() -> Result<(), anyhow::Error> {
     db::metrics::cache_prom_cluster_leader()
         .await
         .expect("prom cluster leader cache failed");
+    db::recording_rules::cache()
+        .await
+        .expect("recording rules cache failed");
+    db::recording_rules::cache_leaders()
+        .await
+        .expect("recording rule leaders cache failed");
+    db::kafka::cache()
+        .await
+        .expect("kafka sources cache failed");
+    db::kafka::cache_leaders()
+        .await
+        .expect("kafka source leaders cache failed");
 
     // cache alerts
     db::alerts::templates::cache()
@@ -130,6 +154,12 @@ pub async fn init() -> Result<(), anyhow::Error> {
     db::alerts::destinations::cache()
         .await
         .expect("alerts destinations cache failed");
+    db::alerts::silences::cache()
+        .await
+        .expect("alerts silences cache failed");
+    db::alerts::maintenance_windows::cache()
+        .await
+        .expect("alerts maintenance windows cache failed");
     db::alerts::cache().await.expect("alerts cache failed");
     db::triggers::cache()
         .await
@@ -138,6 +168,9 @@ pub async fn init() -> Result<(), anyhow::Error> {
     db::syslog::cache_syslog_settings()
         .await
         .expect("syslog settings cache failed");
+    db::fluent::cache()
+        .await
+        .expect("fluent routes cache failed");
 
     // cache file list
     if !CONFIG.common.meta_store_external {
@@ -179,6 +212,11 @@ pub async fn init() -> Result<(), anyhow::Error> {
     tokio::task::spawn(async move { compact::run().await });
     tokio::task::spawn(async move { metrics::run().await });
     tokio::task::spawn(async move { prom::run().await });
+    tokio::task::spawn(async move { recording_rules::run().await });
+    tokio::task::spawn(async move { span_metrics::run().await });
+    tokio::task::spawn(async move { trace_sampling::run().await });
+    tokio::task::spawn(async move { kafka::run().await });
+    tokio::task::spawn(async move { hec_acks::run().await });
     tokio::task::spawn(async move { alert_manager::run().await });
 
     // Shouldn't serve request until initialization finishes
@@ -195,5 +233,14 @@ pub async fn init() -> Result<(), anyhow::Error> {
             .expect("syslog server run failed");
     }
 
+    // Fluent Forward server start
+    tokio::task::spawn(async move { db::fluent::watch().await });
+    tokio::task::spawn(async move { db::fluent::watch_keys().await });
+    if CONFIG.fluent.enabled && cluster::is_ingester(&cluster::LOCAL_NODE_ROLE) {
+        fluent_server::run()
+            .await
+            .expect("fluent server run failed");
+    }
+
     Ok(())
 }
@@ -0,0 +1,35 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use tokio::time::{self, Duration};
+
+use crate::common::infra::{cluster, config::CONFIG};
+use crate::service::traces::span_metrics;
+
+pub async fn run() -> Result<(), anyhow::Error> {
+    if !CONFIG.common.traces_span_metrics_enabled
+        || !cluster::is_ingester(&cluster::LOCAL_NODE_ROLE)
+    {
+        return Ok(()); // spans are aggregated by the ingesters receiving them
+    }
+
+    let mut interval = time::interval(Duration::from_secs(
+        CONFIG.limit.traces_span_metrics_interval,
+    ));
+    interval.tick().await; // trigger the first run
+    loop {
+        interval.tick().await;
+        span_metrics::flush().await;
+    }
+}
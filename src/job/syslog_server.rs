@@ -13,19 +13,17 @@
 // limitations under the License.
 
 use once_cell::sync::Lazy;
-use std::io::Write;
-use std::net::{SocketAddr, TcpStream};
+use std::net::SocketAddr;
 use tokio::sync::RwLock;
 use tokio::{
     net::{TcpListener, UdpSocket},
     sync::broadcast,
 };
 
-use crate::handler::tcp_udp::STOP_SRV;
 use crate::service::db::syslog::toggle_syslog_setting;
 use crate::{
     common::infra::config::{CONFIG, SYSLOG_ENABLED},
-    handler::tcp_udp::{tcp_server, udp_server},
+    handler::tcp_udp::{tcp_server, tls_acceptor, udp_server},
 };
 
 //TCP UDP Server
@@ -41,27 +39,28 @@ pub async fn run(start_srv: bool, is_init: bool) -> Result<(), anyhow::Error> {
     let udp_addr: SocketAddr = format!("{bind_addr}:{}", CONFIG.tcp.udp_port).parse()?;
     if (!server_running || is_init) && start_srv {
         log::info!("Starting TCP UDP server");
+        let tls = if CONFIG.tcp.tcp_tls_enabled {
+            Some(tls_acceptor(
+                &CONFIG.tcp.tcp_tls_cert_path,
+                &CONFIG.tcp.tcp_tls_key_path,
+            )?)
+        } else {
+            None
+        };
         let tcp_listener: TcpListener = TcpListener::bind(tcp_addr).await?;
         let udp_socket = UdpSocket::bind(udp_addr).await?;
         tokio::task::spawn(async move {
-            _ = tcp_server(tcp_listener).await;
+            _ = tcp_server(tcp_listener, tls).await;
         });
         tokio::task::spawn(async move {
             _ = udp_server(udp_socket).await;
         });
         toggle_syslog_setting(start_srv).await.unwrap();
     } else if server_running && !start_srv {
-        // stop running server
+        // stop running server, the servers and their connections listen to the broadcast
         let sender = BROADCASTER.read().await;
         let _ = sender.send(start_srv);
-
-        let socket = UdpSocket::bind("0.0.0.0:34254").await?;
-        socket.send_to(STOP_SRV.as_bytes(), udp_addr).await?;
-        let mut stream = TcpStream::connect(tcp_addr)?;
-        stream.write_all(STOP_SRV.as_bytes())?;
-
-        drop(socket);
-        drop(stream);
+        drop(sender);
         toggle_syslog_setting(start_srv).await.unwrap();
     }
 
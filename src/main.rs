@@ -59,9 +59,11 @@ use openobserve::{
                 metrics_server::MetricsServer, search_server::SearchServer,
                 usage_server::UsageServer,
             },
+            flight_rpc::flight_service_server::FlightServiceServer,
             request::{
                 event::Eventer,
                 file_list::Filelister,
+                flight::FlightSqlServer,
                 logs::LogsServer,
                 metrics::{ingester::Ingester, querier::Querier},
                 search::Searcher,
@@ -72,7 +74,7 @@ use openobserve::{
         http::router::*,
     },
     job,
-    service::{compact, db, distinct_values, file_list, router, users},
+    service::{compact, db, distinct_values, file_list, router, traces, users},
 };
 
 #[cfg(feature = "profiling")]
@@ -260,6 +262,8 @@ async fn main() -> Result<(), anyhow::Error> {
         .await;
     // leave the cluster
     _ = cluster::leave().await;
+    // write the spans of the traces buffered for tail sampling
+    traces::sampling::flush_all().await;
     // flush WAL cache to disk
     infra::wal::flush_all_to_disk().await;
     // flush compact offset cache to disk disk
@@ -302,6 +306,7 @@ fn init_grpc_server() -> Result<(), anyhow::Error> {
     let logs_svc = LogsServiceServer::new(LogsServer)
         .send_compressed(CompressionEncoding::Gzip)
         .accept_compressed(CompressionEncoding::Gzip);
+    let flight_svc = FlightServiceServer::new(FlightSqlServer);
     let tracer = TraceServer::default();
     let trace_svc = TraceServiceServer::new(tracer)
         .send_compressed(CompressionEncoding::Gzip)
@@ -319,6 +324,7 @@ fn init_grpc_server() -> Result<(), anyhow::Error> {
             .add_service(trace_svc)
             .add_service(usage_svc)
             .add_service(logs_svc)
+            .add_service(flight_svc)
             .serve(gaddr)
             .await
             .expect("gRPC server init failed");
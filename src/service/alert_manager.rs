@@ -12,24 +12,37 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use ahash::HashMap as AHashMap;
 use chrono::Utc;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use tokio::time;
 
 use crate::common::infra::config::{TRIGGERS, TRIGGERS_IN_PROCESS};
+use crate::common::infra::errors::Error;
 use crate::common::meta;
-use crate::common::meta::alert::{Evaluate, Trigger, TriggerTimer};
+use crate::common::meta::alert::{
+    Alert, AlertState, Evaluate, Trigger, TriggerHistory, TriggerState, TriggerTimer,
+    ALERT_AGG_MAX_GROUPS, ALERT_AGG_VALUE_COLUMN,
+};
 use crate::common::meta::search::Request;
-use crate::common::utils::notification::send_notification;
+use crate::common::utils::json::{self, Map, Value};
+use crate::common::utils::notification::send_group_notification;
+use crate::service::alerts::silences;
+use crate::service::promql;
 use crate::service::search as SearchService;
 use crate::service::triggers;
 
+/// labels and result row of every matching alert instance, keyed by group
+type ActiveInstances = HashMap<String, (AHashMap<String, String>, Map<String, Value>)>;
+
 #[cfg_attr(coverage_nightly, no_coverage)]
 pub async fn run() -> Result<(), anyhow::Error> {
     for trigger in TRIGGERS.iter() {
+        let trigger = trigger.clone();
         if !trigger.is_ingest_time {
-            let trigger = trigger.clone();
             tokio::task::spawn(async move { handle_triggers(trigger).await });
+        } else if !trigger.instances.is_empty() {
+            tokio::task::spawn(async move { resolve_real_time_trigger(trigger).await });
         }
     }
     Ok(())
@@ -103,41 +116,16 @@ async fn handle_trigger(alert_key: &str, frequency: i64) {
 
             match alert_resp.unwrap_or(None) {
                 Some(alert) => {
-                    let mut query = alert.query.clone().unwrap();
-                    let curr_ts = Utc::now().timestamp_micros();
-                    query.end_time = curr_ts;
-                    query.start_time = curr_ts - get_micros_from_min(alert.duration);
-                    let req: meta::search::Request = Request {
-                        query,
-                        aggs: HashMap::new(),
-                        encoding: meta::search::RequestEncoding::Empty,
-                        timeout: 0,
+                    let active = if alert.is_promql() {
+                        evaluate_promql(&trigger.org, &alert).await
+                    } else {
+                        evaluate_query(&trigger.org, &alert).await
                     };
-                    // do search
-                    match SearchService::search(&trigger.org, alert.stream_type.unwrap(), &req)
-                        .await
-                    {
-                        Ok(res) => {
-                            if !res.hits.is_empty() {
-                                let record = res.hits.first().unwrap().as_object().unwrap();
-                                if alert.condition.evaluate(record.clone()) {
-                                    let curr_ts = Utc::now().timestamp_micros();
-                                    let mut local_trigger = trigger.clone();
-
-                                    if trigger.last_sent_at == 0
-                                        || (trigger.last_sent_at > 0
-                                            && curr_ts - trigger.last_sent_at
-                                                > get_micros_from_min(alert.time_between_alerts))
-                                    {
-                                        let _ = send_notification(&alert, &trigger).await;
-                                        local_trigger.last_sent_at = curr_ts;
-                                    }
-                                    //Update trigger for last sent
-
-                                    local_trigger.count += 1;
-                                    let _ =
-                                        triggers::save_trigger(&alert.name, &local_trigger).await;
-                                }
+                    match active {
+                        Ok(active) => {
+                            let mut local_trigger = trigger.clone();
+                            if update_instances(&alert, &mut local_trigger, &active).await {
+                                let _ = triggers::save_trigger(&alert.name, &local_trigger).await;
                             }
                         }
                         Err(err) => {
@@ -151,6 +139,310 @@ async fn handle_trigger(alert_key: &str, frequency: i64) {
     }
 }
 
+/// Runs the alert query and returns the labels and result row of every matching alert
+/// instance, keyed by group.
+async fn evaluate_query(org_id: &str, alert: &Alert) -> Result<ActiveInstances, Error> {
+    let mut query = alert.query.clone().unwrap();
+    let curr_ts = Utc::now().timestamp_micros();
+    query.end_time = curr_ts;
+    query.start_time = curr_ts - get_micros_from_min(alert.duration);
+    let req: meta::search::Request = Request {
+        query,
+        aggs: HashMap::new(),
+        encoding: meta::search::RequestEncoding::Empty,
+        timeout: 0,
+    };
+    let res = SearchService::search(org_id, alert.stream_type.unwrap(), &req).await?;
+    let mut active = HashMap::new();
+    match alert.aggregation.as_ref() {
+        Some(aggregation) => {
+            if res.hits.len() >= ALERT_AGG_MAX_GROUPS {
+                log::warn!(
+                    "alert_manager: alert {} has more than {ALERT_AGG_MAX_GROUPS} groups, only the first ones are evaluated",
+                    alert.name
+                );
+            }
+            for hit in res.hits.iter() {
+                let record = hit.as_object().unwrap();
+                if alert.evaluate(record.clone()) {
+                    active.insert(
+                        aggregation.group_key(record),
+                        (aggregation.group_labels(record), record.clone()),
+                    );
+                }
+            }
+        }
+        None => {
+            if let Some(hit) = res.hits.first() {
+                let record = hit.as_object().unwrap();
+                if alert.evaluate(record.clone()) {
+                    active.insert(String::new(), (Default::default(), record.clone()));
+                }
+            }
+        }
+    }
+    Ok(active)
+}
+
+/// Evaluates the PromQL expression of the alert at the current instant, every resulting
+/// series that matches the alert conditions is an alert instance.
+async fn evaluate_promql(org_id: &str, alert: &Alert) -> Result<ActiveInstances, Error> {
+    let curr_ts = Utc::now().timestamp_micros();
+    let req = promql::MetricsQueryRequest {
+        query: alert.promql.clone().unwrap(),
+        start: curr_ts,
+        end: curr_ts,
+        step: get_micros_from_min(alert.frequency.max(1)),
+    };
+    let value = promql::search::search(org_id, &req, 0).await?;
+    Ok(promql_instances(value)
+        .into_iter()
+        .filter(|(_, (_, row))| alert.evaluate(row.clone()))
+        .collect())
+}
+
+/// Turns the result of an instant PromQL query into alert instances keyed by the series
+/// labels, e.g. `instance=a,job=api`. The row holds the labels and the sample value as
+/// `zo_alert_value`, so they can be used in conditions and templates.
+fn promql_instances(value: promql::value::Value) -> ActiveInstances {
+    use promql::value::{Labels, Value as PromValue};
+
+    let series: Vec<(Labels, f64)> = match value {
+        PromValue::Instant(v) => vec![(v.labels, v.sample.value)],
+        PromValue::Vector(vs) => vs.into_iter().map(|v| (v.labels, v.sample.value)).collect(),
+        PromValue::Range(v) => v
+            .samples
+            .last()
+            .map(|s| vec![(v.labels.clone(), s.value)])
+            .unwrap_or_default(),
+        PromValue::Matrix(vs) => vs
+            .into_iter()
+            .filter_map(|v| v.samples.last().map(|s| (v.labels.clone(), s.value)))
+            .collect(),
+        PromValue::Sample(s) => vec![(Labels::default(), s.value)],
+        PromValue::Float(val) => vec![(Labels::default(), val)],
+        PromValue::String(_) | PromValue::None => vec![],
+    };
+
+    let mut active = HashMap::new();
+    for (labels, value) in series {
+        if value.is_nan() {
+            continue;
+        }
+        let mut row = Map::new();
+        let mut group_labels = AHashMap::default();
+        for label in labels.iter() {
+            row.insert(label.name.clone(), Value::String(label.value.clone()));
+            group_labels.insert(label.name.clone(), label.value.clone());
+        }
+        row.insert(ALERT_AGG_VALUE_COLUMN.to_string(), json::json!(value));
+        let group_key = labels
+            .iter()
+            .map(|label| format!("{}={}", label.name, label.value))
+            .collect::<Vec<_>>()
+            .join(",");
+        active.insert(group_key, (group_labels, row));
+    }
+    active
+}
+
+/// Moves every known and every newly matching instance of the alert through its states,
+/// records the state changes and sends the notifications. Returns true if the trigger changed.
+async fn update_instances(alert: &Alert, trigger: &mut Trigger, active: &ActiveInstances) -> bool {
+    let now = Utc::now().timestamp_micros();
+    let mut group_keys: HashSet<String> = trigger.instances.keys().cloned().collect();
+    group_keys.extend(active.keys().cloned());
+
+    let mut changed = false;
+    for group_key in group_keys {
+        let mut instance = trigger.instances.remove(&group_key).unwrap_or_default();
+        let row = match active.get(&group_key) {
+            Some((labels, row)) => {
+                instance.labels = labels.clone();
+                Some(row)
+            }
+            None => None,
+        };
+        changed |= update_instance(alert, trigger, &group_key, &mut instance, row, now).await;
+        if instance.state != AlertState::Resolved {
+            trigger.instances.insert(group_key, instance);
+        }
+    }
+    changed
+}
+
+/// Moves one alert instance through its states, the instance is active if there is a
+/// row. A silenced firing notification doesn't move the instance to firing: it stays
+/// pending and fires once the silence is over, so it is never resolved without having
+/// been notified. Returns true if the instance or the trigger changed.
+async fn update_instance(
+    alert: &Alert,
+    trigger: &mut Trigger,
+    group_key: &str,
+    instance: &mut TriggerState,
+    row: Option<&Map<String, Value>>,
+    now: i64,
+) -> bool {
+    let for_duration = get_micros_from_min(alert.for_duration);
+    let time_between_alerts = get_micros_from_min(alert.time_between_alerts);
+    let before = instance.clone();
+    let mut notify = instance.transition(row.is_some(), now, for_duration, time_between_alerts);
+    if let Some(state) = notify {
+        if silences::is_silenced(&trigger.org, &trigger.labels(&instance.labels), now) {
+            log::info!(
+                "[ALERT MANAGER] {} notification of {}/{} [{}] is silenced",
+                state,
+                trigger.org,
+                trigger.alert_name,
+                group_key
+            );
+            if state == AlertState::Firing && before.state != AlertState::Firing {
+                instance.state = AlertState::Pending;
+                instance.last_sent_at = before.last_sent_at;
+            }
+            notify = None;
+        }
+    }
+
+    if before.state != instance.state {
+        let history = TriggerHistory {
+            timestamp: now,
+            org: trigger.org.clone(),
+            stream: trigger.stream.clone(),
+            stream_type: trigger.stream_type,
+            alert_name: trigger.alert_name.clone(),
+            group_key: group_key.to_string(),
+            from: before.state,
+            to: instance.state,
+        };
+        if let Err(e) = super::db::trigger_history::add(&history).await {
+            log::error!("[ALERT MANAGER] Error saving trigger history: {}", e);
+        }
+    }
+    if let Some(state) = notify {
+        // a resolved instance isn't active anymore
+        let active_at = if instance.active_at > 0 {
+            instance.active_at
+        } else {
+            before.active_at
+        };
+        let _ = send_group_notification(
+            alert,
+            trigger,
+            state,
+            group_key,
+            &instance.labels,
+            row,
+            active_at,
+        )
+        .await;
+        trigger.last_sent_at = now;
+        if state == AlertState::Firing {
+            trigger.count += 1;
+        }
+    }
+    notify.is_some() || before != *instance
+}
+
+/// Handles a record matching a real time alert, the alert has a single instance which is
+/// resolved by `resolve_real_time_trigger` once no record matched for the alert duration.
+pub async fn handle_real_time_trigger(alert: &Alert, trigger: Trigger) {
+    let now = Utc::now().timestamp_micros();
+    let key = format!("{}/{}", &trigger.org, &trigger.alert_name);
+    let mut trigger = match TRIGGERS.get(&key) {
+        Some(saved) => saved.value().clone(),
+        None => trigger,
+    };
+    // the last time a record matched
+    trigger.timestamp = now;
+    trigger.is_ingest_time = true;
+    let mut instance = trigger.instances.remove("").unwrap_or_default();
+    update_instance(
+        alert,
+        &mut trigger,
+        "",
+        &mut instance,
+        Some(&Map::new()),
+        now,
+    )
+    .await;
+    if instance.state != AlertState::Resolved {
+        trigger.instances.insert(String::new(), instance);
+    }
+    let _ = triggers::save_trigger(&alert.name, &trigger).await;
+}
+
+/// Resolves the instance of a real time alert when no record matched for the alert duration.
+async fn resolve_real_time_trigger(mut trigger: Trigger) {
+    let now = Utc::now().timestamp_micros();
+    let mut instance = match trigger.instances.remove("") {
+        Some(instance) => instance,
+        None => return,
+    };
+    let alert = match super::db::alerts::get(
+        &trigger.org,
+        &trigger.stream,
+        trigger.stream_type,
+        &trigger.alert_name,
+    )
+    .await
+    {
+        Ok(Some(alert)) => alert,
+        Ok(None) => return,
+        Err(e) => {
+            log::error!("[ALERT MANAGER] Error fetching alert: {}", e);
+            return;
+        }
+    };
+    if now - trigger.timestamp < get_micros_from_min(alert.duration.max(1)) {
+        return;
+    }
+    update_instance(&alert, &mut trigger, "", &mut instance, None, now).await;
+    if instance.state != AlertState::Resolved {
+        trigger.instances.insert(String::new(), instance);
+    }
+    let _ = triggers::save_trigger(&alert.name, &trigger).await;
+}
+
 fn get_micros_from_min(min: i64) -> i64 {
     min * 60 * 1000000
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::promql::value::{InstantValue, Label, Sample, Value as PromValue};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_promql_instances() {
+        let series = |instance: &str, value: f64| InstantValue {
+            labels: vec![
+                Arc::new(Label::new("instance", instance)),
+                Arc::new(Label::new("job", "api")),
+            ],
+            sample: Sample::new(0, value),
+        };
+        let active = promql_instances(PromValue::Vector(vec![
+            series("a", 1.5),
+            series("b", f64::NAN),
+        ]));
+        assert_eq!(active.len(), 1);
+        let (labels, row) = active.get("instance=a,job=api").unwrap();
+        assert_eq!(labels.get("instance").unwrap(), "a");
+        assert_eq!(row.get("job").unwrap(), "api");
+        assert_eq!(row.get(ALERT_AGG_VALUE_COLUMN).unwrap(), 1.5);
+
+        let active = promql_instances(PromValue::Float(3.0));
+        assert_eq!(
+            active
+                .get("")
+                .unwrap()
+                .1
+                .get(ALERT_AGG_VALUE_COLUMN)
+                .unwrap(),
+            3.0
+        );
+        assert!(promql_instances(PromValue::None).is_empty());
+    }
+}
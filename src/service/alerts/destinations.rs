@@ -13,19 +13,107 @@
 // limitations under the License.
 
 use actix_web::{http, HttpResponse};
+use chrono::{TimeZone, Utc};
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use once_cell::sync::Lazy;
 use std::io::Error;
+use std::time::Duration;
 
-use crate::common::infra::config::STREAM_ALERTS;
-use crate::common::meta::alert::AlertDestination;
+use crate::common::infra::config::{CONFIG, STREAM_ALERTS};
+use crate::common::meta::alert::{
+    AlertDestType, AlertDestination, AlertDestinationResponse, AlertHTTPType, AlertState,
+    ALERT_AGG_VALUE_COLUMN,
+};
 use crate::common::meta::http::HttpResponse as MetaHttpResponse;
+use crate::common::meta::StreamType;
+use crate::common::utils::json::{self, json, Map, Value};
 use crate::service::db;
 
+const PAGERDUTY_EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+const ALERTMANAGER_ALERTS_PATH: &str = "/api/v2/alerts";
+
+/// Clients of the HTTP destinations, shared so that connections are reused.
+static HTTP_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| http_client(false));
+static HTTP_CLIENT_SKIP_TLS_VERIFY: Lazy<reqwest::Client> = Lazy::new(|| http_client(true));
+
+fn http_client(skip_tls_verify: bool) -> reqwest::Client {
+    let timeout = Duration::from_secs(CONFIG.limit.alert_notification_timeout);
+    reqwest::Client::builder()
+        .connect_timeout(timeout)
+        .timeout(timeout)
+        .danger_accept_invalid_certs(skip_tls_verify)
+        .build()
+        .expect("alert destinations http client build failed")
+}
+
+/// One rendered notification of an alert instance.
+#[derive(Clone, Debug)]
+pub struct Notification {
+    pub org: String,
+    pub stream: String,
+    pub stream_type: StreamType,
+    pub alert_name: String,
+    pub state: AlertState,
+    pub group_key: String,
+    /// labels identifying the alert instance, i.e. the group by values
+    pub labels: Vec<(String, String)>,
+    /// the rendered destination template
+    pub message: Value,
+    /// in microseconds
+    pub timestamp: i64,
+    /// since when the instance is active, in microseconds
+    pub starts_at: i64,
+    /// until when the instance is considered active without another notification, the
+    /// time it got resolved for resolved instances, in microseconds
+    pub ends_at: i64,
+}
+
+impl Notification {
+    fn text(&self) -> String {
+        match &self.message {
+            Value::String(v) => v.clone(),
+            v => json::to_string(v).unwrap(),
+        }
+    }
+
+    fn dedup_key(&self) -> String {
+        let key = format!(
+            "{}/{}/{}/{}",
+            self.org, self.stream_type, self.stream, self.alert_name
+        );
+        if self.group_key.is_empty() {
+            key
+        } else {
+            format!("{key}/{}", self.group_key)
+        }
+    }
+
+    fn rfc3339(&self) -> String {
+        rfc3339(self.timestamp)
+    }
+}
+
+fn rfc3339(micros: i64) -> String {
+    Utc.timestamp_nanos(micros * 1000).to_rfc3339()
+}
+
 #[tracing::instrument(skip(destination))]
 pub async fn save_destination(
     org_id: String,
     name: String,
     destination: AlertDestination,
 ) -> Result<HttpResponse, Error> {
+    if let Err(e) = validate_destination(&destination) {
+        return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+            http::StatusCode::BAD_REQUEST.into(),
+            e,
+        )));
+    }
+
     db::alerts::destinations::set(org_id.as_str(), name.as_str(), destination.clone())
         .await
         .unwrap();
@@ -81,3 +169,401 @@ pub async fn get_destination(org_id: String, name: String) -> Result<HttpRespons
         ))),
     }
 }
+
+fn validate_destination(destination: &AlertDestination) -> Result<(), String> {
+    match destination.destination_type {
+        AlertDestType::Email => {
+            if !CONFIG.smtp.smtp_enabled {
+                return Err("SMTP is not configured, set ZO_SMTP_ENABLED".to_string());
+            }
+            if destination.emails.is_empty() {
+                return Err("Email destination requires at least one email".to_string());
+            }
+            for email in destination.emails.iter() {
+                if email.parse::<Mailbox>().is_err() {
+                    return Err(format!("Invalid email address: {email}"));
+                }
+            }
+        }
+        AlertDestType::PagerDuty => {
+            if destination.routing_key.is_none() {
+                return Err("PagerDuty destination requires a routing_key".to_string());
+            }
+        }
+        AlertDestType::Http | AlertDestType::Slack | AlertDestType::AlertManager => {
+            if url::Url::parse(&destination.url).is_err() {
+                return Err(format!("Invalid destination url: {}", destination.url));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Delivers the notification in a task of its own, so the caller doesn't wait for the
+/// retries, see [`send`].
+pub fn deliver(dest: AlertDestinationResponse, notification: Notification) {
+    tokio::task::spawn(async move {
+        if let Err(e) = send(&dest, &notification).await {
+            log::error!(
+                "Notification of alert {}/{} to destination {} failed: {}",
+                notification.org,
+                notification.alert_name,
+                dest.name,
+                e
+            );
+        }
+    });
+}
+
+/// Delivers the notification to the destination, retrying failed attempts with an
+/// exponential backoff.
+pub async fn send(
+    dest: &AlertDestinationResponse,
+    notification: &Notification,
+) -> Result<(), anyhow::Error> {
+    match dest.destination_type {
+        AlertDestType::Email => with_retry(|| send_email(dest, notification)).await,
+        AlertDestType::Http => {
+            let req = http_request(dest, &dest.url, &dest.method)?.json(&notification.message);
+            with_retry(|| send_request(&req)).await
+        }
+        AlertDestType::Slack => {
+            let req = http_request(dest, &dest.url, &AlertHTTPType::POST)?
+                .json(&slack_payload(notification));
+            with_retry(|| send_request(&req)).await
+        }
+        AlertDestType::PagerDuty => {
+            let url = if dest.url.is_empty() {
+                PAGERDUTY_EVENTS_URL
+            } else {
+                dest.url.as_str()
+            };
+            let routing_key = dest.routing_key.as_deref().unwrap_or_default();
+            let req = http_request(dest, url, &AlertHTTPType::POST)?
+                .json(&pagerduty_payload(routing_key, notification));
+            with_retry(|| send_request(&req)).await
+        }
+        AlertDestType::AlertManager => {
+            let url = if dest.url.ends_with(ALERTMANAGER_ALERTS_PATH) {
+                dest.url.clone()
+            } else {
+                format!(
+                    "{}{ALERTMANAGER_ALERTS_PATH}",
+                    dest.url.trim_end_matches('/')
+                )
+            };
+            let req = http_request(dest, &url, &AlertHTTPType::POST)?
+                .json(&alertmanager_payload(notification));
+            with_retry(|| send_request(&req)).await
+        }
+    }
+}
+
+enum SendError {
+    /// the destination rejected the notification, retrying won't help
+    Permanent(anyhow::Error),
+    Transient(anyhow::Error),
+}
+
+async fn with_retry<F, Fut>(mut f: F) -> Result<(), anyhow::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<(), SendError>>,
+{
+    let mut backoff = Duration::from_millis(CONFIG.limit.alert_notification_backoff);
+    let mut attempt = 0;
+    loop {
+        match f().await {
+            Ok(()) => return Ok(()),
+            Err(SendError::Permanent(e)) => return Err(e),
+            Err(SendError::Transient(e)) => {
+                if attempt >= CONFIG.limit.alert_notification_retries {
+                    return Err(e);
+                }
+                log::warn!(
+                    "Notification sending failed, retry in {}ms: {}",
+                    backoff.as_millis(),
+                    e
+                );
+            }
+        }
+        attempt += 1;
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+    }
+}
+
+fn http_request(
+    dest: &AlertDestinationResponse,
+    url: &str,
+    method: &AlertHTTPType,
+) -> Result<reqwest::RequestBuilder, anyhow::Error> {
+    let client = if dest.skip_tls_verify {
+        &HTTP_CLIENT_SKIP_TLS_VERIFY
+    } else {
+        &HTTP_CLIENT
+    };
+    let url = url::Url::parse(url)?;
+    let mut req = match method {
+        AlertHTTPType::POST => client.post(url),
+        AlertHTTPType::PUT => client.put(url),
+        AlertHTTPType::GET => client.get(url),
+    }
+    .header("Content-type", "application/json");
+
+    // Add additional headers if any from destination description
+    if let Some(headers) = dest.headers.as_ref() {
+        for (key, value) in headers {
+            if !key.is_empty() && !value.is_empty() {
+                req = req.header(key, value);
+            }
+        }
+    };
+    Ok(req)
+}
+
+async fn send_request(req: &reqwest::RequestBuilder) -> Result<(), SendError> {
+    let req = req
+        .try_clone()
+        .ok_or_else(|| SendError::Permanent(anyhow::anyhow!("request can not be cloned")))?;
+    match req.send().await {
+        Ok(resp) => {
+            let status = resp.status();
+            if status.is_success() {
+                return Ok(());
+            }
+            let err = anyhow::anyhow!("{}: {:?}", status, resp.bytes().await);
+            if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                Err(SendError::Transient(err))
+            } else {
+                Err(SendError::Permanent(err))
+            }
+        }
+        Err(e) => Err(SendError::Transient(e.into())),
+    }
+}
+
+async fn send_email(
+    dest: &AlertDestinationResponse,
+    notification: &Notification,
+) -> Result<(), SendError> {
+    let email = email_message(dest, notification).map_err(SendError::Permanent)?;
+    let cfg = &CONFIG.smtp;
+    let mut transport = match cfg.smtp_encryption.as_str() {
+        "ssl" => AsyncSmtpTransport::<Tokio1Executor>::relay(&cfg.smtp_host)
+            .map_err(|e| SendError::Permanent(e.into()))?,
+        "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&cfg.smtp_host)
+            .map_err(|e| SendError::Permanent(e.into()))?,
+        _ => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&cfg.smtp_host),
+    }
+    .port(cfg.smtp_port)
+    .timeout(Some(Duration::from_secs(
+        CONFIG.limit.alert_notification_timeout,
+    )));
+    if !cfg.smtp_username.is_empty() {
+        transport = transport.credentials(Credentials::new(
+            cfg.smtp_username.clone(),
+            cfg.smtp_password.clone(),
+        ));
+    }
+    match transport.build().send(email).await {
+        Ok(_) => Ok(()),
+        Err(e) if e.is_permanent() => Err(SendError::Permanent(e.into())),
+        Err(e) => Err(SendError::Transient(e.into())),
+    }
+}
+
+fn email_message(
+    dest: &AlertDestinationResponse,
+    notification: &Notification,
+) -> Result<Message, anyhow::Error> {
+    if !CONFIG.smtp.smtp_enabled {
+        return Err(anyhow::anyhow!("SMTP is not configured"));
+    }
+    let mut subject = format!(
+        "[{}] {}",
+        notification.state.to_string().to_uppercase(),
+        notification.alert_name
+    );
+    if !notification.group_key.is_empty() {
+        subject.push_str(&format!(" ({})", notification.group_key));
+    }
+    let mut email = Message::builder()
+        .from(CONFIG.smtp.smtp_from_email.parse()?)
+        .subject(subject);
+    if !CONFIG.smtp.smtp_reply_to.is_empty() {
+        email = email.reply_to(CONFIG.smtp.smtp_reply_to.parse()?);
+    }
+    for to in dest.emails.iter() {
+        email = email.to(to.parse()?);
+    }
+    Ok(email
+        .header(ContentType::TEXT_PLAIN)
+        .body(notification.text())?)
+}
+
+/// Slack incoming webhook body, an object template is sent as is.
+pub fn slack_payload(notification: &Notification) -> Value {
+    match &notification.message {
+        Value::Object(_) => notification.message.clone(),
+        _ => json!({ "text": notification.text() }),
+    }
+}
+
+/// PagerDuty Events API v2 body, resolving uses the same dedup key as triggering.
+pub fn pagerduty_payload(routing_key: &str, notification: &Notification) -> Value {
+    let event_action = match notification.state {
+        AlertState::Resolved => "resolve",
+        _ => "trigger",
+    };
+    let mut details = Map::new();
+    for (key, value) in notification.labels.iter() {
+        details.insert(key.to_string(), Value::String(value.to_string()));
+    }
+    let summary: String = notification.text().chars().take(1024).collect();
+    json!({
+        "routing_key": routing_key,
+        "event_action": event_action,
+        "dedup_key": notification.dedup_key(),
+        "payload": {
+            "summary": summary,
+            "source": "openobserve",
+            "severity": "critical",
+            "timestamp": notification.rfc3339(),
+            "component": notification.stream,
+            "group": notification.org,
+            "class": notification.alert_name,
+            "custom_details": details,
+        }
+    })
+}
+
+/// Alertmanager `/api/v2/alerts` body. Firing alerts end in the future, Alertmanager
+/// resolves them by itself if they aren't sent again until then.
+pub fn alertmanager_payload(notification: &Notification) -> Value {
+    let mut labels = Map::new();
+    labels.insert(
+        "alertname".to_string(),
+        Value::String(notification.alert_name.clone()),
+    );
+    labels.insert(
+        "organization".to_string(),
+        Value::String(notification.org.clone()),
+    );
+    labels.insert(
+        "stream".to_string(),
+        Value::String(notification.stream.clone()),
+    );
+    labels.insert(
+        "stream_type".to_string(),
+        Value::String(notification.stream_type.to_string()),
+    );
+    for (key, value) in notification.labels.iter() {
+        if key != ALERT_AGG_VALUE_COLUMN {
+            labels.insert(key.to_string(), Value::String(value.to_string()));
+        }
+    }
+    let alert = json!({
+        "labels": labels,
+        "annotations": {
+            "summary": notification.text(),
+        },
+        "startsAt": rfc3339(notification.starts_at),
+        "endsAt": rfc3339(notification.ends_at),
+    });
+    json!([alert])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn notification(state: AlertState) -> Notification {
+        Notification {
+            org: "default".to_string(),
+            stream: "olympics".to_string(),
+            stream_type: StreamType::Logs,
+            alert_name: "medals".to_string(),
+            state,
+            group_key: "country=USA".to_string(),
+            labels: vec![("country".to_string(), "USA".to_string())],
+            message: Value::String("too many medals".to_string()),
+            timestamp: 1_690_000_000_000_000,
+            starts_at: 1_689_999_400_000_000,
+            ends_at: match state {
+                AlertState::Resolved => 1_690_000_000_000_000,
+                _ => 1_690_000_900_000_000,
+            },
+        }
+    }
+
+    #[test]
+    fn test_slack_payload() {
+        let payload = slack_payload(&notification(AlertState::Firing));
+        assert_eq!(payload, json!({"text": "too many medals"}));
+    }
+
+    #[test]
+    fn test_pagerduty_payload() {
+        let payload = pagerduty_payload("key", &notification(AlertState::Firing));
+        assert_eq!(payload["event_action"], "trigger");
+        assert_eq!(
+            payload["dedup_key"],
+            "default/logs/olympics/medals/country=USA"
+        );
+        assert_eq!(payload["payload"]["custom_details"]["country"], "USA");
+        let payload = pagerduty_payload("key", &notification(AlertState::Resolved));
+        assert_eq!(payload["event_action"], "resolve");
+    }
+
+    #[test]
+    fn test_alertmanager_payload() {
+        let payload = alertmanager_payload(&notification(AlertState::Firing));
+        assert_eq!(payload[0]["labels"]["alertname"], "medals");
+        assert_eq!(payload[0]["labels"]["country"], "USA");
+        assert_eq!(payload[0]["startsAt"], "2023-07-22T04:16:40+00:00");
+        assert_eq!(payload[0]["endsAt"], "2023-07-22T04:41:40+00:00");
+        let payload = alertmanager_payload(&notification(AlertState::Resolved));
+        assert_eq!(payload[0]["startsAt"], "2023-07-22T04:16:40+00:00");
+        assert_eq!(payload[0]["endsAt"], "2023-07-22T04:26:40+00:00");
+    }
+
+    #[actix_web::test]
+    async fn test_send_with_retry() {
+        // a local http sink failing the first request
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let sink = tokio::spawn(async move {
+            let mut bodies = vec![];
+            for status in ["500 Internal Server Error", "200 OK"] {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let n = socket.read(&mut buf).await.unwrap();
+                bodies.push(String::from_utf8_lossy(&buf[..n]).to_string());
+                let resp =
+                    format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+                socket.write_all(resp.as_bytes()).await.unwrap();
+            }
+            bodies
+        });
+
+        let dest = AlertDestinationResponse {
+            name: "am".to_string(),
+            destination_type: AlertDestType::AlertManager,
+            url: format!("http://{addr}"),
+            method: AlertHTTPType::POST,
+            skip_tls_verify: false,
+            headers: None,
+            template: None,
+            emails: vec![],
+            routing_key: None,
+        };
+        send(&dest, &notification(AlertState::Firing))
+            .await
+            .unwrap();
+        let bodies = sink.await.unwrap();
+        assert_eq!(bodies.len(), 2);
+        assert!(bodies[1].starts_with("POST /api/v2/alerts"));
+    }
+}
@@ -20,7 +20,10 @@ use std::io::Error;
 
 use super::search::sql::Sql;
 use super::{db, triggers};
-use crate::common::meta::alert::{Alert, AlertList, Trigger};
+use crate::common::meta::alert::{
+    AggFunction, Alert, AlertList, Condition, Trigger, TriggerHistoryList, ALERT_AGG_MAX_GROUPS,
+    ALERT_AGG_VALUE_COLUMN,
+};
 use crate::common::meta::http::HttpResponse as MetaHttpResponse;
 use crate::common::meta::search::Query;
 use crate::common::meta::{self, StreamType};
@@ -29,6 +32,7 @@ use crate::common::utils::schema_ext::SchemaExt;
 use crate::handler::grpc::cluster_rpc;
 
 pub mod destinations;
+pub mod silences;
 pub mod templates;
 
 #[tracing::instrument(skip_all)]
@@ -68,7 +72,122 @@ pub async fn save_alert(
         )));
     }
 
-    if alert.query.is_none() {
+    if alert.conditions.is_none()
+        && alert.condition.column.is_empty()
+        && !alert.is_aggregate()
+        && !alert.is_promql()
+    {
+        return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+            http::StatusCode::BAD_REQUEST.into(),
+            "Alert condition is required".to_string(),
+        )));
+    }
+
+    if let Some(promql) = alert.promql.as_ref() {
+        if stream_type != StreamType::Metrics {
+            return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+                http::StatusCode::BAD_REQUEST.into(),
+                "PromQL alerts are only supported on metrics streams".to_string(),
+            )));
+        }
+        if alert.is_aggregate() {
+            return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+                http::StatusCode::BAD_REQUEST.into(),
+                "Alert can't have both a PromQL expression and an aggregation".to_string(),
+            )));
+        }
+        if let Err(e) = crate::service::promql::parse(promql) {
+            return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+                http::StatusCode::BAD_REQUEST.into(),
+                format!("Invalid PromQL expression: {e}"),
+            )));
+        }
+        // promql alerts are always evaluated by the alert manager
+        alert.query = None;
+        alert.is_real_time = false;
+    }
+
+    if let Some(aggregation) = alert.aggregation.as_ref() {
+        // aggregate alerts are always evaluated by the alert manager
+        let mut columns = aggregation.group_by.clone();
+        if aggregation.function != AggFunction::Count {
+            columns.push(aggregation.column.clone());
+        }
+        for column in columns {
+            if !fields.iter().any(|field| field.name().eq(&column)) {
+                return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+                    http::StatusCode::BAD_REQUEST.into(),
+                    format!("Column named {column} not found on stream {stream_name}"),
+                )));
+            }
+        }
+        let sql = match aggregation.to_sql(&stream_name) {
+            Ok(sql) => sql,
+            Err(e) => {
+                return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+                    http::StatusCode::BAD_REQUEST.into(),
+                    format!("Invalid aggregation filter: {e}"),
+                )));
+            }
+        };
+
+        // conditions see the rows of the aggregated groups, which only have the group
+        // by columns and the aggregated value
+        let mut invalid_column = None;
+        let mut check_column = |condition: &mut Condition| {
+            if condition.column == ALERT_AGG_VALUE_COLUMN {
+                condition.is_numeric = Some(true);
+            } else if aggregation.group_by.contains(&condition.column) {
+                condition.is_numeric = fields
+                    .iter()
+                    .find(|field| field.name().eq(&condition.column))
+                    .map(|field| !matches!(field.data_type(), DataType::Boolean | DataType::Utf8));
+            } else if invalid_column.is_none() {
+                invalid_column = Some(condition.column.clone());
+            }
+        };
+        match alert.conditions.as_mut() {
+            Some(conditions) => conditions.for_each_condition_mut(&mut check_column),
+            None if !alert.condition.column.is_empty() => check_column(&mut alert.condition),
+            None => {}
+        }
+        if let Some(column) = invalid_column {
+            return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+                http::StatusCode::BAD_REQUEST.into(),
+                format!(
+                    "Condition column {column} of an aggregate alert has to be a group by column or {ALERT_AGG_VALUE_COLUMN}"
+                ),
+            )));
+        }
+
+        alert.query = Some(Query {
+            sql,
+            start_time: 0,
+            end_time: 0,
+            sort_by: None,
+            sql_mode: "full".to_owned(),
+            query_type: "logs".to_owned(),
+            track_total_hits: false,
+            from: 0,
+            size: ALERT_AGG_MAX_GROUPS,
+            query_context: None,
+            uses_zo_fn: false,
+            query_fn: None,
+        });
+        alert.is_real_time = false;
+    }
+
+    if alert.is_promql() {
+        // conditions see the labels of every series and its value as zo_alert_value
+        let mut set_numeric = |condition: &mut Condition| {
+            condition.is_numeric = Some(condition.column == ALERT_AGG_VALUE_COLUMN)
+        };
+        match alert.conditions.as_mut() {
+            Some(conditions) => conditions.for_each_condition_mut(&mut set_numeric),
+            None if !alert.condition.column.is_empty() => set_numeric(&mut alert.condition),
+            None => {}
+        }
+    } else if alert.query.is_none() {
         alert.query = Some(Query {
             sql: format!("select * from {stream_name}"),
             start_time: 0,
@@ -85,22 +204,34 @@ pub async fn save_alert(
         });
         alert.is_real_time = true;
 
-        let mut local_fields = fields.clone();
-        local_fields.retain(|field| field.name().eq(&alert.condition.column));
+        let mut missing_column = None;
+        let mut set_numeric = |condition: &mut Condition| match fields
+            .iter()
+            .find(|field| field.name().eq(&condition.column))
+        {
+            Some(field) => {
+                condition.is_numeric = Some(!matches!(
+                    field.data_type(),
+                    DataType::Boolean | DataType::Utf8
+                ))
+            }
+            None => {
+                if missing_column.is_none() {
+                    missing_column = Some(condition.column.clone());
+                }
+            }
+        };
+        match alert.conditions.as_mut() {
+            Some(conditions) => conditions.for_each_condition_mut(&mut set_numeric),
+            None => set_numeric(&mut alert.condition),
+        }
 
-        if local_fields.is_empty() {
+        if let Some(column) = missing_column {
             return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
                 http::StatusCode::BAD_REQUEST.into(),
-                format!(
-                    "Column named {} not found on stream {stream_name}",
-                    &alert.condition.column
-                ),
+                format!("Column named {column} not found on stream {stream_name}"),
             )));
         }
-        alert.condition.is_numeric = Some(!matches!(
-            local_fields[0].data_type(),
-            DataType::Boolean | DataType::Utf8
-        ));
     } else {
         let meta_req = meta::search::Request {
             query: alert.clone().query.unwrap(),
@@ -140,6 +271,7 @@ pub async fn save_alert(
             last_sent_at: 0,
             count: 0,
             is_ingest_time: false,
+            ..Default::default()
         };
         let _ = triggers::save_trigger(&trigger.alert_name, &trigger).await;
     }
@@ -177,10 +309,13 @@ pub async fn delete_alert(
     )
     .await;
     match result {
-        Ok(_) => Ok(HttpResponse::Ok().json(MetaHttpResponse::message(
-            http::StatusCode::OK.into(),
-            "Alert deleted ".to_string(),
-        ))),
+        Ok(_) => {
+            let _ = db::trigger_history::delete(&org_id, &name).await;
+            Ok(HttpResponse::Ok().json(MetaHttpResponse::message(
+                http::StatusCode::OK.into(),
+                "Alert deleted ".to_string(),
+            )))
+        }
         Err(e) => Ok(HttpResponse::NotFound().json(MetaHttpResponse::error(
             http::StatusCode::NOT_FOUND.into(),
             e.to_string(),
@@ -211,6 +346,27 @@ pub async fn get_alert(
     }
 }
 
+#[tracing::instrument]
+pub async fn get_alert_history(
+    org_id: String,
+    stream_name: String,
+    name: String,
+) -> Result<HttpResponse, Error> {
+    match db::trigger_history::list(&org_id, &name).await {
+        Ok(mut list) => {
+            // alerts of different streams can share their name
+            list.retain(|history| history.stream == stream_name);
+            Ok(HttpResponse::Ok().json(TriggerHistoryList { list }))
+        }
+        Err(e) => Ok(
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        ),
+    }
+}
+
 #[tracing::instrument]
 pub async fn trigger_alert(
     org_id: String,
@@ -238,6 +394,7 @@ pub async fn trigger_alert(
                 count: 0,
                 is_ingest_time: alert.is_real_time,
                 stream_type,
+                ..Default::default()
             };
             let _ = send_notification(&alert, &trigger).await;
             Ok(HttpResponse::Ok().json(MetaHttpResponse::message(
@@ -290,6 +447,10 @@ mod test {
             destination: "test".to_string(),
             is_real_time: false,
             context_attributes: None,
+            for_duration: 0,
+            conditions: None,
+            aggregation: None,
+            promql: None,
         }
     }
 
@@ -0,0 +1,190 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use actix_web::{http, HttpResponse};
+use ahash::HashMap;
+use chrono::Utc;
+use std::io::Error;
+
+use crate::common::infra::config::{ALERT_MAINTENANCE_WINDOWS, ALERT_SILENCES};
+use crate::common::infra::ider;
+use crate::common::meta::alert::{
+    compile_matchers, MaintenanceWindow, MaintenanceWindowList, Matcher, Silence, SilenceList,
+};
+use crate::common::meta::http::HttpResponse as MetaHttpResponse;
+use crate::service::db;
+
+#[tracing::instrument(skip(silence))]
+pub async fn save_silence(org_id: String, mut silence: Silence) -> Result<HttpResponse, Error> {
+    if let Err(e) = validate_matchers(&mut silence.matchers) {
+        return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+            http::StatusCode::BAD_REQUEST.into(),
+            e,
+        )));
+    }
+    if silence.ends_at <= silence.starts_at {
+        return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+            http::StatusCode::BAD_REQUEST.into(),
+            "Silence must end after it starts".to_string(),
+        )));
+    }
+    if silence.id.is_empty() {
+        silence.id = ider::generate();
+    }
+    match db::alerts::silences::set(&org_id, &silence).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(silence)),
+        Err(e) => Ok(
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        ),
+    }
+}
+
+#[tracing::instrument]
+pub async fn list_silences(org_id: String) -> Result<HttpResponse, Error> {
+    match db::alerts::silences::list(&org_id).await {
+        Ok(mut list) => {
+            list.sort_by(|a, b| b.starts_at.cmp(&a.starts_at));
+            Ok(HttpResponse::Ok().json(SilenceList { list }))
+        }
+        Err(e) => Ok(
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        ),
+    }
+}
+
+#[tracing::instrument]
+pub async fn delete_silence(org_id: String, id: String) -> Result<HttpResponse, Error> {
+    match db::alerts::silences::delete(&org_id, &id).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(MetaHttpResponse::message(
+            http::StatusCode::OK.into(),
+            "Silence deleted".to_string(),
+        ))),
+        Err(e) => Ok(HttpResponse::NotFound().json(MetaHttpResponse::error(
+            http::StatusCode::NOT_FOUND.into(),
+            e.to_string(),
+        ))),
+    }
+}
+
+#[tracing::instrument(skip(window))]
+pub async fn save_maintenance_window(
+    org_id: String,
+    name: String,
+    mut window: MaintenanceWindow,
+) -> Result<HttpResponse, Error> {
+    if let Err(e) = validate_matchers(&mut window.matchers) {
+        return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+            http::StatusCode::BAD_REQUEST.into(),
+            e,
+        )));
+    }
+    if window.start_minute().is_none() || window.duration <= 0 {
+        return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+            http::StatusCode::BAD_REQUEST.into(),
+            "Maintenance window requires a start_time as HH:MM and a positive duration".to_string(),
+        )));
+    }
+    if window.weekdays.iter().any(|day| *day > 6) {
+        return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+            http::StatusCode::BAD_REQUEST.into(),
+            "Weekdays range from 0 (Monday) to 6 (Sunday)".to_string(),
+        )));
+    }
+    window.name = name;
+    match db::alerts::maintenance_windows::set(&org_id, &window).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(MetaHttpResponse::message(
+            http::StatusCode::OK.into(),
+            "Maintenance window saved".to_string(),
+        ))),
+        Err(e) => Ok(
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        ),
+    }
+}
+
+#[tracing::instrument]
+pub async fn list_maintenance_windows(org_id: String) -> Result<HttpResponse, Error> {
+    match db::alerts::maintenance_windows::list(&org_id).await {
+        Ok(list) => Ok(HttpResponse::Ok().json(MaintenanceWindowList { list })),
+        Err(e) => Ok(
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        ),
+    }
+}
+
+#[tracing::instrument]
+pub async fn delete_maintenance_window(
+    org_id: String,
+    name: String,
+) -> Result<HttpResponse, Error> {
+    match db::alerts::maintenance_windows::delete(&org_id, &name).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(MetaHttpResponse::message(
+            http::StatusCode::OK.into(),
+            "Maintenance window deleted".to_string(),
+        ))),
+        Err(e) => Ok(HttpResponse::NotFound().json(MetaHttpResponse::error(
+            http::StatusCode::NOT_FOUND.into(),
+            e.to_string(),
+        ))),
+    }
+}
+
+/// Returns true if an active silence or maintenance window of the organization matches
+/// the labels of the alert instance. `now` is in microseconds.
+pub fn is_silenced(org_id: &str, labels: &HashMap<String, String>, now: i64) -> bool {
+    let prefix = format!("{org_id}/");
+    ALERT_SILENCES.iter().any(|silence| {
+        silence.key().starts_with(&prefix) && silence.is_active(now) && silence.matches(labels)
+    }) || ALERT_MAINTENANCE_WINDOWS.iter().any(|window| {
+        window.key().starts_with(&prefix) && window.is_active(now) && window.matches(labels)
+    })
+}
+
+/// Deletes the silences which ended, of every organization.
+pub async fn prune_expired() -> Result<(), anyhow::Error> {
+    let now = Utc::now().timestamp_micros();
+    let expired: Vec<String> = ALERT_SILENCES
+        .iter()
+        .filter(|silence| silence.ends_at <= now)
+        .map(|silence| silence.key().to_string())
+        .collect();
+    for key in expired {
+        if let Some((org_id, id)) = key.split_once('/') {
+            db::alerts::silences::delete(org_id, id).await?;
+        }
+    }
+    Ok(())
+}
+
+fn validate_matchers(matchers: &mut [Matcher]) -> Result<(), String> {
+    if matchers.is_empty() {
+        return Err("At least one matcher is required".to_string());
+    }
+    if matchers.iter().any(|matcher| matcher.name.is_empty()) {
+        return Err("Matcher name can not be empty".to_string());
+    }
+    compile_matchers(matchers)
+}
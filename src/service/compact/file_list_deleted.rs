@@ -19,17 +19,30 @@ use futures::future::try_join_all;
 use std::io::{BufRead, BufReader};
 
 use crate::common::infra::{config::CONFIG, file_list as infra_file_list, storage};
+use crate::service::search::inverted_index;
 
 pub async fn delete(org_id: &str, time_min: i64, time_max: i64) -> Result<(), anyhow::Error> {
     let files = query_deleted(org_id, time_min, time_max).await?;
     if files.is_empty() {
         return Ok(());
     }
-    // delete files from storage
+    // delete files from storage, with their term indexes
+    let mut del_files = files
+        .values()
+        .flatten()
+        .map(|file| file.to_owned())
+        .collect::<Vec<_>>();
+    if CONFIG.common.inverted_index_enabled {
+        let index_files = del_files
+            .iter()
+            .filter(|file| file.contains("/logs/"))
+            .map(|file| inverted_index::index_key(file))
+            .collect::<Vec<_>>();
+        del_files.extend(index_files);
+    }
     if let Err(e) = storage::del(
-        &files
-            .values()
-            .flatten()
+        &del_files
+            .iter()
             .map(|file| file.as_str())
             .collect::<Vec<_>>(),
     )
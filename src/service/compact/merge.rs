@@ -30,7 +30,11 @@ use crate::common::{
     },
     utils::json,
 };
-use crate::service::{db, file_list, search::datafusion, stream};
+use crate::service::{
+    db, file_list,
+    search::{datafusion, inverted_index},
+    stream,
+};
 
 /// compactor run steps on a stream:
 /// 3. get a cluster lock for compactor stream
@@ -378,10 +382,19 @@ async fn merge_files(
         return Ok(("".to_string(), FileMeta::default(), vec![]));
     }
 
+    let stream_schema = db::schema::get(org_id, stream_name, stream_type).await?;
+    let bf_fields = stream::get_stream_setting_bloom_filter_fields(&stream_schema, stream_type);
+    let fts_fields = stream::get_stream_setting_fts_fields(&stream_schema).unwrap_or_default();
     let mut buf = Vec::new();
-    let mut new_file_meta =
-        datafusion::exec::merge_parquet_files(tmp_dir.name(), &mut buf, schema, stream_type)
-            .await?;
+    let (mut new_file_meta, index) = datafusion::exec::merge_parquet_files(
+        tmp_dir.name(),
+        &mut buf,
+        schema,
+        stream_type,
+        &bf_fields,
+        &fts_fields,
+    )
+    .await?;
     new_file_meta.original_size = new_file_size;
     new_file_meta.compressed_size = buf.len() as i64;
     if new_file_meta.records == 0 {
@@ -398,7 +411,8 @@ async fn merge_files(
         new_file_meta.compressed_size,
     );
 
-    // upload file
+    // upload file, after its index
+    inverted_index::write(&new_file_key, index).await;
     match storage::put(&new_file_key, buf.into()).await {
         Ok(_) => Ok((new_file_key, new_file_meta, new_file_list)),
         Err(e) => Err(e),
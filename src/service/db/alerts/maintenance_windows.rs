@@ -0,0 +1,116 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use crate::common::{
+    infra::{config::ALERT_MAINTENANCE_WINDOWS, db as infra_db},
+    meta::alert::{compile_matchers, MaintenanceWindow},
+    utils::json,
+};
+
+pub async fn get(org_id: &str, name: &str) -> Result<Option<MaintenanceWindow>, anyhow::Error> {
+    let map_key = format!("{org_id}/{name}");
+    if let Some(val) = ALERT_MAINTENANCE_WINDOWS.get(&map_key) {
+        return Ok(Some(val.clone()));
+    }
+    let db = &infra_db::DEFAULT;
+    let key = format!("/maintenance_windows/{org_id}/{name}");
+    Ok(db
+        .get(&key)
+        .await
+        .map(|val| json::from_slice(&val).unwrap())
+        .ok())
+}
+
+pub async fn set(org_id: &str, val: &MaintenanceWindow) -> Result<(), anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = format!("/maintenance_windows/{org_id}/{}", val.name);
+    Ok(db
+        .put(
+            &key,
+            json::to_vec(val).unwrap().into(),
+            infra_db::NEED_WATCH,
+        )
+        .await?)
+}
+
+pub async fn delete(org_id: &str, name: &str) -> Result<(), anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = format!("/maintenance_windows/{org_id}/{name}");
+    Ok(db.delete(&key, false, infra_db::NEED_WATCH).await?)
+}
+
+pub async fn list(org_id: &str) -> Result<Vec<MaintenanceWindow>, anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = format!("/maintenance_windows/{org_id}/");
+    Ok(db
+        .list_values(&key)
+        .await?
+        .iter()
+        .map(|val| json::from_slice(val))
+        .collect::<Result<_, _>>()?)
+}
+
+pub async fn watch() -> Result<(), anyhow::Error> {
+    let key = "/maintenance_windows/";
+    let db = &infra_db::CLUSTER_COORDINATOR;
+    let mut events = db.watch(key).await?;
+    let events = Arc::get_mut(&mut events).unwrap();
+    log::info!("Start watching alert maintenance windows");
+    loop {
+        let ev = match events.recv().await {
+            Some(ev) => ev,
+            None => {
+                log::error!("watch_maintenance_windows: event channel closed");
+                break;
+            }
+        };
+        match ev {
+            infra_db::Event::Put(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                let mut item_value: MaintenanceWindow =
+                    json::from_slice(&ev.value.unwrap()).unwrap();
+                if let Err(e) = compile_matchers(&mut item_value.matchers) {
+                    log::error!(
+                        "Error compiling the matchers of maintenance window {item_key}: {e}"
+                    );
+                }
+                ALERT_MAINTENANCE_WINDOWS.insert(item_key.to_owned(), item_value);
+            }
+            infra_db::Event::Delete(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                ALERT_MAINTENANCE_WINDOWS.remove(item_key);
+            }
+            infra_db::Event::Empty => {}
+        }
+    }
+    Ok(())
+}
+
+pub async fn cache() -> Result<(), anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = "/maintenance_windows/";
+    let ret = db.list(key).await?;
+    for (item_key, item_value) in ret {
+        let item_key = item_key.strip_prefix(key).unwrap();
+        let mut json_val: MaintenanceWindow = json::from_slice(&item_value).unwrap();
+        if let Err(e) = compile_matchers(&mut json_val.matchers) {
+            log::error!("Error compiling the matchers of maintenance window {item_key}: {e}");
+        }
+        ALERT_MAINTENANCE_WINDOWS.insert(item_key.to_owned(), json_val);
+    }
+    log::info!("Alert maintenance windows Cached");
+    Ok(())
+}
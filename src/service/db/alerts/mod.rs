@@ -24,6 +24,8 @@ use crate::common::{
 };
 
 pub mod destinations;
+pub mod maintenance_windows;
+pub mod silences;
 pub mod templates;
 
 pub async fn get(
@@ -0,0 +1,113 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use crate::common::{
+    infra::{config::ALERT_SILENCES, db as infra_db},
+    meta::alert::{compile_matchers, Silence},
+    utils::json,
+};
+
+pub async fn get(org_id: &str, id: &str) -> Result<Option<Silence>, anyhow::Error> {
+    let map_key = format!("{org_id}/{id}");
+    if let Some(val) = ALERT_SILENCES.get(&map_key) {
+        return Ok(Some(val.clone()));
+    }
+    let db = &infra_db::DEFAULT;
+    let key = format!("/silences/{org_id}/{id}");
+    Ok(db
+        .get(&key)
+        .await
+        .map(|val| json::from_slice(&val).unwrap())
+        .ok())
+}
+
+pub async fn set(org_id: &str, val: &Silence) -> Result<(), anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = format!("/silences/{org_id}/{}", val.id);
+    Ok(db
+        .put(
+            &key,
+            json::to_vec(val).unwrap().into(),
+            infra_db::NEED_WATCH,
+        )
+        .await?)
+}
+
+pub async fn delete(org_id: &str, id: &str) -> Result<(), anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = format!("/silences/{org_id}/{id}");
+    Ok(db.delete(&key, false, infra_db::NEED_WATCH).await?)
+}
+
+pub async fn list(org_id: &str) -> Result<Vec<Silence>, anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = format!("/silences/{org_id}/");
+    Ok(db
+        .list_values(&key)
+        .await?
+        .iter()
+        .map(|val| json::from_slice(val))
+        .collect::<Result<_, _>>()?)
+}
+
+pub async fn watch() -> Result<(), anyhow::Error> {
+    let key = "/silences/";
+    let db = &infra_db::CLUSTER_COORDINATOR;
+    let mut events = db.watch(key).await?;
+    let events = Arc::get_mut(&mut events).unwrap();
+    log::info!("Start watching alert silences");
+    loop {
+        let ev = match events.recv().await {
+            Some(ev) => ev,
+            None => {
+                log::error!("watch_silences: event channel closed");
+                break;
+            }
+        };
+        match ev {
+            infra_db::Event::Put(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                let mut item_value: Silence = json::from_slice(&ev.value.unwrap()).unwrap();
+                if let Err(e) = compile_matchers(&mut item_value.matchers) {
+                    log::error!("Error compiling the matchers of silence {item_key}: {e}");
+                }
+                ALERT_SILENCES.insert(item_key.to_owned(), item_value);
+            }
+            infra_db::Event::Delete(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                ALERT_SILENCES.remove(item_key);
+            }
+            infra_db::Event::Empty => {}
+        }
+    }
+    Ok(())
+}
+
+pub async fn cache() -> Result<(), anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = "/silences/";
+    let ret = db.list(key).await?;
+    for (item_key, item_value) in ret {
+        let item_key = item_key.strip_prefix(key).unwrap();
+        let mut json_val: Silence = json::from_slice(&item_value).unwrap();
+        if let Err(e) = compile_matchers(&mut json_val.matchers) {
+            log::error!("Error compiling the matchers of silence {item_key}: {e}");
+        }
+        ALERT_SILENCES.insert(item_key.to_owned(), json_val);
+    }
+    log::info!("Alert silences Cached");
+    Ok(())
+}
@@ -0,0 +1,160 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use crate::common::{
+    infra::{
+        config::{FLUENT_KEYS, FLUENT_ROUTES},
+        db as infra_db,
+        errors::{DbError, Error},
+    },
+    meta::fluent::FluentRoute,
+    utils::json,
+};
+
+#[tracing::instrument(name = "service:db:fluent:list")]
+pub async fn list() -> Result<Vec<FluentRoute>, anyhow::Error> {
+    Ok(infra_db::DEFAULT
+        .list("/fluent/route/")
+        .await?
+        .values()
+        .map(|val| json::from_slice(val))
+        .collect::<Result<_, _>>()?)
+}
+
+#[tracing::instrument(name = "service:db:fluent:set", skip_all)]
+pub async fn set(route: &FluentRoute) -> Result<(), anyhow::Error> {
+    Ok(infra_db::DEFAULT
+        .put(
+            &format!("/fluent/route/{}", route.id),
+            json::to_vec(route).unwrap().into(),
+            infra_db::NEED_WATCH,
+        )
+        .await?)
+}
+
+#[tracing::instrument(name = "service:db:fluent:get")]
+pub async fn get(id: &str) -> Result<FluentRoute, anyhow::Error> {
+    let val = infra_db::DEFAULT
+        .get(&format!("/fluent/route/{id}"))
+        .await?;
+    Ok(json::from_slice(&val)?)
+}
+
+#[tracing::instrument(name = "service:db:fluent:delete")]
+pub async fn delete(id: &str) -> Result<(), anyhow::Error> {
+    Ok(infra_db::DEFAULT
+        .delete(&format!("/fluent/route/{id}"), false, infra_db::NEED_WATCH)
+        .await?)
+}
+
+#[tracing::instrument(name = "service:db:fluent:get_key")]
+pub async fn get_key(org_id: &str) -> Result<Option<String>, anyhow::Error> {
+    match infra_db::DEFAULT
+        .get(&format!("/fluent/key/{org_id}"))
+        .await
+    {
+        Ok(val) => Ok(Some(String::from_utf8(val.to_vec())?)),
+        Err(Error::DbError(DbError::KeyNotExists(_))) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[tracing::instrument(name = "service:db:fluent:set_key", skip(key))]
+pub async fn set_key(org_id: &str, key: &str) -> Result<(), anyhow::Error> {
+    Ok(infra_db::DEFAULT
+        .put(
+            &format!("/fluent/key/{org_id}"),
+            key.to_string().into(),
+            infra_db::NEED_WATCH,
+        )
+        .await?)
+}
+
+pub async fn watch() -> Result<(), anyhow::Error> {
+    let key = "/fluent/route/";
+    let db = &infra_db::CLUSTER_COORDINATOR;
+    let mut events = db.watch(key).await?;
+    let events = Arc::get_mut(&mut events).unwrap();
+    log::info!("Start watching fluent routes");
+    loop {
+        let ev = match events.recv().await {
+            Some(ev) => ev,
+            None => {
+                log::error!("watch_fluent_routes: event channel closed");
+                break;
+            }
+        };
+        match ev {
+            infra_db::Event::Put(ev) => {
+                let item_value: FluentRoute = json::from_slice(&ev.value.unwrap()).unwrap();
+                FLUENT_ROUTES.insert(item_value.id.to_owned(), item_value);
+            }
+            infra_db::Event::Delete(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                FLUENT_ROUTES.remove(item_key);
+            }
+            infra_db::Event::Empty => {}
+        }
+    }
+    Ok(())
+}
+
+pub async fn watch_keys() -> Result<(), anyhow::Error> {
+    let key = "/fluent/key/";
+    let db = &infra_db::CLUSTER_COORDINATOR;
+    let mut events = db.watch(key).await?;
+    let events = Arc::get_mut(&mut events).unwrap();
+    log::info!("Start watching fluent keys");
+    loop {
+        let ev = match events.recv().await {
+            Some(ev) => ev,
+            None => {
+                log::error!("watch_fluent_keys: event channel closed");
+                break;
+            }
+        };
+        match ev {
+            infra_db::Event::Put(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                let item_value = String::from_utf8(ev.value.unwrap().to_vec()).unwrap();
+                FLUENT_KEYS.insert(item_key.to_owned(), item_value);
+            }
+            infra_db::Event::Delete(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                FLUENT_KEYS.remove(item_key);
+            }
+            infra_db::Event::Empty => {}
+        }
+    }
+    Ok(())
+}
+
+pub async fn cache() -> Result<(), anyhow::Error> {
+    let key = "/fluent/route/";
+    let ret = infra_db::DEFAULT.list(key).await?;
+    for (_, item_value) in ret {
+        let json_val: FluentRoute = json::from_slice(&item_value).unwrap();
+        FLUENT_ROUTES.insert(json_val.id.to_owned(), json_val);
+    }
+    let key = "/fluent/key/";
+    let ret = infra_db::DEFAULT.list(key).await?;
+    for (item_key, item_value) in ret {
+        let item_key = item_key.strip_prefix(key).unwrap();
+        FLUENT_KEYS.insert(item_key.to_owned(), String::from_utf8(item_value.to_vec())?);
+    }
+    log::info!("FluentRoutes Cached");
+    Ok(())
+}
@@ -0,0 +1,209 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::{collections::HashMap, sync::Arc};
+
+use crate::common::{
+    infra::{
+        config::{KAFKA_SOURCES, KAFKA_SOURCE_LEADERS},
+        db as infra_db,
+        errors::{DbError, Error},
+    },
+    meta::{kafka::KafkaSource, prom::ClusterLeader},
+    utils::json,
+};
+
+pub async fn get(org_id: &str, name: &str) -> Result<Option<KafkaSource>, anyhow::Error> {
+    let map_key = format!("{org_id}/{name}");
+    if let Some(val) = KAFKA_SOURCES.get(&map_key) {
+        return Ok(Some(val.clone()));
+    }
+    let db = &infra_db::DEFAULT;
+    let key = format!("/kafka_sources/{org_id}/{name}");
+    match db.get(&key).await {
+        Ok(val) => Ok(Some(json::from_slice(&val)?)),
+        Err(Error::DbError(DbError::KeyNotExists(_))) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn set(org_id: &str, source: &KafkaSource) -> Result<(), anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = format!("/kafka_sources/{org_id}/{}", source.name);
+    Ok(db
+        .put(&key, json::to_vec(source)?.into(), infra_db::NEED_WATCH)
+        .await?)
+}
+
+/// Deletes the source with its leader and committed offsets.
+pub async fn delete(org_id: &str, name: &str) -> Result<(), anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = format!("/kafka_sources/{org_id}/{name}");
+    db.delete(&key, false, infra_db::NEED_WATCH).await?;
+    let key = format!("/kafka_sources_leader/{org_id}/{name}");
+    db.delete_if_exists(&key, false, infra_db::NEED_WATCH)
+        .await?;
+    let key = format!("/kafka_offsets/{org_id}/{name}/");
+    db.delete_if_exists(&key, true, infra_db::NO_NEED_WATCH)
+        .await?;
+    Ok(())
+}
+
+pub async fn list(org_id: &str) -> Result<Vec<KafkaSource>, anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = format!("/kafka_sources/{org_id}/");
+    db.list_values(&key)
+        .await?
+        .iter()
+        .map(|val| Ok(json::from_slice(val)?))
+        .collect()
+}
+
+/// Returns the committed offsets of the partitions of `topic`, the offset is the next one
+/// to consume.
+pub async fn get_offsets(
+    source_key: &str,
+    topic: &str,
+) -> Result<HashMap<i32, i64>, anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = format!("/kafka_offsets/{source_key}/{topic}/");
+    Ok(db
+        .list(&key)
+        .await?
+        .into_iter()
+        .filter_map(|(item_key, val)| {
+            let partition = item_key.strip_prefix(&key)?.parse().ok()?;
+            Some((partition, json::from_slice(&val).ok()?))
+        })
+        .collect())
+}
+
+pub async fn set_offset(
+    source_key: &str,
+    topic: &str,
+    partition: i32,
+    offset: i64,
+) -> Result<(), anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = format!("/kafka_offsets/{source_key}/{topic}/{partition}");
+    Ok(db
+        .put(&key, json::to_vec(&offset)?.into(), infra_db::NO_NEED_WATCH)
+        .await?)
+}
+
+pub async fn watch() -> Result<(), anyhow::Error> {
+    let key = "/kafka_sources/";
+    let db = &infra_db::CLUSTER_COORDINATOR;
+    let mut events = db.watch(key).await?;
+    let events = Arc::get_mut(&mut events).unwrap();
+    log::info!("Start watching kafka sources");
+    loop {
+        let ev = match events.recv().await {
+            Some(ev) => ev,
+            None => {
+                log::error!("watch_kafka_sources: event channel closed");
+                break;
+            }
+        };
+        match ev {
+            infra_db::Event::Put(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                let item_value: KafkaSource = json::from_slice(&ev.value.unwrap()).unwrap();
+                KAFKA_SOURCES.insert(item_key.to_owned(), item_value);
+            }
+            infra_db::Event::Delete(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                KAFKA_SOURCES.remove(item_key);
+            }
+            infra_db::Event::Empty => {}
+        }
+    }
+    Ok(())
+}
+
+pub async fn cache() -> Result<(), anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = "/kafka_sources/";
+    let ret = db.list(key).await?;
+    for (item_key, item_value) in ret {
+        let item_key = item_key.strip_prefix(key).unwrap();
+        let json_val: KafkaSource = json::from_slice(&item_value).unwrap();
+        KAFKA_SOURCES.insert(item_key.to_owned(), json_val);
+    }
+    log::info!("Kafka sources Cached");
+    Ok(())
+}
+
+/// Returns the stored leader of the source `{org_id}/{name}`, not the cached one.
+pub async fn get_leader(source_key: &str) -> Result<Option<ClusterLeader>, anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = format!("/kafka_sources_leader/{source_key}");
+    match db.get(&key).await {
+        Ok(val) => Ok(Some(json::from_slice(&val)?)),
+        Err(Error::DbError(DbError::KeyNotExists(_))) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Records `leader` as the node consuming the source `{org_id}/{name}`.
+pub async fn set_leader(source_key: &str, leader: &ClusterLeader) -> Result<(), anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = format!("/kafka_sources_leader/{source_key}");
+    KAFKA_SOURCE_LEADERS.insert(source_key.to_owned(), leader.clone());
+    Ok(db
+        .put(&key, json::to_vec(leader)?.into(), infra_db::NEED_WATCH)
+        .await?)
+}
+
+pub async fn watch_leaders() -> Result<(), anyhow::Error> {
+    let key = "/kafka_sources_leader/";
+    let db = &infra_db::CLUSTER_COORDINATOR;
+    let mut events = db.watch(key).await?;
+    let events = Arc::get_mut(&mut events).unwrap();
+    log::info!("Start watching kafka source leaders");
+    loop {
+        let ev = match events.recv().await {
+            Some(ev) => ev,
+            None => {
+                log::error!("watch_kafka_source_leaders: event channel closed");
+                break;
+            }
+        };
+        match ev {
+            infra_db::Event::Put(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                let item_value: ClusterLeader = json::from_slice(&ev.value.unwrap()).unwrap();
+                KAFKA_SOURCE_LEADERS.insert(item_key.to_owned(), item_value);
+            }
+            infra_db::Event::Delete(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                KAFKA_SOURCE_LEADERS.remove(item_key);
+            }
+            infra_db::Event::Empty => {}
+        }
+    }
+    Ok(())
+}
+
+pub async fn cache_leaders() -> Result<(), anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = "/kafka_sources_leader/";
+    let ret = db.list(key).await?;
+    for (item_key, item_value) in ret {
+        let item_key = item_key.strip_prefix(key).unwrap();
+        let json_val: ClusterLeader = json::from_slice(&item_value).unwrap();
+        KAFKA_SOURCE_LEADERS.insert(item_key.to_owned(), json_val);
+    }
+    log::info!("Kafka source leaders Cached");
+    Ok(())
+}
@@ -19,12 +19,16 @@ pub mod compact;
 pub mod dashboards;
 pub mod enrichment_table;
 pub mod file_list;
+pub mod fluent;
 pub mod functions;
+pub mod kafka;
 pub mod kv;
 pub mod metrics;
 pub mod organization;
+pub mod recording_rules;
 pub mod schema;
 pub mod syslog;
+pub mod trigger_history;
 pub mod triggers;
 pub mod user;
 pub mod version;
@@ -0,0 +1,183 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use crate::common::{
+    infra::{
+        config::{RECORDING_RULES, RECORDING_RULE_LEADERS},
+        db as infra_db,
+        errors::{DbError, Error},
+    },
+    meta::prom::{ClusterLeader, RecordingRule},
+    utils::json,
+};
+
+pub async fn get(org_id: &str, name: &str) -> Result<Option<RecordingRule>, anyhow::Error> {
+    let map_key = format!("{org_id}/{name}");
+    if let Some(val) = RECORDING_RULES.get(&map_key) {
+        return Ok(Some(val.clone()));
+    }
+    let db = &infra_db::DEFAULT;
+    let key = format!("/recording_rules/{org_id}/{name}");
+    Ok(db
+        .get(&key)
+        .await
+        .map(|val| json::from_slice(&val).unwrap())
+        .ok())
+}
+
+pub async fn set(org_id: &str, rule: &RecordingRule) -> Result<(), anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = format!("/recording_rules/{org_id}/{}", rule.name);
+    Ok(db
+        .put(
+            &key,
+            json::to_vec(rule).unwrap().into(),
+            infra_db::NEED_WATCH,
+        )
+        .await?)
+}
+
+pub async fn delete(org_id: &str, name: &str) -> Result<(), anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = format!("/recording_rules/{org_id}/{name}");
+    db.delete(&key, false, infra_db::NEED_WATCH).await?;
+    let key = format!("/recording_rules_leader/{org_id}/{name}");
+    let _ = db.delete(&key, false, infra_db::NEED_WATCH).await;
+    Ok(())
+}
+
+pub async fn list(org_id: &str) -> Result<Vec<RecordingRule>, anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = format!("/recording_rules/{org_id}/");
+    Ok(db
+        .list_values(&key)
+        .await?
+        .iter()
+        .map(|val| json::from_slice(val))
+        .collect::<Result<_, _>>()?)
+}
+
+pub async fn watch() -> Result<(), anyhow::Error> {
+    let key = "/recording_rules/";
+    let db = &infra_db::CLUSTER_COORDINATOR;
+    let mut events = db.watch(key).await?;
+    let events = Arc::get_mut(&mut events).unwrap();
+    log::info!("Start watching recording rules");
+    loop {
+        let ev = match events.recv().await {
+            Some(ev) => ev,
+            None => {
+                log::error!("watch_recording_rules: event channel closed");
+                break;
+            }
+        };
+        match ev {
+            infra_db::Event::Put(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                let item_value: RecordingRule = json::from_slice(&ev.value.unwrap()).unwrap();
+                RECORDING_RULES.insert(item_key.to_owned(), item_value);
+            }
+            infra_db::Event::Delete(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                RECORDING_RULES.remove(item_key);
+            }
+            infra_db::Event::Empty => {}
+        }
+    }
+    Ok(())
+}
+
+pub async fn cache() -> Result<(), anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = "/recording_rules/";
+    let ret = db.list(key).await?;
+    for (item_key, item_value) in ret {
+        let item_key = item_key.strip_prefix(key).unwrap();
+        let json_val: RecordingRule = json::from_slice(&item_value).unwrap();
+        RECORDING_RULES.insert(item_key.to_owned(), json_val);
+    }
+    log::info!("Recording rules Cached");
+    Ok(())
+}
+
+/// Reads the leader of the rule `{org_id}/{name}` from the meta store, bypassing the
+/// cache which may lag behind the claims of the other nodes.
+pub async fn get_leader(rule_key: &str) -> Result<Option<ClusterLeader>, anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = format!("/recording_rules_leader/{rule_key}");
+    match db.get(&key).await {
+        Ok(val) => Ok(Some(json::from_slice(&val)?)),
+        Err(Error::DbError(DbError::KeyNotExists(_))) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Records `leader` as the node evaluating the rule `{org_id}/{name}`.
+pub async fn set_leader(rule_key: &str, leader: &ClusterLeader) -> Result<(), anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = format!("/recording_rules_leader/{rule_key}");
+    RECORDING_RULE_LEADERS.insert(rule_key.to_owned(), leader.clone());
+    Ok(db
+        .put(
+            &key,
+            json::to_vec(leader).unwrap().into(),
+            infra_db::NEED_WATCH,
+        )
+        .await?)
+}
+
+pub async fn watch_leaders() -> Result<(), anyhow::Error> {
+    let key = "/recording_rules_leader/";
+    let db = &infra_db::CLUSTER_COORDINATOR;
+    let mut events = db.watch(key).await?;
+    let events = Arc::get_mut(&mut events).unwrap();
+    log::info!("Start watching recording rule leaders");
+    loop {
+        let ev = match events.recv().await {
+            Some(ev) => ev,
+            None => {
+                log::error!("watch_recording_rule_leaders: event channel closed");
+                break;
+            }
+        };
+        match ev {
+            infra_db::Event::Put(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                let item_value: ClusterLeader = json::from_slice(&ev.value.unwrap()).unwrap();
+                RECORDING_RULE_LEADERS.insert(item_key.to_owned(), item_value);
+            }
+            infra_db::Event::Delete(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                RECORDING_RULE_LEADERS.remove(item_key);
+            }
+            infra_db::Event::Empty => {}
+        }
+    }
+    Ok(())
+}
+
+pub async fn cache_leaders() -> Result<(), anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = "/recording_rules_leader/";
+    let ret = db.list(key).await?;
+    for (item_key, item_value) in ret {
+        let item_key = item_key.strip_prefix(key).unwrap();
+        let json_val: ClusterLeader = json::from_slice(&item_value).unwrap();
+        RECORDING_RULE_LEADERS.insert(item_key.to_owned(), json_val);
+    }
+    log::info!("Recording rule leaders Cached");
+    Ok(())
+}
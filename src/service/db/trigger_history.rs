@@ -0,0 +1,67 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::{
+    infra::{config::CONFIG, db as infra_db, ider},
+    meta::alert::TriggerHistory,
+    utils::json,
+};
+
+pub async fn add(history: &TriggerHistory) -> Result<(), anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let prefix = format!("/trigger_history/{}/{}/", history.org, history.alert_name);
+    let key = format!("{prefix}{}", ider::generate());
+    if let Err(e) = db
+        .put(
+            &key,
+            json::to_vec(history).unwrap().into(),
+            infra_db::NO_NEED_WATCH,
+        )
+        .await
+    {
+        log::error!("Error saving trigger history: {}", e);
+        return Err(anyhow::anyhow!("Error saving trigger history: {}", e));
+    }
+
+    // only keep the latest entries of every alert
+    let mut keys = db.list_keys(&prefix).await?;
+    if keys.len() > CONFIG.limit.alert_history_limit {
+        keys.sort();
+        for key in keys[..keys.len() - CONFIG.limit.alert_history_limit].iter() {
+            db.delete(key, false, infra_db::NO_NEED_WATCH).await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn list(org_id: &str, alert_name: &str) -> Result<Vec<TriggerHistory>, anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = format!("/trigger_history/{org_id}/{alert_name}/");
+    let mut list: Vec<TriggerHistory> = db
+        .list_values(&key)
+        .await?
+        .iter()
+        .map(|val| json::from_slice(val).unwrap())
+        .collect();
+    list.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    Ok(list)
+}
+
+pub async fn delete(org_id: &str, alert_name: &str) -> Result<(), anyhow::Error> {
+    let db = &infra_db::DEFAULT;
+    let key = format!("/trigger_history/{org_id}/{alert_name}/");
+    Ok(db
+        .delete_if_exists(&key, true, infra_db::NO_NEED_WATCH)
+        .await?)
+}
@@ -0,0 +1,183 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use actix_web::{http::StatusCode, HttpResponse};
+use rand::distributions::{Alphanumeric, DistString};
+use std::io;
+
+use crate::common::infra::config::FLUENT_ROUTES;
+use crate::common::meta::fluent::{FluentRoute, FluentRoutes, FluentSharedKey};
+use crate::common::meta::http::HttpResponse as MetaHttpResponse;
+use crate::service::db::fluent;
+
+#[tracing::instrument(skip(route))]
+pub async fn create_route(org_id: &str, mut route: FluentRoute) -> Result<HttpResponse, io::Error> {
+    if route.tag.trim().is_empty() {
+        return Ok(Response::BadRequest("Please provide tag for route".to_owned()).into());
+    }
+    route.org_id = org_id.to_owned();
+    if let Err(e) = check_route(&route) {
+        return Ok(Response::BadRequest(e).into());
+    }
+
+    route.id = crate::common::infra::ider::generate();
+    if let Err(e) = fluent::set(&route).await {
+        return Ok(Response::InternalServerError(e).into());
+    }
+    tracing::info!(id = route.id, "Fluent Route created");
+    Ok(HttpResponse::Created().json(route))
+}
+
+#[tracing::instrument(skip(route))]
+pub async fn update_route(
+    org_id: &str,
+    id: &str,
+    route: &mut FluentRoute,
+) -> Result<HttpResponse, io::Error> {
+    if route.stream_name.trim().is_empty() && route.tag.trim().is_empty() {
+        return Ok(Response::BadRequest(
+            "Please provide stream name/tag for route to update".to_owned(),
+        )
+        .into());
+    }
+
+    route.id = id.to_owned();
+    let old_route = match get_org_route(org_id, id).await {
+        Ok(route) => route,
+        Err(error) => {
+            tracing::info!(%error, id, "Fluent Route not found");
+            return Ok(Response::NotFound.into());
+        }
+    };
+    route.org_id = old_route.org_id.clone();
+    if route.stream_name.is_empty() {
+        route.stream_name = old_route.stream_name.clone();
+    }
+    if route.tag.is_empty() {
+        route.tag = old_route.tag.clone();
+    }
+
+    if route == &old_route {
+        return Ok(HttpResponse::Ok().json(route));
+    }
+    if let Err(e) = check_route(route) {
+        return Ok(Response::BadRequest(e).into());
+    }
+
+    if let Err(error) = fluent::set(route).await {
+        tracing::error!(%error, id, "Failed to save the fluent route");
+        return Ok(Response::InternalServerError(error).into());
+    }
+    Ok(HttpResponse::Ok().json(route))
+}
+
+#[tracing::instrument]
+pub async fn list_routes(org_id: &str) -> Result<HttpResponse, io::Error> {
+    let routes = match fluent::list().await {
+        Ok(routes) => routes
+            .into_iter()
+            .filter(|route| route.org_id == org_id)
+            .collect(),
+        Err(e) => return Ok(Response::InternalServerError(e).into()),
+    };
+    Ok(HttpResponse::Ok().json(FluentRoutes { routes }))
+}
+
+#[tracing::instrument]
+pub async fn delete_route(org_id: &str, id: &str) -> Result<HttpResponse, io::Error> {
+    let resp = if get_org_route(org_id, id).await.is_err() || fluent::delete(id).await.is_err() {
+        Response::NotFound
+    } else {
+        Response::OkMessage("Fluent route deleted".to_owned())
+    };
+    Ok(resp.into())
+}
+
+#[tracing::instrument]
+pub async fn get_shared_key(org_id: &str) -> Result<HttpResponse, io::Error> {
+    match fluent::get_key(org_id).await {
+        Ok(Some(shared_key)) => Ok(HttpResponse::Ok().json(FluentSharedKey { shared_key })),
+        Ok(None) => Ok(Response::BadRequest(
+            "The organization has no fluent shared key, please generate one".to_owned(),
+        )
+        .into()),
+        Err(e) => Ok(Response::InternalServerError(e).into()),
+    }
+}
+
+/// Generates a new shared key for the senders of the organization, the connections
+/// authenticated with the old one stay open.
+#[tracing::instrument]
+pub async fn rotate_shared_key(org_id: &str) -> Result<HttpResponse, io::Error> {
+    // the server finds the organization of a sender by its key, so keys are long random
+    // strings which don't collide
+    let shared_key = Alphanumeric.sample_string(&mut rand::thread_rng(), 32);
+    if let Err(e) = fluent::set_key(org_id, &shared_key).await {
+        return Ok(Response::InternalServerError(e).into());
+    }
+    Ok(HttpResponse::Ok().json(FluentSharedKey { shared_key }))
+}
+
+/// Gets the route if it belongs to the organization.
+async fn get_org_route(org_id: &str, id: &str) -> Result<FluentRoute, anyhow::Error> {
+    let route = fluent::get(id).await?;
+    if route.org_id != org_id {
+        return Err(anyhow::anyhow!("Fluent route {id} not found in {org_id}"));
+    }
+    Ok(route)
+}
+
+/// Rejects invalid tag patterns and patterns already used by another route of the
+/// organization, the events of a tag would go to either of them otherwise.
+fn check_route(route: &FluentRoute) -> Result<(), String> {
+    if route.tag.split('.').any(|part| part.is_empty()) {
+        return Err(format!("Invalid tag pattern: {}", route.tag));
+    }
+    for existing_route in FLUENT_ROUTES.iter() {
+        if existing_route.org_id == route.org_id
+            && existing_route.id != route.id
+            && existing_route.tag == route.tag
+        {
+            return Err(format!("Tag pattern {} is already routed", route.tag));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+enum Response {
+    OkMessage(String),
+    NotFound,
+    InternalServerError(anyhow::Error),
+    BadRequest(String),
+}
+
+impl From<Response> for HttpResponse {
+    fn from(resp: Response) -> Self {
+        match resp {
+            Response::OkMessage(message) => {
+                Self::Ok().json(MetaHttpResponse::message(StatusCode::OK.into(), message))
+            }
+            Response::NotFound => Self::NotFound().json(MetaHttpResponse::error(
+                StatusCode::NOT_FOUND.into(),
+                "Fluent route not found".to_owned(),
+            )),
+            Response::InternalServerError(err) => Self::InternalServerError().json(
+                MetaHttpResponse::error(StatusCode::INTERNAL_SERVER_ERROR.into(), err.to_string()),
+            ),
+            Response::BadRequest(err) => Self::BadRequest()
+                .json(MetaHttpResponse::error(StatusCode::BAD_REQUEST.into(), err)),
+        }
+    }
+}
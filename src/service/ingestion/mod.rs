@@ -16,7 +16,9 @@ use ahash::AHashMap;
 use arrow_schema::Schema;
 use bytes::{BufMut, BytesMut};
 use chrono::{TimeZone, Utc};
+use once_cell::sync::Lazy;
 use std::{collections::BTreeMap, io::BufReader};
+use tokio::sync::mpsc;
 use vector_enrichment::TableRegistry;
 use vrl::{
     compiler::{runtime::Runtime, CompilationResult, TargetValueRef},
@@ -40,14 +42,25 @@ use crate::common::{
         flatten,
         functions::get_vrl_compiler_config,
         json::{Map, Value},
-        notification::send_notification,
         schema::infer_json_schema,
     },
 };
-use crate::service::{db, format_partition_key, stream::stream_settings, triggers};
+use crate::service::{db, format_partition_key, stream::stream_settings};
 
 pub mod grpc;
 
+/// Real time alerts matched by ingested records, evaluated one after the other by a
+/// background task.
+static INGEST_NOTIFICATIONS: Lazy<mpsc::Sender<(Trigger, Alert)>> = Lazy::new(|| {
+    let (tx, mut rx) = mpsc::channel::<(Trigger, Alert)>(1024);
+    tokio::task::spawn(async move {
+        while let Some((trigger, alert)) = rx.recv().await {
+            crate::service::alert_manager::handle_real_time_trigger(&alert, trigger).await;
+        }
+    });
+    tx
+});
+
 pub fn compile_vrl_function(func: &str, org_id: &str) -> Result<VRLRuntimeConfig, std::io::Error> {
     if func.contains("get_env_var") {
         return Err(std::io::Error::new(
@@ -193,19 +206,18 @@ pub fn get_wal_time_key(
     time_key
 }
 
+/// Queues the evaluation of a real time alert, so that ingestion doesn't wait for the meta
+/// store and the destinations of the alert.
 pub async fn send_ingest_notification(trigger: Trigger, alert: Alert) {
-    log::info!(
-        "Sending notification for alert {} {}",
-        alert.name,
-        alert.stream
-    );
-    let _ = send_notification(&alert, &trigger).await;
-    let trigger_to_save = Trigger {
-        last_sent_at: Utc::now().timestamp_micros(),
-        count: trigger.count + 1,
-        ..trigger
-    };
-    let _ = triggers::save_trigger(&trigger_to_save.alert_name, &trigger_to_save).await;
+    if let Err(e) = INGEST_NOTIFICATIONS.try_send((trigger, alert)) {
+        let msg = e.to_string();
+        let (trigger, _) = e.into_inner();
+        log::warn!(
+            "Error queueing real time alert {}/{}: {msg}",
+            trigger.org,
+            trigger.alert_name
+        );
+    }
 }
 
 pub fn register_stream_transforms(
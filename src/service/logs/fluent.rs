@@ -0,0 +1,389 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use flate2::read::MultiGzDecoder;
+use rmpv::Value as MsgValue;
+use std::io::Read;
+
+use crate::common::{
+    infra::config::{CONFIG, FLUENT_ROUTES},
+    meta::{fluent::FluentRoute, ingestion::IngestionResponse, usage::UsageType},
+    utils::json::{self, Map, Value},
+};
+
+/// Events of a Fluent Forward message, cf.
+/// https://github.com/fluent/fluentd/wiki/Forward-Protocol-Specification-v1
+#[derive(Debug, Default)]
+pub struct ForwardMessage {
+    pub tag: String,
+    pub records: Vec<Value>,
+    /// Chunk id to acknowledge once the records are ingested
+    pub chunk: Option<String>,
+}
+
+/// Decodes a message in any of the Message, Forward, PackedForward and
+/// CompressedPackedForward modes.
+pub fn decode_message(msg: MsgValue) -> Result<ForwardMessage, anyhow::Error> {
+    let MsgValue::Array(mut items) = msg else {
+        return Err(anyhow::anyhow!("Invalid message, expected an array"));
+    };
+    if items.len() < 2 {
+        return Err(anyhow::anyhow!(
+            "Invalid message, expected [tag, entries, ...]"
+        ));
+    }
+    let tag = match as_bytes(&items[0]) {
+        Some(tag) if !tag.is_empty() => String::from_utf8_lossy(tag).into_owned(),
+        _ => return Err(anyhow::anyhow!("Invalid message, missing tag")),
+    };
+
+    let mut message = ForwardMessage {
+        tag,
+        ..Default::default()
+    };
+    let option = match &items[1] {
+        // Forward mode: [tag, [[time, record], ...], option]
+        MsgValue::Array(_) => {
+            let MsgValue::Array(entries) = std::mem::replace(&mut items[1], MsgValue::Nil) else {
+                unreachable!()
+            };
+            for entry in entries {
+                message.records.push(decode_entry(entry)?);
+            }
+            items.get(2)
+        }
+        // (Compressed)PackedForward mode: [tag, <msgpack stream of [time, record]>, option]
+        MsgValue::String(_) | MsgValue::Binary(_) => {
+            let option = items.get(2);
+            let compressed = option_value(option, "compressed").and_then(MsgValue::as_str);
+            let packed = as_bytes(&items[1]).unwrap_or_default();
+            let packed = match compressed {
+                Some("gzip") => {
+                    // the decoded size is bounded, a small chunk may decompress to a lot
+                    let max_size = CONFIG.fluent.max_chunk_size;
+                    let mut decoded = Vec::with_capacity((packed.len() * 4).min(max_size));
+                    MultiGzDecoder::new(packed)
+                        .take(max_size as u64 + 1)
+                        .read_to_end(&mut decoded)?;
+                    if decoded.len() > max_size {
+                        return Err(anyhow::anyhow!(
+                            "Decompressed chunk exceeds the max size of {max_size} bytes"
+                        ));
+                    }
+                    decoded
+                }
+                None | Some("text") => packed.to_vec(),
+                Some(v) => return Err(anyhow::anyhow!("Unsupported compression: {v}")),
+            };
+            let mut reader = packed.as_slice();
+            while !reader.is_empty() {
+                let entry = rmpv::decode::read_value(&mut reader)?;
+                message.records.push(decode_entry(entry)?);
+            }
+            option
+        }
+        // Message mode: [tag, time, record, option]
+        _ => {
+            if items.len() < 3 {
+                return Err(anyhow::anyhow!("Invalid message, missing record"));
+            }
+            let time = std::mem::replace(&mut items[1], MsgValue::Nil);
+            let record = std::mem::replace(&mut items[2], MsgValue::Nil);
+            message
+                .records
+                .push(decode_entry(MsgValue::Array(vec![time, record]))?);
+            items.get(3)
+        }
+    };
+    message.chunk = option_value(option, "chunk")
+        .and_then(as_bytes)
+        .map(|v| String::from_utf8_lossy(v).into_owned());
+    Ok(message)
+}
+
+/// Ingests the records of a message into the stream routed for its tag in the organization
+/// the sender authenticated for.
+pub async fn ingest(org_id: &str, msg: ForwardMessage) -> Result<IngestionResponse, anyhow::Error> {
+    let Some(route) = get_route(org_id, &msg.tag) else {
+        return Err(anyhow::anyhow!("No fluent route for tag {}", msg.tag));
+    };
+    let stream_name = if route.stream_name.is_empty() {
+        &msg.tag
+    } else {
+        &route.stream_name
+    };
+    super::json::ingest_records(
+        &route.org_id,
+        stream_name,
+        msg.records,
+        0,
+        "fluent",
+        UsageType::Fluent,
+    )
+    .await
+}
+
+/// Returns the most specific route of the organization matching the tag, the one with most
+/// literal parts.
+fn get_route(org_id: &str, tag: &str) -> Option<FluentRoute> {
+    let tag: Vec<&str> = tag.split('.').collect();
+    FLUENT_ROUTES
+        .iter()
+        .filter(|route| {
+            route.org_id == org_id && match_tag(&route.tag.split('.').collect::<Vec<_>>(), &tag)
+        })
+        .max_by_key(|route| {
+            let parts = route.tag.split('.');
+            (
+                parts.clone().filter(|p| *p != "*" && *p != "**").count(),
+                parts.count(),
+            )
+        })
+        .map(|route| route.value().clone())
+}
+
+/// Matches the parts of a tag against a pattern, `*` matches one part and `**` zero or
+/// more parts.
+fn match_tag(pattern: &[&str], tag: &[&str]) -> bool {
+    match (pattern.first(), tag.first()) {
+        (None, None) => true,
+        (Some(&"**"), _) => {
+            match_tag(&pattern[1..], tag) || (!tag.is_empty() && match_tag(pattern, &tag[1..]))
+        }
+        (Some(p), Some(t)) => (*p == "*" || p == t) && match_tag(&pattern[1..], &tag[1..]),
+        _ => false,
+    }
+}
+
+/// Converts a `[time, record]` entry into a record with the timestamp column.
+fn decode_entry(entry: MsgValue) -> Result<Value, anyhow::Error> {
+    let MsgValue::Array(mut entry) = entry else {
+        return Err(anyhow::anyhow!("Invalid entry, expected [time, record]"));
+    };
+    if entry.len() != 2 {
+        return Err(anyhow::anyhow!("Invalid entry, expected [time, record]"));
+    }
+    let timestamp = decode_time(&entry[0])?;
+    let Value::Object(mut record) = to_json(entry.pop().unwrap()) else {
+        return Err(anyhow::anyhow!("Invalid entry, record is not a map"));
+    };
+    record.insert(
+        CONFIG.common.column_timestamp.clone(),
+        Value::Number(timestamp.into()),
+    );
+    Ok(Value::Object(record))
+}
+
+/// Returns the time in microseconds, sent as seconds or as EventTime extension.
+fn decode_time(time: &MsgValue) -> Result<i64, anyhow::Error> {
+    match time {
+        MsgValue::Integer(v) => v
+            .as_i64()
+            .map(|v| v * 1_000_000)
+            .ok_or_else(|| anyhow::anyhow!("Invalid time: {time}")),
+        MsgValue::F32(v) => Ok((*v as f64 * 1_000_000.0) as i64),
+        MsgValue::F64(v) => Ok((v * 1_000_000.0) as i64),
+        // EventTime: 32 bit seconds and nanoseconds, both big-endian
+        MsgValue::Ext(0, data) if data.len() == 8 => {
+            let secs = u32::from_be_bytes(data[..4].try_into().unwrap()) as i64;
+            let nanos = u32::from_be_bytes(data[4..].try_into().unwrap()) as i64;
+            Ok(secs * 1_000_000 + nanos / 1000)
+        }
+        _ => Err(anyhow::anyhow!("Invalid time: {time}")),
+    }
+}
+
+fn to_json(value: MsgValue) -> Value {
+    match value {
+        MsgValue::Nil => Value::Null,
+        MsgValue::Boolean(v) => Value::Bool(v),
+        MsgValue::Integer(v) => match v.as_i64() {
+            Some(v) => Value::Number(v.into()),
+            None => Value::Number(v.as_u64().unwrap_or_default().into()),
+        },
+        MsgValue::F32(v) => json::json!(v),
+        MsgValue::F64(v) => json::json!(v),
+        MsgValue::String(v) => Value::String(String::from_utf8_lossy(v.as_bytes()).into_owned()),
+        MsgValue::Binary(v) => Value::String(String::from_utf8_lossy(&v).into_owned()),
+        MsgValue::Array(v) => Value::Array(v.into_iter().map(to_json).collect()),
+        MsgValue::Map(v) => {
+            let mut map = Map::with_capacity(v.len());
+            for (key, value) in v {
+                let key = match as_bytes(&key) {
+                    Some(key) => String::from_utf8_lossy(key).into_owned(),
+                    None => key.to_string(),
+                };
+                map.insert(key, to_json(value));
+            }
+            Value::Object(map)
+        }
+        MsgValue::Ext(_, v) => Value::String(hex::encode(v)),
+    }
+}
+
+/// Senders may encode strings as str or bin.
+pub(crate) fn as_bytes(value: &MsgValue) -> Option<&[u8]> {
+    match value {
+        MsgValue::String(v) => Some(v.as_bytes()),
+        MsgValue::Binary(v) => Some(v),
+        _ => None,
+    }
+}
+
+pub(crate) fn option_value<'a>(option: Option<&'a MsgValue>, key: &str) -> Option<&'a MsgValue> {
+    option?
+        .as_map()?
+        .iter()
+        .find(|(k, _)| as_bytes(k) == Some(key.as_bytes()))
+        .map(|(_, v)| v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    fn event_time(secs: u32, nanos: u32) -> MsgValue {
+        let mut data = secs.to_be_bytes().to_vec();
+        data.extend_from_slice(&nanos.to_be_bytes());
+        MsgValue::Ext(0, data)
+    }
+
+    fn record(message: &str) -> MsgValue {
+        MsgValue::Map(vec![(MsgValue::from("log"), MsgValue::from(message))])
+    }
+
+    fn entries(n: usize) -> Vec<u8> {
+        let mut buf = vec![];
+        for i in 0..n {
+            let entry =
+                MsgValue::Array(vec![MsgValue::from(1_700_000_000 + i as i64), record("a")]);
+            rmpv::encode::write_value(&mut buf, &entry).unwrap();
+        }
+        buf
+    }
+
+    #[test]
+    fn test_decode_message_mode() {
+        let msg = MsgValue::Array(vec![
+            MsgValue::from("app.nginx"),
+            event_time(1_700_000_000, 123_456_789),
+            record("GET /"),
+            MsgValue::Map(vec![(MsgValue::from("chunk"), MsgValue::from("abc"))]),
+        ]);
+        let msg = decode_message(msg).unwrap();
+        assert_eq!(msg.tag, "app.nginx");
+        assert_eq!(msg.chunk.as_deref(), Some("abc"));
+        assert_eq!(msg.records.len(), 1);
+        assert_eq!(msg.records[0].get("log").unwrap(), "GET /");
+        assert_eq!(
+            msg.records[0].get(&CONFIG.common.column_timestamp).unwrap(),
+            &json::json!(1_700_000_000_123_456i64)
+        );
+    }
+
+    #[test]
+    fn test_decode_forward_modes() {
+        let forward = MsgValue::Array(vec![
+            MsgValue::from("app"),
+            MsgValue::Array(vec![
+                MsgValue::Array(vec![MsgValue::from(1_700_000_000), record("a")]),
+                MsgValue::Array(vec![MsgValue::from(1_700_000_001), record("b")]),
+            ]),
+        ]);
+        let msg = decode_message(forward).unwrap();
+        assert_eq!(msg.records.len(), 2);
+        assert_eq!(msg.chunk, None);
+
+        let packed = MsgValue::Array(vec![MsgValue::from("app"), MsgValue::Binary(entries(3))]);
+        assert_eq!(decode_message(packed).unwrap().records.len(), 3);
+
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&entries(4)).unwrap();
+        let compressed = MsgValue::Array(vec![
+            MsgValue::from("app"),
+            MsgValue::Binary(encoder.finish().unwrap()),
+            MsgValue::Map(vec![(MsgValue::from("compressed"), MsgValue::from("gzip"))]),
+        ]);
+        assert_eq!(decode_message(compressed).unwrap().records.len(), 4);
+
+        let invalid = MsgValue::Array(vec![MsgValue::from("app"), MsgValue::from(1)]);
+        assert!(decode_message(invalid).is_err());
+    }
+
+    #[test]
+    fn test_decode_compressed_max_size() {
+        // zeros compress to a tiny fraction of their size
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder
+            .write_all(&vec![0; CONFIG.fluent.max_chunk_size + 1])
+            .unwrap();
+        let compressed = MsgValue::Array(vec![
+            MsgValue::from("app"),
+            MsgValue::Binary(encoder.finish().unwrap()),
+            MsgValue::Map(vec![(MsgValue::from("compressed"), MsgValue::from("gzip"))]),
+        ]);
+        let err = decode_message(compressed).unwrap_err();
+        assert!(err.to_string().contains("max size"));
+    }
+
+    #[test]
+    fn test_get_route() {
+        for (id, org_id, tag) in [
+            ("route_org1_app", "route_org1", "app.**"),
+            ("route_org1_nginx", "route_org1", "app.nginx"),
+            ("route_org2_nginx", "route_org2", "app.nginx.*"),
+        ] {
+            FLUENT_ROUTES.insert(
+                id.to_string(),
+                FluentRoute {
+                    org_id: org_id.to_string(),
+                    stream_name: "".to_string(),
+                    tag: tag.to_string(),
+                    id: id.to_string(),
+                },
+            );
+        }
+        let route_id = |org_id: &str, tag: &str| get_route(org_id, tag).map(|r| r.id);
+        assert_eq!(
+            route_id("route_org1", "app.nginx").as_deref(),
+            Some("route_org1_nginx")
+        );
+        // the more specific route of another org isn't used
+        assert_eq!(
+            route_id("route_org1", "app.nginx.access").as_deref(),
+            Some("route_org1_app")
+        );
+        assert_eq!(route_id("route_org2", "app.web"), None);
+        assert_eq!(route_id("route_org3", "app.nginx"), None);
+    }
+
+    #[test]
+    fn test_match_tag() {
+        let matches = |pattern: &str, tag: &str| {
+            match_tag(
+                &pattern.split('.').collect::<Vec<_>>(),
+                &tag.split('.').collect::<Vec<_>>(),
+            )
+        };
+        assert!(matches("app.nginx", "app.nginx"));
+        assert!(matches("app.*", "app.nginx"));
+        assert!(!matches("app.*", "app.nginx.access"));
+        assert!(matches("app.**", "app"));
+        assert!(matches("app.**", "app.nginx.access"));
+        assert!(matches("**.access", "app.nginx.access"));
+        assert!(!matches("app.**", "web.nginx"));
+    }
+}
@@ -0,0 +1,338 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use actix_web::web;
+use ahash::AHashMap;
+use chrono::Utc;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::common::{
+    infra::{config::CONFIG, wal},
+    meta::{
+        ingestion::{HecAckRequest, HecAckResponse, HecEvent, HecRawQuery, HecResponse},
+        usage::UsageType,
+    },
+    utils::json::{Map, Value},
+};
+
+/// Field holding the event when it isn't an object
+const MESSAGE_FIELD: &str = "message";
+const DEFAULT_STREAM: &str = "default";
+
+/// Seconds after which an idle channel without pending acks is forgotten
+const CHANNEL_IDLE_TIMEOUT: i64 = 3600;
+
+/// The ack ids of the channels, by `{org_id}/{channel}`. The acks are only known to the
+/// ingester which received the events, so senders need a sticky load balancer, as for
+/// Splunk.
+static CHANNELS: Lazy<Mutex<AHashMap<String, ChannelAcks>>> =
+    Lazy::new(|| Mutex::new(AHashMap::new()));
+
+#[derive(Debug, Default)]
+struct ChannelAcks {
+    /// the next ack id to hand out
+    next_id: u64,
+    /// the events of the ack ids below this one are flushed to the WAL on the disk
+    durable_id: u64,
+    /// seconds
+    last_used: i64,
+}
+
+/// Ingests the events of the HEC event endpoint, the body holds one or more JSON events
+/// which are not separated or separated by whitespace.
+pub async fn ingest_events(
+    org_id: &str,
+    body: web::Bytes,
+    thread_id: usize,
+    channel: Option<&str>,
+) -> HecResponse {
+    let streams = match decode_events(&body) {
+        Ok(streams) => streams,
+        Err(resp) => return resp,
+    };
+    ingest_streams(org_id, streams, thread_id, channel).await
+}
+
+/// Ingests every line of the body as one event of the HEC raw endpoint.
+pub async fn ingest_raw(
+    org_id: &str,
+    body: web::Bytes,
+    thread_id: usize,
+    query: &HecRawQuery,
+) -> HecResponse {
+    let streams = match decode_raw(&body, query) {
+        Ok(streams) => streams,
+        Err(resp) => return resp,
+    };
+    ingest_streams(org_id, streams, thread_id, query.channel.as_deref()).await
+}
+
+/// Answers the ack status of the given ids of the channel, an id is acknowledged once
+/// its events are flushed to the WAL on the disk.
+pub fn ack(
+    org_id: &str,
+    channel: Option<&str>,
+    req: &HecAckRequest,
+) -> Result<HecAckResponse, HecResponse> {
+    let channel = channel.unwrap_or_default();
+    if channel.is_empty() {
+        return Err(HecResponse::channel_missing());
+    }
+    let key = format!("{org_id}/{channel}");
+    let durable_id = CHANNELS.lock().get_mut(&key).map_or(0, |acks| {
+        acks.last_used = Utc::now().timestamp();
+        acks.durable_id
+    });
+    Ok(HecAckResponse {
+        acks: req
+            .acks
+            .iter()
+            .map(|id| (id.to_string(), *id < durable_id))
+            .collect(),
+    })
+}
+
+/// Flushes the WAL to the disk and acknowledges the ack ids handed out before, called
+/// periodically by the HEC acks job.
+pub async fn sync_acks() {
+    let issued: Vec<(String, u64)> = CHANNELS
+        .lock()
+        .iter()
+        .filter(|(_, acks)| acks.durable_id < acks.next_id)
+        .map(|(channel, acks)| (channel.clone(), acks.next_id))
+        .collect();
+    if !issued.is_empty() {
+        // the ack ids are handed out once the events are written to the WAL
+        wal::sync_all().await;
+    }
+
+    let now = Utc::now().timestamp();
+    let mut channels = CHANNELS.lock();
+    for (channel, next_id) in issued {
+        if let Some(acks) = channels.get_mut(&channel) {
+            acks.durable_id = acks.durable_id.max(next_id);
+        }
+    }
+    channels.retain(|_, acks| {
+        acks.durable_id < acks.next_id || now - acks.last_used < CHANNEL_IDLE_TIMEOUT
+    });
+}
+
+fn next_ack_id(org_id: &str, channel: &str) -> u64 {
+    let mut channels = CHANNELS.lock();
+    let acks = channels.entry(format!("{org_id}/{channel}")).or_default();
+    acks.last_used = Utc::now().timestamp();
+    acks.next_id += 1;
+    acks.next_id - 1
+}
+
+async fn ingest_streams(
+    org_id: &str,
+    streams: AHashMap<String, Vec<Value>>,
+    thread_id: usize,
+    channel: Option<&str>,
+) -> HecResponse {
+    for (stream_name, records) in streams {
+        if let Err(e) = super::json::ingest_records(
+            org_id,
+            &stream_name,
+            records,
+            thread_id,
+            "/services/collector",
+            UsageType::Hec,
+        )
+        .await
+        {
+            log::error!("Error processing HEC request: {:?}", e);
+            return HecResponse::server_error();
+        }
+    }
+    // acks are only used by senders with a channel
+    let ack_id = channel
+        .filter(|v| !v.is_empty())
+        .map(|channel| next_ack_id(org_id, channel));
+    HecResponse::success(ack_id)
+}
+
+fn decode_events(body: &[u8]) -> Result<AHashMap<String, Vec<Value>>, HecResponse> {
+    let mut streams: AHashMap<String, Vec<Value>> = AHashMap::new();
+    let events = serde_json::Deserializer::from_slice(body).into_iter::<HecEvent>();
+    for (i, event) in events.enumerate() {
+        let Ok(event) = event else {
+            return Err(HecResponse::invalid_data_format(i));
+        };
+        let (stream_name, record) = event_to_record(event, i)?;
+        streams.entry(stream_name).or_default().push(record);
+    }
+    if streams.is_empty() {
+        return Err(HecResponse::no_data());
+    }
+    Ok(streams)
+}
+
+fn decode_raw(
+    body: &[u8],
+    query: &HecRawQuery,
+) -> Result<AHashMap<String, Vec<Value>>, HecResponse> {
+    let body = String::from_utf8_lossy(body);
+    let mut records = vec![];
+    for line in body.lines().filter(|line| !line.trim().is_empty()) {
+        let event = HecEvent {
+            host: query.host.clone(),
+            source: query.source.clone(),
+            sourcetype: query.sourcetype.clone(),
+            event: Some(Value::String(line.to_string())),
+            ..Default::default()
+        };
+        let (_, record) = event_to_record(event, records.len())?;
+        records.push(record);
+    }
+    if records.is_empty() {
+        return Err(HecResponse::no_data());
+    }
+    let stream_name = query
+        .index
+        .clone()
+        .unwrap_or_else(|| DEFAULT_STREAM.to_string());
+    Ok([(stream_name, records)].into_iter().collect())
+}
+
+/// Converts an event into a record of the stream named by its `index`. Object events keep
+/// their fields, others are stored in the `message` field.
+fn event_to_record(event: HecEvent, event_number: usize) -> Result<(String, Value), HecResponse> {
+    let mut record = match event.event {
+        None | Some(Value::Null) => return Err(HecResponse::event_required(event_number)),
+        Some(Value::String(v)) if v.is_empty() => {
+            return Err(HecResponse::event_blank(event_number));
+        }
+        Some(Value::Object(map)) => map,
+        Some(Value::String(v)) => {
+            let mut map = Map::new();
+            map.insert(MESSAGE_FIELD.to_string(), Value::String(v));
+            map
+        }
+        Some(v) => {
+            let mut map = Map::new();
+            map.insert(MESSAGE_FIELD.to_string(), Value::String(v.to_string()));
+            map
+        }
+    };
+    for (name, value) in [
+        ("host", event.host),
+        ("source", event.source),
+        ("sourcetype", event.sourcetype),
+    ] {
+        if let Some(value) = value {
+            record.insert(name.to_string(), Value::String(value));
+        }
+    }
+    for (name, value) in event.fields.into_iter().flatten() {
+        record.insert(name, value);
+    }
+    if let Some(time) = event.time {
+        let secs = match &time {
+            Value::Number(v) => v.as_f64(),
+            Value::String(v) => v.parse::<f64>().ok(),
+            _ => None,
+        };
+        let Some(secs) = secs else {
+            return Err(HecResponse::invalid_data_format(event_number));
+        };
+        record.insert(
+            CONFIG.common.column_timestamp.clone(),
+            Value::Number(((secs * 1_000_000.0) as i64).into()),
+        );
+    }
+    let stream_name = event
+        .index
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| DEFAULT_STREAM.to_string());
+    Ok((stream_name, Value::Object(record)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_events() {
+        let body = r#"{"time": 1426279439.5, "host": "web01", "index": "web", "event": "hello"}
+            {"event": {"user": "alice", "action": "login"}, "sourcetype": "auth", "fields": {"dc": "eu"}}"#;
+        let streams = decode_events(body.as_bytes()).unwrap();
+        let web = streams.get("web").unwrap();
+        assert_eq!(web[0].get(MESSAGE_FIELD).unwrap(), "hello");
+        assert_eq!(web[0].get("host").unwrap(), "web01");
+        assert_eq!(
+            web[0].get(&CONFIG.common.column_timestamp).unwrap(),
+            &Value::Number(1_426_279_439_500_000i64.into())
+        );
+        let default = streams.get(DEFAULT_STREAM).unwrap();
+        assert_eq!(default[0].get("user").unwrap(), "alice");
+        assert_eq!(default[0].get("sourcetype").unwrap(), "auth");
+        assert_eq!(default[0].get("dc").unwrap(), "eu");
+    }
+
+    #[test]
+    fn test_decode_invalid_events() {
+        assert_eq!(decode_events(b"").unwrap_err(), HecResponse::no_data());
+        assert_eq!(
+            decode_events(br#"{"event": "a"}{"host": "web01"}"#).unwrap_err(),
+            HecResponse::event_required(1)
+        );
+        assert_eq!(
+            decode_events(br#"{"event": ""}"#).unwrap_err(),
+            HecResponse::event_blank(0)
+        );
+        assert_eq!(
+            decode_events(br#"{"event": "a"} not json"#).unwrap_err(),
+            HecResponse::invalid_data_format(1)
+        );
+    }
+
+    #[test]
+    fn test_decode_raw() {
+        let query = HecRawQuery {
+            sourcetype: Some("syslog".to_string()),
+            index: Some("raw".to_string()),
+            ..Default::default()
+        };
+        let streams = decode_raw(b"first line\n\nsecond line\n", &query).unwrap();
+        let records = streams.get("raw").unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].get(MESSAGE_FIELD).unwrap(), "second line");
+        assert_eq!(records[1].get("sourcetype").unwrap(), "syslog");
+    }
+
+    #[actix_web::test]
+    async fn test_ack() {
+        let req = HecAckRequest { acks: vec![0, 1] };
+        assert!(ack("default", None, &req).is_err());
+
+        assert_eq!(next_ack_id("default", "test_ack"), 0);
+        let resp = ack("default", Some("test_ack"), &req).unwrap();
+        assert_eq!(resp.acks.get("0"), Some(&false));
+
+        // acknowledged once flushed
+        sync_acks().await;
+        let resp = ack("default", Some("test_ack"), &req).unwrap();
+        assert_eq!(resp.acks.get("0"), Some(&true));
+        assert_eq!(resp.acks.get("1"), Some(&false));
+
+        // the ids are counted per channel of an organization
+        assert_eq!(next_ack_id("default", "test_ack_other"), 0);
+        assert_eq!(next_ack_id("other", "test_ack"), 0);
+        assert_eq!(next_ack_id("default", "test_ack"), 1);
+    }
+}
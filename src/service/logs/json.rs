@@ -42,6 +42,32 @@ pub async fn ingest(
     in_stream_name: &str,
     body: web::Bytes,
     thread_id: usize,
+) -> Result<IngestionResponse, anyhow::Error> {
+    let reader: Vec<json::Value> = json::from_slice(&body).unwrap_or({
+        let val: json::Value = json::from_slice(&body)?;
+        vec![val]
+    });
+    ingest_records(
+        org_id,
+        in_stream_name,
+        reader,
+        thread_id,
+        "/api/org/ingest/logs/_json",
+        UsageType::Json,
+    )
+    .await
+}
+
+/// Ingests decoded records into one stream, used by the endpoints which convert their
+/// payload to JSON records first. `endpoint` and `usage_type` label the request metrics
+/// and usage.
+pub(crate) async fn ingest_records(
+    org_id: &str,
+    in_stream_name: &str,
+    reader: Vec<json::Value>,
+    thread_id: usize,
+    endpoint: &str,
+    usage_type: UsageType,
 ) -> Result<IngestionResponse, anyhow::Error> {
     let start = std::time::Instant::now();
     let mut stream_schema_map: AHashMap<String, Schema> = AHashMap::new();
@@ -81,10 +107,6 @@ pub async fn ingest(
     // End get stream alert
 
     let mut buf: AHashMap<String, Vec<String>> = AHashMap::new();
-    let reader: Vec<json::Value> = json::from_slice(&body).unwrap_or({
-        let val: json::Value = json::from_slice(&body)?;
-        vec![val]
-    });
     for item in reader.iter() {
         //JSON Flattening
         let mut value = flatten::flatten(item)?;
@@ -207,7 +229,7 @@ pub async fn ingest(
     let time = start.elapsed().as_secs_f64();
     metrics::HTTP_RESPONSE_TIME
         .with_label_values(&[
-            "/api/org/ingest/logs/_json",
+            endpoint,
             "200",
             org_id,
             stream_name,
@@ -216,7 +238,7 @@ pub async fn ingest(
         .observe(time);
     metrics::HTTP_INCOMING_REQUESTS
         .with_label_values(&[
-            "/api/org/ingest/logs/_json",
+            endpoint,
             "200",
             org_id,
             stream_name,
@@ -231,7 +253,7 @@ pub async fn ingest(
         org_id,
         stream_name,
         StreamType::Logs,
-        UsageType::Json,
+        usage_type,
         local_trans.len() as u16,
     )
     .await;
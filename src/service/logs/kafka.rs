@@ -0,0 +1,748 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use actix_web::{http, HttpResponse};
+use async_trait::async_trait;
+use chrono::Utc;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use rskafka::client::{
+    error::{Error as KafkaError, ProtocolError},
+    partition::{OffsetAt, PartitionClient, UnknownTopicHandling},
+    Client, ClientBuilder,
+};
+use std::{future::Future, io::Error, sync::Arc, time::Duration};
+
+use crate::common::{
+    infra::{
+        cluster::LOCAL_NODE_UUID,
+        config::{RwHashMap, RwHashSet, CONFIG, KAFKA_SOURCES, KAFKA_SOURCE_LEADERS},
+        dist_lock, wal,
+    },
+    meta::{
+        http::HttpResponse as MetaHttpResponse,
+        kafka::{KafkaFormat, KafkaSource, KafkaSourceList, KafkaStartOffset},
+        prom::ClusterLeader,
+        usage::UsageType,
+    },
+    utils::json::{self, Map, Value},
+};
+use crate::service::{db, format_stream_name, ingestion::is_ingestion_allowed};
+
+/// Field of the records decoded from plain text messages.
+const MESSAGE_FIELD: &str = "message";
+
+/// Connections of the sources consumed by this node, by source key.
+static CLIENTS: Lazy<RwHashMap<String, Arc<KafkaClient>>> = Lazy::new(DashMap::default);
+
+/// Sources consumed by a task of this node, by source key.
+static RUNNING: Lazy<RwHashSet<String>> = Lazy::new(Default::default);
+
+/// A message of a topic partition.
+#[derive(Clone, Debug, Default)]
+pub struct Message {
+    pub offset: i64,
+    /// In microseconds
+    pub timestamp: i64,
+    pub value: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum Fetched {
+    Messages(Vec<Message>),
+    /// The offset isn't available anymore, e.g. deleted by the topic retention
+    OutOfRange,
+}
+
+/// Reads topic partitions, implemented on top of the Kafka protocol by `KafkaClient`.
+#[async_trait]
+pub trait Consumer: Send + Sync {
+    async fn partitions(&self, topic: &str) -> Result<Vec<i32>, anyhow::Error>;
+    async fn offset(
+        &self,
+        topic: &str,
+        partition: i32,
+        at: KafkaStartOffset,
+    ) -> Result<i64, anyhow::Error>;
+    async fn fetch(
+        &self,
+        topic: &str,
+        partition: i32,
+        offset: i64,
+    ) -> Result<Fetched, anyhow::Error>;
+}
+
+pub struct KafkaClient {
+    brokers: Vec<String>,
+    client: Client,
+    partitions: RwHashMap<(String, i32), Arc<PartitionClient>>,
+}
+
+/// Fails the request to the brokers after `ZO_KAFKA_REQUEST_TIMEOUT`, so an unreachable
+/// broker doesn't hold the consumption of its source forever.
+async fn with_timeout<T, E>(fut: impl Future<Output = Result<T, E>>) -> Result<T, anyhow::Error>
+where
+    E: Into<anyhow::Error>,
+{
+    let timeout = Duration::from_secs(CONFIG.kafka.request_timeout);
+    match tokio::time::timeout(timeout, fut).await {
+        Ok(ret) => ret.map_err(Into::into),
+        Err(_) => Err(anyhow::anyhow!(
+            "kafka request timed out after {}s",
+            timeout.as_secs()
+        )),
+    }
+}
+
+impl KafkaClient {
+    pub async fn connect(brokers: &[String]) -> Result<Self, anyhow::Error> {
+        let client = with_timeout(ClientBuilder::new(brokers.to_vec()).build()).await?;
+        Ok(Self {
+            brokers: brokers.to_vec(),
+            client,
+            partitions: DashMap::default(),
+        })
+    }
+
+    async fn partition_client(
+        &self,
+        topic: &str,
+        partition: i32,
+    ) -> Result<Arc<PartitionClient>, anyhow::Error> {
+        let key = (topic.to_string(), partition);
+        if let Some(client) = self.partitions.get(&key) {
+            return Ok(client.clone());
+        }
+        let client = Arc::new(
+            with_timeout(self.client.partition_client(
+                topic,
+                partition,
+                UnknownTopicHandling::Error,
+            ))
+            .await?,
+        );
+        self.partitions.insert(key, client.clone());
+        Ok(client)
+    }
+}
+
+#[async_trait]
+impl Consumer for KafkaClient {
+    async fn partitions(&self, topic: &str) -> Result<Vec<i32>, anyhow::Error> {
+        let topics = with_timeout(self.client.list_topics()).await?;
+        match topics.into_iter().find(|t| t.name == topic) {
+            Some(t) => Ok(t.partitions.into_iter().collect()),
+            None => Err(anyhow::anyhow!("topic [{topic}] not found")),
+        }
+    }
+
+    async fn offset(
+        &self,
+        topic: &str,
+        partition: i32,
+        at: KafkaStartOffset,
+    ) -> Result<i64, anyhow::Error> {
+        let at = match at {
+            KafkaStartOffset::Earliest => OffsetAt::Earliest,
+            KafkaStartOffset::Latest => OffsetAt::Latest,
+        };
+        let client = self.partition_client(topic, partition).await?;
+        with_timeout(client.get_offset(at)).await
+    }
+
+    async fn fetch(
+        &self,
+        topic: &str,
+        partition: i32,
+        offset: i64,
+    ) -> Result<Fetched, anyhow::Error> {
+        let client = self.partition_client(topic, partition).await?;
+        let timeout = Duration::from_secs(CONFIG.kafka.request_timeout);
+        let ret = tokio::time::timeout(
+            timeout,
+            client.fetch_records(
+                offset,
+                1..CONFIG.kafka.fetch_max_bytes,
+                CONFIG.kafka.fetch_max_wait,
+            ),
+        )
+        .await
+        .map_err(|_| anyhow::anyhow!("kafka request timed out after {}s", timeout.as_secs()))?;
+        match ret {
+            Ok((records, _)) => Ok(Fetched::Messages(
+                records
+                    .into_iter()
+                    // compacted topics keep tombstones without value
+                    .filter_map(|r| {
+                        Some(Message {
+                            offset: r.offset,
+                            timestamp: r.record.timestamp.timestamp_micros(),
+                            value: r.record.value?,
+                        })
+                    })
+                    .collect(),
+            )),
+            Err(KafkaError::ServerError {
+                protocol_error: ProtocolError::OffsetOutOfRange,
+                ..
+            }) => Ok(Fetched::OutOfRange),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[tracing::instrument(skip(source))]
+pub async fn save_source(
+    org_id: String,
+    name: String,
+    mut source: KafkaSource,
+) -> Result<HttpResponse, Error> {
+    source.name = name;
+    if let Err(e) = validate_source(&source) {
+        return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+            http::StatusCode::BAD_REQUEST.into(),
+            e,
+        )));
+    }
+    match db::kafka::set(&org_id, &source).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(MetaHttpResponse::message(
+            http::StatusCode::OK.into(),
+            "Kafka source saved".to_string(),
+        ))),
+        Err(e) => Ok(
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        ),
+    }
+}
+
+#[tracing::instrument]
+pub async fn get_source(org_id: String, name: String) -> Result<HttpResponse, Error> {
+    match db::kafka::get(&org_id, &name).await {
+        Ok(Some(source)) => Ok(HttpResponse::Ok().json(source)),
+        Ok(None) => Ok(HttpResponse::NotFound().json(MetaHttpResponse::error(
+            http::StatusCode::NOT_FOUND.into(),
+            "Kafka source not found".to_string(),
+        ))),
+        Err(e) => Ok(
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        ),
+    }
+}
+
+#[tracing::instrument]
+pub async fn list_sources(org_id: String) -> Result<HttpResponse, Error> {
+    let mut list = match db::kafka::list(&org_id).await {
+        Ok(list) => list,
+        Err(e) => {
+            return Ok(
+                HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                    http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                    e.to_string(),
+                )),
+            )
+        }
+    };
+    list.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(HttpResponse::Ok().json(KafkaSourceList { list }))
+}
+
+#[tracing::instrument]
+pub async fn delete_source(org_id: String, name: String) -> Result<HttpResponse, Error> {
+    match db::kafka::delete(&org_id, &name).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(MetaHttpResponse::message(
+            http::StatusCode::OK.into(),
+            "Kafka source deleted".to_string(),
+        ))),
+        Err(e) => Ok(HttpResponse::NotFound().json(MetaHttpResponse::error(
+            http::StatusCode::NOT_FOUND.into(),
+            e.to_string(),
+        ))),
+    }
+}
+
+fn validate_source(source: &KafkaSource) -> Result<(), String> {
+    if source.name.is_empty() || source.name.contains('/') {
+        return Err("Kafka source name can not be empty or contain '/'".to_string());
+    }
+    if source.brokers.is_empty() || source.brokers.iter().any(|b| b.trim().is_empty()) {
+        return Err("Kafka source needs at least one broker".to_string());
+    }
+    if source.topics.is_empty()
+        || source
+            .topics
+            .iter()
+            .any(|t| t.is_empty() || t.contains('/'))
+    {
+        return Err("Kafka source topics can not be empty or contain '/'".to_string());
+    }
+    if source.stream_name.is_empty()
+        || format_stream_name(&source.stream_name) != source.stream_name
+    {
+        return Err(format!("Invalid stream name: {}", source.stream_name));
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    Consume,
+    Claim,
+    Skip,
+}
+
+/// Decides what this node does with a source. The leader consumes it, the others wait
+/// until the lease of the leader expires and then claim the source, the claim turns into
+/// consumption on the next run. Only decisions made on the stored leader, under the lock
+/// of the source, are acted on, see [`update_leader`].
+fn next_action(leader: Option<&ClusterLeader>, node: &str, now: i64, lease: i64) -> Action {
+    match leader {
+        Some(leader) if leader.updated_by == node => Action::Consume,
+        Some(leader) if now - leader.last_received <= lease => Action::Skip,
+        _ => Action::Claim,
+    }
+}
+
+/// Consumes the enabled sources led by this node, called periodically by the kafka job.
+/// Every source is consumed by a task of its own, so a slow or unreachable broker only
+/// holds its source, and a source still consumed since the last run is left to that run.
+pub async fn run() {
+    let now = Utc::now().timestamp_micros();
+    let node = LOCAL_NODE_UUID.to_string();
+    let lease = (CONFIG.kafka.poll_interval as i64 + CONFIG.limit.metrics_leader_election_interval)
+        * 1_000_000;
+    CLIENTS.retain(|key, _| KAFKA_SOURCES.contains_key(key));
+    let sources: Vec<(String, KafkaSource)> = KAFKA_SOURCES
+        .iter()
+        .filter(|source| source.enabled)
+        .map(|source| (source.key().clone(), source.value().clone()))
+        .collect();
+    for (key, source) in sources {
+        // the cached leader filters out the sources this node has nothing to do with
+        let leader = KAFKA_SOURCE_LEADERS.get(&key).map(|v| v.value().clone());
+        if next_action(leader.as_ref(), &node, now, lease) == Action::Skip {
+            continue;
+        }
+        if !RUNNING.insert(key.clone()) {
+            continue;
+        }
+        let node = node.clone();
+        tokio::task::spawn(async move {
+            run_source(&key, &source, &node, now, lease).await;
+            RUNNING.remove(&key);
+        });
+    }
+}
+
+async fn run_source(key: &str, source: &KafkaSource, node: &str, now: i64, lease: i64) {
+    let Some((org_id, _)) = key.split_once('/') else {
+        return;
+    };
+    let action = match update_leader(key, node, now, lease).await {
+        Ok(action) => action,
+        Err(e) => {
+            log::error!("kafka source [{key}] error updating leader: {e}");
+            return;
+        }
+    };
+    if action == Action::Consume {
+        if let Err(e) = consume(org_id, key, source, node, lease).await {
+            log::error!("kafka source [{key}] consume error: {e}");
+        }
+    }
+}
+
+/// Decides again with the stored leader, under the lock of the source, and records the
+/// claim or renews the lease of the leader before consuming. So a source is consumed by
+/// one node only.
+async fn update_leader(
+    key: &str,
+    node: &str,
+    now: i64,
+    lease: i64,
+) -> Result<Action, anyhow::Error> {
+    let lock_key = format!("kafka_sources/{key}");
+    let locker = dist_lock::lock(&lock_key, CONFIG.etcd.command_timeout).await?;
+    let ret = async {
+        let leader = db::kafka::get_leader(key).await?;
+        let action = next_action(leader.as_ref(), node, now, lease);
+        if action == Action::Skip {
+            return Ok(action);
+        }
+        let leader = ClusterLeader {
+            name: key.to_string(),
+            last_received: now,
+            updated_by: node.to_string(),
+        };
+        db::kafka::set_leader(key, &leader).await?;
+        Ok::<_, anyhow::Error>(action)
+    }
+    .await;
+    dist_lock::unlock(&locker).await?;
+    ret
+}
+
+async fn client(key: &str, source: &KafkaSource) -> Result<Arc<KafkaClient>, anyhow::Error> {
+    if let Some(client) = CLIENTS.get(key) {
+        if client.brokers == source.brokers {
+            return Ok(client.clone());
+        }
+    }
+    let client = Arc::new(KafkaClient::connect(&source.brokers).await?);
+    CLIENTS.insert(key.to_string(), client.clone());
+    Ok(client)
+}
+
+/// Ingests the next batch of every partition of the source topics. The offsets are only
+/// committed after the records are written and the WAL is synced to the disk, so that a
+/// failed write or a crash consumes the batch again. The lease is renewed when half of it
+/// passed, and the consumption stops if this node isn't the leader anymore.
+async fn consume(
+    org_id: &str,
+    key: &str,
+    source: &KafkaSource,
+    node: &str,
+    lease: i64,
+) -> Result<(), anyhow::Error> {
+    let mut offsets = Vec::new();
+    let ret = consume_batches(org_id, key, source, node, lease, &mut offsets).await;
+    if !offsets.is_empty() {
+        wal::sync_all().await;
+        for (topic, partition, offset) in offsets {
+            db::kafka::set_offset(key, &topic, partition, offset).await?;
+        }
+    }
+    ret
+}
+
+/// Ingests the batches and collects the offsets to commit, also the ones of the batches
+/// ingested before an error.
+async fn consume_batches(
+    org_id: &str,
+    key: &str,
+    source: &KafkaSource,
+    node: &str,
+    lease: i64,
+    offsets: &mut Vec<(String, i32, i64)>,
+) -> Result<(), anyhow::Error> {
+    let consumer = client(key, source).await?;
+    let mut renewed = Utc::now().timestamp_micros();
+    for topic in source.topics.iter() {
+        let committed = db::kafka::get_offsets(key, topic).await?;
+        for partition in consumer.partitions(topic).await? {
+            let now = Utc::now().timestamp_micros();
+            if now - renewed > lease / 2 {
+                if update_leader(key, node, now, lease).await? != Action::Consume {
+                    log::warn!("kafka source [{key}] is led by another node, stop consuming");
+                    return Ok(());
+                }
+                renewed = now;
+            }
+            let committed = committed.get(&partition).copied();
+            let (records, offset) =
+                fetch_batch(consumer.as_ref(), source, topic, partition, committed).await?;
+            if !records.is_empty() {
+                // e.g. quota exceeded, the batch is consumed again on a later run
+                if let Some(e) = is_ingestion_allowed(org_id, Some(&source.stream_name)) {
+                    return Err(e);
+                }
+                let context = format!("{topic}/{partition} up to offset {offset}");
+                ingest_batch(org_id, key, &source.stream_name, records, &context).await;
+            }
+            if committed != Some(offset) {
+                offsets.push((topic.to_string(), partition, offset));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Ingests the records of a batch. A batch which can't be ingested, e.g. because a
+/// function of the stream fails on a record, is ingested record by record and the records
+/// which fail are skipped, as the batch would fail on every retry and hold its partition.
+async fn ingest_batch(
+    org_id: &str,
+    key: &str,
+    stream_name: &str,
+    records: Vec<Value>,
+    context: &str,
+) {
+    let ingest = |records: Vec<Value>| {
+        super::json::ingest_records(org_id, stream_name, records, 0, "kafka", UsageType::Kafka)
+    };
+    let Err(e) = ingest(records.clone()).await else {
+        return;
+    };
+    log::warn!("kafka source [{key}] error ingesting {context}: {e}, retrying record by record");
+    let mut skipped = 0;
+    for record in records {
+        if let Err(e) = ingest(vec![record]).await {
+            skipped += 1;
+            log::debug!("kafka source [{key}] skipped a record of {context}: {e}");
+        }
+    }
+    if skipped > 0 {
+        log::error!("kafka source [{key}] skipped {skipped} records of {context}");
+    }
+}
+
+/// Reads the next batch of a partition, from the committed offset or the start offset of
+/// the source. Returns the decoded records and the offset to commit once they are written.
+pub async fn fetch_batch(
+    consumer: &dyn Consumer,
+    source: &KafkaSource,
+    topic: &str,
+    partition: i32,
+    committed: Option<i64>,
+) -> Result<(Vec<Value>, i64), anyhow::Error> {
+    let mut offset = match committed {
+        Some(offset) => offset,
+        None => consumer.offset(topic, partition, source.start_from).await?,
+    };
+    let mut fetched = consumer.fetch(topic, partition, offset).await?;
+    if let Fetched::OutOfRange = fetched {
+        offset = consumer
+            .offset(topic, partition, KafkaStartOffset::Earliest)
+            .await?;
+        log::warn!(
+            "kafka topic [{topic}/{partition}] offset {} is out of range, continuing from {offset}",
+            committed.unwrap_or_default()
+        );
+        fetched = consumer.fetch(topic, partition, offset).await?;
+    }
+    let Fetched::Messages(messages) = fetched else {
+        return Err(anyhow::anyhow!(
+            "kafka topic [{topic}/{partition}] offset {offset} is out of range"
+        ));
+    };
+    let next_offset = messages.last().map(|m| m.offset + 1).unwrap_or(offset);
+    Ok((decode(source.format, &messages), next_offset))
+}
+
+/// Decodes messages into records, using the time of the message when the record has no
+/// timestamp. Messages which aren't valid JSON objects are skipped.
+fn decode(format: KafkaFormat, messages: &[Message]) -> Vec<Value> {
+    let mut records = Vec::with_capacity(messages.len());
+    let mut push = |mut record: Map<String, Value>, timestamp: i64| {
+        record
+            .entry(CONFIG.common.column_timestamp.clone())
+            .or_insert_with(|| timestamp.into());
+        records.push(Value::Object(record));
+    };
+    for message in messages {
+        match format {
+            KafkaFormat::Json => match json::from_slice::<Value>(&message.value) {
+                Ok(Value::Object(record)) => push(record, message.timestamp),
+                Ok(Value::Array(items)) => {
+                    for item in items {
+                        match item {
+                            Value::Object(record) => push(record, message.timestamp),
+                            _ => log::warn!(
+                                "kafka message at offset {} contains a non object record",
+                                message.offset
+                            ),
+                        }
+                    }
+                }
+                _ => log::warn!(
+                    "kafka message at offset {} is not a JSON object",
+                    message.offset
+                ),
+            },
+            KafkaFormat::Lines => {
+                for line in String::from_utf8_lossy(&message.value).lines() {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let mut record = Map::new();
+                    record.insert(MESSAGE_FIELD.to_string(), line.into());
+                    push(record, message.timestamp);
+                }
+            }
+        }
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    /// Partitions with the messages from their first offset on.
+    struct MockConsumer {
+        partitions: BTreeMap<i32, (i64, Vec<&'static str>)>,
+    }
+
+    #[async_trait]
+    impl Consumer for MockConsumer {
+        async fn partitions(&self, _topic: &str) -> Result<Vec<i32>, anyhow::Error> {
+            Ok(self.partitions.keys().copied().collect())
+        }
+
+        async fn offset(
+            &self,
+            _topic: &str,
+            partition: i32,
+            at: KafkaStartOffset,
+        ) -> Result<i64, anyhow::Error> {
+            let (first, messages) = &self.partitions[&partition];
+            Ok(match at {
+                KafkaStartOffset::Earliest => *first,
+                KafkaStartOffset::Latest => first + messages.len() as i64,
+            })
+        }
+
+        async fn fetch(
+            &self,
+            _topic: &str,
+            partition: i32,
+            offset: i64,
+        ) -> Result<Fetched, anyhow::Error> {
+            let (first, messages) = &self.partitions[&partition];
+            if offset < *first || offset > first + messages.len() as i64 {
+                return Ok(Fetched::OutOfRange);
+            }
+            Ok(Fetched::Messages(
+                messages
+                    .iter()
+                    .enumerate()
+                    .skip((offset - first) as usize)
+                    .take(2)
+                    .map(|(i, value)| Message {
+                        offset: first + i as i64,
+                        timestamp: 1_000_000 + i as i64,
+                        value: value.as_bytes().to_vec(),
+                    })
+                    .collect(),
+            ))
+        }
+    }
+
+    fn consumer() -> MockConsumer {
+        MockConsumer {
+            partitions: BTreeMap::from([
+                (
+                    0,
+                    (0, vec![r#"{"a":1}"#, r#"[{"a":2},{"a":3}]"#, "not json"]),
+                ),
+                (1, (10, vec!["line 1\nline 2\n", "line 3"])),
+            ]),
+        }
+    }
+
+    fn source(format: KafkaFormat, start_from: KafkaStartOffset) -> KafkaSource {
+        KafkaSource {
+            name: "test".to_string(),
+            brokers: vec!["localhost:9092".to_string()],
+            topics: vec!["logs".to_string()],
+            stream_name: "default".to_string(),
+            format,
+            start_from,
+            enabled: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_batch() {
+        let consumer = consumer();
+        let source = source(KafkaFormat::Json, KafkaStartOffset::Earliest);
+        let (records, offset) = fetch_batch(&consumer, &source, "logs", 0, None)
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(offset, 2);
+        assert_eq!(records[2]["a"], 3);
+        assert_eq!(records[2][&CONFIG.common.column_timestamp], 1_000_001);
+
+        // the next batch continues from the committed offset, invalid messages are skipped
+        let (records, offset) = fetch_batch(&consumer, &source, "logs", 0, Some(offset))
+            .await
+            .unwrap();
+        assert!(records.is_empty());
+        assert_eq!(offset, 3);
+
+        // nothing new, the offset stays
+        let (records, offset) = fetch_batch(&consumer, &source, "logs", 0, Some(offset))
+            .await
+            .unwrap();
+        assert!(records.is_empty());
+        assert_eq!(offset, 3);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_batch_start_offset() {
+        let consumer = consumer();
+        let source = source(KafkaFormat::Lines, KafkaStartOffset::Latest);
+        let (records, offset) = fetch_batch(&consumer, &source, "logs", 1, None)
+            .await
+            .unwrap();
+        assert!(records.is_empty());
+        assert_eq!(offset, 12);
+
+        // a committed offset deleted by retention continues from the earliest one
+        let (records, offset) = fetch_batch(&consumer, &source, "logs", 1, Some(3))
+            .await
+            .unwrap();
+        assert_eq!(offset, 12);
+        let lines: Vec<_> = records
+            .iter()
+            .map(|r| r[MESSAGE_FIELD].as_str().unwrap())
+            .collect();
+        assert_eq!(lines, vec!["line 1", "line 2", "line 3"]);
+    }
+
+    #[test]
+    fn test_decode_keeps_timestamp() {
+        let messages = vec![Message {
+            offset: 0,
+            timestamp: 5,
+            value: format!(r#"{{"{}":7}}"#, CONFIG.common.column_timestamp).into_bytes(),
+        }];
+        let records = decode(KafkaFormat::Json, &messages);
+        assert_eq!(records[0][&CONFIG.common.column_timestamp], 7);
+    }
+
+    #[test]
+    fn test_next_action() {
+        let leader = ClusterLeader {
+            name: "default/test".to_string(),
+            last_received: 100,
+            updated_by: "node1".to_string(),
+        };
+        assert_eq!(
+            next_action(Some(&leader), "node1", 500, 50),
+            Action::Consume
+        );
+        assert_eq!(next_action(Some(&leader), "node2", 120, 50), Action::Skip);
+        assert_eq!(next_action(Some(&leader), "node2", 200, 50), Action::Claim);
+        assert_eq!(next_action(None, "node2", 200, 50), Action::Claim);
+    }
+
+    #[test]
+    fn test_validate_source() {
+        let mut source = source(KafkaFormat::Json, KafkaStartOffset::Latest);
+        assert!(validate_source(&source).is_ok());
+        source.stream_name = "Bad Stream".to_string();
+        assert!(validate_source(&source).is_err());
+        source.stream_name = "default".to_string();
+        source.topics.clear();
+        assert!(validate_source(&source).is_err());
+    }
+}
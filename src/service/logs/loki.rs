@@ -0,0 +1,279 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use actix_web::{http, web};
+use ahash::AHashMap;
+use chrono::Utc;
+use promql_parser::{
+    label::MatchOp,
+    parser::{self, Expr},
+};
+use prost::Message;
+
+use crate::common::{
+    infra::config::CONFIG,
+    meta::{
+        ingestion::{IngestionResponse, LokiPushRequest},
+        usage::UsageType,
+    },
+    utils::json::{self, Map, Value},
+};
+
+pub(crate) mod logproto {
+    include!(concat!(env!("OUT_DIR"), "/logproto.rs"));
+}
+
+/// Field holding the log line
+const MESSAGE_FIELD: &str = "message";
+const DEFAULT_STREAM: &str = "default";
+
+/// Ingests a snappy compressed protobuf push request, as sent by Promtail and Grafana Agent.
+/// Records go to the stream named by the stream label, else to `in_stream_name`.
+pub async fn ingest_proto(
+    org_id: &str,
+    body: web::Bytes,
+    thread_id: usize,
+    in_stream_name: Option<&str>,
+) -> Result<IngestionResponse, anyhow::Error> {
+    let streams = decode_proto(&body, in_stream_name.unwrap_or(DEFAULT_STREAM))?;
+    ingest_streams(org_id, streams, thread_id).await
+}
+
+/// Ingests a JSON push request, cf. [`LokiPushRequest`].
+pub async fn ingest_json(
+    org_id: &str,
+    body: web::Bytes,
+    thread_id: usize,
+    in_stream_name: Option<&str>,
+) -> Result<IngestionResponse, anyhow::Error> {
+    let streams = decode_json(&body, in_stream_name.unwrap_or(DEFAULT_STREAM))?;
+    ingest_streams(org_id, streams, thread_id).await
+}
+
+async fn ingest_streams(
+    org_id: &str,
+    streams: AHashMap<String, Vec<Value>>,
+    thread_id: usize,
+) -> Result<IngestionResponse, anyhow::Error> {
+    let mut status = Vec::with_capacity(streams.len());
+    for (stream_name, records) in streams {
+        let resp = super::json::ingest_records(
+            org_id,
+            &stream_name,
+            records,
+            thread_id,
+            "/api/org/loki/api/v1/push",
+            UsageType::Loki,
+        )
+        .await?;
+        status.extend(resp.status);
+    }
+    Ok(IngestionResponse::new(http::StatusCode::OK.into(), status))
+}
+
+fn decode_proto(
+    body: &[u8],
+    default_stream: &str,
+) -> Result<AHashMap<String, Vec<Value>>, anyhow::Error> {
+    let decoded = snap::raw::Decoder::new()
+        .decompress_vec(body)
+        .map_err(|e| anyhow::anyhow!("Invalid snappy compressed data: {}", e.to_string()))?;
+    let request = logproto::PushRequest::decode(bytes::Bytes::from(decoded))
+        .map_err(|e| anyhow::anyhow!("Invalid protobuf: {}", e.to_string()))?;
+
+    let mut streams: AHashMap<String, Vec<Value>> = AHashMap::new();
+    for stream in request.streams {
+        let mut labels = parse_labels(&stream.labels)?;
+        let stream_name = take_stream_name(&mut labels, default_stream);
+        let records = streams.entry(stream_name).or_default();
+        for entry in stream.entries {
+            let timestamp = match entry.timestamp {
+                Some(ts) => ts.seconds * 1_000_000 + ts.nanos as i64 / 1000,
+                None => Utc::now().timestamp_micros(),
+            };
+            let metadata = entry
+                .structured_metadata
+                .into_iter()
+                .map(|label| (label.name, label.value));
+            records.push(to_record(&labels, metadata, entry.line, timestamp));
+        }
+    }
+    Ok(streams)
+}
+
+fn decode_json(
+    body: &[u8],
+    default_stream: &str,
+) -> Result<AHashMap<String, Vec<Value>>, anyhow::Error> {
+    let request: LokiPushRequest = json::from_slice(body)?;
+
+    let mut streams: AHashMap<String, Vec<Value>> = AHashMap::new();
+    for stream in request.streams {
+        let mut labels: Map<String, Value> = stream
+            .stream
+            .into_iter()
+            .map(|(name, value)| (name, Value::String(value)))
+            .collect();
+        let stream_name = take_stream_name(&mut labels, default_stream);
+        let records = streams.entry(stream_name).or_default();
+        for value in stream.values {
+            let (Some(ts), Some(line)) = (
+                value.first().and_then(Value::as_str),
+                value.get(1).and_then(Value::as_str),
+            ) else {
+                return Err(anyhow::anyhow!(
+                    "Invalid entry, expected [\"<timestamp>\", \"<line>\"]"
+                ));
+            };
+            let timestamp = ts
+                .parse::<i64>()
+                .map_err(|_| anyhow::anyhow!("Invalid timestamp: {ts}"))?
+                / 1000;
+            let metadata = value
+                .get(2)
+                .and_then(Value::as_object)
+                .into_iter()
+                .flatten()
+                .map(|(name, value)| {
+                    let value = match value {
+                        Value::String(v) => v.clone(),
+                        v => v.to_string(),
+                    };
+                    (name.clone(), value)
+                });
+            records.push(to_record(&labels, metadata, line.to_string(), timestamp));
+        }
+    }
+    Ok(streams)
+}
+
+/// Parses the labels of a stream in the Prometheus format, e.g. `{app="api", env="prod"}`.
+fn parse_labels(labels: &str) -> Result<Map<String, Value>, anyhow::Error> {
+    let mut map = Map::new();
+    let labels = labels.trim();
+    if labels.is_empty() || labels == "{}" {
+        return Ok(map);
+    }
+    let Ok(Expr::VectorSelector(selector)) = parser::parse(labels) else {
+        return Err(anyhow::anyhow!("Invalid stream labels: {labels}"));
+    };
+    for mat in selector.matchers.matchers.iter() {
+        if !matches!(mat.op, MatchOp::Equal) {
+            return Err(anyhow::anyhow!("Invalid stream labels: {labels}"));
+        }
+        map.insert(mat.name.clone(), Value::String(mat.value.clone()));
+    }
+    Ok(map)
+}
+
+/// Removes the stream label and returns the name of the target stream.
+fn take_stream_name(labels: &mut Map<String, Value>, default_stream: &str) -> String {
+    match labels.remove(&CONFIG.loki.stream_label) {
+        Some(Value::String(v)) if !v.is_empty() => v,
+        _ => default_stream.to_string(),
+    }
+}
+
+fn to_record(
+    labels: &Map<String, Value>,
+    metadata: impl IntoIterator<Item = (String, String)>,
+    line: String,
+    timestamp: i64,
+) -> Value {
+    let mut record = labels.clone();
+    for (name, value) in metadata {
+        record.insert(name, Value::String(value));
+    }
+    record.insert(MESSAGE_FIELD.to_string(), Value::String(line));
+    record.insert(
+        CONFIG.common.column_timestamp.clone(),
+        Value::Number(timestamp.into()),
+    );
+    Value::Object(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_proto() {
+        let request = logproto::PushRequest {
+            streams: vec![logproto::StreamAdapter {
+                labels: format!(r#"{{job="varlogs", {}="nginx"}}"#, CONFIG.loki.stream_label),
+                entries: vec![logproto::EntryAdapter {
+                    timestamp: Some(logproto::Timestamp {
+                        seconds: 1_700_000_000,
+                        nanos: 123_456_789,
+                    }),
+                    line: "GET /index.html 200".to_string(),
+                    structured_metadata: vec![logproto::LabelPairAdapter {
+                        name: "trace_id".to_string(),
+                        value: "abc".to_string(),
+                    }],
+                }],
+                hash: 0,
+            }],
+        };
+        let body = snap::raw::Encoder::new()
+            .compress_vec(&request.encode_to_vec())
+            .unwrap();
+        let streams = decode_proto(&body, DEFAULT_STREAM).unwrap();
+        let records = streams.get("nginx").unwrap();
+        assert_eq!(records.len(), 1);
+        let record = records[0].as_object().unwrap();
+        assert_eq!(record.get("job").unwrap(), "varlogs");
+        assert_eq!(record.get("trace_id").unwrap(), "abc");
+        assert_eq!(record.get(MESSAGE_FIELD).unwrap(), "GET /index.html 200");
+        assert_eq!(
+            record.get(&CONFIG.common.column_timestamp).unwrap(),
+            &json::json!(1_700_000_000_123_456i64)
+        );
+        assert!(!record.contains_key(&CONFIG.loki.stream_label));
+    }
+
+    #[test]
+    fn test_decode_json() {
+        let body = json::json!({
+            "streams": [{
+                "stream": {"job": "varlogs"},
+                "values": [
+                    ["1700000000123456789", "first"],
+                    ["1700000001000000000", "second", {"user": "alice"}]
+                ]
+            }]
+        });
+        let streams = decode_json(&json::to_vec(&body).unwrap(), "app").unwrap();
+        let records = streams.get("app").unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].get(MESSAGE_FIELD).unwrap(), "first");
+        assert_eq!(
+            records[0].get(&CONFIG.common.column_timestamp).unwrap(),
+            &json::json!(1_700_000_000_123_456i64)
+        );
+        assert_eq!(records[1].get("user").unwrap(), "alice");
+
+        let body = json::json!({"streams": [{"stream": {}, "values": [["bad", "line"]]}]});
+        assert!(decode_json(&json::to_vec(&body).unwrap(), "app").is_err());
+    }
+
+    #[test]
+    fn test_parse_labels() {
+        let labels = parse_labels(r#"{app="api", path="/var/log/a \"b\".log"}"#).unwrap();
+        assert_eq!(labels.get("app").unwrap(), "api");
+        assert_eq!(labels.get("path").unwrap(), "/var/log/a \"b\".log");
+        assert!(parse_labels("{}").unwrap().is_empty());
+        assert!(parse_labels(r#"{app=~"api"}"#).is_err());
+    }
+}
@@ -38,10 +38,14 @@ use super::{
 };
 
 pub mod bulk;
+pub mod fluent;
 pub mod gcs_pub_sub;
+pub mod hec;
 pub mod ingest;
 pub mod json;
+pub mod kafka;
 pub mod kinesis_firehose;
+pub mod loki;
 pub mod multi;
 pub mod otlp_grpc;
 pub mod otlp_http;
@@ -284,7 +288,7 @@ async fn add_valid_record(
                 if let Some(alerts) = stream_meta.stream_alerts_map.get(&key) {
                     for alert in alerts {
                         if alert.is_real_time {
-                            let set_trigger = alert.condition.evaluate(local_val.clone());
+                            let set_trigger = alert.evaluate(local_val.clone());
                             if set_trigger {
                                 // let _ = triggers::save_trigger(alert.name.clone(), trigger).await;
                                 trigger = Some(Trigger {
@@ -297,6 +301,7 @@ async fn add_valid_record(
                                     last_sent_at: 0,
                                     count: 0,
                                     is_ingest_time: true,
+                                    ..Default::default()
                                 });
                             }
                         }
@@ -24,6 +24,8 @@ pub mod json;
 pub mod otlp_grpc;
 pub mod otlp_http;
 pub mod prom;
+pub mod recording_rules;
+pub mod temporality;
 
 const EXCLUDE_LABELS: [&str; 5] = [VALUE_LABEL, "start_time", "is_monotonic", "exemplars", "le"];
 
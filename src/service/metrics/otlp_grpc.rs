@@ -25,7 +25,7 @@ use opentelemetry_proto::tonic::{
 };
 use prost::Message;
 
-use super::{format_label_name, get_exclude_labels};
+use super::{format_label_name, get_exclude_labels, temporality};
 use crate::common::meta::stream::StreamParams;
 use crate::common::{
     infra::{cluster, config::CONFIG, metrics},
@@ -76,6 +76,7 @@ pub async fn handle_grpc_request(
     let mut stream_alerts_map: AHashMap<String, Vec<alert::Alert>> = AHashMap::new();
     let mut stream_trigger_map: AHashMap<String, alert::Trigger> = AHashMap::new();
     let mut stream_partitioning_map: AHashMap<String, PartitioningDetails> = AHashMap::new();
+    let mut deltas = temporality::DeltaBatch::new(org_id);
 
     for resource_metric in &request.resource_metrics {
         for scope_metric in &resource_metric.scope_metrics {
@@ -156,10 +157,16 @@ pub async fn handle_grpc_request(
                         Data::Gauge(gauge) => {
                             process_gauge(&mut rec, gauge, &mut metadata, &mut prom_meta)
                         }
-                        Data::Sum(sum) => process_sum(&mut rec, sum, &mut metadata, &mut prom_meta),
-                        Data::Histogram(hist) => {
-                            process_histogram(&mut rec, hist, &mut metadata, &mut prom_meta)
+                        Data::Sum(sum) => {
+                            process_sum(&mut deltas, &mut rec, sum, &mut metadata, &mut prom_meta)
                         }
+                        Data::Histogram(hist) => process_histogram(
+                            &mut deltas,
+                            &mut rec,
+                            hist,
+                            &mut metadata,
+                            &mut prom_meta,
+                        ),
                         Data::ExponentialHistogram(exp_hist) => process_exponential_histogram(
                             &mut rec,
                             exp_hist,
@@ -236,10 +243,8 @@ pub async fn handle_grpc_request(
                         if let Some(alerts) = stream_alerts_map.get(&key) {
                             for alert in alerts {
                                 if alert.is_real_time {
-                                    let set_trigger = alert::Evaluate::evaluate(
-                                        &alert.condition,
-                                        val_map.clone(),
-                                    );
+                                    let set_trigger =
+                                        alert::Evaluate::evaluate(alert, val_map.clone());
                                     if set_trigger {
                                         stream_trigger_map.insert(
                                             metric_name.to_owned(),
@@ -253,6 +258,7 @@ pub async fn handle_grpc_request(
                                                 last_sent_at: 0,
                                                 count: 0,
                                                 is_ingest_time: true,
+                                                ..Default::default()
                                             },
                                         );
                                     }
@@ -339,6 +345,8 @@ pub async fn handle_grpc_request(
             ])
             .inc();
     }
+    // the points are written, a retry of the request would be dropped as duplicated
+    deltas.commit();
 
     // only one trigger per request, as it updates etcd
     for (_, entry) in &stream_trigger_map {
@@ -400,6 +408,7 @@ fn process_gauge(
 }
 
 fn process_sum(
+    deltas: &mut temporality::DeltaBatch,
     rec: &mut json::Value,
     sum: &Sum,
     metadata: &mut Metadata,
@@ -413,7 +422,11 @@ fn process_sum(
     );
 
     let mut records = vec![];
-    process_aggregation_temporality(rec, sum.aggregation_temporality);
+    let is_delta = temporality::is_converted(sum.aggregation_temporality as u64);
+    process_aggregation_temporality(
+        rec,
+        temporality::stored_temporality(sum.aggregation_temporality as u64),
+    );
     rec["is_monotonic"] = sum.is_monotonic.to_string().into();
     for data_point in &sum.data_points {
         let mut dp_rec = rec.clone();
@@ -422,14 +435,18 @@ fn process_sum(
 
         let vec: Vec<&str> = get_exclude_labels();
 
-        let hash = super::signature_without_labels(val_map, &vec);
-        val_map.insert(HASH_LABEL.to_string(), json::Value::String(hash.into()));
+        let hash: String = super::signature_without_labels(val_map, &vec).into();
+        if is_delta && !deltas.accumulate(&hash, val_map) {
+            continue;
+        }
+        val_map.insert(HASH_LABEL.to_string(), json::Value::String(hash));
         records.push(dp_rec.clone());
     }
     records
 }
 
 fn process_histogram(
+    deltas: &mut temporality::DeltaBatch,
     rec: &mut json::Value,
     hist: &Histogram,
     metadata: &mut Metadata,
@@ -443,14 +460,21 @@ fn process_histogram(
     );
 
     let mut records = vec![];
-    process_aggregation_temporality(rec, hist.aggregation_temporality);
+    let is_delta = temporality::is_converted(hist.aggregation_temporality as u64);
+    process_aggregation_temporality(
+        rec,
+        temporality::stored_temporality(hist.aggregation_temporality as u64),
+    );
     for data_point in &hist.data_points {
         let mut dp_rec = rec.clone();
         for mut bucket_rec in process_hist_data_point(&mut dp_rec, data_point) {
             let val_map = bucket_rec.as_object_mut().unwrap();
             let vec: Vec<&str> = get_exclude_labels();
-            let hash = super::signature_without_labels(val_map, &vec);
-            val_map.insert(HASH_LABEL.to_string(), json::Value::String(hash.into()));
+            let hash: String = super::signature_without_labels(val_map, &vec).into();
+            if is_delta && !deltas.accumulate(&hash, val_map) {
+                continue;
+            }
+            val_map.insert(HASH_LABEL.to_string(), json::Value::String(hash));
             records.push(bucket_rec);
         }
     }
@@ -470,14 +494,15 @@ fn process_exponential_histogram(
         json::to_string(&metadata).unwrap(),
     );
     let mut records = vec![];
-    process_aggregation_temporality(rec, hist.aggregation_temporality);
+    // the buckets aren't series of their own, so they stay delta
+    process_aggregation_temporality(rec, hist.aggregation_temporality as u64);
     for data_point in &hist.data_points {
         let mut dp_rec = rec.clone();
         for mut bucket_rec in process_exp_hist_data_point(&mut dp_rec, data_point) {
             let val_map = bucket_rec.as_object_mut().unwrap();
             let vec: Vec<&str> = get_exclude_labels();
-            let hash = super::signature_without_labels(val_map, &vec);
-            val_map.insert(HASH_LABEL.to_string(), json::Value::String(hash.into()));
+            let hash: String = super::signature_without_labels(val_map, &vec).into();
+            val_map.insert(HASH_LABEL.to_string(), json::Value::String(hash));
             records.push(bucket_rec);
         }
     }
@@ -710,7 +735,7 @@ fn process_exemplars(rec: &mut json::Value, exemplars: &Vec<Exemplar>) {
     rec["exemplars"] = exemplar_coll.into();
 }
 
-fn process_aggregation_temporality(rec: &mut json::Value, val: i32) {
+fn process_aggregation_temporality(rec: &mut json::Value, val: u64) {
     rec["aggregation_temporality"] = match val {
         0 => AggregationTemporality::Unspecified.as_str_name(),
         1 => AggregationTemporality::Delta.as_str_name(),
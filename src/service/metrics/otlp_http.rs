@@ -24,7 +24,7 @@ use opentelemetry_proto::tonic::{
 };
 use prost::Message;
 
-use super::{format_label_name, get_exclude_labels, otlp_grpc::handle_grpc_request};
+use super::{format_label_name, get_exclude_labels, otlp_grpc::handle_grpc_request, temporality};
 use crate::handler::http::request::CONTENT_TYPE_JSON;
 use crate::service::{
     db,
@@ -100,6 +100,7 @@ pub async fn metrics_json_handler(
     let mut stream_alerts_map: AHashMap<String, Vec<Alert>> = AHashMap::new();
     let mut stream_trigger_map: AHashMap<String, Trigger> = AHashMap::new();
     let mut stream_partitioning_map: AHashMap<String, PartitioningDetails> = AHashMap::new();
+    let mut deltas = temporality::DeltaBatch::new(org_id);
 
     let body: json::Value = match json::from_slice(body.as_ref()) {
         Ok(v) => v,
@@ -239,10 +240,17 @@ pub async fn metrics_json_handler(
 
                     let records = if metric.get("sum").is_some() {
                         let sum = metric.get("sum").unwrap().as_object().unwrap();
-                        process_sum(&mut rec, sum, &mut metadata.clone(), &mut prom_meta)
+                        process_sum(
+                            &mut deltas,
+                            &mut rec,
+                            sum,
+                            &mut metadata.clone(),
+                            &mut prom_meta,
+                        )
                     } else if metric.get("histogram").is_some() {
                         let histogram = metric.get("histogram").unwrap().as_object().unwrap();
                         process_histogram(
+                            &mut deltas,
                             &mut rec,
                             histogram,
                             &mut metadata.clone(),
@@ -340,10 +348,8 @@ pub async fn metrics_json_handler(
                             if let Some(alerts) = stream_alerts_map.get(&key) {
                                 for alert in alerts {
                                     if alert.is_real_time {
-                                        let set_trigger = meta::alert::Evaluate::evaluate(
-                                            &alert.condition,
-                                            val_map.clone(),
-                                        );
+                                        let set_trigger =
+                                            meta::alert::Evaluate::evaluate(alert, val_map.clone());
                                         if set_trigger {
                                             stream_trigger_map.insert(
                                                 metric_name.to_owned(),
@@ -357,6 +363,7 @@ pub async fn metrics_json_handler(
                                                     last_sent_at: 0,
                                                     count: 0,
                                                     is_ingest_time: true,
+                                                    ..Default::default()
                                                 },
                                             );
                                         }
@@ -438,6 +445,8 @@ pub async fn metrics_json_handler(
             ])
             .inc();
     }
+    // the points are written, a retry of the request would be dropped as duplicated
+    deltas.commit();
 
     // only one trigger per request, as it updates etcd
     for (_, entry) in &stream_trigger_map {
@@ -474,6 +483,7 @@ pub async fn metrics_json_handler(
 }
 
 fn process_sum(
+    deltas: &mut temporality::DeltaBatch,
     rec: &mut json::Value,
     sum: &json::Map<String, json::Value>,
     metadata: &mut prom::Metadata,
@@ -487,9 +497,11 @@ fn process_sum(
     );
 
     let mut records = vec![];
+    let aggregation_temporality = sum.get("aggregationTemporality").unwrap().as_u64().unwrap();
+    let is_delta = temporality::is_converted(aggregation_temporality);
     process_aggregation_temporality(
         rec,
-        sum.get("aggregationTemporality").unwrap().as_u64().unwrap(),
+        temporality::stored_temporality(aggregation_temporality),
     );
     rec["is_monotonic"] = sum.get("isMonotonic").unwrap().as_bool().unwrap().into();
     for data_point in sum.get("dataPoints").unwrap().as_array().unwrap_or(&vec![]) {
@@ -500,14 +512,18 @@ fn process_sum(
 
         let vec: Vec<&str> = get_exclude_labels();
 
-        let hash = super::signature_without_labels(val_map, &vec);
-        val_map.insert(HASH_LABEL.to_string(), json::Value::String(hash.into()));
+        let hash: String = super::signature_without_labels(val_map, &vec).into();
+        if is_delta && !deltas.accumulate(&hash, val_map) {
+            continue;
+        }
+        val_map.insert(HASH_LABEL.to_string(), json::Value::String(hash));
         records.push(dp_rec.clone());
     }
     records
 }
 
 fn process_histogram(
+    deltas: &mut temporality::DeltaBatch,
     rec: &mut json::Value,
     hist: &json::Map<String, json::Value>,
     metadata: &mut prom::Metadata,
@@ -521,12 +537,15 @@ fn process_histogram(
     );
 
     let mut records = vec![];
+    let aggregation_temporality = hist
+        .get("aggregationTemporality")
+        .unwrap()
+        .as_u64()
+        .unwrap();
+    let is_delta = temporality::is_converted(aggregation_temporality);
     process_aggregation_temporality(
         rec,
-        hist.get("aggregationTemporality")
-            .unwrap()
-            .as_u64()
-            .unwrap(),
+        temporality::stored_temporality(aggregation_temporality),
     );
     for data_point in hist
         .get("dataPoints")
@@ -539,8 +558,11 @@ fn process_histogram(
         for mut bucket_rec in process_hist_data_point(&mut dp_rec, dp) {
             let val_map = bucket_rec.as_object_mut().unwrap();
             let vec: Vec<&str> = get_exclude_labels();
-            let hash = super::signature_without_labels(val_map, &vec);
-            val_map.insert(HASH_LABEL.to_string(), json::Value::String(hash.into()));
+            let hash: String = super::signature_without_labels(val_map, &vec).into();
+            if is_delta && !deltas.accumulate(&hash, val_map) {
+                continue;
+            }
+            val_map.insert(HASH_LABEL.to_string(), json::Value::String(hash));
             records.push(bucket_rec);
         }
     }
@@ -624,13 +646,13 @@ fn process_exponential_histogram(
         json::to_string(&metadata).unwrap(),
     );
     let mut records = vec![];
-    process_aggregation_temporality(
-        rec,
-        hist.get("aggregationTemporality")
-            .unwrap()
-            .as_u64()
-            .unwrap(),
-    );
+    let aggregation_temporality = hist
+        .get("aggregationTemporality")
+        .unwrap()
+        .as_u64()
+        .unwrap();
+    // the buckets aren't series of their own, so they stay delta
+    process_aggregation_temporality(rec, aggregation_temporality);
     for data_point in hist
         .get("dataPoints")
         .unwrap()
@@ -642,8 +664,8 @@ fn process_exponential_histogram(
         for mut bucket_rec in process_exp_hist_data_point(&mut dp_rec, dp) {
             let val_map = bucket_rec.as_object_mut().unwrap();
             let vec: Vec<&str> = get_exclude_labels();
-            let hash = super::signature_without_labels(val_map, &vec);
-            val_map.insert(HASH_LABEL.to_string(), json::Value::String(hash.into()));
+            let hash: String = super::signature_without_labels(val_map, &vec).into();
+            val_map.insert(HASH_LABEL.to_string(), json::Value::String(hash));
             records.push(bucket_rec);
         }
     }
@@ -23,6 +23,7 @@ use std::collections::HashMap;
 use crate::service::{
     db,
     ingestion::{chk_schema_by_record, write_file},
+    promql,
     schema::{set_schema_metadata, stream_schema_exists},
     search as search_service,
     stream::unwrap_partition_time_level,
@@ -61,6 +62,20 @@ pub async fn remote_write(
     org_id: &str,
     thread_id: usize,
     body: web::Bytes,
+) -> std::result::Result<(), anyhow::Error> {
+    let decoded = snap::raw::Decoder::new()
+        .decompress_vec(&body)
+        .map_err(|e| anyhow::anyhow!("Invalid snappy compressed data: {}", e.to_string()))?;
+    let request = prometheus::WriteRequest::decode(bytes::Bytes::from(decoded))
+        .map_err(|e| anyhow::anyhow!("Invalid protobuf: {}", e.to_string()))?;
+    write_request(org_id, thread_id, request).await
+}
+
+/// Ingests a decoded remote write request, also used to store the results of recording rules.
+pub(crate) async fn write_request(
+    org_id: &str,
+    thread_id: usize,
+    request: prometheus::WriteRequest,
 ) -> std::result::Result<(), anyhow::Error> {
     let start = std::time::Instant::now();
     if !cluster::is_ingester(&cluster::LOCAL_NODE_ROLE) {
@@ -86,12 +101,6 @@ pub async fn remote_write(
     let mut stream_transform_map: AHashMap<String, Vec<StreamTransform>> = AHashMap::new();
     let mut stream_partitioning_map: AHashMap<String, PartitioningDetails> = AHashMap::new();
 
-    let decoded = snap::raw::Decoder::new()
-        .decompress_vec(&body)
-        .map_err(|e| anyhow::anyhow!("Invalid snappy compressed data: {}", e.to_string()))?;
-    let request = prometheus::WriteRequest::decode(bytes::Bytes::from(decoded))
-        .map_err(|e| anyhow::anyhow!("Invalid protobuf: {}", e.to_string()))?;
-
     // parse metadata
     for item in request.metadata {
         let metric_name = format_stream_name(&item.metric_family_name.clone());
@@ -150,9 +159,18 @@ pub async fn remote_write(
 
         let buf = metric_data_map.entry(metric_name.to_owned()).or_default();
 
-        // parse samples
-        for sample in event.samples {
-            let mut sample_val = sample.value;
+        // parse samples, native histograms are stored as their count of observations
+        // with the histogram columns
+        let samples = event
+            .samples
+            .iter()
+            .map(|sample| (sample.timestamp, sample.value, None))
+            .chain(event.histograms.iter().map(|h| {
+                let histogram = NativeHistogram::from(h);
+                (h.timestamp, histogram.count, Some(histogram))
+            }));
+        for (sample_ts, sample_val, histogram) in samples {
+            let mut sample_val = sample_val;
             // revisit in future
             if sample_val.is_infinite() {
                 if sample_val == f64::INFINITY || sample_val > f64::MAX {
@@ -169,7 +187,7 @@ pub async fn remote_write(
                 value: sample_val,
             };
 
-            let timestamp = parse_i64_to_timestamp_micros(sample.timestamp);
+            let timestamp = parse_i64_to_timestamp_micros(sample_ts);
             if timestamp < min_ts {
                 min_ts = timestamp;
             }
@@ -273,6 +291,9 @@ pub async fn remote_write(
                 CONFIG.common.column_timestamp.clone(),
                 json::Value::Number(timestamp.into()),
             );
+            if let Some(histogram) = &histogram {
+                histogram.write_columns(val_map);
+            }
             let value_str = crate::common::utils::json::to_string(&val_map).unwrap();
             chk_schema_by_record(
                 &mut metric_schema_map,
@@ -308,7 +329,7 @@ pub async fn remote_write(
                     for alert in alerts {
                         if alert.is_real_time {
                             let set_trigger = alert::Evaluate::evaluate(
-                                &alert.condition,
+                                alert,
                                 value.as_object().unwrap().clone(),
                             );
                             if set_trigger {
@@ -324,6 +345,7 @@ pub async fn remote_write(
                                         last_sent_at: 0,
                                         count: 0,
                                         is_ingest_time: true,
+                                        ..Default::default()
                                     },
                                 );
                             }
@@ -428,6 +450,206 @@ pub async fn remote_write(
     Ok(())
 }
 
+/// Answers a Prometheus remote-read request with raw samples. Both the request and the
+/// response are snappy compressed protobuf messages.
+pub async fn remote_read(
+    org_id: &str,
+    body: web::Bytes,
+) -> std::result::Result<Vec<u8>, anyhow::Error> {
+    let start = std::time::Instant::now();
+    let decoded = snap::raw::Decoder::new()
+        .decompress_vec(&body)
+        .map_err(|e| anyhow::anyhow!("Invalid snappy compressed data: {}", e.to_string()))?;
+    let request = prometheus::ReadRequest::decode(bytes::Bytes::from(decoded))
+        .map_err(|e| anyhow::anyhow!("Invalid protobuf: {}", e.to_string()))?;
+
+    // only the SAMPLES response type is supported
+    let samples = prometheus::read_request::ResponseType::Samples as i32;
+    if !request.accepted_response_types.is_empty()
+        && !request.accepted_response_types.contains(&samples)
+    {
+        return Err(anyhow::anyhow!(
+            "Unsupported response types, only SAMPLES is supported"
+        ));
+    }
+
+    let mut results = Vec::with_capacity(request.queries.len());
+    for query in request.queries.iter() {
+        let mut series = vec![];
+        for metric_name in remote_read_metric_names(org_id, query).await? {
+            let selector = remote_read_selector(query, &metric_name);
+            let end = query.end_timestamp_ms * 1000;
+            let req = promql::MetricsQueryRequest {
+                query: selector,
+                start: end,
+                end,
+                step: promql::micros(promql::DEFAULT_LOOKBACK),
+            };
+            let value = promql::search::search(org_id, &req, 0)
+                .await
+                .map_err(|e| anyhow::anyhow!("Error fetching samples: {}", e))?;
+            match value {
+                promql::value::Value::Matrix(v) => series.extend(v),
+                promql::value::Value::Range(v) => series.push(v),
+                _ => {}
+            };
+        }
+        let timeseries = series
+            .into_iter()
+            .map(|series| prometheus::TimeSeries {
+                labels: series
+                    .labels
+                    .iter()
+                    .map(|label| prometheus::Label {
+                        name: label.name.clone(),
+                        value: label.value.clone(),
+                    })
+                    .collect(),
+                samples: series
+                    .samples
+                    .iter()
+                    .map(|sample| prometheus::Sample {
+                        value: sample.value,
+                        timestamp: sample.timestamp / 1000,
+                    })
+                    .collect(),
+                ..Default::default()
+            })
+            .collect();
+        results.push(prometheus::QueryResult { timeseries });
+    }
+
+    let resp = prometheus::ReadResponse { results }.encode_to_vec();
+    let resp = snap::raw::Encoder::new()
+        .compress_vec(&resp)
+        .map_err(|e| anyhow::anyhow!("Error compressing response: {}", e.to_string()))?;
+
+    let time = start.elapsed().as_secs_f64();
+    metrics::HTTP_RESPONSE_TIME
+        .with_label_values(&[
+            "/prometheus/api/v1/read",
+            "200",
+            org_id,
+            "",
+            &StreamType::Metrics.to_string(),
+        ])
+        .observe(time);
+    metrics::HTTP_INCOMING_REQUESTS
+        .with_label_values(&[
+            "/prometheus/api/v1/read",
+            "200",
+            org_id,
+            "",
+            &StreamType::Metrics.to_string(),
+        ])
+        .inc();
+
+    Ok(resp)
+}
+
+/// Returns the metrics a remote-read query selects: the value of its equality matcher on
+/// the metric name, or else the metrics of the organization with samples in the query
+/// range which match all its matchers on the name, up to
+/// `ZO_PROMETHEUS_REMOTE_READ_MAX_METRICS`.
+async fn remote_read_metric_names(
+    org_id: &str,
+    query: &prometheus::Query,
+) -> std::result::Result<Vec<String>, anyhow::Error> {
+    use prometheus::label_matcher::Type;
+
+    if let Some(m) = query
+        .matchers
+        .iter()
+        .find(|m| m.name == NAME_LABEL && m.r#type() == Type::Eq)
+    {
+        return Ok(vec![m.value.clone()]);
+    }
+    let start = query.start_timestamp_ms * 1000;
+    let end = query.end_timestamp_ms * 1000;
+    let metric_names = db::schema::list(org_id, Some(StreamType::Metrics), false)
+        .await?
+        .into_iter()
+        .map(|schema| schema.stream_name)
+        .filter(|name| {
+            stats::get_stream_stats(org_id, name, StreamType::Metrics)
+                .time_range_intersects(start, end)
+        });
+    let metric_names = match_metric_names(&query.matchers, metric_names)?;
+    let max_metrics = CONFIG.limit.prometheus_remote_read_max_metrics;
+    if metric_names.len() > max_metrics {
+        return Err(anyhow::anyhow!(
+            "Query selects {} metrics, more than the limit of {max_metrics}, add a matcher on {NAME_LABEL}",
+            metric_names.len()
+        ));
+    }
+    Ok(metric_names)
+}
+
+/// Keeps the metric names matching all the matchers on the name.
+fn match_metric_names(
+    matchers: &[prometheus::LabelMatcher],
+    metric_names: impl Iterator<Item = String>,
+) -> std::result::Result<Vec<String>, anyhow::Error> {
+    use prometheus::label_matcher::Type;
+
+    let mut name_matchers = vec![];
+    for m in matchers.iter().filter(|m| m.name == NAME_LABEL) {
+        let re = match m.r#type() {
+            Type::Re | Type::Nre => Some(
+                regex::Regex::new(&format!("^(?:{})$", m.value))
+                    .map_err(|e| anyhow::anyhow!("Invalid regex matcher {}: {}", m.value, e))?,
+            ),
+            Type::Eq | Type::Neq => None,
+        };
+        name_matchers.push((m, re));
+    }
+    Ok(metric_names
+        .filter(|name| {
+            name_matchers.iter().all(|(m, re)| match (m.r#type(), re) {
+                (Type::Eq, _) => *name == m.value,
+                (Type::Neq, _) => *name != m.value,
+                (Type::Re, Some(re)) => re.is_match(name),
+                (Type::Nre, Some(re)) => !re.is_match(name),
+                _ => false,
+            })
+        })
+        .collect())
+}
+
+/// Builds the PromQL range selector returning the raw samples of a remote-read query for
+/// one of its metrics, e.g. `{__name__="up",job=~"api.*"}[300000ms]`.
+fn remote_read_selector(query: &prometheus::Query, metric_name: &str) -> String {
+    use prometheus::label_matcher::Type;
+
+    let escape = |value: &str| {
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    };
+    let matchers = std::iter::once(format!("{NAME_LABEL}=\"{}\"", escape(metric_name)))
+        .chain(
+            query
+                .matchers
+                .iter()
+                .filter(|m| m.name != NAME_LABEL)
+                .map(|m| {
+                    let op = match m.r#type() {
+                        Type::Eq => "=",
+                        Type::Neq => "!=",
+                        Type::Re => "=~",
+                        Type::Nre => "!~",
+                    };
+                    format!("{}{}\"{}\"", m.name, op, escape(&m.value))
+                }),
+        )
+        .collect::<Vec<_>>()
+        .join(",");
+    // the selector range excludes its start, the remote-read range includes it
+    let range = (query.end_timestamp_ms - query.start_timestamp_ms).max(0) + 1;
+    format!("{{{matchers}}}[{range}ms]")
+}
+
 pub(crate) async fn get_metadata(org_id: &str, req: RequestMetadata) -> Result<ResponseMetadata> {
     if req.limit == Some(0) {
         return Ok(ahash::HashMap::default());
@@ -534,7 +756,12 @@ pub(crate) async fn get_series(
         .fields()
         .iter()
         .map(|f| f.name().as_str())
-        .filter(|&s| s != CONFIG.common.column_timestamp && s != VALUE_LABEL && s != HASH_LABEL)
+        .filter(|&s| {
+            s != CONFIG.common.column_timestamp
+                && s != VALUE_LABEL
+                && s != HASH_LABEL
+                && !HISTOGRAM_LABELS.contains(&s)
+        })
         .collect::<Vec<_>>()
         .join(", ");
     if label_names.is_empty() {
@@ -633,7 +860,10 @@ pub(crate) async fn get_labels(
                 .iter()
                 .map(|f| f.name())
                 .filter(|&s| {
-                    s != &CONFIG.common.column_timestamp && s != VALUE_LABEL && s != HASH_LABEL
+                    s != &CONFIG.common.column_timestamp
+                        && s != VALUE_LABEL
+                        && s != HASH_LABEL
+                        && !HISTOGRAM_LABELS.contains(&s.as_str())
                 })
                 .cloned();
             label_names.extend(field_names);
@@ -830,3 +1060,60 @@ async fn prom_ha_handler(
 
     _accept_record
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remote_read_selector() {
+        let matcher = |name: &str, r#type: prometheus::label_matcher::Type, value: &str| {
+            prometheus::LabelMatcher {
+                r#type: r#type as i32,
+                name: name.to_string(),
+                value: value.to_string(),
+            }
+        };
+        let mut query = prometheus::Query {
+            start_timestamp_ms: 1000,
+            end_timestamp_ms: 61000,
+            matchers: vec![
+                matcher(NAME_LABEL, prometheus::label_matcher::Type::Eq, "up"),
+                matcher("job", prometheus::label_matcher::Type::Re, "api.*"),
+                matcher("env", prometheus::label_matcher::Type::Neq, "a\"b"),
+            ],
+            hints: None,
+        };
+        assert_eq!(
+            remote_read_selector(&query, "up"),
+            r#"{__name__="up",job=~"api.*",env!="a\"b"}[60001ms]"#
+        );
+
+        let names = || {
+            ["up", "up_total", "process_cpu", "node_cpu"]
+                .into_iter()
+                .map(String::from)
+        };
+        query.matchers[0] = matcher(NAME_LABEL, prometheus::label_matcher::Type::Re, "up.*");
+        assert_eq!(
+            match_metric_names(&query.matchers, names()).unwrap(),
+            vec!["up", "up_total"]
+        );
+        query.matchers.push(matcher(
+            NAME_LABEL,
+            prometheus::label_matcher::Type::Neq,
+            "up",
+        ));
+        assert_eq!(
+            match_metric_names(&query.matchers, names()).unwrap(),
+            vec!["up_total"]
+        );
+        query.matchers[0] = matcher(NAME_LABEL, prometheus::label_matcher::Type::Nre, ".*cpu");
+        assert_eq!(
+            match_metric_names(&query.matchers, names()).unwrap(),
+            vec!["up_total"]
+        );
+        query.matchers[0] = matcher(NAME_LABEL, prometheus::label_matcher::Type::Re, "(");
+        assert!(match_metric_names(&query.matchers, names()).is_err());
+    }
+}
@@ -0,0 +1,389 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use actix_web::{http, HttpResponse};
+use chrono::Utc;
+use std::io::Error;
+
+use super::{
+    format_label_name,
+    prom::{self, prometheus},
+};
+use crate::common::{
+    infra::{
+        cluster::LOCAL_NODE_UUID,
+        config::{CONFIG, RECORDING_RULES, RECORDING_RULE_LEADERS},
+        dist_lock,
+    },
+    meta::{
+        http::HttpResponse as MetaHttpResponse,
+        prom::{ClusterLeader, RecordingRule, RecordingRuleList, NAME_LABEL},
+    },
+};
+use crate::service::{db, format_stream_name, promql};
+
+#[tracing::instrument(skip(rule))]
+pub async fn save_rule(
+    org_id: String,
+    name: String,
+    mut rule: RecordingRule,
+) -> Result<HttpResponse, Error> {
+    rule.name = name;
+    if let Err(e) = validate_rule(&rule) {
+        return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+            http::StatusCode::BAD_REQUEST.into(),
+            e,
+        )));
+    }
+    match db::recording_rules::set(&org_id, &rule).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(MetaHttpResponse::message(
+            http::StatusCode::OK.into(),
+            "Recording rule saved".to_string(),
+        ))),
+        Err(e) => Ok(
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        ),
+    }
+}
+
+#[tracing::instrument]
+pub async fn get_rule(org_id: String, name: String) -> Result<HttpResponse, Error> {
+    match db::recording_rules::get(&org_id, &name).await {
+        Ok(Some(rule)) => Ok(HttpResponse::Ok().json(rule)),
+        _ => Ok(HttpResponse::NotFound().json(MetaHttpResponse::error(
+            http::StatusCode::NOT_FOUND.into(),
+            "Recording rule not found".to_string(),
+        ))),
+    }
+}
+
+#[tracing::instrument]
+pub async fn list_rules(org_id: String) -> Result<HttpResponse, Error> {
+    let mut list = match db::recording_rules::list(&org_id).await {
+        Ok(list) => list,
+        Err(e) => {
+            return Ok(
+                HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                    http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                    e.to_string(),
+                )),
+            )
+        }
+    };
+    list.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(HttpResponse::Ok().json(RecordingRuleList { list }))
+}
+
+#[tracing::instrument]
+pub async fn delete_rule(org_id: String, name: String) -> Result<HttpResponse, Error> {
+    match db::recording_rules::delete(&org_id, &name).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(MetaHttpResponse::message(
+            http::StatusCode::OK.into(),
+            "Recording rule deleted".to_string(),
+        ))),
+        Err(e) => Ok(HttpResponse::NotFound().json(MetaHttpResponse::error(
+            http::StatusCode::NOT_FOUND.into(),
+            e.to_string(),
+        ))),
+    }
+}
+
+fn validate_rule(rule: &RecordingRule) -> Result<(), String> {
+    if rule.name.is_empty() || rule.name.contains('/') {
+        return Err("Recording rule name can not be empty or contain '/'".to_string());
+    }
+    if rule.metric.is_empty() || format_stream_name(&rule.metric) != rule.metric {
+        return Err(format!("Invalid metric name: {}", rule.metric));
+    }
+    if rule.interval <= 0 {
+        return Err("Recording rule interval must be positive".to_string());
+    }
+    if let Some(name) = rule
+        .labels
+        .keys()
+        .find(|name| name.as_str() == NAME_LABEL || format_label_name(name) != **name)
+    {
+        return Err(format!("Invalid label name: {name}"));
+    }
+    promql::parse(&rule.query).map_err(|e| format!("Invalid query: {e}"))?;
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    Evaluate,
+    Claim,
+    Skip,
+}
+
+/// Decides what this node does with a rule at `eval_ts`. The leader evaluates every step
+/// once, the others wait until the lease of the leader expires and then claim the rule.
+/// A claim only turns into an evaluation on the next run.
+fn next_action(
+    leader: Option<&ClusterLeader>,
+    node: &str,
+    eval_ts: i64,
+    now: i64,
+    lease: i64,
+) -> Action {
+    match leader {
+        Some(leader) if leader.updated_by == node => {
+            if eval_ts > leader.last_received {
+                Action::Evaluate
+            } else {
+                Action::Skip
+            }
+        }
+        Some(leader) if now - leader.last_received <= lease => Action::Skip,
+        _ => Action::Claim,
+    }
+}
+
+/// Evaluates the recording rules which are due on this node, called periodically by the
+/// recording rules job.
+pub async fn run() {
+    let now = Utc::now().timestamp_micros();
+    let node = LOCAL_NODE_UUID.to_string();
+    let election_interval = CONFIG.limit.metrics_leader_election_interval;
+    let rules: Vec<(String, RecordingRule)> = RECORDING_RULES
+        .iter()
+        .filter(|rule| rule.enabled)
+        .map(|rule| (rule.key().clone(), rule.value().clone()))
+        .collect();
+    for (key, rule) in rules {
+        let Some((org_id, _)) = key.split_once('/') else {
+            continue;
+        };
+        let step = rule.interval.max(1) * 1_000_000;
+        let eval_ts = now - now % step;
+        let lease = step + rule.interval.max(election_interval) * 1_000_000;
+        // the cached leader filters out the rules this node has nothing to do with
+        let leader = RECORDING_RULE_LEADERS.get(&key).map(|v| v.value().clone());
+        if next_action(leader.as_ref(), &node, eval_ts, now, lease) == Action::Skip {
+            continue;
+        }
+        let action = match update_leader(&key, &node, eval_ts, now, lease, step).await {
+            Ok(action) => action,
+            Err(e) => {
+                log::error!("recording rule [{key}] error updating leader: {e}");
+                continue;
+            }
+        };
+        if action == Action::Evaluate {
+            if let Err(e) = evaluate(org_id, &rule, eval_ts).await {
+                log::error!("recording rule [{key}] evaluation error: {e}");
+            }
+        }
+    }
+}
+
+/// Decides again with the stored leader, under the lock of the rule, and records the
+/// outcome before any evaluation. So a rule is claimed by one node only, and a step is
+/// evaluated at most once even if an evaluation outlives the lease.
+async fn update_leader(
+    key: &str,
+    node: &str,
+    eval_ts: i64,
+    now: i64,
+    lease: i64,
+    step: i64,
+) -> Result<Action, anyhow::Error> {
+    let lock_key = format!("recording_rules/{key}");
+    let locker = dist_lock::lock(&lock_key, CONFIG.etcd.command_timeout).await?;
+    let ret = async {
+        let leader = db::recording_rules::get_leader(key).await?;
+        let action = next_action(leader.as_ref(), node, eval_ts, now, lease);
+        let last_received = match action {
+            Action::Skip => return Ok(action),
+            Action::Claim => eval_ts - step,
+            // renews the lease for the evaluation
+            Action::Evaluate => eval_ts,
+        };
+        let leader = ClusterLeader {
+            name: key.to_string(),
+            last_received,
+            updated_by: node.to_string(),
+        };
+        db::recording_rules::set_leader(key, &leader).await?;
+        Ok::<_, anyhow::Error>(action)
+    }
+    .await;
+    dist_lock::unlock(&locker).await?;
+    ret
+}
+
+/// Evaluates the rule as an instant query at `timestamp`, in microseconds, and ingests the
+/// result into the metrics stream of the rule.
+pub async fn evaluate(
+    org_id: &str,
+    rule: &RecordingRule,
+    timestamp: i64,
+) -> Result<(), anyhow::Error> {
+    let req = promql::MetricsQueryRequest {
+        query: rule.query.clone(),
+        start: timestamp,
+        end: timestamp,
+        step: rule.interval.max(1) * 1_000_000,
+    };
+    let value = promql::search::search(org_id, &req, 0).await?;
+    let timeseries = to_timeseries(rule, value, timestamp / 1000);
+    if timeseries.is_empty() {
+        return Ok(());
+    }
+    let request = prometheus::WriteRequest {
+        timeseries,
+        ..Default::default()
+    };
+    prom::write_request(org_id, 0, request).await
+}
+
+/// Converts the result of an instant query into time series named after the rule metric,
+/// `timestamp` is in milliseconds.
+fn to_timeseries(
+    rule: &RecordingRule,
+    value: promql::value::Value,
+    timestamp: i64,
+) -> Vec<prometheus::TimeSeries> {
+    use promql::value::{Labels, Value as PromValue};
+
+    let series: Vec<(Labels, f64)> = match value {
+        PromValue::Instant(v) => vec![(v.labels, v.sample.value)],
+        PromValue::Vector(vs) => vs.into_iter().map(|v| (v.labels, v.sample.value)).collect(),
+        PromValue::Sample(s) => vec![(Labels::default(), s.value)],
+        PromValue::Float(val) => vec![(Labels::default(), val)],
+        PromValue::Range(_) | PromValue::Matrix(_) | PromValue::String(_) | PromValue::None => {
+            vec![]
+        }
+    };
+
+    series
+        .into_iter()
+        .filter(|(_, value)| !value.is_nan())
+        .map(|(labels, value)| {
+            let mut labels: Vec<prometheus::Label> = labels
+                .iter()
+                .filter(|label| label.name != NAME_LABEL && !rule.labels.contains_key(&label.name))
+                .map(|label| prometheus::Label {
+                    name: label.name.clone(),
+                    value: label.value.clone(),
+                })
+                .collect();
+            labels.extend(rule.labels.iter().map(|(name, value)| prometheus::Label {
+                name: name.clone(),
+                value: value.clone(),
+            }));
+            labels.push(prometheus::Label {
+                name: NAME_LABEL.to_string(),
+                value: rule.metric.clone(),
+            });
+            labels.sort_by(|a, b| a.name.cmp(&b.name));
+            prometheus::TimeSeries {
+                labels,
+                samples: vec![prometheus::Sample { value, timestamp }],
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::promql::value::{InstantValue, Label, Sample, Value};
+    use std::sync::Arc;
+
+    fn rule() -> RecordingRule {
+        RecordingRule {
+            name: "job_rate".to_string(),
+            query: "sum by (job) (rate(http_requests_total[5m]))".to_string(),
+            metric: "job_http_requests_rate5m".to_string(),
+            labels: [("env".to_string(), "prod".to_string())]
+                .into_iter()
+                .collect(),
+            interval: 60,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn test_validate_rule() {
+        assert!(validate_rule(&rule()).is_ok());
+        let mut invalid = rule();
+        invalid.metric = "job:rate".to_string();
+        assert!(validate_rule(&invalid).is_err());
+        let mut invalid = rule();
+        invalid.query = "sum(".to_string();
+        assert!(validate_rule(&invalid).is_err());
+        let mut invalid = rule();
+        invalid.interval = 0;
+        assert!(validate_rule(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_next_action() {
+        let leader = |node: &str, last_received| ClusterLeader {
+            name: "default/job_rate".to_string(),
+            last_received,
+            updated_by: node.to_string(),
+        };
+        assert_eq!(next_action(None, "a", 120, 125, 90), Action::Claim);
+        assert_eq!(
+            next_action(Some(&leader("a", 60)), "a", 120, 125, 90),
+            Action::Evaluate
+        );
+        assert_eq!(
+            next_action(Some(&leader("a", 120)), "a", 120, 125, 90),
+            Action::Skip
+        );
+        assert_eq!(
+            next_action(Some(&leader("b", 60)), "a", 120, 125, 90),
+            Action::Skip
+        );
+        assert_eq!(
+            next_action(Some(&leader("b", 0)), "a", 120, 125, 90),
+            Action::Claim
+        );
+    }
+
+    #[test]
+    fn test_to_timeseries() {
+        let value = Value::Vector(vec![InstantValue {
+            labels: vec![
+                Arc::new(Label::new("job", "api")),
+                Arc::new(Label::new("env", "dev")),
+            ],
+            sample: Sample::new(120_000_000, 1.5),
+        }]);
+        let series = to_timeseries(&rule(), value, 120_000);
+        assert_eq!(series.len(), 1);
+        let labels: Vec<(&str, &str)> = series[0]
+            .labels
+            .iter()
+            .map(|l| (l.name.as_str(), l.value.as_str()))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                ("__name__", "job_http_requests_rate5m"),
+                ("env", "prod"),
+                ("job", "api"),
+            ]
+        );
+        assert_eq!(series[0].samples[0].timestamp, 120_000);
+        assert_eq!(series[0].samples[0].value, 1.5);
+    }
+}
@@ -0,0 +1,340 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use ahash::AHashMap;
+use chrono::Utc;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use opentelemetry_proto::tonic::metrics::v1::{AggregationTemporality, DataPointFlags};
+
+use crate::common::{
+    infra::config::{RwHashMap, CONFIG},
+    meta::prom::VALUE_LABEL,
+    utils::json::{Map, Value},
+};
+
+/// Running totals of the delta series, by org and series hash. The state is kept per
+/// ingester, so the points of a delta series have to be sent to the same ingester.
+static SERIES: Lazy<RwHashMap<String, DeltaState>> = Lazy::new(DashMap::default);
+
+#[derive(Debug, Clone, Copy)]
+struct DeltaState {
+    /// Start of the cumulative series, in nanoseconds
+    start_time: u64,
+    /// Time of the last accumulated point, in microseconds
+    last_time: i64,
+    total: f64,
+    /// Wall clock time of the last update, in microseconds
+    updated_at: i64,
+}
+
+/// Returns the temporality to record for points of `temporality`, delta sums and
+/// histograms are stored as cumulative ones when `ZO_METRICS_DELTA_TO_CUMULATIVE` is on.
+/// Exponential histograms are stored as sent, their buckets are no series of their own.
+pub fn stored_temporality(temporality: u64) -> u64 {
+    if is_converted(temporality) {
+        AggregationTemporality::Cumulative as u64
+    } else {
+        temporality
+    }
+}
+
+pub fn is_converted(temporality: u64) -> bool {
+    CONFIG.common.metrics_delta_to_cumulative && temporality == AggregationTemporality::Delta as u64
+}
+
+/// Delta points of a request. Their running totals are kept apart until the request is
+/// written and [`DeltaBatch::commit`] is called, so a retried request isn't dropped as
+/// already seen.
+#[derive(Debug)]
+pub struct DeltaBatch {
+    org_id: String,
+    series: AHashMap<String, Option<DeltaState>>,
+}
+
+impl DeltaBatch {
+    pub fn new(org_id: &str) -> Self {
+        Self {
+            org_id: org_id.to_string(),
+            series: AHashMap::new(),
+        }
+    }
+
+    /// Adds a delta point to the running total of its series and rewrites it as cumulative
+    /// point. Returns `false` if the point has to be dropped, because it is older than or
+    /// overlaps with the points accumulated so far.
+    ///
+    /// Gaps between points are accumulated as is, a series that didn't get points for
+    /// `ZO_METRICS_DELTA_MAX_STALE` seconds starts over, which queries see as counter reset.
+    pub fn accumulate(&mut self, hash: &str, rec: &mut Map<String, Value>) -> bool {
+        let key = state_key(&self.org_id, hash, rec);
+        let state = self
+            .series
+            .entry(key)
+            .or_insert_with_key(|key| SERIES.get(key).map(|v| *v.value()));
+        accumulate(state, rec)
+    }
+
+    /// Saves the running totals of the written points. A series updated meanwhile by
+    /// another request keeps the state with the latest point.
+    pub fn commit(self) {
+        for (key, state) in self.series {
+            let Some(state) = state else {
+                continue;
+            };
+            SERIES
+                .entry(key)
+                .and_modify(|v| {
+                    if v.last_time <= state.last_time {
+                        *v = state;
+                    }
+                })
+                .or_insert(state);
+        }
+    }
+}
+
+fn accumulate(state: &mut Option<DeltaState>, rec: &mut Map<String, Value>) -> bool {
+    let time = rec
+        .get(&CONFIG.common.column_timestamp)
+        .and_then(Value::as_i64)
+        .unwrap_or_default();
+    let start_time = rec
+        .get("start_time")
+        .and_then(Value::as_str)
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or_default();
+    let no_value = rec.get("flag").and_then(Value::as_str)
+        == Some(DataPointFlags::FlagNoRecordedValue.as_str_name());
+    let value = match rec.get(VALUE_LABEL).and_then(Value::as_f64) {
+        Some(v) if !no_value && !v.is_nan() => Some(v),
+        _ => None,
+    };
+
+    let now = Utc::now().timestamp_micros();
+    let max_stale = CONFIG.limit.metrics_delta_max_stale * 1_000_000;
+    let new_state = || DeltaState {
+        // senders may leave the start time unset
+        start_time: if start_time > 0 {
+            start_time
+        } else {
+            time as u64 * 1000
+        },
+        last_time: 0,
+        total: 0.0,
+        updated_at: now,
+    };
+    let state = state.get_or_insert_with(new_state);
+    if now - state.updated_at > max_stale {
+        *state = new_state();
+    }
+
+    if let Some(value) = value {
+        // out of order or duplicated point
+        if time <= state.last_time {
+            return false;
+        }
+        // overlaps with the accumulated points, adding it would count twice
+        if start_time > 0 && (start_time / 1000) < state.last_time as u64 {
+            return false;
+        }
+        state.total += value;
+        state.last_time = time;
+        state.updated_at = now;
+    }
+
+    rec.insert(VALUE_LABEL.to_string(), state.total.into());
+    rec.insert(
+        "start_time".to_string(),
+        state.start_time.to_string().into(),
+    );
+    true
+}
+
+/// The series hash leaves out `le`, but every bucket of a histogram is a series of its
+/// own. The `_count` and `_sum` series differ by their name, which is part of the hash.
+fn state_key(org_id: &str, hash: &str, rec: &Map<String, Value>) -> String {
+    match rec.get("le").and_then(Value::as_str) {
+        Some(le) => format!("{org_id}/{hash}/{le}"),
+        None => format!("{org_id}/{hash}"),
+    }
+}
+
+/// Drops the state of series that didn't get points for `ZO_METRICS_DELTA_MAX_STALE`.
+pub fn clean_stale_series() {
+    let min_updated_at =
+        Utc::now().timestamp_micros() - CONFIG.limit.metrics_delta_max_stale * 1_000_000;
+    SERIES.retain(|_, state| state.updated_at >= min_updated_at);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{meta::prom::NAME_LABEL, utils::json};
+    use crate::service::metrics::{get_exclude_labels, signature_without_labels};
+
+    fn point(start_secs: u64, secs: i64, value: f64) -> Map<String, Value> {
+        let mut rec = Map::new();
+        rec.insert(
+            CONFIG.common.column_timestamp.clone(),
+            (secs * 1_000_000).into(),
+        );
+        rec.insert(
+            "start_time".to_string(),
+            (start_secs * 1_000_000_000).to_string().into(),
+        );
+        rec.insert(VALUE_LABEL.to_string(), value.into());
+        rec
+    }
+
+    fn value(rec: &Map<String, Value>) -> f64 {
+        rec.get(VALUE_LABEL).unwrap().as_f64().unwrap()
+    }
+
+    fn accumulate_one(org_id: &str, hash: &str, rec: &mut Map<String, Value>) -> bool {
+        let mut batch = DeltaBatch::new(org_id);
+        let accumulated = batch.accumulate(hash, rec);
+        batch.commit();
+        accumulated
+    }
+
+    #[test]
+    fn test_accumulate() {
+        let hash = "test_accumulate";
+        let mut rec = point(100, 110, 3.0);
+        assert!(accumulate_one("default", hash, &mut rec));
+        assert_eq!(value(&rec), 3.0);
+        assert_eq!(rec.get("start_time").unwrap(), "100000000000");
+
+        let mut rec = point(110, 120, 2.0);
+        assert!(accumulate_one("default", hash, &mut rec));
+        assert_eq!(value(&rec), 5.0);
+        assert_eq!(rec.get("start_time").unwrap(), "100000000000");
+
+        // gaps are accumulated
+        let mut rec = point(150, 160, 1.0);
+        assert!(accumulate_one("default", hash, &mut rec));
+        assert_eq!(value(&rec), 6.0);
+
+        // out of order and overlapping points are dropped
+        assert!(!accumulate_one("default", hash, &mut point(110, 120, 2.0)));
+        assert!(!accumulate_one("default", hash, &mut point(155, 170, 2.0)));
+
+        // other orgs have their own series
+        let mut rec = point(110, 120, 2.0);
+        assert!(accumulate_one("other", hash, &mut rec));
+        assert_eq!(value(&rec), 2.0);
+    }
+
+    /// The records of a histogram point, as written by the OTLP ingestion.
+    fn histogram_point(
+        start_secs: u64,
+        secs: i64,
+        count: f64,
+        sum: f64,
+        buckets: &[f64],
+    ) -> Vec<Map<String, Value>> {
+        let base = |name: &str, value: f64| {
+            let mut rec = point(start_secs, secs, value);
+            rec.insert(NAME_LABEL.to_string(), name.into());
+            rec.insert("job".to_string(), "api".into());
+            rec
+        };
+        let mut recs = vec![base("latency_count", count), base("latency_sum", sum)];
+        for (le, value) in ["1", "5", "+Inf"].iter().zip(buckets) {
+            let mut rec = base("latency", *value);
+            rec.insert("le".to_string(), (*le).into());
+            recs.push(rec);
+        }
+        recs
+    }
+
+    fn accumulate_histogram(org_id: &str, recs: &mut [Map<String, Value>]) -> Vec<f64> {
+        recs.iter_mut()
+            .filter_map(|rec| {
+                let hash: String = signature_without_labels(rec, &get_exclude_labels()).into();
+                accumulate_one(org_id, &hash, rec).then(|| value(rec))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_accumulate_histogram() {
+        let org_id = "test_accumulate_histogram";
+        let mut recs = histogram_point(100, 110, 3.0, 7.0, &[1.0, 1.0, 1.0]);
+        assert_eq!(
+            accumulate_histogram(org_id, &mut recs),
+            [3.0, 7.0, 1.0, 1.0, 1.0]
+        );
+
+        // every bucket, the count and the sum have their own total
+        let mut recs = histogram_point(110, 120, 2.0, 4.0, &[0.0, 2.0, 0.0]);
+        assert_eq!(
+            accumulate_histogram(org_id, &mut recs),
+            [5.0, 11.0, 1.0, 3.0, 1.0]
+        );
+
+        // a duplicated point is dropped as a whole
+        let mut recs = histogram_point(110, 120, 2.0, 4.0, &[0.0, 2.0, 0.0]);
+        assert!(accumulate_histogram(org_id, &mut recs).is_empty());
+    }
+
+    #[test]
+    fn test_accumulate_uncommitted() {
+        let hash = "test_accumulate_uncommitted";
+        assert!(accumulate_one("default", hash, &mut point(100, 110, 3.0)));
+
+        // the points of a batch add up, but not to the totals until committed
+        let mut batch = DeltaBatch::new("default");
+        let mut rec = point(110, 120, 2.0);
+        assert!(batch.accumulate(hash, &mut rec));
+        assert_eq!(value(&rec), 5.0);
+        let mut rec = point(120, 130, 1.0);
+        assert!(batch.accumulate(hash, &mut rec));
+        assert_eq!(value(&rec), 6.0);
+        drop(batch);
+
+        // so a failed request can be retried
+        let mut rec = point(110, 120, 2.0);
+        assert!(accumulate_one("default", hash, &mut rec));
+        assert_eq!(value(&rec), 5.0);
+    }
+
+    #[test]
+    fn test_accumulate_no_value() {
+        let hash = "test_accumulate_no_value";
+        assert!(accumulate_one("default", hash, &mut point(100, 110, 3.0)));
+        let mut rec = point(110, 120, 0.0);
+        rec.insert(
+            "flag".to_string(),
+            json::json!(DataPointFlags::FlagNoRecordedValue.as_str_name()),
+        );
+        assert!(accumulate_one("default", hash, &mut rec));
+        assert_eq!(value(&rec), 3.0);
+    }
+
+    #[test]
+    fn test_stale_series_reset() {
+        let hash = "test_stale_series_reset";
+        assert!(accumulate_one("default", hash, &mut point(100, 110, 3.0)));
+        SERIES
+            .get_mut(&format!("default/{hash}"))
+            .unwrap()
+            .updated_at -= (CONFIG.limit.metrics_delta_max_stale + 1) * 1_000_000;
+        let mut rec = point(200, 210, 1.0);
+        assert!(accumulate_one("default", hash, &mut rec));
+        assert_eq!(value(&rec), 1.0);
+        assert_eq!(rec.get("start_time").unwrap(), "200000000000");
+    }
+}
@@ -28,6 +28,7 @@ pub mod distinct_values;
 pub mod enrichment;
 pub mod enrichment_table;
 pub mod file_list;
+pub mod fluent_routes;
 pub mod functions;
 pub mod ingestion;
 pub mod kv;
@@ -0,0 +1,131 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use ahash::AHashMap as HashMap;
+use datafusion::error::{DataFusionError, Result};
+use promql_parser::parser::{Expr as PromExpr, LabelModifier};
+
+use super::Engine;
+use crate::service::promql::value::{InstantValue, Labels, LabelsExt, Signature, Value};
+
+/// Returns up to `k` series of every group. The series are picked by their label set,
+/// so every evaluation of a range query returns the same series.
+pub async fn limitk(
+    ctx: &mut Engine,
+    param: &PromExpr,
+    modifier: &Option<LabelModifier>,
+    data: &Value,
+) -> Result<Value> {
+    let k = match ctx.exec_expr(param).await? {
+        Value::Float(v) => v,
+        _ => {
+            return Err(DataFusionError::Plan(
+                "[limitk] param must be NumberLiteral".into(),
+            ))
+        }
+    };
+    eval_limitk(k, modifier, data)
+}
+
+fn eval_limitk(k: f64, modifier: &Option<LabelModifier>, data: &Value) -> Result<Value> {
+    let data = match data {
+        Value::Vector(v) => v,
+        Value::None => return Ok(Value::None),
+        _ => {
+            return Err(DataFusionError::Plan(
+                "[limitk] function only accept vector values".into(),
+            ))
+        }
+    };
+    if k.is_nan() || k < 1.0 {
+        return Ok(Value::Vector(vec![]));
+    }
+
+    let mut groups: HashMap<Signature, Vec<&InstantValue>> = HashMap::default();
+    for item in data.iter() {
+        let group_labels = match modifier {
+            Some(LabelModifier::Include(labels)) => {
+                super::labels_to_include(&labels.labels, &item.labels)
+            }
+            Some(LabelModifier::Exclude(labels)) => {
+                super::labels_to_exclude(&labels.labels, &item.labels)
+            }
+            None => Labels::default(),
+        };
+        groups
+            .entry(group_labels.signature())
+            .or_default()
+            .push(item);
+    }
+
+    let values = groups
+        .into_values()
+        .flat_map(|mut items| {
+            items.sort_by(|a, b| {
+                let a = a.labels.iter().map(|l| (&l.name, &l.value));
+                let b = b.labels.iter().map(|l| (&l.name, &l.value));
+                a.cmp(b)
+            });
+            items.truncate(k as usize);
+            items
+        })
+        .cloned()
+        .collect();
+    Ok(Value::Vector(values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::promql::value::{Label, Sample};
+    use promql_parser::label::Labels as ModifierLabels;
+    use std::sync::Arc;
+
+    fn jobs(value: Value) -> Vec<String> {
+        match value {
+            Value::Vector(v) => {
+                let mut jobs: Vec<String> = v.iter().map(|v| v.labels.get_value("job")).collect();
+                jobs.sort();
+                jobs
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_limitk() {
+        let data = Value::Vector(
+            [("c", "x"), ("a", "x"), ("b", "y"), ("d", "y")]
+                .iter()
+                .map(|(job, group)| InstantValue {
+                    labels: vec![
+                        Arc::new(Label::new("group", *group)),
+                        Arc::new(Label::new("job", *job)),
+                    ],
+                    sample: Sample::new(0, 1.0),
+                })
+                .collect(),
+        );
+        assert_eq!(jobs(eval_limitk(2.0, &None, &data).unwrap()), ["a", "c"]);
+        assert!(jobs(eval_limitk(0.0, &None, &data).unwrap()).is_empty());
+
+        let by_group = Some(LabelModifier::Include(ModifierLabels {
+            labels: vec!["group".to_string()],
+        }));
+        assert_eq!(
+            jobs(eval_limitk(1.0, &by_group, &data).unwrap()),
+            ["a", "b"]
+        );
+    }
+}
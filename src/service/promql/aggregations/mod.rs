@@ -27,6 +27,7 @@ mod bottomk;
 mod count;
 mod count_values;
 mod group;
+mod limitk;
 mod max;
 mod min;
 mod quantile;
@@ -40,12 +41,13 @@ pub(crate) use bottomk::bottomk;
 pub(crate) use count::count;
 pub(crate) use count_values::count_values;
 pub(crate) use group::group;
+pub(crate) use limitk::limitk;
 pub(crate) use max::max;
 pub(crate) use min::min;
 pub(crate) use quantile::quantile;
 pub(crate) use stddev::stddev;
 pub(crate) use stdvar::stdvar;
-pub(crate) use sum::sum;
+pub(crate) use sum::{sum, sum_histograms};
 pub(crate) use topk::topk;
 
 #[derive(Debug, Clone, Default)]
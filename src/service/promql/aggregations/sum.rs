@@ -12,10 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::service::promql::value::{InstantValue, Sample, Value};
+use crate::service::promql::functions::add_histograms;
+use crate::service::promql::value::{
+    HistogramValue, InstantValue, Labels, LabelsExt, Sample, Signature, Value,
+};
+use ahash::AHashMap as HashMap;
 use datafusion::error::Result;
 use promql_parser::parser::LabelModifier;
 use rayon::prelude::*;
+use std::collections::hash_map::Entry;
 
 pub fn sum(timestamp: i64, param: &Option<LabelModifier>, data: &Value) -> Result<Value> {
     let score_values = super::eval_arithmetic(param, data, "sum", |total, val| total + val)?;
@@ -32,3 +37,35 @@ pub fn sum(timestamp: i64, param: &Option<LabelModifier>, data: &Value) -> Resul
         .collect();
     Ok(Value::Vector(values))
 }
+
+/// `sum` of native histograms, bucket by bucket.
+pub fn sum_histograms(
+    param: &Option<LabelModifier>,
+    data: Vec<HistogramValue>,
+) -> Vec<HistogramValue> {
+    let mut groups: HashMap<Signature, HistogramValue> = HashMap::default();
+    for item in data {
+        let labels = match param {
+            Some(LabelModifier::Include(labels)) => {
+                super::labels_to_include(&labels.labels, &item.labels)
+            }
+            Some(LabelModifier::Exclude(labels)) => {
+                super::labels_to_exclude(&labels.labels, &item.labels)
+            }
+            None => Labels::default(),
+        };
+        match groups.entry(labels.signature()) {
+            Entry::Occupied(mut entry) => {
+                let sum = add_histograms(&entry.get().histogram, &item.histogram, 1.0);
+                entry.get_mut().histogram = sum;
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(HistogramValue {
+                    labels,
+                    histogram: item.histogram,
+                });
+            }
+        }
+    }
+    groups.into_values().collect()
+}
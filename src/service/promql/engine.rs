@@ -16,8 +16,10 @@ use ahash::AHashMap as HashMap;
 use async_recursion::async_recursion;
 use datafusion::{
     arrow::{
-        array::{Float64Array, Int64Array, StringArray},
-        datatypes::Schema,
+        array::{Array, Float64Array, Int64Array, StringArray},
+        compute::cast,
+        datatypes::{DataType, Schema},
+        record_batch::RecordBatch,
     },
     error::{DataFusionError, Result},
     prelude::{col, lit, SessionContext},
@@ -26,30 +28,50 @@ use futures::future::try_join_all;
 use promql_parser::{
     label::MatchOp,
     parser::{
-        token, AggregateExpr, Call, Expr as PromExpr, Function, FunctionArgs, LabelModifier,
-        MatrixSelector, NumberLiteral, Offset, ParenExpr, StringLiteral, TokenType, UnaryExpr,
-        VectorSelector,
+        token, AggregateExpr, AtModifier, Call, Expr as PromExpr, Extension, Function,
+        FunctionArgs, LabelModifier, MatrixSelector, NumberLiteral, Offset, ParenExpr,
+        StringLiteral, SubqueryExpr, TokenType, UnaryExpr, VectorSelector,
     },
 };
 use rayon::prelude::*;
-use std::{collections::HashSet, str::FromStr, sync::Arc, time::Duration};
+use std::{
+    collections::HashSet,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use crate::common::meta::prom::{HASH_LABEL, VALUE_LABEL};
-use crate::common::{infra::config::CONFIG, meta::prom::NAME_LABEL};
-use crate::service::promql::{aggregations, binaries, functions, micros, value::*};
+use crate::common::meta::prom::{
+    NativeHistogram, HASH_LABEL, HISTOGRAM_LABELS, HISTOGRAM_NEGATIVE_LABEL,
+    HISTOGRAM_POSITIVE_LABEL, HISTOGRAM_SCHEMA_LABEL, HISTOGRAM_SUM_LABEL,
+    HISTOGRAM_ZERO_COUNT_LABEL, HISTOGRAM_ZERO_THRESHOLD_LABEL, VALUE_LABEL,
+};
+use crate::common::{infra::config::CONFIG, meta::prom::NAME_LABEL, utils::json};
+use crate::service::promql::{
+    aggregations, binaries,
+    extension::{LimitK, SortByLabel},
+    functions, micros,
+    value::*,
+    DEFAULT_SUBQUERY_STEP,
+};
 
 pub struct Engine {
     ctx: Arc<super::exec::Query>,
     /// The time boundaries for the evaluation.
     time: i64,
+    /// The evaluation times the selectors load data for, the query range unless
+    /// the engine evaluates the inner expression of a subquery.
+    window: (i64, i64),
     result_type: Option<String>,
 }
 
 impl Engine {
     pub fn new(ctx: Arc<super::exec::Query>, time: i64) -> Self {
+        let window = (ctx.start, ctx.end);
         Self {
             ctx,
             time,
+            window,
             result_type: None,
         }
     }
@@ -146,49 +168,12 @@ impl Engine {
             }
             PromExpr::Paren(ParenExpr { expr }) => self.exec_expr(expr).await?,
             PromExpr::Subquery(expr) => {
-                let val = self.exec_expr(&expr.expr).await?;
-                let time_window = Some(TimeWindow::new(self.time, expr.range));
-                let matrix = match val{
-                    Value::Vector(v) => {
-                        v.iter()
-                        .map(|v| {
-                            RangeValue{
-                                labels: v.labels.to_owned(),
-                                samples: vec![v.sample],
-                                time_window: time_window.clone()
-                            }
-                        }
-                        )
-                        .collect()
-                    },
-                    Value::Instant(v) => {
-                        vec![RangeValue{
-                            labels: v.labels.to_owned(),
-                            samples: vec![v.sample],
-                            time_window
-                        }]
-                    },
-                    Value::Range(v) => vec![v],
-                    Value::Matrix(vs) => vs,
-                    Value::Sample(s) => vec![RangeValue{
-                        labels: Labels::default(),
-                        samples: vec![s],
-                        time_window
-                    }],
-                    Value::Float(val) => vec![RangeValue{
-                        labels: Labels::default(),
-                        samples: vec![Sample::new(self.time, val)],
-                        time_window
-                    }],
-                    v => {
-                        return Err(DataFusionError::NotImplemented(format!(
-                            "Unsupported subquery, the return value should have been a matrix but got {:?}",
-                            v.get_type()
-                        )))
-                    }
-                };
-
-                Value::Matrix(matrix)
+                let data = self.eval_subquery(expr).await?;
+                if data.is_empty() {
+                    Value::None
+                } else {
+                    Value::Matrix(data)
+                }
             }
             PromExpr::NumberLiteral(NumberLiteral { val }) => Value::Float(*val),
             PromExpr::StringLiteral(StringLiteral { val }) => Value::String(val.clone()),
@@ -218,11 +203,31 @@ impl Engine {
                 // output
                 self.call_expr(func, args).await?
             }
-            PromExpr::Extension(expr) => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Extension: {:?}",
-                    expr
-                )));
+            PromExpr::Extension(Extension { expr }) => {
+                if let Some(limitk) = expr.as_any().downcast_ref::<LimitK>() {
+                    let input = self.exec_expr(limitk.expr()).await?;
+                    aggregations::limitk(self, limitk.param(), &limitk.modifier, &input).await?
+                } else if let Some(sort) = expr.as_any().downcast_ref::<SortByLabel>() {
+                    let input = self.exec_expr(sort.expr()).await?;
+                    let mut labels = vec![];
+                    for label in sort.labels() {
+                        match self.exec_expr(label).await? {
+                            Value::String(label) => labels.push(label),
+                            _ => {
+                                return Err(DataFusionError::NotImplemented(format!(
+                                    "Invalid args, expected \"{}(v instant-vector, label string, ...)\"",
+                                    expr.name()
+                                )))
+                            }
+                        }
+                    }
+                    functions::sort_by_label(&input, &labels, sort.desc)?
+                } else {
+                    return Err(DataFusionError::NotImplemented(format!(
+                        "Unsupported Extension: {:?}",
+                        expr
+                    )));
+                }
             }
         })
     }
@@ -248,14 +253,13 @@ impl Engine {
             selector.name = Some(name);
         }
 
-        let metrics_name = selector.name.as_ref().expect("Missing selector name");
-
-        let cache_exists = { self.ctx.data_cache.read().await.contains_key(metrics_name) };
+        let cache_key = self.cache_key(&selector, None);
+        let cache_exists = { self.ctx.data_cache.read().await.contains_key(&cache_key) };
         if !cache_exists {
-            self.selector_load_data(&selector, None).await?;
+            self.selector_load_data(&selector, None, &cache_key).await?;
         }
         let metrics_cache = self.ctx.data_cache.read().await;
-        let metrics_cache = match metrics_cache.get(metrics_name) {
+        let metrics_cache = match metrics_cache.get(&cache_key) {
             Some(v) => match v.get_ref_matrix_values() {
                 Some(v) => v,
                 None => return Ok(vec![]),
@@ -263,21 +267,10 @@ impl Engine {
             None => return Ok(vec![]),
         };
 
-        // Evaluation timestamp.
-        let eval_ts = self.time;
+        // Evaluation timestamp, pinned by the `@` modifier.
+        let eval_ts = self.eval_time(&selector.at);
         let start = eval_ts - self.ctx.lookback_delta;
-
-        let mut offset_modifier: i64 = 0;
-        if let Some(offset) = selector.offset.clone() {
-            match offset {
-                Offset::Pos(off) => {
-                    offset_modifier = micros(off);
-                }
-                Offset::Neg(off) => {
-                    offset_modifier = -micros(off);
-                }
-            }
-        }
+        let offset_modifier = offset_micros(&selector.offset);
 
         let mut values = vec![];
         for metric in metrics_cache {
@@ -292,7 +285,7 @@ impl Engine {
                     // See https://promlabs.com/blog/2020/06/18/the-anatomy-of-a-promql-query/#instant-queries
                     InstantValue {
                         labels: metric.labels.clone(),
-                        sample: Sample::new(eval_ts, last_value),
+                        sample: Sample::new(self.time, last_value),
                     },
                 );
             }
@@ -300,6 +293,102 @@ impl Engine {
         Ok(values)
     }
 
+    /// Native histograms of the last argument of the `histogram_*` functions. Selectors,
+    /// `rate`, `increase` and `delta` of range selectors, and `sum` are evaluated on the
+    /// histograms, any other expression has no native histograms.
+    #[async_recursion]
+    async fn eval_native_histograms(&mut self, expr: &PromExpr) -> Result<Vec<HistogramValue>> {
+        Ok(match expr {
+            PromExpr::Paren(ParenExpr { expr }) => self.eval_native_histograms(expr).await?,
+            PromExpr::VectorSelector(selector) => self.eval_histogram_selector(selector).await?,
+            PromExpr::Call(Call { func, args }) => {
+                let kind = match func.name {
+                    "rate" => ExtrapolationKind::Rate,
+                    "increase" => ExtrapolationKind::Increase,
+                    "delta" => ExtrapolationKind::Delta,
+                    _ => return Ok(vec![]),
+                };
+                let data = match args.args.first().map(|arg| arg.as_ref()) {
+                    Some(PromExpr::MatrixSelector(MatrixSelector { vs, range })) => {
+                        self.eval_matrix_selector(vs, *range).await?
+                    }
+                    _ => return Ok(vec![]),
+                };
+                data.iter()
+                    .filter_map(|series| {
+                        Some(HistogramValue {
+                            labels: series.labels.without_metric_name(),
+                            histogram: functions::histogram_rate(series, &kind)?,
+                        })
+                    })
+                    .collect()
+            }
+            PromExpr::Aggregate(AggregateExpr {
+                op, expr, modifier, ..
+            }) if op.id() == token::T_SUM => {
+                let data = self.eval_native_histograms(expr).await?;
+                aggregations::sum_histograms(modifier, data)
+            }
+            _ => vec![],
+        })
+    }
+
+    /// Instant vector selector of native histograms, see [`Self::eval_vector_selector`].
+    async fn eval_histogram_selector(
+        &mut self,
+        selector: &VectorSelector,
+    ) -> Result<Vec<HistogramValue>> {
+        if self.result_type.is_none() {
+            self.result_type = Some("vector".to_string());
+        }
+
+        let mut selector = selector.clone();
+        if selector.name.is_none() {
+            let name = selector
+                .matchers
+                .find_matcher_value(NAME_LABEL)
+                .expect("Missing selector name");
+
+            selector.name = Some(name);
+        }
+
+        let cache_key = self.cache_key(&selector, None);
+        let cache_exists = { self.ctx.data_cache.read().await.contains_key(&cache_key) };
+        if !cache_exists {
+            self.selector_load_data(&selector, None, &cache_key).await?;
+        }
+        let metrics_cache = self.ctx.data_cache.read().await;
+        let metrics_cache = match metrics_cache
+            .get(&cache_key)
+            .and_then(|v| v.get_ref_matrix_values())
+        {
+            Some(v) => v,
+            None => return Ok(vec![]),
+        };
+
+        let eval_ts = self.eval_time(&selector.at);
+        let start = eval_ts - self.ctx.lookback_delta;
+        let offset_modifier = offset_micros(&selector.offset);
+
+        Ok(metrics_cache
+            .iter()
+            .filter_map(|metric| {
+                let last = metric
+                    .histograms
+                    .iter()
+                    .filter(|h| {
+                        let timestamp = h.timestamp + offset_modifier;
+                        start < timestamp && timestamp <= eval_ts
+                    })
+                    .last()?;
+                Some(HistogramValue {
+                    labels: metric.labels.clone(),
+                    histogram: last.histogram.as_ref().clone(),
+                })
+            })
+            .collect())
+    }
+
     /// Range vector selector --- select a whole time range at each evaluation timestamp.
     ///
     /// See <https://promlabs.com/blog/2020/07/02/selecting-data-in-promql/#confusion-alert-instantrange-selectors-vs-instantrange-queries>
@@ -324,13 +413,14 @@ impl Engine {
             selector.name = Some(name);
         }
 
-        let metrics_name = selector.name.as_ref().expect("Missing selector name");
-        let cache_exists = { self.ctx.data_cache.read().await.contains_key(metrics_name) };
+        let cache_key = self.cache_key(&selector, Some(range));
+        let cache_exists = { self.ctx.data_cache.read().await.contains_key(&cache_key) };
         if !cache_exists {
-            self.selector_load_data(&selector, Some(range)).await?;
+            self.selector_load_data(&selector, Some(range), &cache_key)
+                .await?;
         }
         let metrics_cache = self.ctx.data_cache.read().await;
-        let metrics_cache = match metrics_cache.get(metrics_name) {
+        let metrics_cache = match metrics_cache.get(&cache_key) {
             Some(v) => match v.get_ref_matrix_values() {
                 Some(v) => v,
                 None => return Ok(vec![]),
@@ -338,21 +428,11 @@ impl Engine {
             None => return Ok(vec![]),
         };
 
-        // Evaluation timestamp --- end of the time window.
-        let eval_ts = self.time;
+        // Evaluation timestamp --- end of the time window, pinned by the `@` modifier.
+        let eval_ts = self.eval_time(&selector.at);
         // Start of the time window.
         let start = eval_ts - micros(range); // e.g. [5m]
-        let mut offset_modifier = 0;
-        if let Some(offset) = selector.offset.clone() {
-            match offset {
-                Offset::Pos(offset) => {
-                    offset_modifier = micros(offset);
-                }
-                Offset::Neg(offset) => {
-                    offset_modifier = -micros(offset);
-                }
-            }
-        };
+        let offset_modifier = offset_micros(&selector.offset);
 
         let mut values = Vec::with_capacity(metrics_cache.len());
         for metric in metrics_cache {
@@ -365,9 +445,19 @@ impl Engine {
                 })
                 .filter(|v| start < v.timestamp && v.timestamp <= eval_ts)
                 .collect();
+            let histograms = metric
+                .histograms
+                .iter()
+                .map(|h| HistogramSample {
+                    timestamp: h.timestamp + offset_modifier,
+                    histogram: h.histogram.clone(),
+                })
+                .filter(|h| start < h.timestamp && h.timestamp <= eval_ts)
+                .collect();
             values.push(RangeValue {
                 labels: metric.labels.clone(),
                 samples,
+                histograms,
                 time_window: Some(TimeWindow::new(eval_ts, range)),
             });
         }
@@ -375,29 +465,121 @@ impl Engine {
         Ok(values)
     }
 
+    /// Subquery --- evaluate the inner expression at every step of the subquery range
+    /// and return the results as a range vector, e.g. `max_over_time(rate(x[5m])[1h:1m])`.
+    ///
+    /// See <https://prometheus.io/blog/2019/01/28/subquery-support/>
+    async fn eval_subquery(&mut self, expr: &SubqueryExpr) -> Result<Vec<RangeValue>> {
+        if self.result_type.is_none() {
+            self.result_type = Some("matrix".to_string());
+        }
+
+        let offset = offset_micros(&expr.offset);
+        let range = micros(expr.range);
+        let step = micros(expr.step.unwrap_or(DEFAULT_SUBQUERY_STEP));
+        if step <= 0 {
+            return Err(DataFusionError::Plan(
+                "subquery step must be greater than zero".to_string(),
+            ));
+        }
+
+        // Evaluation timestamp --- end of the time window, pinned by the `@` modifier.
+        let eval_ts = self.eval_time(&expr.at);
+        // The inner expression is evaluated at multiples of the step in (end - range, end].
+        let end = eval_ts - offset;
+        let start = end - range;
+        let first_step = start - start.rem_euclid(step) + step;
+
+        // Load the data of the inner selectors once for all evaluations of this subquery.
+        let window = match expr.at {
+            Some(_) => (start, end),
+            None => (self.window.0 - offset - range, self.window.1 - offset),
+        };
+
+        let mut series: HashMap<Signature, RangeValue> = HashMap::default();
+        let mut time = first_step;
+        while time <= end {
+            let mut engine = Engine {
+                ctx: self.ctx.clone(),
+                time,
+                window,
+                result_type: None,
+            };
+            let values = match engine.exec_expr(&expr.expr).await? {
+                Value::Vector(vs) => vs
+                    .into_iter()
+                    .map(|v| (v.labels, v.sample.value))
+                    .collect(),
+                Value::Instant(v) => vec![(v.labels, v.sample.value)],
+                Value::Sample(s) => vec![(Labels::default(), s.value)],
+                Value::Float(val) => vec![(Labels::default(), val)],
+                Value::None => vec![],
+                v => {
+                    return Err(DataFusionError::NotImplemented(format!(
+                        "Unsupported subquery, the inner expression should return an instant vector but got {:?}",
+                        v.get_type()
+                    )))
+                }
+            };
+            for (labels, value) in values {
+                series
+                    .entry(labels.signature())
+                    .or_insert_with(|| RangeValue::new(labels, Vec::new()))
+                    .samples
+                    // same as the samples of a range vector selector with offset
+                    .push(Sample::new(time + offset, value));
+            }
+            time += step;
+        }
+
+        let time_window = TimeWindow::new(eval_ts, expr.range);
+        Ok(series
+            .into_values()
+            .map(|mut v| {
+                v.time_window = Some(time_window.clone());
+                v
+            })
+            .collect())
+    }
+
+    /// Evaluation timestamp of a selector or subquery, taking its `@` modifier into account.
+    fn eval_time(&self, at: &Option<AtModifier>) -> i64 {
+        match at {
+            None => self.time,
+            Some(AtModifier::Start) => self.ctx.start,
+            Some(AtModifier::End) => self.ctx.end,
+            Some(AtModifier::At(t)) => at_micros(*t),
+        }
+    }
+
+    /// The loaded data depends on the whole selector and the time range, e.g.
+    /// `rate(x[1h]) / rate(x[5m])` and `x / x @ 1700000000` need different ranges of `x`.
+    fn cache_key(&self, selector: &VectorSelector, range: Option<Duration>) -> String {
+        format!(
+            "{:?}/{:?}/{}/{}",
+            selector, range, self.window.0, self.window.1
+        )
+    }
+
     #[tracing::instrument(name = "promql:engine:load_data", skip_all)]
     async fn selector_load_data(
         &mut self,
         selector: &VectorSelector,
         range: Option<Duration>,
+        cache_key: &str,
     ) -> Result<()> {
         // https://promlabs.com/blog/2020/07/02/selecting-data-in-promql/#lookback-delta
 
-        let mut start = self.ctx.start - range.map_or(self.ctx.lookback_delta, micros);
-        let mut end = self.ctx.end; // 30 minutes + 5m = 35m
-
-        if let Some(offset) = selector.offset.clone() {
-            match offset {
-                Offset::Pos(offset) => {
-                    start -= micros(offset);
-                    end -= micros(offset);
-                }
-                Offset::Neg(offset) => {
-                    start += micros(offset);
-                    end += micros(offset);
-                }
+        let (start, end) = match selector.at {
+            Some(_) => {
+                let eval_ts = self.eval_time(&selector.at);
+                (eval_ts, eval_ts)
             }
-        }
+            None => self.window,
+        };
+        let offset = offset_micros(&selector.offset);
+        let start = start - range.map_or(self.ctx.lookback_delta, micros) - offset;
+        let end = end - offset;
 
         // 1. Group by metrics (sets of label name-value pairs)
         let table_name = selector.name.as_ref().unwrap();
@@ -442,6 +624,7 @@ impl Engine {
                     .entry(key)
                     .or_insert_with(|| RangeValue::new(value.labels, Vec::with_capacity(20)));
                 metric.samples.extend(value.samples);
+                metric.histograms.extend(value.histograms);
             }
         }
 
@@ -451,7 +634,7 @@ impl Engine {
                 .data_cache
                 .write()
                 .await
-                .insert(table_name.to_string(), Value::None);
+                .insert(cache_key.to_string(), Value::None);
             return Ok(());
         }
 
@@ -459,6 +642,9 @@ impl Engine {
         let mut metric_values = metrics.into_values().collect::<Vec<_>>();
         for metric in metric_values.iter_mut() {
             metric.samples.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+            metric
+                .histograms
+                .sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
         }
         let values = if metric_values.is_empty() {
             Value::None
@@ -469,7 +655,7 @@ impl Engine {
             .data_cache
             .write()
             .await
-            .insert(table_name.to_string(), values);
+            .insert(cache_key.to_string(), values);
         Ok(())
     }
 
@@ -599,6 +785,17 @@ impl Engine {
             "year",
         ]);
         let input = match functions_without_args.contains(func.name) {
+            // evaluated on their native histograms, see `Self::eval_native_histograms`
+            _ if matches!(
+                func_name,
+                Func::HistogramCount
+                    | Func::HistogramFraction
+                    | Func::HistogramQuantile
+                    | Func::HistogramSum
+            ) =>
+            {
+                Value::None
+            }
             true => match args.len() {
                 0 => {
                     // Found no arg to pass to, lets use a `vector(time())` as the arg.
@@ -692,16 +889,20 @@ impl Engine {
             Func::Exp => functions::exp(&input)?,
             Func::Floor => functions::floor(&input)?,
             Func::HistogramCount => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
+                let data = self.eval_native_histograms(&args.args[0]).await?;
+                functions::histogram_count(self.time, data)
             }
             Func::HistogramFraction => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
+                let err = "Invalid args, expected \"histogram_fraction(lower scalar, upper scalar, v instant-vector)\"";
+                self.ensure_three_args(args, err)?;
+
+                let lower = self.call_expr_first_arg(args).await?;
+                let upper = self.call_expr_second_arg(args).await?;
+                let lower = self.parse_f64_else_err(&lower, err)?;
+                let upper = self.parse_f64_else_err(&upper, err)?;
+
+                let data = self.eval_native_histograms(&args.args[2]).await?;
+                functions::histogram_fraction(self.time, lower, upper, data)
             }
             Func::HistogramQuantile => {
                 let args = &args.args;
@@ -724,13 +925,19 @@ impl Engine {
                     }
                 };
                 let sample_time = self.time;
-                functions::histogram_quantile(sample_time, phi, input)?
+                // the native histograms are evaluated first, the float samples are only
+                // evaluated as classic histogram buckets when there are none
+                let native = self.eval_native_histograms(&args[1]).await?;
+                let input = if native.is_empty() {
+                    self.exec_expr(&args[1]).await?
+                } else {
+                    Value::None
+                };
+                functions::histogram_quantile(sample_time, phi, input, native)?
             }
             Func::HistogramSum => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
+                let data = self.eval_native_histograms(&args.args[0]).await?;
+                functions::histogram_sum(self.time, data)
             }
             Func::HoltWinters => {
                 let err =
@@ -806,6 +1013,7 @@ impl Engine {
             Func::MinOverTime => functions::min_over_time(&input)?,
             Func::Minute => functions::minute(&input)?,
             Func::Month => functions::month(&input)?,
+            Func::PresentOverTime => functions::present_over_time(&input)?,
             Func::PredictLinear => {
                 let err = "Invalid args, expected \"predict_linear(v range-vector, t scalar)\"";
 
@@ -847,18 +1055,8 @@ impl Engine {
                 }
             },
             Func::Sgn => functions::sgn(&input)?,
-            Func::Sort => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::SortDesc => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
+            Func::Sort => functions::sort(&input, false)?,
+            Func::SortDesc => functions::sort(&input, true)?,
             Func::Sqrt => functions::sqrt(&input)?,
             Func::StddevOverTime => functions::stddev_over_time(&input)?,
             Func::StdvarOverTime => functions::stdvar_over_time(&input)?,
@@ -891,6 +1089,23 @@ impl Engine {
     }
 }
 
+/// Converts `t` to the number of microseconds since the Unix epoch, `@` modifiers can
+/// refer to times earlier than the epoch.
+fn at_micros(t: SystemTime) -> i64 {
+    match t.duration_since(UNIX_EPOCH) {
+        Ok(d) => micros(d),
+        Err(e) => -micros(e.duration()),
+    }
+}
+
+fn offset_micros(offset: &Option<Offset>) -> i64 {
+    match offset {
+        Some(Offset::Pos(offset)) => micros(*offset),
+        Some(Offset::Neg(offset)) => -micros(*offset),
+        None => 0,
+    }
+}
+
 async fn selector_load_data_from_datafusion(
     ctx: SessionContext,
     schema: Arc<Schema>,
@@ -966,6 +1181,7 @@ async fn selector_load_data_from_datafusion(
             .as_any()
             .downcast_ref::<Float64Array>()
             .unwrap();
+        let mut histograms = load_native_histograms(batch)?;
         for i in 0..batch.num_rows() {
             let hash = hash_values.value(i).to_string();
             let entry = metrics.entry(hash).or_insert_with(|| {
@@ -975,6 +1191,7 @@ async fn selector_load_data_from_datafusion(
                     if name == &CONFIG.common.column_timestamp
                         || name == HASH_LABEL
                         || name == VALUE_LABEL
+                        || HISTOGRAM_LABELS.contains(&name.as_str())
                     {
                         continue;
                     }
@@ -990,7 +1207,75 @@ async fn selector_load_data_from_datafusion(
             entry
                 .samples
                 .push(Sample::new(time_values.value(i), value_values.value(i)));
+            if let Some(histogram) = histograms.get_mut(i).and_then(Option::take) {
+                entry.histograms.push(HistogramSample {
+                    timestamp: time_values.value(i),
+                    histogram: Arc::new(histogram),
+                });
+            }
         }
     }
     Ok(metrics)
 }
+
+/// Reads the native histogram columns of a batch, `None` for the rows of float samples.
+fn load_native_histograms(batch: &RecordBatch) -> Result<Vec<Option<NativeHistogram>>> {
+    let mut histograms = vec![None; batch.num_rows()];
+    let (
+        Some(schemas),
+        Some(zero_thresholds),
+        Some(zero_counts),
+        Some(sums),
+        Some(positives),
+        Some(negatives),
+    ) = (
+        float_column(batch, HISTOGRAM_SCHEMA_LABEL)?,
+        float_column(batch, HISTOGRAM_ZERO_THRESHOLD_LABEL)?,
+        float_column(batch, HISTOGRAM_ZERO_COUNT_LABEL)?,
+        float_column(batch, HISTOGRAM_SUM_LABEL)?,
+        string_column(batch, HISTOGRAM_POSITIVE_LABEL),
+        string_column(batch, HISTOGRAM_NEGATIVE_LABEL),
+    )
+    else {
+        return Ok(histograms);
+    };
+    let counts = float_column(batch, VALUE_LABEL)?.unwrap();
+    for (i, histogram) in histograms.iter_mut().enumerate() {
+        if schemas.is_null(i) {
+            continue;
+        }
+        let buckets = |values: &StringArray| -> Result<Vec<(i32, f64)>> {
+            if values.is_null(i) {
+                return Ok(vec![]);
+            }
+            json::from_str(values.value(i)).map_err(|e| {
+                DataFusionError::Execution(format!("invalid native histogram buckets: {e}"))
+            })
+        };
+        *histogram = Some(NativeHistogram {
+            schema: schemas.value(i) as i32,
+            zero_threshold: zero_thresholds.value(i),
+            zero_count: zero_counts.value(i),
+            count: counts.value(i),
+            sum: sums.value(i),
+            positive_buckets: buckets(&positives)?,
+            negative_buckets: buckets(&negatives)?,
+        });
+    }
+    Ok(histograms)
+}
+
+/// Numeric columns are inferred as integers or floats, depending on the first record.
+fn float_column(batch: &RecordBatch, name: &str) -> Result<Option<Float64Array>> {
+    let Some(values) = batch.column_by_name(name) else {
+        return Ok(None);
+    };
+    let values = cast(values, &DataType::Float64)?;
+    Ok(values.as_any().downcast_ref::<Float64Array>().cloned())
+}
+
+fn string_column(batch: &RecordBatch, name: &str) -> Option<StringArray> {
+    batch
+        .column_by_name(name)
+        .and_then(|values| values.as_any().downcast_ref::<StringArray>().cloned())
+}
@@ -14,7 +14,7 @@
 
 use ahash::AHashMap as HashMap;
 use datafusion::error::Result;
-use promql_parser::parser::EvalStmt;
+use promql_parser::parser::{Call, EvalStmt, Expr as PromExpr, Extension, ParenExpr};
 use std::{
     sync::Arc,
     time::{Duration, SystemTime},
@@ -23,7 +23,7 @@ use tokio::sync::RwLock;
 
 use crate::common::meta::stream::ScanStats;
 use crate::service::promql::{
-    micros, micros_since_epoch, value::*, TableProvider, DEFAULT_LOOKBACK,
+    extension::SortByLabel, micros, micros_since_epoch, value::*, TableProvider, DEFAULT_LOOKBACK,
 };
 
 #[derive(Clone)]
@@ -38,7 +38,7 @@ pub struct Query {
     pub interval: i64,
     /// Default look back from sample search.
     pub lookback_delta: i64,
-    /// key — metric name; value — time series data
+    /// key — selector and time range; value — time series data
     pub data_cache: Arc<RwLock<HashMap<String, Value>>>,
     pub scan_stats: Arc<RwLock<ScanStats>>,
     pub timeout: u64, // seconds, query timeout
@@ -90,7 +90,16 @@ impl Query {
             if let Value::Float(val) = value {
                 value = Value::Sample(Sample::new(self.end, val));
             }
-            value.sort();
+            // samples selected with the `@` modifier are reported at the evaluation time
+            if let Value::Vector(vs) = &mut value {
+                for v in vs.iter_mut() {
+                    v.sample.timestamp = self.end;
+                }
+            }
+            // the sort functions define the order of the result
+            if !is_sorted_by_expr(&expr) {
+                value.sort();
+            }
             if result_type_exec.is_some() {
                 result_type = result_type_exec;
             }
@@ -118,13 +127,13 @@ impl Query {
                 result_type = result_type_exec;
             }
             match result {
-                Value::Instant(v) => {
-                    instant_vectors.push(RangeValue::new(v.labels.to_owned(), [v.sample]))
-                }
-                Value::Vector(vs) => instant_vectors.extend(
-                    vs.into_iter()
-                        .map(|v| RangeValue::new(v.labels.to_owned(), [v.sample])),
-                ),
+                Value::Instant(v) => instant_vectors.push(RangeValue::new(
+                    v.labels.to_owned(),
+                    [Sample::new(time, v.sample.value)],
+                )),
+                Value::Vector(vs) => instant_vectors.extend(vs.into_iter().map(|v| {
+                    RangeValue::new(v.labels.to_owned(), [Sample::new(time, v.sample.value)])
+                })),
                 Value::Range(v) => instant_vectors.push(v),
                 Value::Matrix(vs) => instant_vectors.extend(vs),
                 Value::Sample(s) => instant_vectors.push(RangeValue::new(Labels::default(), [s])),
@@ -172,3 +181,13 @@ impl Query {
         Ok((value, result_type, *self.scan_stats.read().await))
     }
 }
+
+/// Checks if the result order of an instant query is defined by a sort function.
+fn is_sorted_by_expr(expr: &PromExpr) -> bool {
+    match expr {
+        PromExpr::Paren(ParenExpr { expr }) => is_sorted_by_expr(expr),
+        PromExpr::Call(Call { func, .. }) => matches!(func.name, "sort" | "sort_desc"),
+        PromExpr::Extension(Extension { expr }) => expr.as_any().is::<SortByLabel>(),
+        _ => false,
+    }
+}
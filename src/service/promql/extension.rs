@@ -0,0 +1,591 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The PromQL parser doesn't know the `limitk` aggregation and the `sort_by_label`
+//! functions yet. Their calls are parsed here into extension nodes of its AST, which
+//! the engine evaluates. The query around a call is parsed with a selector of a
+//! metric name the query doesn't contain in its place, which is then replaced by the
+//! node of the call.
+
+use promql_parser::{
+    label::Labels,
+    parser::{
+        self, AggregateExpr, BinaryExpr, Call, Expr as PromExpr, Extension, ExtensionExpr,
+        LabelModifier, MatrixSelector, ParenExpr, SubqueryExpr, UnaryExpr, ValueType,
+        VectorSelector,
+    },
+};
+use std::{any::Any, sync::Arc};
+
+use crate::common::meta::prom::NAME_LABEL;
+
+/// `limitk [by|without (...)] (k, v)`
+#[derive(Debug)]
+pub(crate) struct LimitK {
+    pub(crate) modifier: Option<LabelModifier>,
+    /// `k` and `v`
+    args: [PromExpr; 2],
+}
+
+impl LimitK {
+    pub(crate) fn param(&self) -> &PromExpr {
+        &self.args[0]
+    }
+
+    pub(crate) fn expr(&self) -> &PromExpr {
+        &self.args[1]
+    }
+}
+
+impl ExtensionExpr for LimitK {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "limitk"
+    }
+
+    fn value_type(&self) -> ValueType {
+        ValueType::Vector
+    }
+
+    fn children(&self) -> &[PromExpr] {
+        &self.args
+    }
+}
+
+/// `sort_by_label[_desc](v, label, ...)`
+#[derive(Debug)]
+pub(crate) struct SortByLabel {
+    pub(crate) desc: bool,
+    /// The vector followed by the labels
+    args: Vec<PromExpr>,
+}
+
+impl SortByLabel {
+    pub(crate) fn expr(&self) -> &PromExpr {
+        &self.args[0]
+    }
+
+    pub(crate) fn labels(&self) -> &[PromExpr] {
+        &self.args[1..]
+    }
+}
+
+impl ExtensionExpr for SortByLabel {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        if self.desc {
+            "sort_by_label_desc"
+        } else {
+            "sort_by_label"
+        }
+    }
+
+    fn value_type(&self) -> ValueType {
+        ValueType::Vector
+    }
+
+    fn children(&self) -> &[PromExpr] {
+        &self.args
+    }
+}
+
+/// Parses a PromQL query, cf. the module documentation.
+pub fn parse(query: &str) -> Result<PromExpr, String> {
+    let calls = find_calls(query);
+    if calls.is_empty() {
+        return parser::parse(query);
+    }
+
+    let mut prefix = "__extension".to_string();
+    while query.contains(&prefix) {
+        prefix.push('_');
+    }
+    let mut placeholders = Vec::with_capacity(calls.len());
+    let mut outer = String::with_capacity(query.len());
+    let mut pos = 0;
+    for (i, call) in calls.iter().enumerate() {
+        let node = call.parse(query)?;
+        let name = format!("{prefix}{i}__");
+        outer.push_str(&query[pos..call.start]);
+        outer.push_str(&name);
+        pos = call.end;
+        placeholders.push(Placeholder {
+            name,
+            text: &query[call.start..call.end],
+            node: Some(node),
+        });
+    }
+    outer.push_str(&query[pos..]);
+
+    let mut expr = parser::parse(&outer).map_err(|e| {
+        placeholders
+            .iter()
+            .fold(e, |e, p| e.replace(&p.name, p.text))
+    })?;
+    replace_placeholders(&mut expr, &mut placeholders)?;
+    Ok(expr)
+}
+
+struct Placeholder<'a> {
+    name: String,
+    /// The text of the call
+    text: &'a str,
+    node: Option<PromExpr>,
+}
+
+fn replace_placeholders(
+    expr: &mut PromExpr,
+    placeholders: &mut [Placeholder],
+) -> Result<(), String> {
+    match expr {
+        PromExpr::Aggregate(AggregateExpr { expr, param, .. }) => {
+            replace_placeholders(expr, placeholders)?;
+            if let Some(param) = param {
+                replace_placeholders(param, placeholders)?;
+            }
+        }
+        PromExpr::Unary(UnaryExpr { expr })
+        | PromExpr::Paren(ParenExpr { expr })
+        | PromExpr::Subquery(SubqueryExpr { expr, .. }) => {
+            replace_placeholders(expr, placeholders)?
+        }
+        PromExpr::Binary(BinaryExpr { lhs, rhs, .. }) => {
+            replace_placeholders(lhs, placeholders)?;
+            replace_placeholders(rhs, placeholders)?;
+        }
+        PromExpr::Call(Call { args, .. }) => {
+            for arg in args.args.iter_mut() {
+                replace_placeholders(arg, placeholders)?;
+            }
+        }
+        PromExpr::MatrixSelector(MatrixSelector { vs, .. }) => {
+            if let Some(p) = find_placeholder(vs, placeholders) {
+                return Err(format!(
+                    "ranges only allowed for vector selectors, got: {}",
+                    p.text
+                ));
+            }
+        }
+        PromExpr::VectorSelector(vs) => {
+            let Some(p) = find_placeholder(vs, placeholders) else {
+                return Ok(());
+            };
+            if vs.offset.is_some() || vs.at.is_some() {
+                return Err(format!(
+                    "offset and @ modifiers must follow a selector or a subquery, got: {}",
+                    p.text
+                ));
+            }
+            if vs.matchers.matchers.iter().any(|m| m.name != NAME_LABEL) {
+                return Err(format!("unexpected label matchers after: {}", p.text));
+            }
+            *expr = p.node.take().unwrap();
+        }
+        PromExpr::NumberLiteral(_) | PromExpr::StringLiteral(_) | PromExpr::Extension(_) => {}
+    }
+    Ok(())
+}
+
+fn find_placeholder<'a, 'b>(
+    vs: &VectorSelector,
+    placeholders: &'a mut [Placeholder<'b>],
+) -> Option<&'a mut Placeholder<'b>> {
+    let name = vs.name.as_deref()?;
+    placeholders.iter_mut().find(|p| p.name == name)
+}
+
+enum CallKind {
+    LimitK {
+        /// The range of the `by|without (...)` grouping
+        grouping: Option<(usize, usize)>,
+    },
+    SortByLabel {
+        desc: bool,
+    },
+}
+
+/// A call of a function the parser doesn't know, `start..end` in the query.
+struct ExtensionCall {
+    kind: CallKind,
+    start: usize,
+    end: usize,
+    /// The position of the parenthesis opening the arguments
+    args_open: usize,
+}
+
+impl ExtensionCall {
+    fn parse(&self, query: &str) -> Result<PromExpr, String> {
+        let args = split_args(query.as_bytes(), self.args_open)
+            .into_iter()
+            .map(|(start, end)| parse(&query[start..end]))
+            .collect::<Result<Vec<_>, _>>()?;
+        let node: Arc<dyn ExtensionExpr> = match self.kind {
+            CallKind::LimitK { grouping } => {
+                let Ok([param, expr]) = <[PromExpr; 2]>::try_from(args) else {
+                    return Err(format!(
+                        "expected 2 arguments in limitk, got: {}",
+                        &query[self.start..self.end]
+                    ));
+                };
+                if param.value_type() != ValueType::Scalar {
+                    return Err("expected type scalar in limitk parameter".to_string());
+                }
+                if expr.value_type() != ValueType::Vector {
+                    return Err("expected type instant vector in limitk expression".to_string());
+                }
+                let modifier = match grouping {
+                    Some((start, end)) => Some(parse_grouping(&query[start..end])?),
+                    None => None,
+                };
+                Arc::new(LimitK {
+                    modifier,
+                    args: [param, expr],
+                })
+            }
+            CallKind::SortByLabel { desc } => {
+                let node = SortByLabel { desc, args };
+                let name = node.name().to_string();
+                match node.args.first() {
+                    Some(expr) if expr.value_type() == ValueType::Vector => {}
+                    _ => {
+                        return Err(format!(
+                            "expected type instant vector in call to function \"{name}\""
+                        ))
+                    }
+                }
+                if node
+                    .labels()
+                    .iter()
+                    .any(|label| !matches!(label, PromExpr::StringLiteral(_)))
+                {
+                    return Err(format!(
+                        "expected label names as strings in call to function \"{name}\""
+                    ));
+                }
+                Arc::new(node)
+            }
+        };
+        Ok(PromExpr::Extension(Extension { expr: node }))
+    }
+}
+
+/// Parses `by (...)` or `without (...)`.
+fn parse_grouping(text: &str) -> Result<LabelModifier, String> {
+    let (include, labels) = match text.split_once('(') {
+        Some((keyword, labels)) if keyword.trim() == "by" => (true, labels),
+        Some((keyword, labels)) if keyword.trim() == "without" => (false, labels),
+        _ => return Err(format!("unexpected grouping: {text}")),
+    };
+    let mut names = vec![];
+    for name in labels.trim_end_matches(')').split(',').map(str::trim) {
+        if name.is_empty() {
+            continue;
+        }
+        if name.starts_with(|c: char| c.is_ascii_digit())
+            || !name.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'_')
+        {
+            return Err(format!("unexpected label name in grouping: {name}"));
+        }
+        names.push(name.to_string());
+    }
+    let labels = Labels { labels: names };
+    Ok(if include {
+        LabelModifier::Include(labels)
+    } else {
+        LabelModifier::Exclude(labels)
+    })
+}
+
+/// Returns the outermost calls of `limitk` and `sort_by_label[_desc]`, the calls in
+/// their arguments are found when those are parsed.
+fn find_calls(query: &str) -> Vec<ExtensionCall> {
+    let bytes = query.as_bytes();
+    let mut calls = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' | b'\'' | b'`' => i = skip_string(bytes, i),
+            b'#' => i = skip_comment(bytes, i),
+            c if is_ident_start(c) => {
+                let start = i;
+                while i < bytes.len() && is_ident_char(bytes[i]) {
+                    i += 1;
+                }
+                let call = match &query[start..i] {
+                    "limitk" => limitk_call(bytes, start, i),
+                    "sort_by_label" => sort_by_label_call(bytes, start, i, false),
+                    "sort_by_label_desc" => sort_by_label_call(bytes, start, i, true),
+                    _ => None,
+                };
+                if let Some(call) = call {
+                    i = call.end;
+                    calls.push(call);
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    calls
+}
+
+/// `limitk [by|without (...)] (k, v) [by|without (...)]`
+fn limitk_call(bytes: &[u8], start: usize, end: usize) -> Option<ExtensionCall> {
+    let mut grouping = None;
+    let mut pos = skip_whitespace(bytes, end);
+    if let Some(close) = grouping_labels(bytes, pos) {
+        grouping = Some((pos, close + 1));
+        pos = skip_whitespace(bytes, close + 1);
+    }
+    if bytes.get(pos) != Some(&b'(') {
+        // e.g. a metric or label named `limitk`
+        return None;
+    }
+    let args_open = pos;
+    let mut end = closing_paren(bytes, args_open)? + 1;
+    if grouping.is_none() {
+        let pos = skip_whitespace(bytes, end);
+        if let Some(close) = grouping_labels(bytes, pos) {
+            grouping = Some((pos, close + 1));
+            end = close + 1;
+        }
+    }
+    Some(ExtensionCall {
+        kind: CallKind::LimitK { grouping },
+        start,
+        end,
+        args_open,
+    })
+}
+
+/// `sort_by_label[_desc](v, label, ...)`
+fn sort_by_label_call(bytes: &[u8], start: usize, end: usize, desc: bool) -> Option<ExtensionCall> {
+    let args_open = skip_whitespace(bytes, end);
+    if bytes.get(args_open) != Some(&b'(') {
+        return None;
+    }
+    let end = closing_paren(bytes, args_open)? + 1;
+    Some(ExtensionCall {
+        kind: CallKind::SortByLabel { desc },
+        start,
+        end,
+        args_open,
+    })
+}
+
+/// Returns the position of the closing parenthesis of `by (...)` or `without (...)`
+/// starting at `pos`.
+fn grouping_labels(bytes: &[u8], pos: usize) -> Option<usize> {
+    let mut end = pos;
+    while end < bytes.len() && is_ident_char(bytes[end]) {
+        end += 1;
+    }
+    if !matches!(&bytes[pos..end], b"by" | b"without") {
+        return None;
+    }
+    let open = skip_whitespace(bytes, end);
+    if bytes.get(open) != Some(&b'(') {
+        return None;
+    }
+    closing_paren(bytes, open)
+}
+
+fn closing_paren(bytes: &[u8], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = open;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' | b'\'' | b'`' => {
+                i = skip_string(bytes, i);
+                continue;
+            }
+            b'#' => {
+                i = skip_comment(bytes, i);
+                continue;
+            }
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Returns the ranges of the arguments of the call whose parenthesis opens at `open`.
+fn split_args(bytes: &[u8], open: usize) -> Vec<(usize, usize)> {
+    let mut args = vec![];
+    let mut depth = 0;
+    let mut start = open + 1;
+    let mut i = open + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' | b'\'' | b'`' => {
+                i = skip_string(bytes, i);
+                continue;
+            }
+            b'#' => {
+                i = skip_comment(bytes, i);
+                continue;
+            }
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' if depth == 0 => break,
+            b')' | b']' | b'}' => depth -= 1,
+            b',' if depth == 0 => {
+                args.push((start, i));
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    if !bytes[start..i].iter().all(u8::is_ascii_whitespace) || !args.is_empty() {
+        args.push((start, i));
+    }
+    args
+}
+
+fn skip_string(bytes: &[u8], start: usize) -> usize {
+    let quote = bytes[start];
+    let mut i = start + 1;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && quote != b'`' {
+            i += 2;
+            continue;
+        }
+        if bytes[i] == quote {
+            return i + 1;
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+fn skip_comment(bytes: &[u8], start: usize) -> usize {
+    bytes[start..]
+        .iter()
+        .position(|c| *c == b'\n')
+        .map_or(bytes.len(), |i| start + i)
+}
+
+fn skip_whitespace(bytes: &[u8], start: usize) -> usize {
+    bytes[start.min(bytes.len())..]
+        .iter()
+        .position(|c| !c.is_ascii_whitespace())
+        .map_or(bytes.len(), |i| start + i)
+}
+
+fn is_ident_start(c: u8) -> bool {
+    c.is_ascii_alphabetic() || c == b'_' || c == b':'
+}
+
+fn is_ident_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c == b':'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extension(expr: &PromExpr) -> &dyn ExtensionExpr {
+        match expr {
+            PromExpr::Extension(Extension { expr }) => expr.as_ref(),
+            _ => unreachable!("{expr:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_limitk() {
+        let expr = parse("limitk(2, x)").unwrap();
+        let limitk = extension(&expr).as_any().downcast_ref::<LimitK>().unwrap();
+        assert!(limitk.modifier.is_none());
+        assert!(matches!(limitk.param(), PromExpr::NumberLiteral(_)));
+        assert!(matches!(limitk.expr(), PromExpr::VectorSelector(_)));
+
+        for query in ["limitk by (job, ) (2, x)", "limitk(2, x) by (job)"] {
+            let expr = parse(query).unwrap();
+            let limitk = extension(&expr).as_any().downcast_ref::<LimitK>().unwrap();
+            match &limitk.modifier {
+                Some(LabelModifier::Include(labels)) => assert_eq!(labels.labels, ["job"]),
+                _ => unreachable!("{query}"),
+            }
+        }
+
+        let expr = parse("sum(limitk(1, x{job=\"limitk(\"})) + limitk(1, limitk(2, y))").unwrap();
+        let PromExpr::Binary(BinaryExpr { lhs, rhs, .. }) = expr else {
+            unreachable!()
+        };
+        let PromExpr::Aggregate(AggregateExpr { expr, .. }) = lhs.as_ref() else {
+            unreachable!()
+        };
+        assert_eq!(extension(expr).name(), "limitk");
+        let limitk = extension(&rhs).as_any().downcast_ref::<LimitK>().unwrap();
+        assert_eq!(extension(limitk.expr()).name(), "limitk");
+
+        // not calls
+        assert!(matches!(parse("limitk + 1"), Ok(PromExpr::Binary(_))));
+        assert!(matches!(
+            parse("x{__limitk__=\"a\", limitk=\"b\"}"),
+            Ok(PromExpr::VectorSelector(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_sort_by_label() {
+        let expr = parse("sort_by_label_desc(rate(x[5m]), \"a\", \"b\")").unwrap();
+        let sort = extension(&expr)
+            .as_any()
+            .downcast_ref::<SortByLabel>()
+            .unwrap();
+        assert!(sort.desc);
+        assert!(matches!(sort.expr(), PromExpr::Call(_)));
+        assert_eq!(sort.labels().len(), 2);
+
+        let expr = parse("sort_by_label(x, \"job\")").unwrap();
+        assert_eq!(extension(&expr).name(), "sort_by_label");
+        assert!(matches!(
+            parse("x{sort_by_label=\"a\"}"),
+            Ok(PromExpr::VectorSelector(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for query in [
+            "limitk(x)",
+            "limitk(x, 2)",
+            "limitk(2, x)[5m]",
+            "limitk(2, x) offset 5m",
+            "limitk(2, x){job=\"a\"}",
+            "limitk by (1a) (2, x)",
+            "sort_by_label(1, \"a\")",
+            "sort_by_label(x, 1)",
+            "sort_by_label()",
+            "rate(limitk(2, x))",
+        ] {
+            let err = parse(query).unwrap_err();
+            assert!(!err.contains("__extension"), "{query}: {err}");
+        }
+    }
+}
@@ -14,11 +14,13 @@
 
 use ahash::AHashMap as HashMap;
 use datafusion::error::{DataFusionError, Result};
+use std::collections::BTreeMap;
 
 use crate::{
-    common::meta::prom::{HASH_LABEL, LE_LABEL, NAME_LABEL},
+    common::meta::prom::{NativeHistogram, HASH_LABEL, LE_LABEL, NAME_LABEL},
     service::promql::value::{
-        signature_without_labels, InstantValue, Labels, LabelsExt, Sample, Signature, Value,
+        extrapolation_factor, range_bounds, signature_without_labels, ExtrapolationKind,
+        HistogramValue, InstantValue, Labels, LabelsExt, RangeValue, Sample, Signature, Value,
     },
 };
 
@@ -43,13 +45,17 @@ struct MetricWithBuckets {
     buckets: Vec<Bucket>,
 }
 
-/// TODO: support native histograms; see [`histogramQuantile`]
-///
-/// [`histogramQuantile`]: https://github.com/prometheus/prometheus/blob/f7c6130ff27a2a12412c02cce223f7a8abc59e49/promql/quantile.go#L146
-pub(crate) fn histogram_quantile(sample_time: i64, phi: f64, data: Value) -> Result<Value> {
+/// `histogram_quantile` of the classic histograms in `data` and of the `native` histograms.
+pub(crate) fn histogram_quantile(
+    sample_time: i64,
+    phi: f64,
+    data: Value,
+    native: Vec<HistogramValue>,
+) -> Result<Value> {
     let in_vec = match data {
         Value::Vector(v) => v,
-        Value::None => return Ok(Value::None),
+        Value::None if native.is_empty() => return Ok(Value::None),
+        Value::None => vec![],
         _ => {
             return Err(DataFusionError::Plan(
                 "histogram_quantile: vector argument expected".to_owned(),
@@ -85,7 +91,7 @@ pub(crate) fn histogram_quantile(sample_time: i64, phi: f64, data: Value) -> Res
         });
     }
 
-    let values = metrics_with_buckets
+    let mut values: Vec<InstantValue> = metrics_with_buckets
         .into_values()
         .filter(|bucket| !bucket.buckets.is_empty())
         .map(|mb| InstantValue {
@@ -93,10 +99,265 @@ pub(crate) fn histogram_quantile(sample_time: i64, phi: f64, data: Value) -> Res
             sample: Sample::new(sample_time, bucket_quantile(phi, mb.buckets)),
         })
         .collect();
+    values.extend(native.into_iter().map(|v| InstantValue {
+        labels: v.labels.without_metric_name(),
+        sample: Sample::new(sample_time, native_histogram_quantile(phi, &v.histogram)),
+    }));
 
     Ok(Value::Vector(values))
 }
 
+/// `histogram_count` returns the count of observations of native histograms.
+pub(crate) fn histogram_count(sample_time: i64, data: Vec<HistogramValue>) -> Value {
+    native_histogram_values(sample_time, data, |h| h.count)
+}
+
+/// `histogram_sum` returns the sum of observations of native histograms.
+pub(crate) fn histogram_sum(sample_time: i64, data: Vec<HistogramValue>) -> Value {
+    native_histogram_values(sample_time, data, |h| h.sum)
+}
+
+/// `histogram_fraction` returns the estimated fraction of observations of native
+/// histograms between `lower` and `upper`.
+pub(crate) fn histogram_fraction(
+    sample_time: i64,
+    lower: f64,
+    upper: f64,
+    data: Vec<HistogramValue>,
+) -> Value {
+    native_histogram_values(sample_time, data, |h| {
+        native_histogram_fraction(lower, upper, h)
+    })
+}
+
+fn native_histogram_values(
+    sample_time: i64,
+    data: Vec<HistogramValue>,
+    f: impl Fn(&NativeHistogram) -> f64,
+) -> Value {
+    let values = data
+        .into_iter()
+        .map(|v| InstantValue {
+            labels: v.labels.without_metric_name(),
+            sample: Sample::new(sample_time, f(&v.histogram)),
+        })
+        .collect();
+    Value::Vector(values)
+}
+
+/// `histogram_rate` calculates `rate`, `increase` or `delta` of the native histogram
+/// samples of a series, bucket by bucket, see [`extrapolated_rate`].
+///
+/// [`extrapolated_rate`]: crate::service::promql::value::extrapolated_rate
+//
+// cf. https://github.com/prometheus/prometheus/blob/f7c6130ff27a2a12412c02cce223f7a8abc59e49/promql/functions.go#L163
+pub(crate) fn histogram_rate(
+    series: &RangeValue,
+    kind: &ExtrapolationKind,
+) -> Option<NativeHistogram> {
+    let tw = series.time_window.as_ref()?;
+    let samples = &series.histograms;
+    if samples.len() < 2 {
+        return None;
+    }
+    let first = &samples[0];
+    let last = samples.last().unwrap();
+
+    let mut result = add_histograms(&last.histogram, &first.histogram, -1.0);
+    if matches!(kind, ExtrapolationKind::Rate | ExtrapolationKind::Increase) {
+        // Any decreasing count is a counter reset.
+        for pair in samples.windows(2) {
+            let diff = add_histograms(&pair[1].histogram, &pair[0].histogram, -1.0);
+            if has_negative_counts(&diff) {
+                result = add_histograms(&result, &pair[0].histogram, 1.0);
+            }
+        }
+    }
+
+    let factor = extrapolation_factor(
+        (first.timestamp, last.timestamp),
+        samples.len(),
+        range_bounds(tw.eval_ts, tw.range, tw.offset),
+        tw.range,
+        None,
+        kind,
+    );
+    Some(scale_histogram(&result, factor))
+}
+
+/// Returns `a + b * factor`. The buckets of the result use the coarser schema and the
+/// wider zero bucket of both histograms.
+pub(crate) fn add_histograms(
+    a: &NativeHistogram,
+    b: &NativeHistogram,
+    factor: f64,
+) -> NativeHistogram {
+    let schema = a.schema.min(b.schema);
+    let zero_threshold = a.zero_threshold.max(b.zero_threshold);
+    let mut zero_count = 0.0;
+    let mut positive = BTreeMap::new();
+    let mut negative = BTreeMap::new();
+    for (h, factor) in [(a, 1.0), (b, factor)] {
+        zero_count += h.zero_count * factor;
+        for (buckets, target) in [
+            (&h.positive_buckets, &mut positive),
+            (&h.negative_buckets, &mut negative),
+        ] {
+            for &(index, count) in buckets.iter() {
+                // cf. https://github.com/prometheus/prometheus/blob/f7c6130ff27a2a12412c02cce223f7a8abc59e49/model/histogram/generic.go#L634
+                let index = ((index - 1) >> (h.schema - schema)) + 1;
+                if bucket_bound(schema, index) <= zero_threshold {
+                    zero_count += count * factor;
+                } else {
+                    *target.entry(index).or_insert(0.0) += count * factor;
+                }
+            }
+        }
+    }
+    NativeHistogram {
+        schema,
+        zero_threshold,
+        zero_count,
+        count: a.count + b.count * factor,
+        sum: a.sum + b.sum * factor,
+        positive_buckets: positive.into_iter().filter(|(_, c)| *c != 0.0).collect(),
+        negative_buckets: negative.into_iter().filter(|(_, c)| *c != 0.0).collect(),
+    }
+}
+
+fn scale_histogram(h: &NativeHistogram, factor: f64) -> NativeHistogram {
+    let empty = NativeHistogram {
+        schema: h.schema,
+        zero_threshold: h.zero_threshold,
+        ..Default::default()
+    };
+    add_histograms(&empty, h, factor)
+}
+
+fn has_negative_counts(h: &NativeHistogram) -> bool {
+    h.count < 0.0
+        || h.zero_count < 0.0
+        || h.positive_buckets.iter().any(|(_, c)| *c < 0.0)
+        || h.negative_buckets.iter().any(|(_, c)| *c < 0.0)
+}
+
+/// A bucket of a native histogram, covering `(lower, upper]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct NativeBucket {
+    lower: f64,
+    upper: f64,
+    count: f64,
+}
+
+/// The upper bound of the positive bucket with the given index, `2^(index * 2^-schema)`.
+fn bucket_bound(schema: i32, index: i32) -> f64 {
+    (index as f64 * (-schema as f64).exp2()).exp2()
+}
+
+/// The non-empty buckets of a native histogram in ascending order: the negative
+/// buckets, the zero bucket and the positive buckets.
+fn native_buckets(h: &NativeHistogram) -> Vec<NativeBucket> {
+    let has_negative = h.negative_buckets.iter().any(|(_, c)| *c > 0.0);
+    let has_positive = h.positive_buckets.iter().any(|(_, c)| *c > 0.0);
+
+    let mut buckets = Vec::with_capacity(h.negative_buckets.len() + h.positive_buckets.len() + 1);
+    for &(index, count) in h.negative_buckets.iter().rev() {
+        if count > 0.0 {
+            buckets.push(NativeBucket {
+                lower: -bucket_bound(h.schema, index),
+                upper: -bucket_bound(h.schema, index - 1),
+                count,
+            });
+        }
+    }
+    if h.zero_count > 0.0 {
+        // The zero bucket only covers one side if there are buckets on that side only.
+        let (lower, upper) = match (has_negative, has_positive) {
+            (false, true) => (0.0, h.zero_threshold),
+            (true, false) => (-h.zero_threshold, 0.0),
+            _ => (-h.zero_threshold, h.zero_threshold),
+        };
+        buckets.push(NativeBucket {
+            lower,
+            upper,
+            count: h.zero_count,
+        });
+    }
+    for &(index, count) in h.positive_buckets.iter() {
+        if count > 0.0 {
+            buckets.push(NativeBucket {
+                lower: bucket_bound(h.schema, index - 1),
+                upper: bucket_bound(h.schema, index),
+                count,
+            });
+        }
+    }
+    buckets
+}
+
+// cf. https://github.com/prometheus/prometheus/blob/f7c6130ff27a2a12412c02cce223f7a8abc59e49/promql/quantile.go#L146
+fn native_histogram_quantile(phi: f64, h: &NativeHistogram) -> f64 {
+    if phi < 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if phi > 1.0 {
+        return f64::INFINITY;
+    }
+    if h.count == 0.0 || phi.is_nan() {
+        return f64::NAN;
+    }
+    let buckets = native_buckets(h);
+    let Some(mut bucket) = buckets.first().copied() else {
+        return f64::NAN;
+    };
+    let mut rank = phi * h.count;
+    let mut count = 0.0;
+    for b in buckets {
+        bucket = b;
+        count += b.count;
+        if count >= rank {
+            break;
+        }
+    }
+    // Interpolate linearly within the bucket.
+    rank -= count - bucket.count;
+    bucket.lower + (bucket.upper - bucket.lower) * (rank / bucket.count)
+}
+
+// cf. https://github.com/prometheus/prometheus/blob/f7c6130ff27a2a12412c02cce223f7a8abc59e49/promql/quantile.go#L214
+fn native_histogram_fraction(lower: f64, upper: f64, h: &NativeHistogram) -> f64 {
+    if h.count == 0.0 || lower.is_nan() || upper.is_nan() {
+        return f64::NAN;
+    }
+    if lower >= upper {
+        return 0.0;
+    }
+    let mut rank = 0.0;
+    let mut lower_rank = None;
+    let mut upper_rank = None;
+    for b in native_buckets(h) {
+        if lower_rank.is_none() && b.lower >= lower {
+            lower_rank = Some(rank);
+        }
+        if upper_rank.is_none() && b.lower >= upper {
+            upper_rank = Some(rank);
+        }
+        if lower_rank.is_none() && b.lower < lower && b.upper > lower {
+            lower_rank = Some(rank + b.count * (lower - b.lower) / (b.upper - b.lower));
+        }
+        if upper_rank.is_none() && b.lower < upper && b.upper > upper {
+            upper_rank = Some(rank + b.count * (upper - b.lower) / (b.upper - b.lower));
+        }
+        if lower_rank.is_some() && upper_rank.is_some() {
+            break;
+        }
+        rank += b.count;
+    }
+    let lower_rank = lower_rank.unwrap_or(h.count).min(h.count);
+    let upper_rank = upper_rank.unwrap_or(h.count).min(h.count);
+    (upper_rank - lower_rank) / h.count
+}
+
 // cf. https://github.com/prometheus/prometheus/blob/cf1bea344a3c390a90c35ea8764c4a468b345d5e/promql/quantile.go#L76
 fn bucket_quantile(phi: f64, mut buckets: Vec<Bucket>) -> f64 {
     if phi.is_nan() {
@@ -198,7 +459,132 @@ fn ensure_monotonic(buckets: &mut [Bucket]) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::service::promql::value::{HistogramSample, Label, TimeWindow};
     use expect_test::expect;
+    use float_cmp::approx_eq;
+    use std::sync::Arc;
+
+    fn native_histogram(schema: i32, positive_buckets: Vec<(i32, f64)>) -> NativeHistogram {
+        let count: f64 = positive_buckets.iter().map(|(_, c)| c).sum();
+        NativeHistogram {
+            schema,
+            zero_threshold: 0.001,
+            count,
+            sum: count * 2.5,
+            positive_buckets,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_native_histogram_quantile() {
+        // buckets (1, 2] and (2, 4]
+        let h = native_histogram(0, vec![(1, 4.0), (2, 4.0)]);
+        assert_eq!(native_histogram_quantile(0.0, &h), 1.0);
+        assert_eq!(native_histogram_quantile(0.5, &h), 2.0);
+        assert_eq!(native_histogram_quantile(0.75, &h), 3.0);
+        assert_eq!(native_histogram_quantile(1.0, &h), 4.0);
+        assert_eq!(native_histogram_quantile(1.5, &h), f64::INFINITY);
+        assert!(native_histogram_quantile(0.5, &NativeHistogram::default()).is_nan());
+
+        // the zero bucket covers [0, 0.001] without negative buckets
+        let mut h = native_histogram(0, vec![(1, 2.0)]);
+        h.zero_count = 2.0;
+        h.count = 4.0;
+        assert_eq!(native_histogram_quantile(0.25, &h), 0.0005);
+    }
+
+    #[test]
+    fn test_native_histogram_fraction() {
+        let h = native_histogram(0, vec![(1, 4.0), (2, 4.0)]);
+        assert_eq!(native_histogram_fraction(0.0, 2.0, &h), 0.5);
+        assert_eq!(native_histogram_fraction(1.5, 3.0, &h), 0.5);
+        assert_eq!(native_histogram_fraction(0.0, f64::INFINITY, &h), 1.0);
+        assert_eq!(native_histogram_fraction(3.0, 1.0, &h), 0.0);
+    }
+
+    #[test]
+    fn test_native_histogram_functions() {
+        let data = vec![HistogramValue {
+            labels: vec![
+                Arc::new(Label::new(NAME_LABEL, "http_request_duration_seconds")),
+                Arc::new(Label::new("job", "api")),
+            ],
+            histogram: native_histogram(0, vec![(1, 4.0), (2, 4.0)]),
+        }];
+        let values = |value: Value| match value {
+            Value::Vector(v) => v
+                .iter()
+                .map(|v| (v.labels.keys(), v.sample.value))
+                .collect::<Vec<_>>(),
+            v => panic!("unexpected value {v:?}"),
+        };
+        let job = vec!["job".to_string()];
+        assert_eq!(
+            values(histogram_count(1, data.clone())),
+            vec![(job.clone(), 8.0)]
+        );
+        assert_eq!(
+            values(histogram_sum(1, data.clone())),
+            vec![(job.clone(), 20.0)]
+        );
+        assert_eq!(
+            values(histogram_fraction(1, 0.0, 2.0, data.clone())),
+            vec![(job.clone(), 0.5)]
+        );
+        assert_eq!(
+            values(histogram_quantile(1, 0.75, Value::None, data).unwrap()),
+            vec![(job, 3.0)]
+        );
+    }
+
+    #[test]
+    fn test_add_histograms() {
+        // (1, 2^0.5], (2^0.5, 2], (2, 2^1.5], (2^1.5, 4] are merged into (1, 2], (2, 4]
+        let a = native_histogram(1, vec![(1, 1.0), (2, 1.0), (3, 1.0), (4, 1.0)]);
+        let b = native_histogram(0, vec![(1, 2.0)]);
+        let sum = add_histograms(&a, &b, 1.0);
+        assert_eq!(sum.schema, 0);
+        assert_eq!(sum.count, 6.0);
+        assert_eq!(sum.positive_buckets, vec![(1, 4.0), (2, 2.0)]);
+
+        let diff = add_histograms(&b, &a, -1.0);
+        assert!(has_negative_counts(&diff));
+        assert_eq!(diff.positive_buckets, vec![(2, -2.0)]);
+    }
+
+    #[test]
+    fn test_histogram_rate() {
+        // one observation every 10s, with a counter reset
+        let histograms = [1.0, 2.0, 3.0, 1.0, 2.0, 3.0]
+            .into_iter()
+            .enumerate()
+            .map(|(i, count)| HistogramSample {
+                timestamp: (70 + i as i64 * 10) * 1_000_000,
+                histogram: Arc::new(native_histogram(0, vec![(1, count)])),
+            })
+            .collect();
+        let series = RangeValue {
+            labels: Labels::default(),
+            samples: vec![],
+            histograms,
+            time_window: Some(TimeWindow::new(
+                120_000_000,
+                std::time::Duration::from_secs(60),
+            )),
+        };
+        let rate = histogram_rate(&series, &ExtrapolationKind::Rate).unwrap();
+        assert!(approx_eq!(f64, rate.count, 0.1, epsilon = 0.0001));
+        assert!(approx_eq!(
+            f64,
+            rate.positive_buckets[0].1,
+            0.1,
+            epsilon = 0.0001
+        ));
+
+        let increase = histogram_rate(&series, &ExtrapolationKind::Increase).unwrap();
+        assert!(approx_eq!(f64, increase.count, 6.0, epsilon = 0.0001));
+    }
 
     #[test]
     fn test_coalesce_buckets() {
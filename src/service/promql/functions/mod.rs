@@ -36,9 +36,11 @@ mod math_operations;
 mod max_over_time;
 mod min_over_time;
 mod predict_linear;
+mod present_over_time;
 mod quantile_over_time;
 mod rate;
 mod resets;
+mod sort;
 mod stddev_over_time;
 mod stdvar_over_time;
 mod sum_over_time;
@@ -53,7 +55,10 @@ pub(crate) use clamp::clamp;
 pub(crate) use count_over_time::count_over_time;
 pub(crate) use delta::delta;
 pub(crate) use deriv::deriv;
-pub(crate) use histogram::histogram_quantile;
+pub(crate) use histogram::{
+    add_histograms, histogram_count, histogram_fraction, histogram_quantile, histogram_rate,
+    histogram_sum,
+};
 pub(crate) use holt_winters::holt_winters;
 pub(crate) use idelta::idelta;
 pub(crate) use increase::increase;
@@ -65,9 +70,11 @@ pub(crate) use math_operations::*;
 pub(crate) use max_over_time::max_over_time;
 pub(crate) use min_over_time::min_over_time;
 pub(crate) use predict_linear::predict_linear;
+pub(crate) use present_over_time::present_over_time;
 pub(crate) use quantile_over_time::quantile_over_time;
 pub(crate) use rate::rate;
 pub(crate) use resets::resets;
+pub(crate) use sort::{sort, sort_by_label};
 pub(crate) use stddev_over_time::stddev_over_time;
 pub(crate) use stdvar_over_time::stdvar_over_time;
 use strum::EnumString;
@@ -119,6 +126,7 @@ pub(crate) enum Func {
     Minute,
     Month,
     PredictLinear,
+    PresentOverTime,
     QuantileOverTime,
     Rate,
     Resets,
@@ -0,0 +1,131 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use datafusion::error::{DataFusionError, Result};
+use std::cmp::Ordering;
+
+use crate::service::promql::value::{InstantValue, LabelsExt, Value};
+
+/// Sorts the vector by sample value, NaN values always come last.
+pub(crate) fn sort(data: &Value, desc: bool) -> Result<Value> {
+    let fn_name = if desc { "sort_desc" } else { "sort" };
+    let mut data = vector_arg(data, fn_name)?;
+    data.sort_by(|a, b| {
+        let (a, b) = (a.sample.value, b.sample.value);
+        match (a.is_nan(), b.is_nan()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            _ if desc => b.partial_cmp(&a).unwrap(),
+            _ => a.partial_cmp(&b).unwrap(),
+        }
+    });
+    Ok(Value::Vector(data))
+}
+
+/// Sorts the vector by the values of the given labels, ties are broken by the
+/// whole label set.
+pub(crate) fn sort_by_label(data: &Value, labels: &[String], desc: bool) -> Result<Value> {
+    let fn_name = if desc {
+        "sort_by_label_desc"
+    } else {
+        "sort_by_label"
+    };
+    let mut data = vector_arg(data, fn_name)?;
+    data.sort_by(|a, b| {
+        let ordering = labels
+            .iter()
+            .map(|name| a.labels.get_value(name).cmp(&b.labels.get_value(name)))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| label_pairs(a).cmp(&label_pairs(b)));
+        if desc {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+    Ok(Value::Vector(data))
+}
+
+fn vector_arg(data: &Value, fn_name: &str) -> Result<Vec<InstantValue>> {
+    match data {
+        Value::Vector(v) => Ok(v.clone()),
+        Value::None => Ok(vec![]),
+        v => Err(DataFusionError::Plan(format!(
+            "{fn_name}: vector argument expected but got {}",
+            v.get_type()
+        ))),
+    }
+}
+
+fn label_pairs(value: &InstantValue) -> Vec<(&str, &str)> {
+    value
+        .labels
+        .iter()
+        .map(|label| (label.name.as_str(), label.value.as_str()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::promql::value::{Label, Sample};
+    use std::sync::Arc;
+
+    fn instant(job: &str, instance: &str, value: f64) -> InstantValue {
+        InstantValue {
+            labels: vec![
+                Arc::new(Label::new("instance", instance)),
+                Arc::new(Label::new("job", job)),
+            ],
+            sample: Sample::new(0, value),
+        }
+    }
+
+    fn jobs(value: Value) -> Vec<String> {
+        match value {
+            Value::Vector(v) => v.iter().map(|v| v.labels.get_value("job")).collect(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_sort() {
+        let data = Value::Vector(vec![
+            instant("a", "1", f64::NAN),
+            instant("b", "1", 2.0),
+            instant("c", "1", 1.0),
+        ]);
+        assert_eq!(jobs(sort(&data, false).unwrap()), vec!["c", "b", "a"]);
+        assert_eq!(jobs(sort(&data, true).unwrap()), vec!["b", "c", "a"]);
+    }
+
+    #[test]
+    fn test_sort_by_label() {
+        let data = Value::Vector(vec![
+            instant("b", "2", 1.0),
+            instant("a", "2", 1.0),
+            instant("c", "1", 1.0),
+        ]);
+        let labels = vec!["instance".to_string()];
+        assert_eq!(
+            jobs(sort_by_label(&data, &labels, false).unwrap()),
+            vec!["c", "a", "b"]
+        );
+        assert_eq!(
+            jobs(sort_by_label(&data, &labels, true).unwrap()),
+            vec!["b", "a", "c"]
+        );
+    }
+}
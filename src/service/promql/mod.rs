@@ -26,19 +26,24 @@ mod binaries;
 pub mod common;
 mod engine;
 mod exec;
+mod extension;
 mod functions;
+#[cfg(test)]
+mod promqltest;
 
 pub mod search;
 pub mod value;
 
 pub use engine::Engine;
 pub use exec::Query;
+pub use extension::parse;
 
 use crate::common::meta::stream::ScanStats;
 
 pub(crate) const DEFAULT_LOOKBACK: Duration = Duration::from_secs(300); // 5m
 pub(crate) const MINIMAL_INTERVAL: Duration = Duration::from_secs(10); // 10s
 pub(crate) const MAX_DATA_POINTS: i64 = 256; // Width of panel
+pub(crate) const DEFAULT_SUBQUERY_STEP: Duration = Duration::from_secs(60); // 1m
 
 #[async_trait]
 pub trait TableProvider: Sync + Send + 'static {
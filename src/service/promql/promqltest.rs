@@ -0,0 +1,353 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Runs test scripts in the format of Prometheus's `promqltest` package against the
+//! engine, see <https://github.com/prometheus/prometheus/tree/main/promql/promqltest>.
+//!
+//! Supported commands are `load <interval>` followed by series and their values, and
+//! `eval instant at <time> <expr>` or `eval_ordered instant at <time> <expr>` followed
+//! by the expected result.
+
+use async_trait::async_trait;
+use datafusion::{
+    arrow::{
+        array::{ArrayRef, Float64Array, Int64Array, StringArray},
+        datatypes::{DataType, Field, Schema},
+        record_batch::RecordBatch,
+    },
+    datasource::MemTable,
+    error::Result,
+    prelude::SessionContext,
+};
+use promql_parser::{label::MatchOp, parser};
+use std::{
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use crate::common::infra::config::CONFIG;
+use crate::common::meta::prom::{HASH_LABEL, NAME_LABEL, VALUE_LABEL};
+use crate::common::meta::stream::ScanStats;
+use crate::service::promql::{self, value::*, Query, TableProvider, DEFAULT_LOOKBACK};
+
+#[derive(Debug, Clone)]
+struct Series {
+    labels: Labels,
+    samples: Vec<Sample>,
+}
+
+/// Serves the loaded series, one table per metric.
+struct MemoryProvider {
+    series: Arc<Vec<Series>>,
+}
+
+#[async_trait]
+impl TableProvider for MemoryProvider {
+    async fn create_context(
+        &self,
+        _org_id: &str,
+        stream_name: &str,
+        _time_range: (i64, i64),
+        _filters: &[(&str, &str)],
+    ) -> Result<Vec<(SessionContext, Arc<Schema>, ScanStats)>> {
+        let series: Vec<&Series> = self
+            .series
+            .iter()
+            .filter(|s| s.labels.get_value(NAME_LABEL) == stream_name)
+            .collect();
+        if series.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut label_names: Vec<String> = series.iter().flat_map(|s| s.labels.keys()).collect();
+        label_names.sort();
+        label_names.dedup();
+        let mut fields = vec![
+            Field::new(HASH_LABEL, DataType::Utf8, false),
+            Field::new(&CONFIG.common.column_timestamp, DataType::Int64, false),
+            Field::new(VALUE_LABEL, DataType::Float64, false),
+        ];
+        fields.extend(
+            label_names
+                .iter()
+                .map(|name| Field::new(name, DataType::Utf8, false)),
+        );
+        let schema = Arc::new(Schema::new(fields));
+
+        let mut hashes = vec![];
+        let mut times = vec![];
+        let mut values = vec![];
+        let mut label_values = vec![vec![]; label_names.len()];
+        for (i, s) in series.iter().enumerate() {
+            for sample in s.samples.iter() {
+                hashes.push(i.to_string());
+                times.push(sample.timestamp);
+                values.push(sample.value);
+                for (name, column) in label_names.iter().zip(label_values.iter_mut()) {
+                    column.push(s.labels.get_value(name));
+                }
+            }
+        }
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(hashes)),
+            Arc::new(Int64Array::from(times)),
+            Arc::new(Float64Array::from(values)),
+        ];
+        columns.extend(
+            label_values
+                .into_iter()
+                .map(|column| Arc::new(StringArray::from(column)) as ArrayRef),
+        );
+        let batch = RecordBatch::try_new(schema.clone(), columns)?;
+
+        let ctx = SessionContext::new();
+        let table = MemTable::try_new(schema.clone(), vec![vec![batch]])?;
+        ctx.register_table(stream_name, Arc::new(table))?;
+        Ok(vec![(ctx, schema, ScanStats::default())])
+    }
+}
+
+/// Result rows of an instant query, labels with empty values are absent in Prometheus.
+type Rows = Vec<(Vec<(String, String)>, f64)>;
+
+pub(crate) async fn run(script: &str) {
+    let lines: Vec<&str> = script.lines().collect();
+    let mut series = vec![];
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i].trim();
+        i += 1;
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // the series of a load and the result of an eval are indented
+        let mut block = vec![];
+        while i < lines.len() && lines[i].starts_with(char::is_whitespace) {
+            if !lines[i].trim().is_empty() {
+                block.push(lines[i].trim());
+            }
+            i += 1;
+        }
+
+        if let Some(interval) = line.strip_prefix("load ") {
+            let interval = parse_duration(interval);
+            series.extend(block.into_iter().map(|def| parse_series(def, interval)));
+            continue;
+        }
+        let (cmd, rest) = line.split_once(' ').unwrap();
+        let rest = rest
+            .strip_prefix("instant at ")
+            .unwrap_or_else(|| panic!("unsupported command: {line}"));
+        let (at, expr) = rest.split_once(' ').unwrap();
+        let ordered = match cmd {
+            "eval" => false,
+            "eval_ordered" => true,
+            _ => panic!("unsupported command: {line}"),
+        };
+
+        let provider = MemoryProvider {
+            series: Arc::new(series.clone()),
+        };
+        let mut query = Query::new("default", provider, 10);
+        let at = UNIX_EPOCH + Duration::from_micros(parse_duration(at) as u64);
+        let stmt = parser::EvalStmt {
+            expr: promql::parse(expr).unwrap_or_else(|e| panic!("{expr}: {e}")),
+            start: at,
+            end: at,
+            interval: Duration::ZERO,
+            lookback_delta: DEFAULT_LOOKBACK,
+        };
+        let (value, _, _) = query
+            .exec(stmt)
+            .await
+            .unwrap_or_else(|e| panic!("{expr}: {e}"));
+        let mut got = result_rows(value);
+        let mut expected = block.into_iter().map(parse_expected).collect::<Rows>();
+        if !ordered {
+            got.sort_by(|a, b| a.0.cmp(&b.0));
+            expected.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+        assert_eq!(got.len(), expected.len(), "{line}: got {got:?}");
+        for (got, expected) in got.iter().zip(expected.iter()) {
+            assert!(
+                got.0 == expected.0 && almost_equal(got.1, expected.1),
+                "{line}: expected {expected:?}, got {got:?}"
+            );
+        }
+    }
+}
+
+fn result_rows(value: Value) -> Rows {
+    let label_pairs = |labels: &Labels| {
+        let mut labels: Vec<(String, String)> = labels
+            .iter()
+            .filter(|l| !l.value.is_empty())
+            .map(|l| (l.name.clone(), l.value.clone()))
+            .collect();
+        labels.sort();
+        labels
+    };
+    match value {
+        Value::Vector(vs) => vs
+            .iter()
+            .map(|v| (label_pairs(&v.labels), v.sample.value))
+            .collect(),
+        Value::Instant(v) => vec![(label_pairs(&v.labels), v.sample.value)],
+        Value::Sample(s) => vec![(vec![], s.value)],
+        Value::Float(val) => vec![(vec![], val)],
+        Value::None => vec![],
+        v => panic!("unsupported result type: {}", v.get_type()),
+    }
+}
+
+/// Parses a series descriptor, e.g. `http_requests{job="api"}`, into its labels.
+fn parse_labels(desc: &str) -> Labels {
+    if desc == "{}" {
+        return Labels::default();
+    }
+    let selector = match parser::parse(desc) {
+        Ok(parser::Expr::VectorSelector(selector)) => selector,
+        _ => panic!("invalid series descriptor: {desc}"),
+    };
+    let mut labels: Labels = selector
+        .matchers
+        .matchers
+        .iter()
+        .filter(|m| m.op == MatchOp::Equal)
+        .map(|m| Arc::new(Label::new(m.name.as_str(), m.value.as_str())))
+        .collect();
+    if let Some(name) = selector.name {
+        labels.push(Arc::new(Label::new(NAME_LABEL.to_string(), name)));
+    }
+    labels.sort_by(|a, b| a.name.cmp(&b.name));
+    labels
+}
+
+/// Splits `desc values` into the series descriptor and the rest of the line.
+fn split_series(line: &str) -> (&str, &str) {
+    let pos = match line.rfind('}') {
+        Some(pos) => pos + 1,
+        None => line.find(char::is_whitespace).unwrap_or(line.len()),
+    };
+    let (desc, rest) = line.split_at(pos);
+    (desc.trim(), rest.trim())
+}
+
+/// Parses a series of the load command, e.g. `metric{job="a"} 0+10x5 _ 3`.
+fn parse_series(def: &str, interval: i64) -> Series {
+    let (desc, values) = split_series(def);
+    let mut samples = vec![];
+    let mut step = 0;
+    for value in values.split_whitespace() {
+        match value.split_once('x') {
+            Some(("_", times)) => step += times.parse::<i64>().unwrap(),
+            Some((expr, times)) => {
+                // `a+bxn` expands to a, a+b, ..., a+n*b
+                let pos = expr[1..].rfind(['+', '-']).unwrap() + 1;
+                let start: f64 = expr[..pos].parse().unwrap();
+                let delta: f64 = expr[pos..].parse().unwrap();
+                for n in 0..=times.parse::<i64>().unwrap() {
+                    samples.push(Sample::new(step * interval, start + delta * n as f64));
+                    step += 1;
+                }
+            }
+            None if value == "_" => step += 1,
+            None => {
+                samples.push(Sample::new(step * interval, parse_value(value)));
+                step += 1;
+            }
+        }
+    }
+    Series {
+        labels: parse_labels(desc),
+        samples,
+    }
+}
+
+/// Parses an expected result, e.g. `{job="a"} 10`, or a scalar.
+fn parse_expected(line: &str) -> (Vec<(String, String)>, f64) {
+    if let Ok(value) = line.parse::<f64>() {
+        return (vec![], value);
+    }
+    let (desc, value) = split_series(line);
+    let labels = parse_labels(desc)
+        .iter()
+        .map(|l| (l.name.clone(), l.value.clone()))
+        .collect();
+    (labels, parse_value(value))
+}
+
+fn parse_value(value: &str) -> f64 {
+    value
+        .parse()
+        .unwrap_or_else(|_| panic!("invalid value: {value}"))
+}
+
+/// Parses a duration like `5m` or `1h30m` into microseconds.
+fn parse_duration(value: &str) -> i64 {
+    let mut total = 0;
+    let mut number = String::new();
+    let mut chars = value.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match (c, chars.peek()) {
+            ('m', Some('s')) => {
+                chars.next();
+                1_000
+            }
+            ('s', _) => 1_000_000,
+            ('m', _) => 60_000_000,
+            ('h', _) => 3_600_000_000,
+            ('d', _) => 86_400_000_000,
+            _ => panic!("invalid duration: {value}"),
+        };
+        total += number.parse::<i64>().unwrap() * unit;
+        number.clear();
+    }
+    if !number.is_empty() {
+        // plain numbers are seconds
+        total += number.parse::<i64>().unwrap() * 1_000_000;
+    }
+    total
+}
+
+fn almost_equal(a: f64, b: f64) -> bool {
+    if a.is_nan() && b.is_nan() {
+        return true;
+    }
+    a == b || (a - b).abs() <= 1e-6 * a.abs().max(b.abs())
+}
+
+#[actix_web::test]
+async fn test_subquery() {
+    run(include_str!("testdata/subquery.test")).await;
+}
+
+#[actix_web::test]
+async fn test_at_modifier() {
+    run(include_str!("testdata/at_modifier.test")).await;
+}
+
+#[actix_web::test]
+async fn test_functions() {
+    run(include_str!("testdata/functions.test")).await;
+}
+
+#[actix_web::test]
+async fn test_limit() {
+    run(include_str!("testdata/limit.test")).await;
+}
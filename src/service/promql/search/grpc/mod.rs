@@ -26,7 +26,7 @@ use crate::common::{
 };
 use crate::handler::grpc::cluster_rpc;
 use crate::service::{
-    promql::{value, Query, TableProvider, DEFAULT_LOOKBACK},
+    promql::{self, value, Query, TableProvider, DEFAULT_LOOKBACK},
     search,
 };
 
@@ -73,7 +73,7 @@ pub async fn search(
 
     let org_id = &req.org_id;
     let query = req.query.as_ref().unwrap();
-    let prom_expr = parser::parse(&query.query).map_err(|e| {
+    let prom_expr = promql::parse(&query.query).map_err(|e| {
         log::error!("promQL parse query error: {e}");
         DataFusionError::Execution(e)
     })?;
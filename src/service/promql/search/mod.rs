@@ -14,8 +14,12 @@
 
 use ahash::AHashMap as HashMap;
 use futures::future::try_join_all;
+use promql_parser::parser::{
+    AggregateExpr, AtModifier, Call, Expr as PromExpr, MatrixSelector, Offset, ParenExpr, UnaryExpr,
+};
 use std::{
     cmp::{max, min},
+    collections::hash_map::Entry,
     sync::Arc,
 };
 use tonic::{codec::CompressionEncoding, metadata::MetadataValue, transport::Channel, Request};
@@ -25,6 +29,7 @@ use uuid::Uuid;
 
 use crate::common::{
     infra::{
+        cache::promql::{self as promql_cache, CachedResult},
         cluster,
         config::CONFIG,
         errors::{Error, ErrorCodes, Result},
@@ -34,7 +39,7 @@ use crate::common::{
 use crate::{
     common::meta::usage::UsageType,
     service::{
-        promql::{micros, value::*, MetricsQueryRequest, DEFAULT_LOOKBACK},
+        promql::{self, micros, value::*, MetricsQueryRequest, DEFAULT_LOOKBACK},
         search::{server_internal_error, MetadataMap},
     },
 };
@@ -52,7 +57,135 @@ pub async fn search(org_id: &str, req: &MetricsQueryRequest, timeout: i64) -> Re
     req.org_id = org_id.to_string();
     req.stype = cluster_rpc::SearchType::User as _;
     req.timeout = timeout;
-    search_in_cluster(req).await
+    if !is_cacheable(&req) {
+        return search_in_cluster(req).await;
+    }
+    search_with_cache(req).await
+}
+
+/// Only range queries are cached. Each step is evaluated independently of the others,
+/// unless the query refers to the start or the end of the range with `@`, or looks
+/// ahead of the step with a negative offset.
+fn is_cacheable(req: &cluster_rpc::MetricsQueryRequest) -> bool {
+    let query = req.query.as_ref().unwrap();
+    CONFIG.memory_cache.promql_enabled
+        && query.start < query.end
+        && query.step > 0
+        && promql::parse(&query.query).map_or(false, |expr| is_step_independent(&expr))
+}
+
+fn is_step_independent(expr: &PromExpr) -> bool {
+    let independent = |at: &Option<AtModifier>, offset: &Option<Offset>| {
+        !matches!(at, Some(AtModifier::Start | AtModifier::End))
+            && !matches!(offset, Some(Offset::Neg(_)))
+    };
+    match expr {
+        PromExpr::Aggregate(AggregateExpr { expr, param, .. }) => {
+            is_step_independent(expr) && param.as_ref().map_or(true, |p| is_step_independent(p))
+        }
+        PromExpr::Unary(UnaryExpr { expr }) => is_step_independent(expr),
+        PromExpr::Binary(expr) => is_step_independent(&expr.lhs) && is_step_independent(&expr.rhs),
+        PromExpr::Paren(ParenExpr { expr }) => is_step_independent(expr),
+        PromExpr::Subquery(expr) => {
+            independent(&expr.at, &expr.offset) && is_step_independent(&expr.expr)
+        }
+        PromExpr::NumberLiteral(_) | PromExpr::StringLiteral(_) => true,
+        PromExpr::VectorSelector(vs) => independent(&vs.at, &vs.offset),
+        PromExpr::MatrixSelector(MatrixSelector { vs, .. }) => independent(&vs.at, &vs.offset),
+        PromExpr::Call(Call { args, .. }) => args.args.iter().all(|arg| is_step_independent(arg)),
+        PromExpr::Extension(ext) => ext.expr.children().iter().all(is_step_independent),
+    }
+}
+
+/// Serves the steps of a range query from the result cache and only evaluates the
+/// steps after the cached ones. The steps of the last `ZO_MEMORY_CACHE_PROMQL_FRESHNESS`
+/// seconds are never cached, as most samples arrive within that window.
+async fn search_with_cache(mut req: cluster_rpc::MetricsQueryRequest) -> Result<Value> {
+    let query = req.query.as_ref().unwrap();
+    let (start, end, step) = (query.start, query.end, query.step);
+    // results can be reused by the queries evaluated at the same steps
+    let key = format!(
+        "{}/{}/{}/{}",
+        req.org_id,
+        step,
+        start.rem_euclid(step),
+        query.query
+    );
+
+    let cached = promql_cache::get(&key).filter(|v| v.start <= start && start <= v.end);
+    let fetch_start = match &cached {
+        Some(cached) if cached.end >= end => {
+            log::info!("promql->search->cache: hit, key: {key}");
+            let mut value = Value::Matrix(slice_series(&cached.series, start, end));
+            value.sort();
+            return Ok(value);
+        }
+        // evaluate the last cached step again, so the rest is still a range query
+        Some(cached) => cached.end,
+        None => start,
+    };
+    if cached.is_some() {
+        log::info!("promql->search->cache: partial hit, key: {key}, fetch from: {fetch_start}");
+    }
+
+    req.query.as_mut().unwrap().start = fetch_start;
+    let fetched = match search_in_cluster(req).await? {
+        Value::Matrix(v) => v,
+        // range queries always return a matrix
+        value => return Ok(value),
+    };
+
+    let mut series: HashMap<Signature, RangeValue> = HashMap::default();
+    if let Some(cached) = cached {
+        for v in slice_series(&cached.series, start, fetch_start - 1) {
+            series.insert(v.labels.signature(), v);
+        }
+    }
+    for v in fetched {
+        match series.entry(v.labels.signature()) {
+            Entry::Occupied(mut entry) => entry.get_mut().samples.extend(v.samples),
+            Entry::Vacant(entry) => {
+                entry.insert(v);
+            }
+        }
+    }
+    let series = series.into_values().collect::<Vec<_>>();
+
+    let stable_end = (chrono::Utc::now()
+        - chrono::Duration::seconds(CONFIG.memory_cache.promql_freshness))
+    .timestamp_micros();
+    let cache_end = min(end, stable_end);
+    let cache_end = cache_end - (cache_end - start).rem_euclid(step);
+    if cache_end >= start {
+        promql_cache::set(
+            &key,
+            CachedResult {
+                start,
+                end: cache_end,
+                series: slice_series(&series, start, cache_end),
+            },
+        );
+    }
+
+    let mut value = Value::Matrix(series);
+    value.sort();
+    Ok(value)
+}
+
+/// The samples of the series in `[start, end]`, series without samples are dropped.
+fn slice_series(series: &[RangeValue], start: i64, end: i64) -> Vec<RangeValue> {
+    series
+        .iter()
+        .filter_map(|v| {
+            let samples = v
+                .samples
+                .iter()
+                .filter(|s| start <= s.timestamp && s.timestamp <= end)
+                .copied()
+                .collect::<Vec<_>>();
+            (!samples.is_empty()).then(|| RangeValue::new(v.labels.clone(), samples))
+        })
+        .collect()
 }
 
 #[tracing::instrument(name = "promql:search:cluster", skip_all, fields(org_id = req.org_id))]
@@ -353,3 +486,62 @@ fn merge_scalar_query(series: &[cluster_rpc::Series]) -> Value {
     }
     Value::Sample(sample)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slice_series() {
+        let series = vec![
+            RangeValue::new(
+                vec![Arc::new(Label::new("job", "a"))],
+                [
+                    Sample::new(10, 1.0),
+                    Sample::new(20, 2.0),
+                    Sample::new(30, 3.0),
+                ],
+            ),
+            RangeValue::new(
+                vec![Arc::new(Label::new("job", "b"))],
+                [Sample::new(40, 4.0)],
+            ),
+        ];
+        let sliced = slice_series(&series, 20, 30);
+        assert_eq!(sliced.len(), 1);
+        assert_eq!(sliced[0].labels.get_value("job"), "a");
+        assert_eq!(
+            sliced[0]
+                .samples
+                .iter()
+                .map(|s| s.timestamp)
+                .collect::<Vec<_>>(),
+            vec![20, 30]
+        );
+    }
+
+    #[test]
+    fn test_is_cacheable() {
+        let req = |query: &str, start: i64, end: i64| {
+            cluster_rpc::MetricsQueryRequest::from(crate::service::promql::MetricsQueryRequest {
+                query: query.to_string(),
+                start,
+                end,
+                step: 10,
+            })
+        };
+        assert!(is_cacheable(&req("rate(up[5m])", 0, 100)));
+        assert!(!is_cacheable(&req("rate(up[5m])", 100, 100)));
+        assert!(!is_cacheable(&req("up @ end()", 0, 100)));
+        assert!(!is_cacheable(&req("sum(rate(up[5m] @ start()))", 0, 100)));
+        assert!(!is_cacheable(&req(
+            "max_over_time(up[1h:1m] offset -5m)",
+            0,
+            100
+        )));
+        assert!(is_cacheable(&req("up @ 100 offset 5m", 0, 100)));
+        // the function names don't matter
+        assert!(is_cacheable(&req("up{job=\"end()\"}", 0, 100)));
+        assert!(!is_cacheable(&req("up{", 0, 100)));
+    }
+}
@@ -22,7 +22,7 @@ use serde::{
 use std::{cmp::Ordering, sync::Arc, time::Duration};
 
 use crate::common::infra::config::FxIndexMap;
-use crate::common::meta::prom::NAME_LABEL;
+use crate::common::meta::prom::{NativeHistogram, NAME_LABEL};
 
 // https://prometheus.io/docs/concepts/data_model/#metric-names-and-labels
 static RE_VALID_LABEL_NAME: Lazy<Regex> =
@@ -183,6 +183,21 @@ impl Sample {
     }
 }
 
+/// A native histogram sample, the float sample of the same timestamp holds its count.
+#[derive(Debug, Clone)]
+pub struct HistogramSample {
+    /// Time in microseconds
+    pub timestamp: i64,
+    pub histogram: Arc<NativeHistogram>,
+}
+
+/// A native histogram of an instant vector, only used by the `histogram_*` functions.
+#[derive(Debug, Clone)]
+pub struct HistogramValue {
+    pub labels: Labels,
+    pub histogram: NativeHistogram,
+}
+
 #[derive(Debug, Clone)]
 pub struct InstantValue {
     pub labels: Labels,
@@ -233,6 +248,8 @@ impl TimeWindow {
 pub struct RangeValue {
     pub labels: Labels,
     pub samples: Vec<Sample>,
+    /// Native histogram samples of the series.
+    pub histograms: Vec<HistogramSample>,
     pub time_window: Option<TimeWindow>,
 }
 
@@ -268,6 +285,7 @@ impl RangeValue {
         Self {
             labels,
             samples: Vec::from_iter(samples),
+            histograms: vec![],
             time_window: None,
         }
     }
@@ -316,28 +334,7 @@ pub(crate) fn extrapolated_rate(
     offset: Duration,
     kind: ExtrapolationKind,
 ) -> Option<f64> {
-    let start = {
-        let range_plus_offset = range
-            .checked_add(offset)
-            .expect("BUG: overflow")
-            .as_micros()
-            .try_into()
-            .expect("BUG: integer conversion failed");
-        eval_ts
-            .checked_sub(range_plus_offset)
-            .expect("BUG: overflow")
-    };
-    assert!(start > 0);
-    let end = eval_ts
-        .checked_sub(
-            offset
-                .as_micros()
-                .try_into()
-                .expect("BUG: integer conversion failed"),
-        )
-        .expect("BUG: overflow");
-    assert!(end > 0);
-    assert!(start <= end);
+    let (start, end) = range_bounds(eval_ts, range, offset);
 
     if samples.len() < 2 {
         // Not enough samples.
@@ -365,21 +362,76 @@ pub(crate) fn extrapolated_rate(
         }
     }
 
+    let duration_to_zero = (is_counter && result > 0.0 && first.value >= 0.0).then(|| {
+        let sampled_interval = (last.timestamp - first.timestamp) as f64 / 1_000.0;
+        sampled_interval * (first.value / result)
+    });
+    let factor = extrapolation_factor(
+        (first.timestamp, last.timestamp),
+        samples.len(),
+        (start, end),
+        range,
+        duration_to_zero,
+        &kind,
+    );
+
+    Some(result * factor)
+}
+
+/// Start and end of the range of a range vector selector, in microseconds.
+pub(crate) fn range_bounds(eval_ts: i64, range: Duration, offset: Duration) -> (i64, i64) {
+    let start = {
+        let range_plus_offset = range
+            .checked_add(offset)
+            .expect("BUG: overflow")
+            .as_micros()
+            .try_into()
+            .expect("BUG: integer conversion failed");
+        eval_ts
+            .checked_sub(range_plus_offset)
+            .expect("BUG: overflow")
+    };
+    assert!(start > 0);
+    let end = eval_ts
+        .checked_sub(
+            offset
+                .as_micros()
+                .try_into()
+                .expect("BUG: integer conversion failed"),
+        )
+        .expect("BUG: overflow");
+    assert!(end > 0);
+    assert!(start <= end);
+    (start, end)
+}
+
+/// `extrapolation_factor` scales the difference between the first and the last of
+/// `len` samples to the whole range, per second if `kind` is Rate.
+///
+/// `duration_to_zero` is the duration from the first sample back to the zero point of
+/// a counter; the result is not extrapolated beyond it.
+pub(crate) fn extrapolation_factor(
+    (first_ts, last_ts): (i64, i64),
+    len: usize,
+    (start, end): (i64, i64),
+    range: Duration,
+    duration_to_zero: Option<f64>,
+    kind: &ExtrapolationKind,
+) -> f64 {
     // Duration between first/last samples and boundary of range.
-    let mut duration_to_start = (first.timestamp - start) as f64 / 1_000.0;
-    let duration_to_end = (end - last.timestamp) as f64 / 1_000.0;
-
-    let sampled_interval = (last.timestamp - first.timestamp) as f64 / 1_000.0;
-    let avg_duration_between_samples = sampled_interval / (samples.len() - 1) as f64;
-
-    if is_counter && result > 0.0 && first.value >= 0.0 {
-        // Counters cannot be negative. If we have any slope at all
-        // (i.e. `result` went up), we can extrapolate the zero point
-        // of the counter. If the duration to the zero point is shorter
-        // than the `duration_to_start`, we take the zero point as the start
-        // of the series, thereby avoiding extrapolation to negative
-        // counter values.
-        let duration_to_zero = sampled_interval * (first.value / result);
+    let mut duration_to_start = (first_ts - start) as f64 / 1_000.0;
+    let duration_to_end = (end - last_ts) as f64 / 1_000.0;
+
+    let sampled_interval = (last_ts - first_ts) as f64 / 1_000.0;
+    let avg_duration_between_samples = sampled_interval / (len - 1) as f64;
+
+    // Counters cannot be negative. If we have any slope at all
+    // (i.e. the result went up), we can extrapolate the zero point
+    // of the counter. If the duration to the zero point is shorter
+    // than the `duration_to_start`, we take the zero point as the start
+    // of the series, thereby avoiding extrapolation to negative
+    // counter values.
+    if let Some(duration_to_zero) = duration_to_zero {
         if duration_to_zero < duration_to_start {
             duration_to_start = duration_to_zero;
         }
@@ -404,12 +456,10 @@ pub(crate) fn extrapolated_rate(
     }
     let factor = extrapolate_to_interval / sampled_interval;
     if matches!(kind, ExtrapolationKind::Rate) {
-        result *= factor / range.as_secs_f64();
+        factor / range.as_secs_f64()
     } else {
-        result *= factor;
+        factor
     }
-
-    Some(result)
 }
 
 pub fn labels_value(labels: &Labels, name: &str) -> Option<String> {
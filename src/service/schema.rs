@@ -721,8 +721,10 @@ pub async fn add_stream_schema(
         let settings = crate::common::meta::stream::StreamSettings {
             partition_keys: vec!["service_name".to_string()],
             full_text_search_keys: vec![],
+            bloom_filter_fields: vec![],
             data_retention: 0,
             partition_time_level: None,
+            trace_sampling: None,
         };
         metadata.insert(
             "settings".to_string(),
@@ -45,7 +45,7 @@ use std::{str::FromStr, sync::Arc};
 use crate::common::{
     infra::{
         cache::tmpfs,
-        config::{COLUMN_TRACE_ID, CONFIG, PARQUET_BATCH_SIZE},
+        config::{CONFIG, PARQUET_BATCH_SIZE},
     },
     meta::{
         common::{FileKey, FileMeta},
@@ -53,9 +53,9 @@ use crate::common::{
         search::Session as SearchSession,
         sql, StreamType,
     },
-    utils::{flatten, json},
+    utils::{flatten, json, stream::populate_field_summary},
 };
-use crate::service::search::sql::Sql;
+use crate::service::search::{inverted_index, sql::Sql};
 
 use super::storage::{file_list, StorageType};
 use super::transform_udf::get_all_transform;
@@ -851,7 +851,7 @@ pub async fn convert_parquet_file(
     let schema: Schema = df.schema().into();
     let schema = Arc::new(schema);
     let batches = df.collect().await?;
-    let mut writer = super::new_parquet_writer(buf, &schema, 0, None);
+    let mut writer = super::new_parquet_writer(buf, &schema, 0, &[]);
     for batch in batches {
         writer.write(&batch)?;
     }
@@ -872,7 +872,9 @@ pub async fn merge_parquet_files(
     buf: &mut Vec<u8>,
     schema: Arc<Schema>,
     stream_type: StreamType,
-) -> Result<FileMeta> {
+    bf_fields: &[String],
+    fts_fields: &[String],
+) -> Result<(FileMeta, Option<Vec<u8>>)> {
     let start = std::time::Instant::now();
     // query data
     let runtime_env = create_runtime_env()?;
@@ -902,7 +904,7 @@ pub async fn merge_parquet_files(
     let batches_ref: Vec<&RecordBatch> = batches.iter().collect();
     let result = arrowJson::writer::record_batches_to_json_rows(&batches_ref).unwrap();
     let record = result.first().unwrap();
-    let file_meta = if record.is_empty() {
+    let mut file_meta = if record.is_empty() {
         FileMeta::default()
     } else {
         FileMeta {
@@ -911,6 +913,7 @@ pub async fn merge_parquet_files(
             records: record["num_records"].as_i64().unwrap(),
             original_size: 0,
             compressed_size: 0,
+            field_summary: Default::default(),
         }
     };
 
@@ -923,13 +926,13 @@ pub async fn merge_parquet_files(
     let schema: Schema = df.schema().into();
     let schema = Arc::new(schema);
     let batches = df.collect().await?;
+    populate_field_summary(&batches, fts_fields, &mut file_meta);
+    let index = if inverted_index::enabled(stream_type) {
+        inverted_index::build(&batches)
+    } else {
+        None
+    };
 
-    let bf_fields =
-        if CONFIG.common.traces_bloom_filter_enabled && stream_type == StreamType::Traces {
-            Some(vec![COLUMN_TRACE_ID])
-        } else {
-            None
-        };
     let mut writer = super::new_parquet_writer(buf, &schema, file_meta.records as u64, bf_fields);
     for batch in batches {
         writer.write(&batch)?;
@@ -943,7 +946,7 @@ pub async fn merge_parquet_files(
         start.elapsed().as_secs_f64()
     );
 
-    Ok(file_meta)
+    Ok((file_meta, index))
 }
 
 pub fn create_session_config() -> SessionConfig {
@@ -952,9 +955,10 @@ pub fn create_session_config() -> SessionConfig {
     options.execution.parquet.pushdown_filters = true;
     options.execution.parquet.reorder_filters = true;
     options.optimizer.repartition_sorts = true;
-    if CONFIG.common.traces_bloom_filter_enabled {
-        options.execution.parquet.bloom_filter_enabled = true;
-    }
+    // equality predicates use the bloom filters and page indexes of the columns which
+    // have them, see `ZO_TRACES_BLOOM_FILTER_ENABLED` and the stream settings
+    options.execution.parquet.bloom_filter_enabled = true;
+    options.execution.parquet.enable_page_index = true;
 
     SessionConfig::from(options)
         .with_batch_size(PARQUET_BATCH_SIZE)
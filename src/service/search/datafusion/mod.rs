@@ -16,7 +16,7 @@ use datafusion::arrow::datatypes::Schema;
 use parquet::{
     arrow::ArrowWriter,
     basic::{Compression, Encoding},
-    file::properties::WriterProperties,
+    file::properties::{EnabledStatistics, WriterProperties},
     format::SortingColumn,
     schema::types::ColumnPath,
 };
@@ -98,7 +98,7 @@ pub fn new_parquet_writer<'a>(
     buf: &'a mut Vec<u8>,
     schema: &'a Arc<Schema>,
     num_rows: u64,
-    bf_fields: Option<Vec<&str>>,
+    bf_fields: &[String],
 ) -> ArrowWriter<&'a mut Vec<u8>> {
     let sort_column_id = schema
         .index_of(&CONFIG.common.column_timestamp)
@@ -125,16 +125,17 @@ pub fn new_parquet_writer<'a>(
         writer_props = writer_props
             .set_column_dictionary_enabled(ColumnPath::from(vec![field.to_string()]), false);
     }
-    if let Some(fields) = bf_fields {
-        for field in fields {
+    for field in bf_fields {
+        writer_props = writer_props
+            .set_column_bloom_filter_enabled(ColumnPath::from(vec![field.to_string()]), true)
+            // page level statistics are written to the page index
+            .set_column_statistics_enabled(
+                ColumnPath::from(vec![field.to_string()]),
+                EnabledStatistics::Page,
+            );
+        if num_rows > 0 {
             writer_props = writer_props
-                .set_column_bloom_filter_enabled(ColumnPath::from(vec![field.to_string()]), true);
-            if num_rows > 0 {
-                writer_props = writer_props.set_column_bloom_filter_ndv(
-                    ColumnPath::from(vec![field.to_string()]),
-                    num_rows,
-                );
-            }
+                .set_column_bloom_filter_ndv(ColumnPath::from(vec![field.to_string()]), num_rows);
         }
     }
     let writer_props = writer_props.build();
@@ -35,6 +35,7 @@ use crate::service::{
     db, file_list,
     search::{
         datafusion::{exec, storage::StorageType},
+        inverted_index,
         sql::Sql,
     },
     stream,
@@ -261,6 +262,7 @@ async fn get_file_list(
             files.push(file.to_owned());
         }
     }
+    let mut files = inverted_index::filter_files(files, &sql.index_terms, stream_type).await;
     files.sort_by(|a, b| a.key.cmp(&b.key));
     Ok(files)
 }
@@ -0,0 +1,377 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Term index of a parquet file: the distinct lowercased alphanumeric tokens of all its
+// string columns, sorted, one per line and zstd compressed. It's stored beside the file
+// with the `.idx` extension, so the retention prefix deletes remove it too. A file
+// without an index is always searched.
+//
+// The decoded indexes are cached. The suffixes of their tokens are sorted, so finding
+// the tokens which contain a term, the full text search matches substrings, is a binary
+// search.
+
+use datafusion::arrow::{
+    array::{Array, StringArray},
+    datatypes::DataType,
+    record_batch::RecordBatch,
+};
+use lru::LruCache;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::{collections::BTreeSet, sync::Arc};
+use tokio::sync::Semaphore;
+
+use crate::common::{
+    infra::{
+        config::{RwHashSet, CONFIG, FILE_EXT_PARQUET},
+        storage,
+    },
+    meta::{common::FileKey, StreamType},
+};
+
+pub const FILE_EXT_INDEX: &str = ".idx";
+
+/// Files known to have no index, the set is cleared when it grows past this size.
+const MAX_MISSING_FILES: usize = 100_000;
+
+static MISSING: Lazy<RwHashSet<String>> = Lazy::new(Default::default);
+
+static INDEXES: Lazy<Mutex<IndexCache>> = Lazy::new(|| {
+    Mutex::new(IndexCache::with_capacity(
+        CONFIG.memory_cache.inverted_index_max_size,
+    ))
+});
+
+/// Indexes are written for the logs streams, if `ZO_INVERTED_INDEX_ENABLED`.
+pub fn enabled(stream_type: StreamType) -> bool {
+    CONFIG.common.inverted_index_enabled && stream_type == StreamType::Logs
+}
+
+pub fn index_key(file_key: &str) -> String {
+    match file_key.strip_suffix(FILE_EXT_PARQUET) {
+        Some(key) => format!("{key}{FILE_EXT_INDEX}"),
+        None => format!("{file_key}{FILE_EXT_INDEX}"),
+    }
+}
+
+/// Splits a text into lowercased alphanumeric tokens.
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_lowercase())
+}
+
+/// Builds the index of the string columns of the batches, returns `None` when there are
+/// more terms than `ZO_INVERTED_INDEX_MAX_TERMS`.
+pub fn build(batches: &[RecordBatch]) -> Option<Vec<u8>> {
+    let mut terms = BTreeSet::new();
+    for batch in batches {
+        for column in batch.columns() {
+            if column.data_type() != &DataType::Utf8 {
+                continue;
+            }
+            let column = column.as_any().downcast_ref::<StringArray>().unwrap();
+            for value in column.iter().flatten() {
+                terms.extend(tokenize(value));
+            }
+            if terms.len() > CONFIG.limit.inverted_index_max_terms {
+                return None;
+            }
+        }
+    }
+    let data = terms.into_iter().collect::<Vec<_>>().join("\n");
+    match zstd::encode_all(data.as_bytes(), 3) {
+        Ok(data) => Some(data),
+        Err(e) => {
+            log::error!("[INVERTED_INDEX] compress index error: {}", e);
+            None
+        }
+    }
+}
+
+/// Uploads the index of a parquet file, the file stays searchable without it so errors
+/// are only logged.
+pub async fn write(file_key: &str, data: Option<Vec<u8>>) {
+    let Some(data) = data else {
+        return;
+    };
+    let key = index_key(file_key);
+    if let Err(e) = storage::put(&key, data.into()).await {
+        log::error!("[INVERTED_INDEX] upload index {} error: {}", key, e);
+    }
+}
+
+/// Drops the files whose index shows they don't contain all of the terms.
+pub async fn filter_files(
+    files: Vec<FileKey>,
+    terms: &[String],
+    stream_type: StreamType,
+) -> Vec<FileKey> {
+    if !enabled(stream_type) || terms.is_empty() || files.is_empty() {
+        return files;
+    }
+    let terms: Arc<Vec<String>> =
+        Arc::new(terms.iter().flat_map(|v| tokenize(v.as_str())).collect());
+    if terms.is_empty() {
+        return files;
+    }
+
+    let mut tasks = Vec::with_capacity(files.len());
+    let semaphore = Arc::new(Semaphore::new(CONFIG.limit.query_thread_num));
+    for file in files.iter() {
+        let file_key = file.key.clone();
+        let terms = terms.clone();
+        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        tasks.push(tokio::task::spawn(async move {
+            let ret = may_match(&file_key, &terms).await;
+            drop(permit);
+            ret
+        }));
+    }
+
+    let mut matched = Vec::with_capacity(files.len());
+    for (file, task) in files.into_iter().zip(tasks) {
+        // keep the file if the check failed
+        if task.await.unwrap_or(true) {
+            matched.push(file);
+        }
+    }
+    matched
+}
+
+async fn may_match(file_key: &str, terms: &[String]) -> bool {
+    let key = index_key(file_key);
+    if MISSING.contains(&key) {
+        return true;
+    }
+    let cached = INDEXES.lock().get(&key);
+    let index = match cached {
+        Some(index) => index,
+        None => match load(&key).await {
+            Some(index) => index,
+            None => return true,
+        },
+    };
+    index.contains_all(terms)
+}
+
+/// Downloads and decodes an index, `None` if the file has no index or it can't be read.
+async fn load(key: &str) -> Option<Arc<Index>> {
+    let data = match storage::get(key).await {
+        Ok(data) => data,
+        Err(e) if is_not_found(&e) => {
+            if MISSING.len() >= MAX_MISSING_FILES {
+                MISSING.clear();
+            }
+            MISSING.insert(key.to_string());
+            return None;
+        }
+        Err(e) => {
+            log::warn!("[INVERTED_INDEX] download index {} error: {}", key, e);
+            return None;
+        }
+    };
+    match tokio::task::spawn_blocking(move || Index::decode(&data)).await {
+        Ok(Ok(index)) => {
+            let index = Arc::new(index);
+            INDEXES.lock().set(key, index.clone());
+            Some(index)
+        }
+        Ok(Err(e)) => {
+            log::error!("[INVERTED_INDEX] decode index {} error: {}", key, e);
+            None
+        }
+        Err(e) => {
+            log::error!("[INVERTED_INDEX] decode index {} error: {}", key, e);
+            None
+        }
+    }
+}
+
+fn is_not_found(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<object_store::Error>(),
+        Some(object_store::Error::NotFound { .. })
+    )
+}
+
+/// A decoded index, the offsets of the suffixes of its tokens are sorted by suffix.
+struct Index {
+    /// The tokens, one per line
+    text: String,
+    suffixes: Vec<u32>,
+}
+
+impl Index {
+    fn decode(data: &[u8]) -> Result<Index, anyhow::Error> {
+        let text = String::from_utf8(zstd::decode_all(data)?)?;
+        u32::try_from(text.len())?;
+        let mut index = Index {
+            suffixes: text
+                .char_indices()
+                .filter(|(_, c)| *c != '\n')
+                .map(|(i, _)| i as u32)
+                .collect(),
+            text,
+        };
+        let mut suffixes = std::mem::take(&mut index.suffixes);
+        suffixes.sort_unstable_by(|a, b| index.suffix(*a).cmp(index.suffix(*b)));
+        index.suffixes = suffixes;
+        Ok(index)
+    }
+
+    /// The suffix of a token, from `offset` to the end of the token.
+    fn suffix(&self, offset: u32) -> &[u8] {
+        let rest = &self.text.as_bytes()[offset as usize..];
+        match memchr::memchr(b'\n', rest) {
+            Some(end) => &rest[..end],
+            None => rest,
+        }
+    }
+
+    /// Checks that each term is part of an indexed token, the full text search matches
+    /// substrings so `err` is found in a file with `error`.
+    fn contains_all(&self, terms: &[String]) -> bool {
+        terms.iter().all(|term| {
+            let term = term.as_bytes();
+            let pos = self.suffixes.partition_point(|s| self.suffix(*s) < term);
+            self.suffixes
+                .get(pos)
+                .map_or(false, |s| self.suffix(*s).starts_with(term))
+        })
+    }
+
+    fn size(&self) -> usize {
+        self.text.len() + self.suffixes.len() * std::mem::size_of::<u32>()
+    }
+}
+
+/// Decoded indexes, the least recently used are released past `max_size` bytes.
+struct IndexCache {
+    max_size: usize,
+    cur_size: usize,
+    data: LruCache<String, Arc<Index>>,
+}
+
+impl IndexCache {
+    fn with_capacity(max_size: usize) -> IndexCache {
+        IndexCache {
+            max_size,
+            cur_size: 0,
+            data: LruCache::unbounded(),
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<Arc<Index>> {
+        self.data.get(key).cloned()
+    }
+
+    fn set(&mut self, key: &str, index: Arc<Index>) {
+        let data_size = key.len() + index.size();
+        if let Some(old) = self.data.pop(key) {
+            self.cur_size -= key.len() + old.size();
+        }
+        if data_size > self.max_size {
+            return;
+        }
+        while self.cur_size + data_size > self.max_size {
+            match self.data.pop_lru() {
+                Some((key, old)) => self.cur_size -= key.len() + old.size(),
+                None => break,
+            }
+        }
+        self.cur_size += data_size;
+        self.data.put(key.to_string(), index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::{
+        array::Int64Array,
+        datatypes::{Field, Schema},
+    };
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("GET /api/Users?id=42 req-7f3a").collect::<Vec<_>>(),
+            vec!["get", "api", "users", "id", "42", "req", "7f3a"]
+        );
+        assert_eq!(tokenize(" -- ").count(), 0);
+    }
+
+    #[test]
+    fn test_index_key() {
+        assert_eq!(
+            index_key("files/default/logs/app/2023/10/01/00/abc.parquet"),
+            "files/default/logs/app/2023/10/01/00/abc.idx"
+        );
+    }
+
+    #[test]
+    fn test_build_and_match() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("log", DataType::Utf8, true),
+            Field::new("code", DataType::Int64, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(StringArray::from(vec![
+                    Some("Connection refused, request_id=8c1f"),
+                    None,
+                    Some("ok"),
+                ])),
+                Arc::new(Int64Array::from(vec![500, 200, 404])),
+            ],
+        )
+        .unwrap();
+        let data = build(&[batch]).unwrap();
+        let index = Index::decode(&data).unwrap();
+        assert_eq!(
+            index.text.lines().collect::<Vec<_>>(),
+            vec!["8c1f", "connection", "id", "ok", "refused", "request"]
+        );
+
+        let terms = |v: &[&str]| v.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        assert!(index.contains_all(&terms(&["refused", "8c1f"])));
+        assert!(index.contains_all(&terms(&["connect", "ection", "f"])));
+        assert!(!index.contains_all(&terms(&["refused", "timeout"])));
+        // a term doesn't span tokens
+        assert!(!index.contains_all(&terms(&["okr"])));
+        // numbers are only indexed from the string columns
+        assert!(!index.contains_all(&terms(&["404"])));
+    }
+
+    #[test]
+    fn test_index_cache() {
+        let index = |tokens: &str| {
+            Arc::new(Index::decode(&zstd::encode_all(tokens.as_bytes(), 3).unwrap()).unwrap())
+        };
+        // key + text + 4 bytes per suffix = 1 + 3 + 12 bytes
+        let mut cache = IndexCache::with_capacity(40);
+        cache.set("a", index("abc"));
+        cache.set("b", index("abc"));
+        assert!(cache.get("a").is_some());
+        // "b" is the least recently used index
+        cache.set("c", index("abc"));
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").unwrap().contains_all(&["bc".to_string()]));
+        // too large to be cached
+        cache.set("d", index("abcdefghij"));
+        assert!(cache.get("d").is_none());
+    }
+}
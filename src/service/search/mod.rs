@@ -12,11 +12,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use ::datafusion::arrow::{datatypes::Schema, ipc, json as arrow_json, record_batch::RecordBatch};
+use ::datafusion::arrow::{
+    array::new_null_array,
+    compute::cast,
+    datatypes::{Field, Schema},
+    ipc, json as arrow_json,
+    record_batch::RecordBatch,
+};
 use ahash::AHashMap as HashMap;
 use once_cell::sync::Lazy;
 use std::{cmp::min, io::Cursor, sync::Arc};
-use tokio::sync::Mutex;
+use tokio::sync::{mpsc, Mutex};
 use tonic::{codec::CompressionEncoding, metadata::MetadataValue, transport::Channel, Request};
 use tracing::{info_span, Instrument};
 use tracing_opentelemetry::OpenTelemetrySpanExt;
@@ -32,6 +38,7 @@ use crate::common::{
     meta::{
         common::FileKey,
         search,
+        sql::Sql as MetaSql,
         stream::{PartitionTimeLevel, ScanStats, StreamParams},
         StreamType,
     },
@@ -42,7 +49,9 @@ use crate::service::{db, file_list, format_partition_key, stream};
 
 pub(crate) mod datafusion;
 pub(crate) mod grpc;
+pub(crate) mod inverted_index;
 pub(crate) mod sql;
+pub(crate) mod streaming;
 
 pub(crate) static QUEUE_LOCKER: Lazy<Arc<Mutex<bool>>> =
     Lazy::new(|| Arc::new(Mutex::const_new(false)));
@@ -60,6 +69,117 @@ pub async fn search(
     search_in_cluster(req).await
 }
 
+/// Runs a search like [`search`] for the clients which read Arrow, and sends the hits to
+/// the returned channel as record batches of one schema. The hits of the queries which
+/// don't need a merge, see [`is_streamable`], are all sent as the nodes answer by the
+/// streaming search. The hits of the others are sent once merged, which holds them in
+/// memory, so they are limited to `ZO_SEARCH_ARROW_MERGE_LIMIT` rows without a LIMIT.
+#[tracing::instrument(name = "service:search:arrow", skip(req))]
+pub async fn search_arrow(
+    org_id: &str,
+    stream_type: StreamType,
+    req: &search::Request,
+) -> Result<mpsc::Receiver<Result<RecordBatch, Error>>, Error> {
+    let mut req = req.to_owned();
+    let meta = MetaSql::new(&req.query.sql).map_err(|e| Error::Message(e.to_string()))?;
+    let (tx, rx) = mpsc::channel(1);
+    if !is_streamable(&meta, &req.query.sql) {
+        req.query.size = CONFIG.limit.search_arrow_merge_limit.max(1);
+        let mut req: cluster_rpc::SearchRequest = req.into();
+        req.org_id = org_id.to_string();
+        req.stype = cluster_rpc::SearchType::User as i32;
+        req.stream_type = stream_type.to_string();
+        let mut result = merge_in_cluster(&req).await?;
+        let batches = result
+            .batches
+            .remove("query")
+            .and_then(|batches| batches.into_iter().next())
+            .unwrap_or_default();
+        tokio::task::spawn(async move {
+            for batch in batches {
+                if tx.send(Ok(batch)).await.is_err() {
+                    break;
+                }
+            }
+        });
+        return Ok(rx);
+    }
+
+    req.query.size = i32::MAX as usize;
+    // the nodes answer with the fields of their files, all fields are the stream ones
+    let mut schema = if meta.fields.is_empty() {
+        let schema = db::schema::get(org_id, &meta.source, stream_type)
+            .await
+            .map_err(|e| Error::Message(e.to_string()))?;
+        Some(nullable_schema(&schema))
+    } else {
+        None
+    };
+    let mut chunks =
+        streaming::search(org_id, stream_type, &req, None, search::StreamFormat::Arrow).await?;
+    tokio::task::spawn(async move {
+        while let Some(data) = chunks.recv().await {
+            let batches = match streaming::decode_arrow(&data) {
+                Ok(batches) => batches,
+                Err(e) => {
+                    _ = tx.send(Err(e)).await;
+                    return;
+                }
+            };
+            for batch in batches {
+                let schema = schema
+                    .get_or_insert_with(|| nullable_schema(&batch.schema()))
+                    .clone();
+                if tx.send(cast_batch(&batch, schema)).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    Ok(rx)
+}
+
+/// Whether the hits of the nodes are the result as is: the query has no aggregation, no
+/// DISTINCT, GROUP BY, ORDER BY, LIMIT or OFFSET. Functions of the fields are taken as
+/// aggregations.
+fn is_streamable(meta: &MetaSql, sql: &str) -> bool {
+    meta.group_by.is_empty()
+        && meta.order_by.is_empty()
+        && meta.limit == 0
+        && meta.offset == 0
+        && !meta.fields.iter().any(|f| f.contains('('))
+        && !meta.field_alias.iter().any(|f| f.0.contains('('))
+        && !sql.to_lowercase().contains("distinct")
+}
+
+fn nullable_schema(schema: &Schema) -> Arc<Schema> {
+    Arc::new(Schema::new(
+        schema
+            .fields()
+            .iter()
+            .map(|f| Field::new(f.name(), f.data_type().clone(), true))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// Converts a batch to `schema`: the missing fields are null and the others cast to its
+/// types, the fields it doesn't have are dropped.
+fn cast_batch(batch: &RecordBatch, schema: Arc<Schema>) -> Result<RecordBatch, Error> {
+    if batch.schema() == schema {
+        return Ok(batch.clone());
+    }
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| match batch.column_by_name(field.name()) {
+            Some(column) if column.data_type() == field.data_type() => Ok(column.clone()),
+            Some(column) => cast(column, field.data_type()),
+            None => Ok(new_null_array(field.data_type(), batch.num_rows())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(RecordBatch::try_new(schema, columns)?)
+}
+
 async fn get_times(sql: &sql::Sql, stream_type: StreamType) -> (i64, i64) {
     let (mut time_min, mut time_max) = sql.meta.time_range.unwrap();
     if time_min == 0 {
@@ -113,6 +233,7 @@ async fn get_file_list(
             files.push(file.to_owned());
         }
     }
+    let mut files = inverted_index::filter_files(files, &sql.index_terms, stream_type).await;
     files.sort_by(|a, b| a.key.cmp(&b.key));
     files
 }
@@ -124,10 +245,115 @@ async fn get_file_list(
 )]
 async fn search_in_cluster(req: cluster_rpc::SearchRequest) -> Result<search::Response, Error> {
     let start = std::time::Instant::now();
+    let ClusterResult {
+        sql,
+        batches,
+        scan_stats,
+        took_wait,
+    } = merge_in_cluster(&req).await?;
+
+    // final result
+    let mut result = search::Response::new(sql.meta.offset, sql.meta.limit);
+
+    // hits
+    let query_type = req.query.as_ref().unwrap().query_type.to_lowercase();
+    let empty_vec = vec![];
+    let batches_query = match batches.get("query") {
+        Some(batches) => batches,
+        None => &empty_vec,
+    };
+    if !batches_query.is_empty() {
+        let batches_query_ref: Vec<&RecordBatch> = batches_query[0].iter().collect();
+        let json_rows = match arrow_json::writer::record_batches_to_json_rows(&batches_query_ref) {
+            Ok(res) => res,
+            Err(err) => {
+                return Err(Error::ErrorCode(ErrorCodes::ServerInternalError(
+                    err.to_string(),
+                )))
+            }
+        };
+        let mut sources: Vec<json::Value> =
+            json_rows.into_iter().map(json::Value::Object).collect();
+
+        // handle metrics response
+        if query_type == "metrics" {
+            sources = handle_metrics_response(sources);
+        }
+
+        if sql.uses_zo_fn {
+            for source in sources {
+                result.add_hit(&flatten::flatten(&source).unwrap());
+            }
+        } else {
+            for source in sources {
+                result.add_hit(&source);
+            }
+        }
+    }
+
+    // aggs
+    for (name, batch) in batches {
+        if name == "query" || batch.is_empty() {
+            continue;
+        }
+        let name = name.strip_prefix("agg_").unwrap().to_string();
+        let batch_ref: Vec<&RecordBatch> = batch[0].iter().collect();
+        let json_rows = match arrow_json::writer::record_batches_to_json_rows(&batch_ref) {
+            Ok(res) => res,
+            Err(err) => {
+                return Err(Error::ErrorCode(ErrorCodes::ServerInternalError(
+                    err.to_string(),
+                )));
+            }
+        };
+        let sources: Vec<json::Value> = json_rows.into_iter().map(json::Value::Object).collect();
+        for source in sources {
+            result.add_agg(&name, &source);
+        }
+    }
+
+    // total
+    let total = match result.aggs.get("_count") {
+        Some(v) => v.get(0).unwrap().get("num").unwrap().as_u64().unwrap() as usize,
+        None => result.hits.len(),
+    };
+    result.aggs.remove("_count");
+
+    result.set_total(total);
+    result.set_cluster_took(start.elapsed().as_millis() as usize, took_wait);
+    result.set_file_count(scan_stats.files as usize);
+    result.set_scan_size(scan_stats.original_size as usize);
+
+    if query_type == "metrics" {
+        result.response_type = "matrix".to_string();
+    }
+
+    log::info!(
+        "search->result: total: {}, took: {}, scan_size: {}",
+        result.total,
+        result.took,
+        result.scan_size,
+    );
+
+    Ok(result)
+}
+
+/// Hits and aggregations of a search, merged from all the nodes.
+struct ClusterResult {
+    sql: Arc<sql::Sql>,
+    /// Batches by `query` or `agg_{name}`
+    batches: HashMap<String, Vec<Vec<RecordBatch>>>,
+    scan_stats: ScanStats,
+    /// Time waiting for the cluster search queue, in milliseconds
+    took_wait: usize,
+}
+
+async fn merge_in_cluster(req: &cluster_rpc::SearchRequest) -> Result<ClusterResult, Error> {
+    let start = std::time::Instant::now();
 
     // handle request time range
     let stream_type = StreamType::from(req.stream_type.as_str());
-    let meta = sql::Sql::new(&req).await?;
+    let meta = sql::Sql::new(req).await?;
 
     // get a cluster search queue lock
     let locker = dist_lock::lock("search/cluster_queue", 0).await?;
@@ -197,72 +423,8 @@ async fn search_in_cluster(req: cluster_rpc::SearchRequest) -> Result<search::Re
             }
         }
 
-        let node_addr = node.grpc_addr.clone();
         let grpc_span = info_span!("service:search:cluster:grpc_search", org_id = req.org_id);
-        let task = tokio::task::spawn(
-            async move {
-                let org_id: MetadataValue<_> = req
-                    .org_id
-                    .parse()
-                    .map_err(|_| Error::Message("invalid org_id".to_string()))?;
-                let mut request = tonic::Request::new(req);
-                // request.set_timeout(Duration::from_secs(CONFIG.grpc.timeout));
-
-                opentelemetry::global::get_text_map_propagator(|propagator| {
-                    propagator.inject_context(
-                        &tracing::Span::current().context(),
-                        &mut MetadataMap(request.metadata_mut()),
-                    )
-                });
-
-                let token: MetadataValue<_> = cluster::get_internal_grpc_token()
-                    .parse()
-                    .map_err(|_| Error::Message("invalid token".to_string()))?;
-                let channel = Channel::from_shared(node_addr)
-                    .unwrap()
-                    .connect()
-                    .await
-                    .map_err(|err| {
-                        log::error!("search->grpc: node: {}, connect err: {:?}", &node.grpc_addr, err);
-                        server_internal_error("connect search node error")
-                    })?;
-                let mut client = cluster_rpc::search_client::SearchClient::with_interceptor(
-                    channel,
-                    move |mut req: Request<()>| {
-                        req.metadata_mut().insert("authorization", token.clone());
-                        req.metadata_mut()
-                            .insert(CONFIG.grpc.org_header_key.as_str(), org_id.clone());
-                        Ok(req)
-                    },
-                );
-                client = client
-                    .send_compressed(CompressionEncoding::Gzip)
-                    .accept_compressed(CompressionEncoding::Gzip);
-                let response: cluster_rpc::SearchResponse = match client.search(request).await {
-                    Ok(res) => res.into_inner(),
-                    Err(err) => {
-                        log::error!("search->grpc: node: {}, search err: {:?}", &node.grpc_addr, err);
-                        if err.code() == tonic::Code::Internal {
-                            let err = ErrorCodes::from_json(err.message())?;
-                            return Err(Error::ErrorCode(err));
-                        }
-                        return Err(server_internal_error("search node error"));
-                    }
-                };
-
-                log::info!(
-                    "search->grpc: result node: {}, is_querier: {}, total: {}, took: {}, files: {}, scan_size: {}",
-                    &node.grpc_addr,
-                    is_querier,
-                    response.total,
-                    response.took,
-                    response.scan_stats.as_ref().unwrap().files,
-                    response.scan_stats.as_ref().unwrap().original_size,
-                );
-                Ok(response)
-            }
-            .instrument(grpc_span),
-        );
+        let task = tokio::task::spawn(search_node(node, req).instrument(grpc_span));
         tasks.push(task);
     }
 
@@ -338,90 +500,86 @@ async fn search_in_cluster(req: cluster_rpc::SearchRequest) -> Result<search::Re
         };
     }
 
-    // final result
-    let mut result = search::Response::new(sql.meta.offset, sql.meta.limit);
-
-    // hits
-    let query_type = req.query.as_ref().unwrap().query_type.to_lowercase();
-    let empty_vec = vec![];
-    let batches_query = match batches.get("query") {
-        Some(batches) => batches,
-        None => &empty_vec,
-    };
-    if !batches_query.is_empty() {
-        let batches_query_ref: Vec<&RecordBatch> = batches_query[0].iter().collect();
-        let json_rows = match arrow_json::writer::record_batches_to_json_rows(&batches_query_ref) {
-            Ok(res) => res,
-            Err(err) => {
-                return Err(Error::ErrorCode(ErrorCodes::ServerInternalError(
-                    err.to_string(),
-                )))
-            }
-        };
-        let mut sources: Vec<json::Value> =
-            json_rows.into_iter().map(json::Value::Object).collect();
-
-        // handle metrics response
-        if query_type == "metrics" {
-            sources = handle_metrics_response(sources);
-        }
-
-        if sql.uses_zo_fn {
-            for source in sources {
-                result.add_hit(&flatten::flatten(&source).unwrap());
-            }
-        } else {
-            for source in sources {
-                result.add_hit(&source);
-            }
-        }
-    }
+    Ok(ClusterResult {
+        sql,
+        batches,
+        scan_stats,
+        took_wait,
+    })
+}
 
-    // aggs
-    for (name, batch) in batches {
-        if name == "query" || batch.is_empty() {
-            continue;
-        }
-        let name = name.strip_prefix("agg_").unwrap().to_string();
-        let batch_ref: Vec<&RecordBatch> = batch[0].iter().collect();
-        let json_rows = match arrow_json::writer::record_batches_to_json_rows(&batch_ref) {
-            Ok(res) => res,
-            Err(err) => {
-                return Err(Error::ErrorCode(ErrorCodes::ServerInternalError(
-                    err.to_string(),
-                )));
+/// Sends a search request to a node of the cluster.
+async fn search_node(
+    node: cluster::Node,
+    req: cluster_rpc::SearchRequest,
+) -> Result<cluster_rpc::SearchResponse, Error> {
+    let org_id: MetadataValue<_> = req
+        .org_id
+        .parse()
+        .map_err(|_| Error::Message("invalid org_id".to_string()))?;
+    let mut request = tonic::Request::new(req);
+    // request.set_timeout(Duration::from_secs(CONFIG.grpc.timeout));
+
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(
+            &tracing::Span::current().context(),
+            &mut MetadataMap(request.metadata_mut()),
+        )
+    });
+
+    let token: MetadataValue<_> = cluster::get_internal_grpc_token()
+        .parse()
+        .map_err(|_| Error::Message("invalid token".to_string()))?;
+    let channel = Channel::from_shared(node.grpc_addr.clone())
+        .unwrap()
+        .connect()
+        .await
+        .map_err(|err| {
+            log::error!(
+                "search->grpc: node: {}, connect err: {:?}",
+                &node.grpc_addr,
+                err
+            );
+            server_internal_error("connect search node error")
+        })?;
+    let mut client = cluster_rpc::search_client::SearchClient::with_interceptor(
+        channel,
+        move |mut req: Request<()>| {
+            req.metadata_mut().insert("authorization", token.clone());
+            req.metadata_mut()
+                .insert(CONFIG.grpc.org_header_key.as_str(), org_id.clone());
+            Ok(req)
+        },
+    );
+    client = client
+        .send_compressed(CompressionEncoding::Gzip)
+        .accept_compressed(CompressionEncoding::Gzip);
+    let response: cluster_rpc::SearchResponse = match client.search(request).await {
+        Ok(res) => res.into_inner(),
+        Err(err) => {
+            log::error!(
+                "search->grpc: node: {}, search err: {:?}",
+                &node.grpc_addr,
+                err
+            );
+            if err.code() == tonic::Code::Internal {
+                let err = ErrorCodes::from_json(err.message())?;
+                return Err(Error::ErrorCode(err));
             }
-        };
-        let sources: Vec<json::Value> = json_rows.into_iter().map(json::Value::Object).collect();
-        for source in sources {
-            result.add_agg(&name, &source);
+            return Err(server_internal_error("search node error"));
         }
-    }
-
-    // total
-    let total = match result.aggs.get("_count") {
-        Some(v) => v.get(0).unwrap().get("num").unwrap().as_u64().unwrap() as usize,
-        None => result.hits.len(),
     };
-    result.aggs.remove("_count");
-
-    result.set_total(total);
-    result.set_cluster_took(start.elapsed().as_millis() as usize, took_wait);
-    result.set_file_count(scan_stats.files as usize);
-    result.set_scan_size(scan_stats.original_size as usize);
-
-    if query_type == "metrics" {
-        result.response_type = "matrix".to_string();
-    }
 
     log::info!(
-        "search->result: total: {}, took: {}, scan_size: {}",
-        result.total,
-        result.took,
-        result.scan_size,
-    );
-
-    Ok(result)
+            "search->grpc: result node: {}, is_querier: {}, total: {}, took: {}, files: {}, scan_size: {}",
+            &node.grpc_addr,
+            cluster::is_querier(&node.role),
+            response.total,
+            response.took,
+            response.scan_stats.as_ref().unwrap().files,
+            response.scan_stats.as_ref().unwrap().original_size,
+        );
+    Ok(response)
 }
 
 fn handle_metrics_response(sources: Vec<json::Value>) -> Vec<json::Value> {
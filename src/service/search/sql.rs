@@ -55,6 +55,7 @@ static RE_HISTOGRAM: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)histogram\(([^\
 static RE_MATCH_ALL: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)match_all\('([^']*)'\)").unwrap());
 static RE_MATCH_ALL_IGNORE_CASE: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"(?i)match_all_ignore_case\('([^']*)'\)").unwrap());
+static RE_OR_NOT: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\b(or|not)\b").unwrap());
 
 #[derive(Clone, Debug, Serialize)]
 pub struct Sql {
@@ -63,6 +64,7 @@ pub struct Sql {
     pub stream_name: String,
     pub meta: MetaSql,
     pub fulltext: Vec<(String, String)>,
+    pub index_terms: Vec<String>, // full text terms every matching file contains
     pub aggs: AHashMap<String, (String, MetaSql)>,
     pub fields: Vec<String>,
     pub sql_mode: SqlMode,
@@ -365,6 +367,13 @@ impl Sql {
                 }
             }
         }
+        // the files must contain all the terms, unless they can be in an OR or NOT
+        let index_terms = if where_tokens.iter().any(|v| RE_OR_NOT.is_match(v)) {
+            vec![]
+        } else {
+            fulltext.iter().map(|(_, term)| term.to_owned()).collect()
+        };
+
         // fetch fts fields
         let fts_fields = get_stream_setting_fts_fields(&schema).unwrap();
         let match_all_fields = if !fts_fields.is_empty() {
@@ -593,6 +602,7 @@ impl Sql {
             stream_name,
             meta,
             fulltext,
+            index_terms,
             aggs,
             fields: vec![],
             sql_mode,
@@ -639,7 +649,7 @@ impl Sql {
             .iter()
             .map(|(k, v, _)| (k.as_str(), v.as_str()))
             .collect::<Vec<(_, _)>>();
-        match_source(
+        if !match_source(
             StreamParams::new(&self.org_id, &self.stream_name, stream_type),
             self.meta.time_range,
             &filters,
@@ -648,6 +658,29 @@ impl Sql {
             match_min_ts_only,
         )
         .await
+        {
+            return false;
+        }
+        is_wal || self.match_field_summary(source)
+    }
+
+    /// Checks the equality filters on string fields against the values of the file, the
+    /// files without a summary of the field always match.
+    fn match_field_summary(&self, source: &FileKey) -> bool {
+        if source.meta.field_summary.is_empty() {
+            return true;
+        }
+        self.meta.equal_filters.iter().all(|(field, value)| {
+            let is_string = self
+                .schema
+                .field_with_name(field)
+                .map(|f| f.data_type() == &DataType::Utf8)
+                .unwrap_or_default();
+            match source.meta.field_summary.get(field) {
+                Some(summary) if is_string => summary.may_contain(value),
+                _ => true,
+            }
+        })
     }
 }
 
@@ -1020,4 +1053,14 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_or_not_tokens() {
+        let tokens = split_sql_token("match_all('timeout') AND (code = 500 OR code = 503)");
+        assert!(tokens.iter().any(|v| RE_OR_NOT.is_match(v)));
+        let tokens = split_sql_token("NOT match_all('timeout')");
+        assert!(tokens.iter().any(|v| RE_OR_NOT.is_match(v)));
+        let tokens = split_sql_token("match_all('error') AND host = 'order-1'");
+        assert!(!tokens.iter().any(|v| RE_OR_NOT.is_match(v)));
+    }
 }
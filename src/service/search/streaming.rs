@@ -0,0 +1,539 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Streaming search: the WAL of the ingesters and then the files are searched in rounds,
+// each querier gets a chunk of `ZO_SEARCH_STREAM_CHUNK_FILES` files and its hits are sent
+// as soon as it answers instead of being merged in memory. A page ends with the round
+// which reached the requested size, its trailer holds the cursor of the next page. Hits
+// are ordered within a chunk only, so aggregations, GROUP BY, LIMIT and OFFSET are
+// rejected.
+//
+// The pages search the same files: the files created after the first page started are
+// skipped, their data was in the WAL searched by the first page or is after its end time.
+// Files merged by compaction between two pages are the exception: their hits would be
+// missed, so the page ends with an error in its trailer and without a cursor instead.
+
+use ::datafusion::arrow::{
+    datatypes::{Field, Schema},
+    ipc, json as arrow_json,
+    record_batch::RecordBatch,
+};
+use bytes::Bytes;
+use std::{
+    cmp::min,
+    collections::HashMap,
+    io::Cursor,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::mpsc::{self, error::TrySendError},
+    task::JoinSet,
+};
+use tracing::{info_span, Instrument};
+use uuid::Uuid;
+
+use super::{get_file_list, search_node, server_internal_error, sql::Sql};
+use crate::common::{
+    infra::{cluster, config::CONFIG, dist_lock, errors::Error, ider},
+    meta::{
+        common::FileKey,
+        search::{self, StreamChunk, StreamCursor, StreamFormat, StreamTrailer},
+        usage::{RequestStats, UsageType},
+        StreamType,
+    },
+    utils::{flatten, json},
+};
+use crate::handler::grpc::cluster_rpc;
+use crate::service::{stream, usage::report_request_usage_stats};
+
+/// Row limit of the chunk queries, a chunk is bounded by its files.
+const CHUNK_LIMIT: usize = i32::MAX as usize;
+
+/// Starts a page of a streaming search, the chunks and the trailer are sent to the
+/// returned channel. The request is checked before, so its errors are returned here.
+#[tracing::instrument(name = "service:search:stream", skip(req, cursor))]
+pub async fn search(
+    org_id: &str,
+    stream_type: StreamType,
+    req: &search::Request,
+    cursor: Option<&str>,
+    format: StreamFormat,
+) -> Result<mpsc::Receiver<Bytes>, Error> {
+    if !req.aggs.is_empty() || req.query.from > 0 {
+        return Err(Error::Message(
+            "streaming search doesn't support aggs or from, use the cursor to paginate".to_string(),
+        ));
+    }
+    let mut cursor = match cursor {
+        Some(cursor) => StreamCursor::decode(cursor).map_err(|e| Error::Message(e.to_string()))?,
+        None => StreamCursor {
+            end_time: if req.query.end_time > 0 {
+                req.query.end_time
+            } else {
+                chrono::Utc::now().timestamp_micros()
+            },
+            ..Default::default()
+        },
+    };
+    let timeout = if req.timeout > 0 {
+        min(req.timeout as u64, CONFIG.limit.query_timeout)
+    } else {
+        CONFIG.limit.query_timeout
+    };
+
+    let mut rpc_req: cluster_rpc::SearchRequest = req.to_owned().into();
+    rpc_req.org_id = org_id.to_string();
+    rpc_req.stype = cluster_rpc::SearchType::User as i32;
+    rpc_req.stream_type = stream_type.to_string();
+    rpc_req.timeout = timeout as i64;
+    if let Some(query) = rpc_req.query.as_mut() {
+        query.size = CHUNK_LIMIT as i32;
+        query.end_time = cursor.end_time;
+    }
+    let sql = Sql::new(&rpc_req).await?;
+    if !sql.meta.group_by.is_empty() || sql.meta.offset > 0 || sql.meta.limit != CHUNK_LIMIT {
+        return Err(Error::Message(
+            "streaming search doesn't support GROUP BY, LIMIT or OFFSET".to_string(),
+        ));
+    }
+
+    let (tx, rx) = mpsc::channel(1);
+    let mut page = Page {
+        sql: Arc::new(sql),
+        req: rpc_req,
+        stream_type,
+        format,
+        timeout,
+        tx,
+        total: 0,
+        scan_size: 0,
+        files_changed: false,
+    };
+    let page_size = req.query.size.max(1);
+    let org_id = org_id.to_string();
+    let num_fn = req.query.query_fn.is_some() as u16;
+    tokio::task::spawn(async move {
+        let start = Instant::now();
+        let ret = page.run(&mut cursor, page_size).await;
+        let trailer = StreamTrailer {
+            cursor: match ret {
+                Ok(false) => None,
+                _ if page.files_changed => None,
+                _ => Some(cursor.encode()),
+            },
+            total: page.total,
+            took: start.elapsed().as_millis() as usize,
+            scan_size: page.scan_size,
+            error: ret.err().map(|e| {
+                log::error!("search->stream: error: {}", e);
+                e.to_string()
+            }),
+        };
+        let req_stats = RequestStats {
+            records: page.total as i64,
+            response_time: start.elapsed().as_secs_f64(),
+            size: page.scan_size as f64,
+            request_body: Some(page.sql.origin_sql.clone()),
+            ..Default::default()
+        };
+        report_request_usage_stats(
+            req_stats,
+            &org_id,
+            &page.sql.stream_name,
+            page.stream_type,
+            UsageType::Search,
+            num_fn,
+        )
+        .await;
+        match encode_trailer(page.format, &trailer) {
+            Ok(data) => {
+                _ = page.tx.send(data).await;
+            }
+            Err(e) => log::error!("search->stream: encode trailer error: {}", e),
+        }
+    });
+    Ok(rx)
+}
+
+struct Page {
+    sql: Arc<Sql>,
+    req: cluster_rpc::SearchRequest,
+    stream_type: StreamType,
+    format: StreamFormat,
+    /// Time limit of each chunk, in seconds
+    timeout: u64,
+    tx: mpsc::Sender<Bytes>,
+    total: usize,
+    scan_size: usize,
+    /// Files left to search by the cursor were merged by compaction since the first page
+    files_changed: bool,
+}
+
+impl Page {
+    /// Searches rounds of chunks from the cursor until the page is full, returns whether
+    /// there may be more hits.
+    async fn run(&mut self, cursor: &mut StreamCursor, page_size: usize) -> Result<bool, Error> {
+        let mut nodes = cluster::get_cached_online_query_nodes().unwrap_or_default();
+        nodes.sort_by_key(|x| x.id);
+
+        if !cursor.wal_done {
+            cursor.created_before = chrono::Utc::now().timestamp_micros();
+        }
+        let stream_settings = stream::stream_settings(&self.sql.schema).unwrap_or_default();
+        let partition_time_level = stream::unwrap_partition_time_level(
+            stream_settings.partition_time_level,
+            self.stream_type,
+        );
+        let mut file_list = get_file_list(&self.sql, self.stream_type, partition_time_level).await;
+        file_list.retain(|f| file_created_at(&f.key) <= cursor.created_before);
+        let mut files = &file_list[file_list.partition_point(|f| f.key <= cursor.file_key)..];
+        if cursor
+            .files_digest
+            .map_or(false, |digest| digest != files_digest(files))
+        {
+            self.files_changed = true;
+            return Err(Error::Message(
+                "files of the search were merged by compaction since the first page, start the search again".to_string(),
+            ));
+        }
+        cursor.files_digest = Some(files_digest(files));
+
+        if !cursor.wal_done {
+            let chunks = nodes
+                .iter()
+                .filter(|node| cluster::is_ingester(&node.role))
+                .map(|node| {
+                    let mut req = self.req.clone();
+                    req.stype = cluster_rpc::SearchType::WalOnly as i32;
+                    (node.clone(), req)
+                })
+                .collect();
+            self.search_round(chunks).await?;
+            cursor.wal_done = true;
+            if self.total >= page_size {
+                return Ok(true);
+            }
+        }
+
+        let queriers = nodes
+            .into_iter()
+            .filter(|node| cluster::is_querier(&node.role))
+            .collect::<Vec<_>>();
+        if queriers.is_empty() && !files.is_empty() {
+            return Err(server_internal_error("no querier node online"));
+        }
+        log::info!(
+            "search->stream: time_range: {:?}, files: {}, queriers: {}",
+            self.sql.meta.time_range,
+            files.len(),
+            queriers.len()
+        );
+
+        let chunk_files = CONFIG.limit.search_stream_chunk_files.max(1);
+        while !files.is_empty() {
+            let (round, rest) = files.split_at(min(files.len(), chunk_files * queriers.len()));
+            let chunks = round
+                .chunks(chunk_files)
+                .zip(queriers.iter())
+                .map(|(files, node)| {
+                    let mut req = self.req.clone();
+                    req.stype = cluster_rpc::SearchType::Cluster as i32;
+                    req.file_list = files.iter().map(cluster_rpc::FileKey::from).collect();
+                    (node.clone(), req)
+                })
+                .collect();
+            self.search_round(chunks).await?;
+            cursor.file_key = round.last().unwrap().key.clone();
+            files = rest;
+            cursor.files_digest = Some(files_digest(files));
+            if self.total >= page_size {
+                return Ok(!files.is_empty());
+            }
+        }
+        Ok(false)
+    }
+
+    /// Sends the chunks to their nodes and their hits to the client as they finish. The
+    /// queue lock isn't held waiting for the client: the hits it isn't ready for are kept
+    /// and sent once the round is done.
+    async fn search_round(
+        &mut self,
+        chunks: Vec<(cluster::Node, cluster_rpc::SearchRequest)>,
+    ) -> Result<(), Error> {
+        if chunks.is_empty() {
+            return Ok(());
+        }
+        let locker = dist_lock::lock("search/cluster_queue", 0).await?;
+
+        let mut session_id = Uuid::new_v4().to_string();
+        let job = cluster_rpc::Job {
+            session_id: session_id.clone(),
+            job: session_id.split_off(30), // take the last 6 characters as job id
+            stage: 0,
+            partition: 0,
+        };
+        let timeout = Duration::from_secs(self.timeout);
+        let mut tasks = JoinSet::new();
+        for (partition_no, (node, mut req)) in chunks.into_iter().enumerate() {
+            let mut job = job.clone();
+            job.partition = partition_no as i32;
+            req.job = Some(job);
+            let grpc_span = info_span!("service:search:stream:grpc_search", org_id = req.org_id);
+            tasks
+                .spawn(tokio::time::timeout(timeout, search_node(node, req)).instrument(grpc_span));
+        }
+
+        // dropping the tasks aborts the chunks left after an error
+        let mut ret = Ok(());
+        let mut pending = Vec::new();
+        while let Some(result) = tasks.join_next().await {
+            let result = match result {
+                Ok(Ok(result)) => result,
+                Ok(Err(_)) => Err(Error::Message(format!(
+                    "search chunk timed out after {} seconds",
+                    self.timeout
+                ))),
+                Err(err) => Err(server_internal_error(err)),
+            };
+            let data = match result.and_then(|resp| self.encode_chunk(resp)) {
+                Ok(Some(data)) => data,
+                Ok(None) => continue,
+                Err(err) => {
+                    ret = Err(err);
+                    break;
+                }
+            };
+            if !pending.is_empty() {
+                pending.push(data);
+                continue;
+            }
+            match self.tx.try_send(data) {
+                Ok(()) => {}
+                Err(TrySendError::Full(data)) => pending.push(data),
+                Err(TrySendError::Closed(_)) => {
+                    ret = Err(client_disconnected());
+                    break;
+                }
+            }
+        }
+        drop(tasks);
+
+        // search done, release lock
+        dist_lock::unlock(&locker).await?;
+        ret?;
+        for data in pending {
+            self.tx
+                .send(data)
+                .await
+                .map_err(|_| client_disconnected())?;
+        }
+        Ok(())
+    }
+
+    /// Encodes the hits of a chunk in the page format, `None` if there are none.
+    fn encode_chunk(&mut self, resp: cluster_rpc::SearchResponse) -> Result<Option<Bytes>, Error> {
+        if let Some(scan_stats) = resp.scan_stats.as_ref() {
+            self.scan_size += scan_stats.original_size as usize;
+        }
+        if resp.hits.is_empty() {
+            return Ok(None);
+        }
+        let reader = ipc::reader::FileReader::try_new(Cursor::new(resp.hits), None)?;
+        let batches = reader
+            .into_iter()
+            .filter(|batch| !matches!(batch, Ok(batch) if batch.num_rows() == 0))
+            .collect::<Result<Vec<_>, _>>()?;
+        if batches.is_empty() {
+            return Ok(None);
+        }
+        let data = match self.format {
+            StreamFormat::Json => encode_json(&batches, self.sql.uses_zo_fn)?,
+            StreamFormat::Arrow => encode_arrow(&batches)?,
+        };
+        self.total += batches.iter().map(|batch| batch.num_rows()).sum::<usize>();
+        Ok(Some(data))
+    }
+}
+
+fn client_disconnected() -> Error {
+    Error::Message("client disconnected".to_string())
+}
+
+/// Returns the time a file was created at from the id in its name, files with another
+/// name are taken as old.
+fn file_created_at(key: &str) -> i64 {
+    key.rsplit('/')
+        .next()
+        .and_then(ider::timestamp_micros)
+        .unwrap_or_default()
+}
+
+fn files_digest(files: &[FileKey]) -> u64 {
+    let mut hasher = xxhash_rust::xxh3::Xxh3::new();
+    for file in files {
+        hasher.update(file.key.as_bytes());
+        hasher.update(b"\n");
+    }
+    hasher.digest()
+}
+
+fn encode_json(batches: &[RecordBatch], uses_zo_fn: bool) -> Result<Bytes, Error> {
+    let batches_ref: Vec<&RecordBatch> = batches.iter().collect();
+    let json_rows = arrow_json::writer::record_batches_to_json_rows(&batches_ref)?;
+    let mut hits = Vec::with_capacity(json_rows.len());
+    for row in json_rows {
+        let hit = json::Value::Object(row);
+        hits.push(if uses_zo_fn {
+            flatten::flatten(&hit).map_err(server_internal_error)?
+        } else {
+            hit
+        });
+    }
+    let mut data = json::to_vec(&StreamChunk { hits })?;
+    data.push(b'\n');
+    Ok(data.into())
+}
+
+fn encode_arrow(batches: &[RecordBatch]) -> Result<Bytes, Error> {
+    let mut writer = ipc::writer::StreamWriter::try_new(Vec::new(), &batches[0].schema())?;
+    for batch in batches {
+        writer.write(batch)?;
+    }
+    writer.finish()?;
+    Ok(writer.into_inner()?.into())
+}
+
+/// The Arrow trailer is a stream without columns, the fields of the JSON trailer are its
+/// schema metadata.
+fn encode_trailer(format: StreamFormat, trailer: &StreamTrailer) -> Result<Bytes, Error> {
+    match format {
+        StreamFormat::Json => {
+            let mut data = json::to_vec(trailer)?;
+            data.push(b'\n');
+            Ok(data.into())
+        }
+        StreamFormat::Arrow => {
+            let mut metadata = HashMap::from([
+                ("total".to_string(), trailer.total.to_string()),
+                ("took".to_string(), trailer.took.to_string()),
+                ("scan_size".to_string(), trailer.scan_size.to_string()),
+            ]);
+            if let Some(cursor) = trailer.cursor.as_ref() {
+                metadata.insert("cursor".to_string(), cursor.clone());
+            }
+            if let Some(error) = trailer.error.as_ref() {
+                metadata.insert("error".to_string(), error.clone());
+            }
+            let schema = Schema::new_with_metadata(Vec::<Field>::new(), metadata);
+            let mut writer = ipc::writer::StreamWriter::try_new(Vec::new(), &schema)?;
+            writer.finish()?;
+            Ok(writer.into_inner()?.into())
+        }
+    }
+}
+
+/// Reads a chunk of an Arrow page, the trailer has no batches and may have an error.
+pub(crate) fn decode_arrow(data: &[u8]) -> Result<Vec<RecordBatch>, Error> {
+    let reader = ipc::reader::StreamReader::try_new(Cursor::new(data), None)?;
+    if let Some(error) = reader.schema().metadata.get("error") {
+        return Err(Error::Message(error.clone()));
+    }
+    Ok(reader.collect::<Result<Vec<_>, _>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::datafusion::arrow::{
+        array::{Int64Array, StringArray},
+        datatypes::DataType,
+    };
+
+    fn batch() -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("_timestamp", DataType::Int64, false),
+            Field::new("log", DataType::Utf8, true),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from(vec![1, 2])),
+                Arc::new(StringArray::from(vec![Some("a"), None])),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_file_created_at() {
+        assert_eq!(
+            file_created_at("files/default/logs/app/2023/01/28/06/7024989428549181440.parquet"),
+            1674887997758000
+        );
+        assert_eq!(file_created_at("files/default/logs/app/old.parquet"), 0);
+    }
+
+    #[test]
+    fn test_encode_json() {
+        let data = encode_json(&[batch(), batch()], false).unwrap();
+        assert_eq!(data.iter().filter(|c| **c == b'\n').count(), 1);
+        let chunk: StreamChunk = json::from_slice(&data).unwrap();
+        assert_eq!(chunk.hits.len(), 4);
+        assert_eq!(chunk.hits[0], json::json!({"_timestamp": 1, "log": "a"}));
+    }
+
+    #[test]
+    fn test_encode_arrow() {
+        let mut data = encode_arrow(&[batch()]).unwrap().to_vec();
+        let trailer = StreamTrailer {
+            cursor: Some("abc".to_string()),
+            total: 2,
+            ..Default::default()
+        };
+        let trailer = encode_trailer(StreamFormat::Arrow, &trailer).unwrap();
+        let chunk_len = data.len();
+        data.extend_from_slice(&trailer);
+
+        // each chunk is a whole stream, readers start again after its end
+        let batches = ipc::reader::StreamReader::try_new(&data[..chunk_len], None)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(batches[0].num_rows(), 2);
+        let mut trailer = ipc::reader::StreamReader::try_new(&data[chunk_len..], None).unwrap();
+        assert!(trailer.next().is_none());
+        let metadata = &trailer.schema().metadata;
+        assert_eq!(metadata.get("cursor").unwrap(), "abc");
+        assert_eq!(metadata.get("total").unwrap(), "2");
+        assert!(metadata.get("error").is_none());
+    }
+
+    #[test]
+    fn test_decode_arrow() {
+        let data = encode_arrow(&[batch(), batch()]).unwrap();
+        let batches = decode_arrow(&data).unwrap();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0], batch());
+
+        let trailer = StreamTrailer {
+            error: Some("search failed".to_string()),
+            ..Default::default()
+        };
+        let data = encode_trailer(StreamFormat::Arrow, &trailer).unwrap();
+        assert!(decode_arrow(&data).is_err());
+        let data = encode_trailer(StreamFormat::Arrow, &Default::default()).unwrap();
+        assert!(decode_arrow(&data).unwrap().is_empty());
+    }
+}
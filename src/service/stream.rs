@@ -20,7 +20,8 @@ use crate::common::{
     infra::{
         cache::stats,
         config::{
-            is_local_disk_storage, CONFIG, SIZE_IN_MB, SQL_FULL_TEXT_SEARCH_FIELDS, STREAM_SCHEMAS,
+            is_local_disk_storage, COLUMN_TRACE_ID, CONFIG, SIZE_IN_MB,
+            SQL_FULL_TEXT_SEARCH_FIELDS, STREAM_SCHEMAS,
         },
     },
     meta::{
@@ -33,7 +34,7 @@ use crate::common::{
     },
     utils::json,
 };
-use crate::service::{db, metrics::get_prom_metadata_from_schema, search as SearchService};
+use crate::service::{db, metrics::get_prom_metadata_from_schema, search as SearchService, traces};
 
 const LOCAL: &str = "disk";
 const S3: &str = "s3";
@@ -179,6 +180,30 @@ pub async fn save_stream_settings(
         }
     }
 
+    for key in setting.bloom_filter_fields.iter() {
+        if key.eq(&CONFIG.common.column_timestamp) {
+            return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+                http::StatusCode::BAD_REQUEST.into(),
+                format!("field [{key}] can't be used for bloom filter"),
+            )));
+        }
+    }
+
+    if let Some(sampling) = &setting.trace_sampling {
+        if stream_type != StreamType::Traces {
+            return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+                http::StatusCode::BAD_REQUEST.into(),
+                "trace sampling is only supported for traces streams".to_string(),
+            )));
+        }
+        if let Err(e) = traces::sampling::validate(sampling) {
+            return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+                http::StatusCode::BAD_REQUEST.into(),
+                e,
+            )));
+        }
+    }
+
     let schema = db::schema::get(org_id, stream_name, stream_type)
         .await
         .unwrap();
@@ -275,6 +300,25 @@ pub fn get_stream_setting_fts_fields(schema: &Schema) -> Result<Vec<String>, any
     }
 }
 
+/// Columns written with bloom filters and page indexes: the stream setting, plus the
+/// trace id of traces streams if `ZO_TRACES_BLOOM_FILTER_ENABLED`.
+pub fn get_stream_setting_bloom_filter_fields(
+    schema: &Schema,
+    stream_type: StreamType,
+) -> Vec<String> {
+    let mut fields = match stream_settings(schema) {
+        Some(setting) => setting.bloom_filter_fields,
+        None => vec![],
+    };
+    if CONFIG.common.traces_bloom_filter_enabled
+        && stream_type == StreamType::Traces
+        && !fields.iter().any(|v| v == COLUMN_TRACE_ID)
+    {
+        fields.push(COLUMN_TRACE_ID.to_string());
+    }
+    fields
+}
+
 fn transform_stats(stats: &mut StreamStats) {
     stats.storage_size /= SIZE_IN_MB;
     stats.compressed_size /= SIZE_IN_MB;
@@ -435,4 +479,21 @@ mod test {
         let res = get_stream_setting_fts_fields(&sch);
         assert!(res.is_ok());
     }
+
+    #[test]
+    fn test_get_stream_setting_bloom_filter_fields() {
+        let settings = StreamSettings {
+            bloom_filter_fields: vec!["request_id".to_string()],
+            ..Default::default()
+        };
+        let sch = Schema::new(vec![Field::new("request_id", DataType::Utf8, false)]).with_metadata(
+            HashMap::from([("settings".to_string(), json::to_string(&settings).unwrap())]),
+        );
+        assert_eq!(
+            get_stream_setting_bloom_filter_fields(&sch, StreamType::Logs),
+            vec!["request_id".to_string()]
+        );
+        let sch = Schema::new(vec![Field::new("f.c", DataType::Int32, false)]);
+        assert!(get_stream_setting_bloom_filter_fields(&sch, StreamType::Logs).is_empty());
+    }
 }
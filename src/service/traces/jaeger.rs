@@ -0,0 +1,586 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use chrono::{Duration, Utc};
+use std::collections::{BTreeMap, HashMap};
+
+use super::query::{self, Span};
+use crate::common::{
+    infra::errors::{Error, Result},
+    meta::{
+        search,
+        traces::{
+            JaegerDependency, JaegerLog, JaegerSpan, JaegerTrace, JaegerTraceQuery, KeyValue,
+            Service, SpanRefType, SpanReference, TraceSearchQuery,
+        },
+        StreamType,
+    },
+    utils::{
+        flatten::format_key,
+        json::{self, Value},
+        time::parse_milliseconds,
+    },
+};
+use crate::service::search as SearchService;
+
+/// Services and operations are listed from the spans of this many days.
+const LIST_LOOKBACK_DAYS: i64 = 7;
+/// Traces are looked up by id in this many days, unless the request has a time range.
+const TRACE_LOOKBACK_DAYS: i64 = 7;
+const MAX_LIST_VALUES: usize = 10000;
+const DEFAULT_TRACES_LIMIT: usize = 20;
+
+const SERVICE_PREFIX: &str = "service_";
+/// Columns of the stored span which aren't span tags.
+const SPAN_COLUMNS: [&str; 14] = [
+    "trace_id",
+    "span_id",
+    "flags",
+    "span_status",
+    "span_kind",
+    "operation_name",
+    "start_time",
+    "end_time",
+    "duration",
+    "reference_parent_span_id",
+    "reference_parent_trace_id",
+    "reference_ref_type",
+    "service_name",
+    "events",
+];
+
+pub async fn services(org_id: &str, stream_name: &str) -> Result<Vec<String>> {
+    list_values(org_id, stream_name, "service_name", None).await
+}
+
+pub async fn operations(org_id: &str, stream_name: &str, service: &str) -> Result<Vec<String>> {
+    let filter = format!("service_name = {}", query::quote(service));
+    list_values(org_id, stream_name, "operation_name", Some(filter)).await
+}
+
+async fn list_values(
+    org_id: &str,
+    stream_name: &str,
+    field: &str,
+    filter: Option<String>,
+) -> Result<Vec<String>> {
+    if !query::is_valid_stream_name(stream_name) {
+        return Err(Error::Message(format!(
+            "Invalid stream name: {stream_name}"
+        )));
+    }
+    let where_clause = filter.map(|f| format!("WHERE {f}")).unwrap_or_default();
+    let sql = format!(
+        "SELECT {field} FROM \"{stream_name}\" {where_clause} GROUP BY {field} ORDER BY {field} LIMIT {MAX_LIST_VALUES}"
+    );
+    let end_time = Utc::now().timestamp_micros();
+    let req = search::Request {
+        query: search::Query {
+            sql,
+            size: MAX_LIST_VALUES,
+            start_time: end_time
+                - Duration::days(LIST_LOOKBACK_DAYS)
+                    .num_microseconds()
+                    .unwrap(),
+            end_time,
+            sql_mode: "full".to_string(),
+            ..Default::default()
+        },
+        aggs: Default::default(),
+        encoding: search::RequestEncoding::Empty,
+        timeout: 0,
+    };
+    let resp = SearchService::search(org_id, StreamType::Traces, &req).await?;
+    Ok(resp
+        .hits
+        .iter()
+        .filter_map(|hit| hit.get(field).and_then(Value::as_str))
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_string())
+        .collect())
+}
+
+/// Finds the traces matching `filters`, see [`trace_filters`] and [`time_range`] for
+/// converting the query parameters.
+pub async fn find_traces(
+    org_id: &str,
+    stream_name: &str,
+    filters: &[String],
+    start_time: i64,
+    end_time: i64,
+    limit: Option<usize>,
+) -> Result<Vec<JaegerTrace>> {
+    let size = limit.filter(|v| *v > 0).unwrap_or(DEFAULT_TRACES_LIMIT);
+    let (traces, truncated) =
+        query::find_traces(org_id, stream_name, filters, start_time, end_time, size).await?;
+    if truncated {
+        log::warn!("[JAEGER] traces of {org_id}/{stream_name} truncated, too many spans");
+    }
+    Ok(traces
+        .into_iter()
+        .map(|(trace_id, spans)| to_jaeger_trace(&trace_id, spans))
+        .collect())
+}
+
+/// Looks up a trace by id, see [`trace_time_range`] for the time range.
+pub async fn get_trace(
+    org_id: &str,
+    stream_name: &str,
+    trace_id: &str,
+    start_time: i64,
+    end_time: i64,
+) -> Result<Option<JaegerTrace>> {
+    let (spans, truncated) =
+        query::get_spans(org_id, stream_name, trace_id, start_time, end_time).await?;
+    if truncated {
+        log::warn!("[JAEGER] trace {org_id}/{stream_name}/{trace_id} truncated, too many spans");
+    }
+    if spans.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(to_jaeger_trace(trace_id, spans)))
+}
+
+/// Returns the calls between services, from the service graph of the time range.
+pub async fn dependencies(
+    org_id: &str,
+    stream_name: &str,
+    start_time: i64,
+    end_time: i64,
+) -> Result<Vec<JaegerDependency>> {
+    let graph = query::service_graph(org_id, stream_name, start_time, end_time).await?;
+    Ok(graph
+        .edges
+        .into_iter()
+        .map(|edge| JaegerDependency {
+            parent: edge.source,
+            child: edge.target,
+            call_count: edge.call_count,
+        })
+        .collect())
+}
+
+/// Jaeger sends trace ids without leading zeros, the stored ones have 32 digits.
+pub fn normalize_trace_id(trace_id: &str) -> String {
+    format!("{:0>32}", trace_id.to_lowercase())
+}
+
+/// Converts the query parameters to span filters, the error is the invalid parameter.
+pub fn trace_filters(req: &JaegerTraceQuery) -> std::result::Result<Vec<String>, String> {
+    let parse = |v: Option<&String>, name: &str| match v.filter(|v| !v.is_empty()) {
+        Some(v) => parse_duration(v)
+            .map(Some)
+            .ok_or_else(|| format!("Invalid {name}: {v}")),
+        None => Ok(None),
+    };
+    let mut filters = query::span_filters(&TraceSearchQuery {
+        service_name: req.service.clone(),
+        operation_name: req.operation.clone(),
+        min_duration: parse(req.min_duration.as_ref(), "minDuration")?,
+        max_duration: parse(req.max_duration.as_ref(), "maxDuration")?,
+        ..Default::default()
+    });
+
+    let tags = match req.tags.as_deref().filter(|v| !v.is_empty()) {
+        Some(tags) => json::from_str::<BTreeMap<String, Value>>(tags)
+            .map_err(|e| format!("Invalid tags: {e}"))?,
+        None => BTreeMap::new(),
+    };
+    for (key, value) in tags {
+        let value = match value {
+            Value::String(v) => v,
+            v => v.to_string(),
+        };
+        // the error tag is how Jaeger marks failed spans
+        if key == "error" {
+            let status = if value == "true" { "=" } else { "!=" };
+            filters.push(format!("span_status {status} 'ERROR'"));
+            continue;
+        }
+        filters.push(format!(
+            "\"{}\" = {}",
+            format_key(&key),
+            query::quote(&value)
+        ));
+    }
+    Ok(filters)
+}
+
+/// Returns the time range of the query in microseconds, `end` defaults to now and
+/// `start` to `lookback` or an hour before `end`.
+pub fn time_range(req: &JaegerTraceQuery) -> std::result::Result<(i64, i64), String> {
+    let end_time = req
+        .end
+        .filter(|v| *v > 0)
+        .unwrap_or_else(|| Utc::now().timestamp_micros());
+    if let Some(start_time) = req.start.filter(|v| *v > 0) {
+        return Ok((start_time, end_time));
+    }
+    let lookback = match req.lookback.as_deref() {
+        Some(v) if !v.is_empty() && v != "custom" => {
+            parse_milliseconds(v).map_err(|_| format!("Invalid lookback: {v}"))? as i64 * 1000
+        }
+        _ => Duration::hours(1).num_microseconds().unwrap(),
+    };
+    Ok((end_time - lookback, end_time))
+}
+
+/// Returns the time range of a trace lookup in microseconds, `end` defaults to now and
+/// `start` to `TRACE_LOOKBACK_DAYS` before `end`.
+pub fn trace_time_range(start: Option<i64>, end: Option<i64>) -> (i64, i64) {
+    let end_time = end
+        .filter(|v| *v > 0)
+        .unwrap_or_else(|| Utc::now().timestamp_micros());
+    let start_time = start.filter(|v| *v > 0).unwrap_or_else(|| {
+        end_time
+            - Duration::days(TRACE_LOOKBACK_DAYS)
+                .num_microseconds()
+                .unwrap()
+    });
+    (start_time, end_time)
+}
+
+/// Parses Go duration strings like `1.5s`, `100ms` or `1h30m` to microseconds.
+/// Negative durations aren't valid span durations.
+pub fn parse_duration(value: &str) -> Option<u64> {
+    let value = value.strip_prefix('+').unwrap_or(value);
+    if value == "0" {
+        return Some(0);
+    }
+    if value.is_empty() {
+        return None;
+    }
+    let mut micros = 0.0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let pos = rest.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+        let (number, tail) = rest.split_at(pos);
+        // `.5s` and `1.s` are valid, `.s` isn't
+        if !number.contains(|c: char| c.is_ascii_digit()) || number.matches('.').count() > 1 {
+            return None;
+        }
+        let number = format!("0{number}")
+            .trim_end_matches('.')
+            .parse::<f64>()
+            .ok()?;
+        let pos = tail
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(pos);
+        micros += number
+            * match unit {
+                "ns" => 0.001,
+                "us" | "µs" | "μs" => 1.0,
+                "ms" => 1_000.0,
+                "s" => 1_000_000.0,
+                "m" => 60_000_000.0,
+                "h" => 3_600_000_000.0,
+                _ => return None,
+            };
+        rest = tail;
+    }
+    Some(micros as u64)
+}
+
+/// Converts the stored spans of a trace to a Jaeger trace. Spans of the same service
+/// with the same service attributes share a process.
+pub fn to_jaeger_trace(trace_id: &str, mut spans: Vec<Span>) -> JaegerTrace {
+    spans.sort_by_key(|span| get_u64(span, "start_time"));
+    let mut processes: HashMap<String, Service> = HashMap::new();
+    let mut process_ids: HashMap<String, String> = HashMap::new();
+    let spans = spans
+        .into_iter()
+        .map(|span| {
+            let process = to_process(&span);
+            let key = json::to_string(&process).unwrap();
+            let process_id = process_ids
+                .entry(key)
+                .or_insert_with(|| {
+                    let id = format!("p{}", processes.len() + 1);
+                    processes.insert(id.clone(), process);
+                    id
+                })
+                .clone();
+            to_jaeger_span(trace_id, span, process_id)
+        })
+        .collect();
+    JaegerTrace {
+        trace_id: trace_id.to_string(),
+        spans,
+        processes,
+        warnings: None,
+    }
+}
+
+fn to_process(span: &Span) -> Service {
+    let tags = span
+        .iter()
+        .filter(|(k, _)| k.as_str() != "service_name")
+        .filter_map(|(k, v)| {
+            k.strip_prefix(SERVICE_PREFIX)
+                .map(|key| to_key_value(key.to_string(), v.clone()))
+        })
+        .collect();
+    Service {
+        name: get_str(span, "service_name").to_string(),
+        tags,
+    }
+}
+
+fn to_jaeger_span(trace_id: &str, span: Span, process_id: String) -> JaegerSpan {
+    let mut tags = Vec::new();
+    if let Some(kind) = span_kind(get_str(&span, "span_kind")) {
+        tags.push(to_key_value("span.kind".to_string(), kind.into()));
+    }
+    match get_str(&span, "span_status") {
+        "" | "UNSET" => {}
+        status => {
+            tags.push(to_key_value("otel.status_code".to_string(), status.into()));
+            if status == "ERROR" {
+                tags.push(to_key_value("error".to_string(), true.into()));
+            }
+        }
+    }
+    for (key, value) in span.iter() {
+        if key.starts_with('_')
+            || key.starts_with(SERVICE_PREFIX)
+            || SPAN_COLUMNS.contains(&key.as_str())
+        {
+            continue;
+        }
+        tags.push(to_key_value(key.to_string(), value.clone()));
+    }
+
+    let references = match get_str(&span, "reference_parent_span_id") {
+        "" => vec![],
+        parent_span_id => vec![SpanReference {
+            ref_type: SpanRefType::ChildOf,
+            trace_id: match get_str(&span, "reference_parent_trace_id") {
+                "" => trace_id.to_string(),
+                v => v.to_string(),
+            },
+            span_id: parent_span_id.to_string(),
+        }],
+    };
+
+    JaegerSpan {
+        trace_id: trace_id.to_string(),
+        span_id: get_str(&span, "span_id").to_string(),
+        flags: get_u64(&span, "flags") as u32,
+        operation_name: get_str(&span, "operation_name").to_string(),
+        references,
+        start_time: get_u64(&span, "start_time") / 1000,
+        duration: get_u64(&span, "duration"),
+        tags,
+        logs: to_logs(get_str(&span, "events")),
+        process_id,
+        warnings: None,
+    }
+}
+
+/// Span events are stored as JSON array with the time in nanoseconds.
+fn to_logs(events: &str) -> Vec<JaegerLog> {
+    let Ok(events) = json::from_str::<Vec<json::Map<String, Value>>>(events) else {
+        return vec![];
+    };
+    events
+        .into_iter()
+        .map(|mut event| {
+            let timestamp = event
+                .remove("_timestamp")
+                .and_then(|v| v.as_u64())
+                .unwrap_or_default()
+                / 1000;
+            let mut fields = Vec::with_capacity(event.len());
+            if let Some(name) = event.remove("name") {
+                fields.push(to_key_value("event".to_string(), name));
+            }
+            fields.extend(event.into_iter().map(|(k, v)| to_key_value(k, v)));
+            JaegerLog { timestamp, fields }
+        })
+        .collect()
+}
+
+fn to_key_value(key: String, value: Value) -> KeyValue {
+    let (value_type, value) = match value {
+        Value::Bool(_) => ("bool", value),
+        Value::Number(ref n) if n.is_f64() => ("float64", value),
+        Value::Number(_) => ("int64", value),
+        Value::String(_) => ("string", value),
+        v => ("string", Value::String(v.to_string())),
+    };
+    KeyValue {
+        key,
+        value_type: value_type.to_string(),
+        value,
+    }
+}
+
+/// Span kinds are stored as the OTLP enum value.
+fn span_kind(kind: &str) -> Option<&'static str> {
+    match kind {
+        "1" => Some("internal"),
+        "2" => Some("server"),
+        "3" => Some("client"),
+        "4" => Some("producer"),
+        "5" => Some("consumer"),
+        _ => None,
+    }
+}
+
+fn get_str<'a>(span: &'a Span, key: &str) -> &'a str {
+    span.get(key).and_then(Value::as_str).unwrap_or_default()
+}
+
+fn get_u64(span: &Span, key: &str) -> u64 {
+    span.get(key).and_then(Value::as_u64).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("1.5s"), Some(1_500_000));
+        assert_eq!(parse_duration("100ms"), Some(100_000));
+        assert_eq!(parse_duration("20us"), Some(20));
+        assert_eq!(parse_duration("2m"), Some(120_000_000));
+        assert_eq!(parse_duration("1h30m"), Some(5_400_000_000));
+        assert_eq!(parse_duration("2m3.5s"), Some(123_500_000));
+        assert_eq!(parse_duration("1s500ms"), Some(1_500_000));
+        assert_eq!(parse_duration(".5s"), Some(500_000));
+        assert_eq!(parse_duration("+1.s"), Some(1_000_000));
+        assert_eq!(parse_duration("1500ns"), Some(1));
+        assert_eq!(parse_duration("0"), Some(0));
+        assert_eq!(parse_duration("100"), None);
+        assert_eq!(parse_duration("1x"), None);
+        assert_eq!(parse_duration("1h30"), None);
+        assert_eq!(parse_duration("-1s"), None);
+        assert_eq!(parse_duration(".s"), None);
+        assert_eq!(parse_duration("1..5s"), None);
+        assert_eq!(parse_duration(""), None);
+    }
+
+    #[test]
+    fn test_normalize_trace_id() {
+        assert_eq!(
+            normalize_trace_id("8448EB211C80319C"),
+            "00000000000000008448eb211c80319c"
+        );
+    }
+
+    #[test]
+    fn test_trace_filters() {
+        let req = JaegerTraceQuery {
+            service: Some("frontend".to_string()),
+            min_duration: Some("1ms".to_string()),
+            tags: Some(r#"{"http.status_code":"500","error":"true"}"#.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            trace_filters(&req).unwrap(),
+            vec![
+                "service_name = 'frontend'",
+                "duration >= 1000",
+                "span_status = 'ERROR'",
+                "\"http_status_code\" = '500'",
+            ]
+        );
+
+        let req = JaegerTraceQuery {
+            max_duration: Some("abc".to_string()),
+            ..Default::default()
+        };
+        assert!(trace_filters(&req).is_err());
+    }
+
+    #[test]
+    fn test_to_jaeger_trace() {
+        let trace_id = "0af7651916cd43dd8448eb211c80319c";
+        let spans = vec![
+            json::json!({
+                "trace_id": trace_id,
+                "span_id": "b7ad6b7169203331",
+                "reference_parent_span_id": "00f067aa0ba902b7",
+                "reference_parent_trace_id": trace_id,
+                "reference_ref_type": "ChildOf",
+                "service_name": "backend",
+                "service_host_name": "host-1",
+                "operation_name": "GET /users",
+                "span_kind": "2",
+                "span_status": "ERROR",
+                "flags": 1,
+                "start_time": 2_000_000u64,
+                "end_time": 3_000_000u64,
+                "duration": 1000,
+                "http_status_code": 500,
+                "events": r#"[{"name":"exception","_timestamp":2500000,"message":"boom"}]"#,
+                "_timestamp": 2000,
+            }),
+            json::json!({
+                "trace_id": trace_id,
+                "span_id": "00f067aa0ba902b7",
+                "service_name": "frontend",
+                "operation_name": "GET /",
+                "span_kind": "3",
+                "span_status": "UNSET",
+                "flags": 1,
+                "start_time": 1_000_000u64,
+                "end_time": 4_000_000u64,
+                "duration": 3000,
+                "events": "[]",
+                "_timestamp": 1000,
+            }),
+        ]
+        .into_iter()
+        .map(|v| v.as_object().unwrap().clone())
+        .collect();
+
+        let trace = to_jaeger_trace(trace_id, spans);
+        assert_eq!(trace.spans.len(), 2);
+        assert_eq!(trace.processes.len(), 2);
+
+        let root = &trace.spans[0];
+        assert_eq!(root.span_id, "00f067aa0ba902b7");
+        assert!(root.references.is_empty());
+        assert_eq!(root.start_time, 1000);
+        assert_eq!(trace.processes[&root.process_id].name, "frontend");
+
+        let child = &trace.spans[1];
+        assert_eq!(
+            child.references,
+            vec![SpanReference {
+                ref_type: SpanRefType::ChildOf,
+                trace_id: trace_id.to_string(),
+                span_id: "00f067aa0ba902b7".to_string(),
+            }]
+        );
+        let process = &trace.processes[&child.process_id];
+        assert_eq!(process.name, "backend");
+        assert_eq!(process.tags[0].key, "host_name");
+        let tags = child
+            .tags
+            .iter()
+            .map(|tag| (tag.key.as_str(), tag.value.clone()))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(tags["span.kind"], "server");
+        assert_eq!(tags["error"], true);
+        assert_eq!(tags["http_status_code"], 500);
+        assert!(!tags.contains_key("duration"));
+        assert_eq!(child.logs.len(), 1);
+        assert_eq!(child.logs[0].timestamp, 2500);
+        assert_eq!(child.logs[0].fields[0].key, "event");
+    }
+}
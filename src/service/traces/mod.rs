@@ -49,7 +49,11 @@ use crate::service::{
     usage::report_request_usage_stats,
 };
 
+pub mod jaeger;
 pub mod otlp_http;
+pub mod query;
+pub mod sampling;
+pub mod span_metrics;
 
 const PARENT_SPAN_ID: &str = "reference.parent_span_id";
 const PARENT_TRACE_ID: &str = "reference.parent_trace_id";
@@ -130,6 +134,12 @@ pub async fn handle_trace_request(
     );
     // End Register Transforms for stream
 
+    let mut sampler = sampling::Sampler::new(
+        org_id,
+        traces_stream_name,
+        traces_schema_map.get(traces_stream_name),
+    );
+
     let mut trigger: Option<Trigger> = None;
 
     let mut data_buf: AHashMap<String, Vec<String>> = AHashMap::new();
@@ -229,6 +239,11 @@ pub async fn handle_trace_request(
                     events: json::to_string(&events).unwrap(),
                 };
 
+                span_metrics::record(org_id, &local_val);
+                let decision = sampler.decide(&local_val);
+                if decision == sampling::Decision::Drop {
+                    continue;
+                }
                 let value: json::Value = json::to_value(local_val).unwrap();
 
                 //JSON Flattening
@@ -292,7 +307,7 @@ pub async fn handle_trace_request(
                         for alert in alerts {
                             if alert.is_real_time {
                                 let set_trigger =
-                                    alert.condition.evaluate(value.as_object().unwrap().clone());
+                                    alert.evaluate(value.as_object().unwrap().clone());
                                 if set_trigger {
                                     trigger = Some(Trigger {
                                         timestamp: timestamp.try_into().unwrap(),
@@ -304,6 +319,7 @@ pub async fn handle_trace_request(
                                         last_sent_at: 0,
                                         count: 0,
                                         is_ingest_time: true,
+                                        ..Default::default()
                                     });
                                 }
                             }
@@ -318,9 +334,13 @@ pub async fn handle_trace_request(
                     hour_key.push_str(&format!("/{}", format_partition_key(&partition_key)));
                 }
 
-                let hour_buf = data_buf.entry(hour_key.clone()).or_default();
-
-                hour_buf.push(value_str);
+                sampler.write(
+                    &mut data_buf,
+                    decision,
+                    hour_key.clone(),
+                    value_str,
+                    timestamp as i64,
+                );
 
                 if timestamp < min_ts.try_into().unwrap() {
                     min_ts = timestamp as i64;
@@ -390,6 +410,7 @@ pub async fn handle_trace_request(
         0,
     )
     .await;
+    sampler.report().await;
 
     let schema_exists = stream_schema_exists(
         org_id,
@@ -136,6 +136,12 @@ pub async fn traces_json(
     );
     // End Register Transforms for stream
 
+    let mut sampler = super::sampling::Sampler::new(
+        org_id,
+        traces_stream_name,
+        traces_schema_map.get(traces_stream_name),
+    );
+
     let mut trigger: Option<Trigger> = None;
 
     let mut service_name: String = traces_stream_name.to_string();
@@ -291,6 +297,11 @@ pub async fn traces_json(
                         min_ts = timestamp as i64;
                     }
 
+                    super::span_metrics::record(org_id, &local_val);
+                    let decision = sampler.decide(&local_val);
+                    if decision == super::sampling::Decision::Drop {
+                        continue;
+                    }
                     let mut value: json::Value = json::to_value(local_val).unwrap();
 
                     //JSON Flattening
@@ -354,7 +365,7 @@ pub async fn traces_json(
                             for alert in alerts {
                                 if alert.is_real_time {
                                     let set_trigger = Evaluate::evaluate(
-                                        &alert.condition,
+                                        alert,
                                         value.as_object().unwrap().clone(),
                                     );
                                     if set_trigger {
@@ -368,6 +379,7 @@ pub async fn traces_json(
                                             last_sent_at: 0,
                                             count: 0,
                                             is_ingest_time: true,
+                                            ..Default::default()
                                         });
                                     }
                                 }
@@ -382,9 +394,13 @@ pub async fn traces_json(
                         hour_key.push_str(&format!("/{}", format_partition_key(&partition_key)));
                     }
 
-                    let hour_buf = data_buf.entry(hour_key.clone()).or_default();
-
-                    hour_buf.push(value_str);
+                    sampler.write(
+                        &mut data_buf,
+                        decision,
+                        hour_key.clone(),
+                        value_str,
+                        timestamp as i64,
+                    );
                     //Trace Metadata
                     let mut trace_meta = json::Map::new();
                     trace_meta.insert("trace_id".to_owned(), json::Value::String(trace_id.clone()));
@@ -445,6 +461,7 @@ pub async fn traces_json(
         0,
     )
     .await;
+    sampler.report().await;
 
     let schema_exists = stream_schema_exists(
         org_id,
@@ -0,0 +1,675 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use ahash::AHashMap;
+use std::collections::BTreeMap;
+
+use crate::common::{
+    infra::{
+        config::{COLUMN_TRACE_ID, CONFIG},
+        errors::{Error, Result},
+    },
+    meta::{
+        search,
+        traces::{
+            ServiceEdge, ServiceGraph, ServiceNode, SpanNode, Trace, TraceSearchQuery,
+            TraceSearchResponse, TraceSummary,
+        },
+        StreamType,
+    },
+    utils::json::{Map, Value},
+};
+use crate::service::{format_stream_name, search as SearchService};
+
+/// Maximum number of spans loaded for the traces of a lookup or search.
+const MAX_TRACE_SPANS: usize = 10000;
+/// Maximum number of spans the service graph is computed from.
+const MAX_GRAPH_SPANS: usize = 50000;
+
+const SPAN_ID: &str = "span_id";
+const PARENT_SPAN_ID: &str = "reference_parent_span_id";
+const SERVICE_NAME: &str = "service_name";
+const OPERATION_NAME: &str = "operation_name";
+const SPAN_STATUS: &str = "span_status";
+const STATUS_ERROR: &str = "ERROR";
+
+pub type Span = Map<String, Value>;
+
+/// Trace ids are hex encoded, anything else can't match and isn't put into SQL.
+pub fn is_valid_trace_id(trace_id: &str) -> bool {
+    !trace_id.is_empty() && trace_id.len() <= 32 && trace_id.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+pub fn is_valid_status(status: &str) -> bool {
+    matches!(status, "OK" | "ERROR" | "UNSET")
+}
+
+/// Stream names are put into SQL, only the names ingestion creates are accepted.
+pub fn is_valid_stream_name(stream_name: &str) -> bool {
+    !stream_name.is_empty() && format_stream_name(stream_name) == stream_name
+}
+
+/// Searches scan all files of the time range, it must be bounded.
+pub fn is_valid_time_range(start_time: i64, end_time: i64) -> bool {
+    start_time > 0 && end_time > start_time
+}
+
+fn check_query(stream_name: &str, start_time: i64, end_time: i64) -> Result<()> {
+    if !is_valid_stream_name(stream_name) {
+        return Err(Error::Message(format!(
+            "Invalid stream name: {stream_name}"
+        )));
+    }
+    if !is_valid_time_range(start_time, end_time) {
+        return Err(Error::Message(format!(
+            "Invalid time range: [{start_time}, {end_time}]"
+        )));
+    }
+    Ok(())
+}
+
+/// Loads all spans of a trace and arranges them in a tree.
+pub async fn get_trace(
+    org_id: &str,
+    stream_name: &str,
+    trace_id: &str,
+    start_time: i64,
+    end_time: i64,
+) -> Result<Option<Trace>> {
+    let trace_id = trace_id.to_lowercase();
+    let (spans, truncated) =
+        get_spans(org_id, stream_name, &trace_id, start_time, end_time).await?;
+    if spans.is_empty() {
+        return Ok(None);
+    }
+    let mut trace = build_trace(&trace_id, spans);
+    trace.truncated = truncated;
+    Ok(Some(trace))
+}
+
+/// Loads the spans of a trace, and whether there were more than `MAX_TRACE_SPANS`.
+/// The lookup is an equality on the trace id, so the parquet bloom filters written
+/// with `ZO_TRACES_BLOOM_FILTER_ENABLED` skip the row groups not containing it.
+pub async fn get_spans(
+    org_id: &str,
+    stream_name: &str,
+    trace_id: &str,
+    start_time: i64,
+    end_time: i64,
+) -> Result<(Vec<Span>, bool)> {
+    check_query(stream_name, start_time, end_time)?;
+    if !is_valid_trace_id(trace_id) {
+        return Err(Error::Message(format!("Invalid trace id: {trace_id}")));
+    }
+    let sql = format!("SELECT * FROM \"{stream_name}\" WHERE {COLUMN_TRACE_ID} = '{trace_id}'");
+    search_spans(org_id, sql, start_time, end_time, MAX_TRACE_SPANS).await
+}
+
+/// Finds the most recent traces having a span matching the query, and summarizes them
+/// from all their spans in the time range.
+pub async fn search_traces(
+    org_id: &str,
+    stream_name: &str,
+    query: &TraceSearchQuery,
+) -> Result<TraceSearchResponse> {
+    let start = std::time::Instant::now();
+    let (traces, truncated) = find_traces(
+        org_id,
+        stream_name,
+        &span_filters(query),
+        query.start_time,
+        query.end_time,
+        query.size,
+    )
+    .await?;
+    let traces = traces
+        .into_iter()
+        .map(|(trace_id, spans)| summarize(&trace_id, &spans))
+        .collect::<Vec<_>>();
+    Ok(TraceSearchResponse {
+        took: start.elapsed().as_millis() as usize,
+        total: traces.len(),
+        traces,
+        truncated,
+    })
+}
+
+/// Returns the spans of the `size` most recent traces having a span matching all
+/// `filters`, most recent trace first, and whether the traces had more than
+/// `MAX_TRACE_SPANS` spans in total.
+pub async fn find_traces(
+    org_id: &str,
+    stream_name: &str,
+    filters: &[String],
+    start_time: i64,
+    end_time: i64,
+    size: usize,
+) -> Result<(Vec<(String, Vec<Span>)>, bool)> {
+    check_query(stream_name, start_time, end_time)?;
+    let where_clause = if filters.is_empty() {
+        "".to_string()
+    } else {
+        format!("WHERE {}", filters.join(" AND "))
+    };
+    let sql = format!(
+        "SELECT {COLUMN_TRACE_ID}, MAX({ts}) AS zo_sql_timestamp FROM \"{stream_name}\" {where_clause} GROUP BY {COLUMN_TRACE_ID} ORDER BY zo_sql_timestamp DESC LIMIT {size}",
+        ts = CONFIG.common.column_timestamp,
+    );
+    let req = search_request(sql, "full", start_time, end_time, size);
+    let resp = SearchService::search(org_id, StreamType::Traces, &req).await?;
+    let trace_ids = resp
+        .hits
+        .iter()
+        .filter_map(|hit| hit.get(COLUMN_TRACE_ID).and_then(Value::as_str))
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>();
+    if trace_ids.is_empty() {
+        return Ok((vec![], false));
+    }
+
+    let sql = format!(
+        "SELECT * FROM \"{stream_name}\" WHERE {COLUMN_TRACE_ID} IN ({})",
+        trace_ids
+            .iter()
+            .map(|id| quote(id))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let (spans, truncated) =
+        search_spans(org_id, sql, start_time, end_time, MAX_TRACE_SPANS).await?;
+    let mut spans_by_trace = group_by_trace(spans);
+    let traces = trace_ids
+        .into_iter()
+        .filter_map(|id| spans_by_trace.remove(&id).map(|spans| (id, spans)))
+        .collect();
+    Ok((traces, truncated))
+}
+
+/// Computes the calls between services from the parent/child relations of the spans in
+/// the time range. Parents are only looked up in the trace of the span. The graph is
+/// computed from the first `MAX_GRAPH_SPANS` spans only, it's marked as truncated if
+/// there were more.
+pub async fn service_graph(
+    org_id: &str,
+    stream_name: &str,
+    start_time: i64,
+    end_time: i64,
+) -> Result<ServiceGraph> {
+    check_query(stream_name, start_time, end_time)?;
+    let sql = format!("SELECT * FROM \"{stream_name}\"");
+    let (spans, truncated) =
+        search_spans(org_id, sql, start_time, end_time, MAX_GRAPH_SPANS).await?;
+    let mut graph = build_service_graph(spans);
+    graph.truncated = truncated;
+    Ok(graph)
+}
+
+/// Returns up to `size` spans, and whether there were more. One more span than
+/// requested is searched for to tell.
+async fn search_spans(
+    org_id: &str,
+    sql: String,
+    start_time: i64,
+    end_time: i64,
+    size: usize,
+) -> Result<(Vec<Span>, bool)> {
+    let req = search_request(sql, "context", start_time, end_time, size + 1);
+    let resp = SearchService::search(org_id, StreamType::Traces, &req).await?;
+    let mut spans = resp
+        .hits
+        .into_iter()
+        .filter_map(|hit| match hit {
+            Value::Object(span) => Some(span),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let truncated = spans.len() > size;
+    spans.truncate(size);
+    Ok((spans, truncated))
+}
+
+fn search_request(
+    sql: String,
+    sql_mode: &str,
+    start_time: i64,
+    end_time: i64,
+    size: usize,
+) -> search::Request {
+    search::Request {
+        query: search::Query {
+            sql,
+            size,
+            start_time,
+            end_time,
+            sql_mode: sql_mode.to_string(),
+            ..Default::default()
+        },
+        aggs: Default::default(),
+        encoding: search::RequestEncoding::Empty,
+        timeout: 0,
+    }
+}
+
+pub fn span_filters(query: &TraceSearchQuery) -> Vec<String> {
+    let mut filters = Vec::new();
+    if let Some(service_name) = query.service_name.as_deref().filter(|v| !v.is_empty()) {
+        filters.push(format!("{SERVICE_NAME} = {}", quote(service_name)));
+    }
+    if let Some(operation_name) = query.operation_name.as_deref().filter(|v| !v.is_empty()) {
+        filters.push(format!("{OPERATION_NAME} = {}", quote(operation_name)));
+    }
+    if let Some(min_duration) = query.min_duration {
+        filters.push(format!("duration >= {min_duration}"));
+    }
+    if let Some(max_duration) = query.max_duration {
+        filters.push(format!("duration <= {max_duration}"));
+    }
+    if let Some(status) = query.status.as_deref().filter(|v| !v.is_empty()) {
+        filters.push(format!("{SPAN_STATUS} = {}", quote(status)));
+    }
+    filters
+}
+
+pub fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+fn get_str<'a>(span: &'a Span, key: &str) -> &'a str {
+    span.get(key).and_then(Value::as_str).unwrap_or_default()
+}
+
+fn get_u64(span: &Span, key: &str) -> u64 {
+    span.get(key).and_then(Value::as_u64).unwrap_or_default()
+}
+
+fn is_error(span: &Span) -> bool {
+    get_str(span, SPAN_STATUS) == STATUS_ERROR
+}
+
+fn group_by_trace(spans: Vec<Span>) -> AHashMap<String, Vec<Span>> {
+    let mut traces: AHashMap<String, Vec<Span>> = AHashMap::new();
+    for span in spans {
+        let trace_id = get_str(&span, COLUMN_TRACE_ID).to_string();
+        traces.entry(trace_id).or_default().push(span);
+    }
+    traces
+}
+
+/// Returns the index of the parent of each span, `None` for spans without parent in
+/// the trace.
+fn parent_indexes(spans: &[Span]) -> Vec<Option<usize>> {
+    let indexes = spans
+        .iter()
+        .enumerate()
+        .map(|(i, span)| (get_str(span, SPAN_ID), i))
+        .collect::<AHashMap<_, _>>();
+    spans
+        .iter()
+        .enumerate()
+        .map(|(i, span)| {
+            indexes
+                .get(get_str(span, PARENT_SPAN_ID))
+                .copied()
+                .filter(|parent| *parent != i)
+        })
+        .collect()
+}
+
+fn summarize(trace_id: &str, spans: &[Span]) -> TraceSummary {
+    let parents = parent_indexes(spans);
+    let root = spans
+        .iter()
+        .zip(parents.iter())
+        .filter(|(_, parent)| parent.is_none())
+        .map(|(span, _)| span)
+        .min_by_key(|span| get_u64(span, "start_time"));
+    let start_time = spans
+        .iter()
+        .map(|span| get_u64(span, "start_time"))
+        .min()
+        .unwrap_or_default();
+    let end_time = spans
+        .iter()
+        .map(|span| get_u64(span, "end_time"))
+        .max()
+        .unwrap_or_default();
+    let services = spans
+        .iter()
+        .map(|span| get_str(span, SERVICE_NAME).to_string())
+        .collect::<std::collections::BTreeSet<_>>();
+    TraceSummary {
+        trace_id: trace_id.to_string(),
+        service_name: root
+            .map(|span| get_str(span, SERVICE_NAME).to_string())
+            .unwrap_or_default(),
+        operation_name: root
+            .map(|span| get_str(span, OPERATION_NAME).to_string())
+            .unwrap_or_default(),
+        start_time,
+        end_time,
+        duration: end_time.saturating_sub(start_time) / 1000,
+        span_count: spans.len(),
+        error_count: spans.iter().filter(|span| is_error(span)).count(),
+        services: services.into_iter().collect(),
+    }
+}
+
+/// Arranges the spans of a trace in a tree, children sorted by start time.
+fn build_trace(trace_id: &str, mut spans: Vec<Span>) -> Trace {
+    spans.sort_by_key(|span| get_u64(span, "start_time"));
+    let summary = summarize(trace_id, &spans);
+    let parents = parent_indexes(&spans);
+    let mut children = vec![Vec::new(); spans.len()];
+    let mut roots = Vec::new();
+    for (i, parent) in parents.iter().enumerate() {
+        match parent {
+            Some(parent) => children[*parent].push(i),
+            None => roots.push(i),
+        }
+    }
+
+    let mut spans = spans.into_iter().map(Some).collect::<Vec<_>>();
+    let mut nodes = roots
+        .into_iter()
+        .filter_map(|i| build_node(i, &mut spans, &children))
+        .collect::<Vec<_>>();
+    // spans in a parent cycle have no root, they are returned as roots
+    for i in 0..spans.len() {
+        if let Some(node) = build_node(i, &mut spans, &children) {
+            nodes.push(node);
+        }
+    }
+
+    Trace {
+        trace_id: summary.trace_id,
+        start_time: summary.start_time,
+        end_time: summary.end_time,
+        duration: summary.duration,
+        span_count: summary.span_count,
+        error_count: summary.error_count,
+        services: summary.services,
+        spans: nodes,
+        truncated: false,
+    }
+}
+
+/// Builds the tree of the span `i` from the spans not taken yet. Traces can be
+/// arbitrarily deep, the tree is built with an explicit stack rather than recursion.
+fn build_node(i: usize, spans: &mut [Option<Span>], children: &[Vec<usize>]) -> Option<SpanNode> {
+    let span = spans[i].take()?;
+    // nodes whose children are being built, with the position of the next child
+    let mut stack = vec![(
+        i,
+        SpanNode {
+            span,
+            children: vec![],
+        },
+        0,
+    )];
+    loop {
+        let (i, _, next) = stack.last_mut().unwrap();
+        if let Some(child) = children[*i].get(*next).copied() {
+            *next += 1;
+            if let Some(span) = spans[child].take() {
+                stack.push((
+                    child,
+                    SpanNode {
+                        span,
+                        children: vec![],
+                    },
+                    0,
+                ));
+            }
+            continue;
+        }
+        let (_, node, _) = stack.pop().unwrap();
+        match stack.last_mut() {
+            Some((_, parent, _)) => parent.children.push(node),
+            None => return Some(node),
+        }
+    }
+}
+
+fn build_service_graph(spans: Vec<Span>) -> ServiceGraph {
+    let mut nodes: BTreeMap<String, ServiceNode> = BTreeMap::new();
+    let mut edges: BTreeMap<(String, String), ServiceEdge> = BTreeMap::new();
+    for spans in group_by_trace(spans).into_values() {
+        let parents = parent_indexes(&spans);
+        for (span, parent) in spans.iter().zip(parents) {
+            let service = get_str(span, SERVICE_NAME);
+            let node = nodes
+                .entry(service.to_string())
+                .or_insert_with(|| ServiceNode {
+                    name: service.to_string(),
+                    ..Default::default()
+                });
+            node.span_count += 1;
+            node.error_count += is_error(span) as usize;
+
+            let Some(parent) = parent else {
+                continue;
+            };
+            let parent_service = get_str(&spans[parent], SERVICE_NAME);
+            if parent_service == service {
+                continue;
+            }
+            let edge = edges
+                .entry((parent_service.to_string(), service.to_string()))
+                .or_insert_with(|| ServiceEdge {
+                    source: parent_service.to_string(),
+                    target: service.to_string(),
+                    ..Default::default()
+                });
+            edge.call_count += 1;
+            edge.error_count += is_error(span) as usize;
+        }
+    }
+    ServiceGraph {
+        nodes: nodes.into_values().collect(),
+        edges: edges.into_values().collect(),
+        truncated: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::utils::json;
+    use ahash::AHashSet;
+
+    fn span_ids(nodes: &[SpanNode]) -> AHashSet<String> {
+        let mut ids = AHashSet::new();
+        for node in nodes {
+            ids.insert(get_str(&node.span, SPAN_ID).to_string());
+            ids.extend(span_ids(&node.children));
+        }
+        ids
+    }
+
+    fn span(span_id: &str, parent: &str, service: &str, start: u64, status: &str) -> Span {
+        json::json!({
+            "trace_id": "0af7651916cd43dd8448eb211c80319c",
+            "span_id": span_id,
+            "reference_parent_span_id": parent,
+            "service_name": service,
+            "operation_name": format!("{service}/{span_id}"),
+            "span_status": status,
+            "start_time": start,
+            "end_time": start + 1_000_000,
+        })
+        .as_object()
+        .unwrap()
+        .clone()
+    }
+
+    #[test]
+    fn test_is_valid_trace_id() {
+        assert!(is_valid_trace_id("0af7651916cd43dd8448eb211c80319c"));
+        assert!(is_valid_trace_id("0AF76519"));
+        assert!(!is_valid_trace_id(""));
+        assert!(!is_valid_trace_id("0af7' OR '1'='1"));
+        assert!(!is_valid_trace_id(&"a".repeat(33)));
+    }
+
+    #[test]
+    fn test_check_query() {
+        assert!(check_query("default", 1, 2).is_ok());
+        assert!(check_query("default\" UNION SELECT 1 --", 1, 2).is_err());
+        assert!(check_query("", 1, 2).is_err());
+        assert!(check_query("default", 0, 0).is_err());
+        assert!(check_query("default", 2, 1).is_err());
+    }
+
+    #[test]
+    fn test_span_filters() {
+        let query = TraceSearchQuery {
+            service_name: Some("o'brien".to_string()),
+            min_duration: Some(100),
+            status: Some("ERROR".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            span_filters(&query),
+            vec![
+                "service_name = 'o''brien'",
+                "duration >= 100",
+                "span_status = 'ERROR'"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_trace() {
+        let spans = vec![
+            span("c", "b", "db", 3_000_000, "ERROR"),
+            span("a", "", "frontend", 1_000_000, "OK"),
+            span("b", "a", "backend", 2_000_000, "UNSET"),
+            span("d", "a", "frontend", 2_500_000, "UNSET"),
+            span("e", "missing", "cache", 500_000, "UNSET"),
+        ];
+        let trace = build_trace("0af7651916cd43dd8448eb211c80319c", spans);
+        assert_eq!(trace.span_count, 5);
+        assert_eq!(trace.error_count, 1);
+        assert_eq!(trace.start_time, 500_000);
+        assert_eq!(trace.end_time, 4_000_000);
+        assert_eq!(trace.duration, 3500);
+        assert_eq!(trace.services, vec!["backend", "cache", "db", "frontend"]);
+
+        assert_eq!(trace.spans.len(), 2);
+        assert_eq!(get_str(&trace.spans[0].span, SPAN_ID), "e");
+        let root = &trace.spans[1];
+        assert_eq!(get_str(&root.span, SPAN_ID), "a");
+        assert_eq!(root.children.len(), 2);
+        assert_eq!(get_str(&root.children[0].span, SPAN_ID), "b");
+        assert_eq!(get_str(&root.children[0].children[0].span, SPAN_ID), "c");
+        assert_eq!(get_str(&root.children[1].span, SPAN_ID), "d");
+    }
+
+    #[test]
+    fn test_build_trace_cycle() {
+        let spans = vec![
+            span("a", "b", "frontend", 1_000_000, "OK"),
+            span("b", "a", "backend", 2_000_000, "OK"),
+        ];
+        let trace = build_trace("0af7651916cd43dd8448eb211c80319c", spans);
+        assert_eq!(trace.spans.len(), 1);
+        assert_eq!(span_ids(&trace.spans).len(), 2);
+    }
+
+    #[test]
+    fn test_build_trace_deep() {
+        let depth = 10_000;
+        let spans = (0..depth)
+            .map(|i| {
+                let parent = if i == 0 {
+                    "".to_string()
+                } else {
+                    (i - 1).to_string()
+                };
+                span(&i.to_string(), &parent, "frontend", i, "OK")
+            })
+            .collect();
+        let trace = build_trace("0af7651916cd43dd8448eb211c80319c", spans);
+        assert_eq!(trace.spans.len(), 1);
+        let mut node = &trace.spans[0];
+        let mut count = 1;
+        while let Some(child) = node.children.first() {
+            node = child;
+            count += 1;
+        }
+        assert_eq!(count, depth);
+    }
+
+    #[test]
+    fn test_summarize() {
+        let spans = vec![
+            span("b", "a", "backend", 2_000_000, "ERROR"),
+            span("a", "", "frontend", 1_000_000, "OK"),
+        ];
+        let summary = summarize("0af7651916cd43dd8448eb211c80319c", &spans);
+        assert_eq!(summary.service_name, "frontend");
+        assert_eq!(summary.operation_name, "frontend/a");
+        assert_eq!(summary.duration, 2000);
+        assert_eq!(summary.error_count, 1);
+    }
+
+    #[test]
+    fn test_build_service_graph() {
+        let spans = vec![
+            span("a", "", "frontend", 1_000_000, "OK"),
+            span("b", "a", "backend", 2_000_000, "OK"),
+            span("c", "b", "db", 3_000_000, "ERROR"),
+            span("d", "b", "db", 3_500_000, "OK"),
+            span("e", "b", "backend", 3_600_000, "OK"),
+        ];
+        let graph = build_service_graph(spans);
+        assert_eq!(
+            graph.nodes,
+            vec![
+                ServiceNode {
+                    name: "backend".to_string(),
+                    span_count: 2,
+                    error_count: 0
+                },
+                ServiceNode {
+                    name: "db".to_string(),
+                    span_count: 2,
+                    error_count: 1
+                },
+                ServiceNode {
+                    name: "frontend".to_string(),
+                    span_count: 1,
+                    error_count: 0
+                },
+            ]
+        );
+        assert_eq!(
+            graph.edges,
+            vec![
+                ServiceEdge {
+                    source: "backend".to_string(),
+                    target: "db".to_string(),
+                    call_count: 2,
+                    error_count: 1
+                },
+                ServiceEdge {
+                    source: "frontend".to_string(),
+                    target: "backend".to_string(),
+                    call_count: 1,
+                    error_count: 0
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,642 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use ahash::AHashMap;
+use chrono::Utc;
+use dashmap::DashMap;
+use datafusion::arrow::datatypes::Schema;
+use once_cell::sync::Lazy;
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs::OpenOptions,
+    hash::{Hash, Hasher},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::common::{
+    infra::{
+        config::{RwHashMap, CONFIG},
+        metrics,
+    },
+    meta::{
+        stream::StreamParams,
+        traces::{Span, SpanDropRule, TailSampling, TraceSampling},
+        usage::{RequestStats, UsageType},
+        StreamType,
+    },
+    utils::json,
+};
+use crate::service::{
+    ingestion::write_file,
+    schema::{add_stream_schema, stream_schema_exists},
+    stream::stream_settings,
+    usage::{report_request_usage_stats, report_sampling_stats},
+};
+
+/// Longest decision window of tail sampling, in seconds.
+const MAX_DECISION_WAIT: u64 = 300;
+
+/// Traces waiting for a tail sampling decision, by org, stream and trace id. Decided traces
+/// are kept for another decision window, so that late spans follow the decision.
+static TRACES: Lazy<RwHashMap<String, TailTrace>> = Lazy::new(DashMap::default);
+
+/// Spans buffered in `TRACES`, bounded by `ZO_TRACES_TAIL_SAMPLING_MAX_SPANS`.
+static BUFFERED_SPANS: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug)]
+struct TailTrace {
+    org_id: String,
+    stream_name: String,
+    trace_id: String,
+    rate: f64,
+    /// Time to decide, in microseconds
+    deadline: i64,
+    /// Time to forget the decision, in microseconds
+    expires: i64,
+    keep: Option<bool>,
+    spans: Vec<BufferedSpan>,
+}
+
+#[derive(Debug)]
+struct BufferedSpan {
+    hour_key: String,
+    value: String,
+    timestamp: i64,
+}
+
+/// What to do with an ingested span.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Decision {
+    Drop,
+    Write,
+    /// Wait for the tail sampling decision of the trace, unless `keep` already decides
+    /// to keep it.
+    Buffer {
+        trace_id: String,
+        keep: bool,
+    },
+}
+
+#[derive(Debug, Default)]
+struct SamplingStats {
+    rule_dropped: usize,
+    head_dropped: usize,
+    tail_dropped: usize,
+    tail_kept: usize,
+}
+
+impl SamplingStats {
+    async fn report(&self, org_id: &str, stream_name: &str) {
+        for (decision, count) in [
+            ("rule_dropped", self.rule_dropped),
+            ("head_dropped", self.head_dropped),
+            ("tail_dropped", self.tail_dropped),
+            ("tail_kept", self.tail_kept),
+        ] {
+            if count > 0 {
+                metrics::INGEST_SAMPLED_SPANS
+                    .with_label_values(&[org_id, stream_name, decision])
+                    .inc_by(count as u64);
+            }
+        }
+        let dropped = self.rule_dropped + self.head_dropped + self.tail_dropped;
+        if dropped > 0 {
+            let stats = RequestStats {
+                records: dropped as i64,
+                ..Default::default()
+            };
+            report_sampling_stats(stats, org_id, stream_name).await;
+        }
+    }
+}
+
+/// Applies the sampling policy of a traces stream to the spans of an ingestion request.
+pub struct Sampler {
+    org_id: String,
+    stream_name: String,
+    policy: Option<TraceSampling>,
+    stats: SamplingStats,
+}
+
+impl Sampler {
+    pub fn new(org_id: &str, stream_name: &str, schema: Option<&Schema>) -> Self {
+        Self {
+            org_id: org_id.to_string(),
+            stream_name: stream_name.to_string(),
+            policy: schema
+                .and_then(stream_settings)
+                .and_then(|settings| settings.trace_sampling),
+            stats: SamplingStats::default(),
+        }
+    }
+
+    /// Decides on a span before it is flattened, drop rules first, then head sampling by
+    /// service and tail sampling.
+    pub fn decide(&mut self, span: &Span) -> Decision {
+        let Some(policy) = &self.policy else {
+            return Decision::Write;
+        };
+        if policy
+            .drop_rules
+            .iter()
+            .any(|rule| matches_rule(rule, span))
+        {
+            self.stats.rule_dropped += 1;
+            return Decision::Drop;
+        }
+        let rate = policy
+            .head_rates
+            .get(&span.service_name)
+            .or_else(|| policy.head_rates.get("*"));
+        if let Some(rate) = rate {
+            if !sample(&span.trace_id, *rate, 0) {
+                self.stats.head_dropped += 1;
+                return Decision::Drop;
+            }
+        }
+        match &policy.tail {
+            Some(tail) => Decision::Buffer {
+                trace_id: span.trace_id.clone(),
+                keep: is_interesting(tail, span),
+            },
+            None => Decision::Write,
+        }
+    }
+
+    /// Adds a span to the request buffer, or holds it back until its trace is decided.
+    pub fn write(
+        &mut self,
+        data_buf: &mut AHashMap<String, Vec<String>>,
+        decision: Decision,
+        hour_key: String,
+        value: String,
+        timestamp: i64,
+    ) {
+        let (trace_id, keep) = match decision {
+            Decision::Drop => return,
+            Decision::Write => {
+                data_buf.entry(hour_key).or_default().push(value);
+                return;
+            }
+            Decision::Buffer { trace_id, keep } => (trace_id, keep),
+        };
+        let Some(tail) = self.policy.as_ref().and_then(|p| p.tail.as_ref()) else {
+            data_buf.entry(hour_key).or_default().push(value);
+            return;
+        };
+
+        let now = Utc::now().timestamp_micros();
+        let wait = tail.decision_wait.min(MAX_DECISION_WAIT) as i64 * 1_000_000;
+        let mut trace = TRACES
+            .entry(format!("{}/{}/{trace_id}", self.org_id, self.stream_name))
+            .or_insert_with(|| TailTrace {
+                org_id: self.org_id.clone(),
+                stream_name: self.stream_name.clone(),
+                trace_id,
+                rate: tail.rate,
+                deadline: now + wait,
+                expires: now + wait * 2,
+                keep: None,
+                spans: vec![],
+            });
+        match trace.keep {
+            Some(true) => {
+                self.stats.tail_kept += 1;
+                data_buf.entry(hour_key).or_default().push(value);
+            }
+            Some(false) if !keep => self.stats.tail_dropped += 1,
+            // a late error or slow span of a dropped trace, keep at least that one
+            Some(false) => {
+                self.stats.tail_kept += 1;
+                data_buf.entry(hour_key).or_default().push(value);
+            }
+            None if keep => {
+                trace.keep = Some(true);
+                let spans = std::mem::take(&mut trace.spans);
+                BUFFERED_SPANS.fetch_sub(spans.len(), Ordering::Relaxed);
+                self.stats.tail_kept += spans.len() + 1;
+                for span in spans {
+                    data_buf.entry(span.hour_key).or_default().push(span.value);
+                }
+                data_buf.entry(hour_key).or_default().push(value);
+            }
+            // the buffer is full, write without sampling
+            None if BUFFERED_SPANS.load(Ordering::Relaxed)
+                >= CONFIG.limit.traces_tail_sampling_max_spans =>
+            {
+                data_buf.entry(hour_key).or_default().push(value);
+            }
+            None => {
+                trace.spans.push(BufferedSpan {
+                    hour_key,
+                    value,
+                    timestamp,
+                });
+                BUFFERED_SPANS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Records the sampling decisions of the request in the metrics and usage stats.
+    pub async fn report(&self) {
+        self.stats.report(&self.org_id, &self.stream_name).await;
+    }
+}
+
+/// Decides the traces whose decision window passed, keeping them by the tail sampling
+/// rate, and writes the kept spans. Called periodically by the trace sampling job.
+pub async fn flush() {
+    decide(false).await
+}
+
+/// Decides all buffered traces and writes the kept spans, so that they aren't lost
+/// on shutdown.
+pub async fn flush_all() {
+    decide(true).await
+}
+
+async fn decide(all: bool) {
+    let now = Utc::now().timestamp_micros();
+    let mut decided: AHashMap<(String, String), Flushed> = AHashMap::new();
+    TRACES.retain(|_, trace| {
+        if trace.keep.is_none() && (all || trace.deadline <= now) {
+            let keep = sample(&trace.trace_id, trace.rate, TAIL_SEED);
+            trace.keep = Some(keep);
+            let spans = std::mem::take(&mut trace.spans);
+            BUFFERED_SPANS.fetch_sub(spans.len(), Ordering::Relaxed);
+            let flushed = decided
+                .entry((trace.org_id.clone(), trace.stream_name.clone()))
+                .or_default();
+            if keep {
+                flushed.stats.tail_kept += spans.len();
+                for span in spans {
+                    flushed.min_ts = flushed.min_ts.min(span.timestamp);
+                    flushed
+                        .data_buf
+                        .entry(span.hour_key)
+                        .or_default()
+                        .push(span.value);
+                }
+            } else {
+                flushed.stats.tail_dropped += spans.len();
+            }
+        }
+        trace.expires > now
+    });
+
+    for ((org_id, stream_name), flushed) in decided {
+        if !flushed.data_buf.is_empty() {
+            write_spans(&org_id, &stream_name, &flushed).await;
+        }
+        flushed.stats.report(&org_id, &stream_name).await;
+    }
+}
+
+#[derive(Debug)]
+struct Flushed {
+    data_buf: AHashMap<String, Vec<String>>,
+    min_ts: i64,
+    stats: SamplingStats,
+}
+
+impl Default for Flushed {
+    fn default() -> Self {
+        Self {
+            data_buf: AHashMap::new(),
+            min_ts: i64::MAX,
+            stats: SamplingStats::default(),
+        }
+    }
+}
+
+async fn write_spans(org_id: &str, stream_name: &str, flushed: &Flushed) {
+    let start = std::time::Instant::now();
+    let mut file_name = "".to_string();
+    let mut req_stats = write_file(
+        &flushed.data_buf,
+        0,
+        &StreamParams::new(org_id, stream_name, StreamType::Traces),
+        &mut file_name,
+        None,
+    )
+    .await;
+    req_stats.response_time = start.elapsed().as_secs_f64();
+    report_request_usage_stats(
+        req_stats,
+        org_id,
+        stream_name,
+        StreamType::Traces,
+        UsageType::Traces,
+        0,
+    )
+    .await;
+
+    // the spans of a new stream may all have been buffered
+    let mut schema_map: AHashMap<String, Schema> = AHashMap::new();
+    let schema_exists =
+        stream_schema_exists(org_id, stream_name, StreamType::Traces, &mut schema_map).await;
+    if !schema_exists.has_fields && !file_name.is_empty() {
+        match OpenOptions::new().read(true).open(&file_name) {
+            Ok(file) => {
+                add_stream_schema(
+                    org_id,
+                    stream_name,
+                    StreamType::Traces,
+                    &file,
+                    &mut schema_map,
+                    flushed.min_ts,
+                )
+                .await
+            }
+            Err(e) => log::error!("trace sampling [{org_id}/{stream_name}] open file error: {e}"),
+        }
+    }
+}
+
+/// Checks the rates and rules of a sampling policy before it is saved.
+pub fn validate(policy: &TraceSampling) -> Result<(), String> {
+    let valid_rate = |rate: f64| (0.0..=1.0).contains(&rate);
+    for (service, rate) in policy.head_rates.iter() {
+        if !valid_rate(*rate) {
+            return Err(format!(
+                "head sampling rate of [{service}] should be between 0 and 1"
+            ));
+        }
+    }
+    if let Some(tail) = &policy.tail {
+        if !valid_rate(tail.rate) {
+            return Err("tail sampling rate should be between 0 and 1".to_string());
+        }
+        if tail.decision_wait == 0 || tail.decision_wait > MAX_DECISION_WAIT {
+            return Err(format!(
+                "tail sampling decision_wait should be between 1 and {MAX_DECISION_WAIT} seconds"
+            ));
+        }
+    }
+    for rule in policy.drop_rules.iter() {
+        if rule.service_name.is_none()
+            && rule.operation_name.is_none()
+            && rule.attributes.is_empty()
+        {
+            return Err("span drop rule without conditions would drop all spans".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Offsets the trace hash of tail sampling, so that it doesn't keep the same traces as
+/// head sampling.
+const TAIL_SEED: u32 = 32;
+
+/// Keeps a trace with probability `rate`, consistently for all spans of the trace on all
+/// ingesters. Trace ids are random, so their low 64 bits are used as is.
+fn sample(trace_id: &str, rate: f64, seed: u32) -> bool {
+    if rate >= 1.0 {
+        return true;
+    }
+    if rate <= 0.0 {
+        return false;
+    }
+    // ids which aren't hex, and so may not be sliced at any byte, are hashed
+    let hash = Some(trace_id)
+        .filter(|id| id.is_ascii())
+        .and_then(|id| u64::from_str_radix(&id[id.len().saturating_sub(16)..], 16).ok())
+        .unwrap_or_else(|| {
+            let mut hasher = DefaultHasher::new();
+            trace_id.hash(&mut hasher);
+            hasher.finish()
+        });
+    (hash.rotate_left(seed) as f64) < rate * u64::MAX as f64
+}
+
+fn is_interesting(tail: &TailSampling, span: &Span) -> bool {
+    (tail.keep_errors && span.span_status.trim_matches('"') == "ERROR")
+        || (tail.latency_threshold > 0 && span.duration >= tail.latency_threshold * 1000)
+}
+
+fn matches_rule(rule: &SpanDropRule, span: &Span) -> bool {
+    if let Some(service_name) = &rule.service_name {
+        if !glob_match(service_name, &span.service_name) {
+            return false;
+        }
+    }
+    if let Some(operation_name) = &rule.operation_name {
+        if !glob_match(operation_name, &span.operation_name) {
+            return false;
+        }
+    }
+    rule.attributes
+        .iter()
+        .all(|(key, pattern)| match span.attributes.get(key) {
+            Some(json::Value::String(value)) => glob_match(pattern, value),
+            Some(value) => glob_match(pattern, &value.to_string()),
+            None => false,
+        })
+}
+
+/// Matches `value` against `pattern`, where `*` matches any sequence of characters.
+fn glob_match(pattern: &str, value: &str) -> bool {
+    let (pattern, value) = (pattern.as_bytes(), value.as_bytes());
+    let (mut p, mut v) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while v < value.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p, v));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == value[v] {
+            p += 1;
+            v += 1;
+        } else if let Some((star_p, star_v)) = star {
+            // let the last star match one more character
+            p = star_p + 1;
+            v = star_v + 1;
+            star = Some((star_p, v));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn span(trace_id: &str, service_name: &str, status: &str, duration: u64) -> Span {
+        Span {
+            trace_id: trace_id.to_string(),
+            span_id: "00f067aa0ba902b7".to_string(),
+            span_kind: "2".to_string(),
+            span_status: status.to_string(),
+            operation_name: "GET /users".to_string(),
+            start_time: 1_000_000_000,
+            end_time: 1_000_000_000 + duration * 1000,
+            duration,
+            reference: AHashMap::new(),
+            service_name: service_name.to_string(),
+            attributes: AHashMap::new(),
+            service: AHashMap::new(),
+            flags: 1,
+            events: "[]".to_string(),
+        }
+    }
+
+    fn sampler(policy: TraceSampling) -> Sampler {
+        Sampler {
+            org_id: "default".to_string(),
+            stream_name: "test_sampling".to_string(),
+            policy: Some(policy),
+            stats: SamplingStats::default(),
+        }
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("/health*", "/healthz"));
+        assert!(glob_match("*/ready", "/api/ready"));
+        assert!(glob_match("GET /*/status", "GET /a/b/status"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("exact", "exact"));
+        assert!(!glob_match("exact", "exactly"));
+        assert!(!glob_match("/health*", "/api/health"));
+        assert!(!glob_match("a*b*c", "abca"));
+    }
+
+    #[test]
+    fn test_sample() {
+        let low = "0af7651916cd43dd0000000000000001";
+        let high = "0af7651916cd43ddffffffffffffff00";
+        assert!(sample(low, 0.1, 0));
+        assert!(!sample(high, 0.9, 0));
+        assert!(sample(high, 1.0, 0));
+        assert!(!sample(low, 0.0, 0));
+        // the same trace is decided the same way
+        assert_eq!(sample("not hex", 0.5, 0), sample("not hex", 0.5, 0));
+        // multi-byte characters around the low 16 bytes don't panic
+        assert_eq!(
+            sample("0af7651916cd43ddéééééééé", 0.5, 0),
+            sample("0af7651916cd43ddéééééééé", 0.5, 0)
+        );
+
+        let kept = (0..1000u64)
+            .filter(|i| {
+                sample(
+                    &format!("{:032x}", i.wrapping_mul(0x9e3779b97f4a7c15)),
+                    0.25,
+                    0,
+                )
+            })
+            .count();
+        assert!((200..300).contains(&kept), "kept {kept}");
+    }
+
+    #[test]
+    fn test_drop_rules() {
+        let mut s = sampler(TraceSampling {
+            drop_rules: vec![SpanDropRule {
+                service_name: Some("frontend".to_string()),
+                operation_name: None,
+                attributes: HashMap::from([("http.target".to_string(), "/health*".to_string())]),
+            }],
+            ..Default::default()
+        });
+        let mut health = span("1", "frontend", "OK", 10);
+        health
+            .attributes
+            .insert("http.target".to_string(), json::json!("/healthz"));
+        assert_eq!(s.decide(&health), Decision::Drop);
+        health.service_name = "backend".to_string();
+        assert_eq!(s.decide(&health), Decision::Write);
+        assert_eq!(s.decide(&span("1", "frontend", "OK", 10)), Decision::Write);
+        assert_eq!(s.stats.rule_dropped, 1);
+    }
+
+    #[test]
+    fn test_head_sampling() {
+        let mut s = sampler(TraceSampling {
+            head_rates: HashMap::from([("frontend".to_string(), 1.0), ("*".to_string(), 0.0)]),
+            ..Default::default()
+        });
+        assert_eq!(s.decide(&span("1", "frontend", "OK", 10)), Decision::Write);
+        assert_eq!(s.decide(&span("1", "backend", "OK", 10)), Decision::Drop);
+        assert_eq!(s.stats.head_dropped, 1);
+    }
+
+    #[test]
+    fn test_tail_sampling() {
+        let tail = TailSampling {
+            keep_errors: true,
+            latency_threshold: 500,
+            rate: 0.0,
+            decision_wait: 10,
+        };
+        let mut s = sampler(TraceSampling {
+            tail: Some(tail),
+            ..Default::default()
+        });
+        let trace_id = "test_tail_sampling";
+        let mut data_buf = AHashMap::new();
+        let mut write = |s: &mut Sampler, span: &Span| {
+            let decision = s.decide(span);
+            s.write(
+                &mut data_buf,
+                decision,
+                "h".to_string(),
+                span.span_id.clone(),
+                0,
+            );
+        };
+
+        write(&mut s, &span(trace_id, "frontend", "OK", 10));
+        write(&mut s, &span(trace_id, "backend", "OK", 20));
+        let key = format!("default/test_sampling/{trace_id}");
+        assert_eq!(TRACES.get(&key).unwrap().spans.len(), 2);
+
+        // a slow span keeps the whole trace
+        write(&mut s, &span(trace_id, "backend", "OK", 600_000));
+        assert_eq!(TRACES.get(&key).unwrap().keep, Some(true));
+        assert!(TRACES.get(&key).unwrap().spans.is_empty());
+        write(&mut s, &span(trace_id, "frontend", "OK", 10));
+        assert_eq!(data_buf.get("h").unwrap().len(), 4);
+        assert_eq!(s.stats.tail_kept, 4);
+
+        let decision = s.decide(&span(trace_id, "frontend", "\"ERROR\"", 10));
+        assert!(matches!(decision, Decision::Buffer { keep: true, .. }));
+        TRACES.remove(&key);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut policy = TraceSampling {
+            head_rates: HashMap::from([("*".to_string(), 0.5)]),
+            tail: Some(TailSampling {
+                keep_errors: true,
+                latency_threshold: 0,
+                rate: 0.1,
+                decision_wait: 10,
+            }),
+            drop_rules: vec![],
+        };
+        assert!(validate(&policy).is_ok());
+        policy.head_rates.insert("frontend".to_string(), 1.5);
+        assert!(validate(&policy).is_err());
+        policy.head_rates.clear();
+        policy.tail.as_mut().unwrap().decision_wait = 0;
+        assert!(validate(&policy).is_err());
+        policy.tail = None;
+        policy.drop_rules.push(SpanDropRule::default());
+        assert!(validate(&policy).is_err());
+    }
+}
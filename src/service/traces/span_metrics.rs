@@ -0,0 +1,303 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use ahash::AHashMap;
+use chrono::Utc;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+
+use crate::common::{
+    infra::config::{RwHashMap, CONFIG},
+    meta::{prom::NAME_LABEL, traces::Span},
+};
+use crate::service::metrics::prom::{self, prometheus};
+
+pub const CALLS_METRIC: &str = "traces_span_metrics_calls_total";
+pub const DURATION_METRIC: &str = "traces_span_metrics_duration_milliseconds";
+
+/// Series which didn't get spans for this many intervals are dropped, queries see a
+/// counter reset when they come back.
+const STALE_INTERVALS: i64 = 20;
+
+/// Request and duration stats of the ingested spans, by org and span labels. The stats
+/// are kept per ingester and emitted with its `instance` label.
+static SERIES: Lazy<RwHashMap<SeriesKey, SpanStats>> = Lazy::new(DashMap::default);
+
+/// Upper bounds of the duration histogram buckets, in milliseconds.
+static BUCKETS: Lazy<Vec<f64>> = Lazy::new(|| {
+    let mut buckets: Vec<f64> = CONFIG
+        .common
+        .traces_span_metrics_buckets
+        .split(',')
+        .filter_map(|v| v.trim().parse().ok())
+        .collect();
+    buckets.sort_by(|a, b| a.total_cmp(b));
+    buckets.dedup();
+    buckets
+});
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct SeriesKey {
+    org_id: String,
+    service_name: String,
+    span_name: String,
+    span_kind: String,
+    status_code: String,
+}
+
+#[derive(Clone, Debug, Default)]
+struct SpanStats {
+    calls: u64,
+    /// Sum of the span durations, in milliseconds
+    duration_sum: f64,
+    /// Spans by duration bucket, the last one is `+Inf`
+    bucket_counts: Vec<u64>,
+    /// Wall clock time of the last span, in microseconds
+    updated_at: i64,
+}
+
+/// Adds an ingested span to the stats of its series, if `ZO_TRACES_SPAN_METRICS_ENABLED`.
+pub fn record(org_id: &str, span: &Span) {
+    if !CONFIG.common.traces_span_metrics_enabled {
+        return;
+    }
+    let key = SeriesKey {
+        org_id: org_id.to_string(),
+        service_name: span.service_name.clone(),
+        span_name: span.operation_name.clone(),
+        span_kind: span_kind(&span.span_kind).to_string(),
+        status_code: status_code(&span.span_status).to_string(),
+    };
+    let duration = span.duration as f64 / 1000.0;
+    let mut stats = SERIES.entry(key).or_insert_with(|| SpanStats {
+        bucket_counts: vec![0; BUCKETS.len() + 1],
+        ..Default::default()
+    });
+    stats.calls += 1;
+    stats.duration_sum += duration;
+    stats.bucket_counts[BUCKETS.partition_point(|le| *le < duration)] += 1;
+    stats.updated_at = Utc::now().timestamp_micros();
+}
+
+/// Ingests the current stats as Prometheus counter and histogram series into the metrics
+/// streams of each org, called periodically by the span metrics job.
+pub async fn flush() {
+    let now = Utc::now().timestamp_micros();
+    let max_stale = CONFIG.limit.traces_span_metrics_interval as i64 * STALE_INTERVALS * 1_000_000;
+    SERIES.retain(|_, stats| now - stats.updated_at <= max_stale);
+
+    let mut org_series: AHashMap<String, Vec<prometheus::TimeSeries>> = AHashMap::new();
+    for item in SERIES.iter() {
+        org_series
+            .entry(item.key().org_id.clone())
+            .or_default()
+            .extend(to_timeseries(item.key(), item.value(), now / 1000));
+    }
+    for (org_id, timeseries) in org_series {
+        let request = prometheus::WriteRequest {
+            timeseries,
+            metadata: metadata(),
+        };
+        if let Err(e) = prom::write_request(&org_id, 0, request).await {
+            log::error!("span metrics [{org_id}] ingestion error: {e}");
+        }
+    }
+}
+
+fn metadata() -> Vec<prometheus::MetricMetadata> {
+    use prometheus::metric_metadata::MetricType;
+
+    vec![
+        prometheus::MetricMetadata {
+            r#type: MetricType::Counter as i32,
+            metric_family_name: CALLS_METRIC.to_string(),
+            help: "Number of spans, by service, operation, kind and status".to_string(),
+            unit: "".to_string(),
+        },
+        prometheus::MetricMetadata {
+            r#type: MetricType::Histogram as i32,
+            metric_family_name: DURATION_METRIC.to_string(),
+            help: "Duration of the spans, by service, operation, kind and status".to_string(),
+            unit: "milliseconds".to_string(),
+        },
+    ]
+}
+
+/// Converts the stats of a series into the calls counter and the duration histogram
+/// series, `timestamp` is in milliseconds.
+fn to_timeseries(
+    key: &SeriesKey,
+    stats: &SpanStats,
+    timestamp: i64,
+) -> Vec<prometheus::TimeSeries> {
+    let series = |name: String, extra: Option<(&str, String)>, value: f64| {
+        let mut labels = vec![
+            label(NAME_LABEL, name),
+            label("service_name", key.service_name.clone()),
+            label("span_name", key.span_name.clone()),
+            label("span_kind", key.span_kind.clone()),
+            label("status_code", key.status_code.clone()),
+            label("instance", CONFIG.common.instance_name.clone()),
+        ];
+        if let Some((name, value)) = extra {
+            labels.push(label(name, value));
+        }
+        labels.sort_by(|a, b| a.name.cmp(&b.name));
+        prometheus::TimeSeries {
+            labels,
+            samples: vec![prometheus::Sample { value, timestamp }],
+            ..Default::default()
+        }
+    };
+
+    let mut timeseries = Vec::with_capacity(BUCKETS.len() + 4);
+    timeseries.push(series(CALLS_METRIC.to_string(), None, stats.calls as f64));
+    let mut cumulative = 0;
+    for (i, count) in stats.bucket_counts.iter().enumerate() {
+        cumulative += count;
+        let le = match BUCKETS.get(i) {
+            Some(le) => le.to_string(),
+            None => "+Inf".to_string(),
+        };
+        timeseries.push(series(
+            format!("{DURATION_METRIC}_bucket"),
+            Some(("le", le)),
+            cumulative as f64,
+        ));
+    }
+    timeseries.push(series(
+        format!("{DURATION_METRIC}_sum"),
+        None,
+        stats.duration_sum,
+    ));
+    timeseries.push(series(
+        format!("{DURATION_METRIC}_count"),
+        None,
+        stats.calls as f64,
+    ));
+    timeseries
+}
+
+fn label(name: &str, value: String) -> prometheus::Label {
+    prometheus::Label {
+        name: name.to_string(),
+        value,
+    }
+}
+
+/// Span kinds are stored as the OTLP enum value.
+fn span_kind(kind: &str) -> &'static str {
+    match kind.trim_matches('"') {
+        "1" | "SPAN_KIND_INTERNAL" => "SPAN_KIND_INTERNAL",
+        "2" | "SPAN_KIND_SERVER" => "SPAN_KIND_SERVER",
+        "3" | "SPAN_KIND_CLIENT" => "SPAN_KIND_CLIENT",
+        "4" | "SPAN_KIND_PRODUCER" => "SPAN_KIND_PRODUCER",
+        "5" | "SPAN_KIND_CONSUMER" => "SPAN_KIND_CONSUMER",
+        _ => "SPAN_KIND_UNSPECIFIED",
+    }
+}
+
+fn status_code(status: &str) -> &'static str {
+    match status.trim_matches('"') {
+        "OK" => "STATUS_CODE_OK",
+        "ERROR" => "STATUS_CODE_ERROR",
+        _ => "STATUS_CODE_UNSET",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> SeriesKey {
+        SeriesKey {
+            org_id: "default".to_string(),
+            service_name: "frontend".to_string(),
+            span_name: "GET /".to_string(),
+            span_kind: "SPAN_KIND_SERVER".to_string(),
+            status_code: "STATUS_CODE_OK".to_string(),
+        }
+    }
+
+    fn value(series: &[prometheus::TimeSeries], name: &str, le: Option<&str>) -> f64 {
+        series
+            .iter()
+            .find(|s| {
+                s.labels
+                    .iter()
+                    .any(|l| l.name == NAME_LABEL && l.value == name)
+                    && le
+                        .iter()
+                        .all(|le| s.labels.iter().any(|l| l.name == "le" && l.value == *le))
+            })
+            .unwrap()
+            .samples[0]
+            .value
+    }
+
+    #[test]
+    fn test_to_timeseries() {
+        let mut bucket_counts = vec![0; BUCKETS.len() + 1];
+        // 3ms, 3ms, 90ms and a span longer than the largest bucket
+        bucket_counts[1] = 2;
+        bucket_counts[6] = 1;
+        bucket_counts[BUCKETS.len()] = 1;
+        let stats = SpanStats {
+            calls: 4,
+            duration_sum: 20096.0,
+            bucket_counts,
+            updated_at: 0,
+        };
+        let series = to_timeseries(&key(), &stats, 1000);
+        assert_eq!(series.len(), BUCKETS.len() + 4);
+        assert_eq!(value(&series, CALLS_METRIC, None), 4.0);
+        let bucket = format!("{DURATION_METRIC}_bucket");
+        assert_eq!(value(&series, &bucket, Some("2")), 0.0);
+        assert_eq!(value(&series, &bucket, Some("4")), 2.0);
+        assert_eq!(value(&series, &bucket, Some("100")), 3.0);
+        assert_eq!(value(&series, &bucket, Some("15000")), 3.0);
+        assert_eq!(value(&series, &bucket, Some("+Inf")), 4.0);
+        assert_eq!(
+            value(&series, &format!("{DURATION_METRIC}_sum"), None),
+            20096.0
+        );
+        assert_eq!(
+            value(&series, &format!("{DURATION_METRIC}_count"), None),
+            4.0
+        );
+
+        let labels = &series[0].labels;
+        assert!(labels.windows(2).all(|w| w[0].name < w[1].name));
+        assert!(labels
+            .iter()
+            .any(|l| l.name == "status_code" && l.value == "STATUS_CODE_OK"));
+    }
+
+    #[test]
+    fn test_buckets() {
+        assert_eq!(BUCKETS.len(), 16);
+        assert_eq!(BUCKETS.partition_point(|le| *le < 3.0), 1);
+        assert_eq!(BUCKETS.partition_point(|le| *le < 4.0), 1);
+        assert_eq!(BUCKETS.partition_point(|le| *le < 20000.0), BUCKETS.len());
+    }
+
+    #[test]
+    fn test_labels() {
+        assert_eq!(span_kind("2"), "SPAN_KIND_SERVER");
+        assert_eq!(span_kind("\"SPAN_KIND_CLIENT\""), "SPAN_KIND_CLIENT");
+        assert_eq!(span_kind("0"), "SPAN_KIND_UNSPECIFIED");
+        assert_eq!(status_code("ERROR"), "STATUS_CODE_ERROR");
+        assert_eq!(status_code(""), "STATUS_CODE_UNSET");
+    }
+}
@@ -118,6 +118,35 @@ pub async fn report_request_usage_stats(
     }
 }
 
+/// Reports the spans dropped by the sampling policy of a traces stream, the kept spans
+/// are reported as ingestion.
+pub async fn report_sampling_stats(stats: RequestStats, org_id: &str, stream_name: &str) {
+    if !CONFIG.common.usage_enabled || stats.records == 0 {
+        return;
+    }
+    let now = Utc::now();
+    let usage = vec![UsageData {
+        event: UsageEvent::SampledOut,
+        year: now.year(),
+        month: now.month(),
+        day: now.day(),
+        hour: now.hour(),
+        org_id: org_id.to_owned(),
+        request_body: UsageType::TracesSampling.to_string(),
+        size: stats.size,
+        unit: "MB".to_owned(),
+        user_email: "".to_owned(),
+        response_time: stats.response_time,
+        num_records: stats.records,
+        stream_type: StreamType::Traces,
+        stream_name: stream_name.to_owned(),
+        min_ts: None,
+        max_ts: None,
+        compressed_size: None,
+    }];
+    publish_usage(usage).await;
+}
+
 pub async fn report_compression_stats(
     stats: RequestStats,
     org_id: &str,
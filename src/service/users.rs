@@ -320,6 +320,18 @@ pub async fn get_user_by_token(org_id: &str, token: &str) -> Option<User> {
     }
 }
 
+/// Returns the organization of the ingestion passcode `token`, for the ingestion endpoints
+/// which only receive a token, like Splunk HEC.
+pub fn get_org_by_ingestion_token(token: &str) -> Option<String> {
+    if token.is_empty() {
+        return None;
+    }
+    USERS
+        .iter()
+        .find(|user| user.token.eq(token))
+        .map(|user| user.org.clone())
+}
+
 pub async fn list_users(org_id: &str) -> Result<HttpResponse, Error> {
     let mut user_list: Vec<UserResponse> = vec![];
     for user in USERS.iter() {